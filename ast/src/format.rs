@@ -0,0 +1,412 @@
+//! Prints syntax trees back out as Lox source.
+//!
+//! Parsing the output gives back the same tree, apart from where the tokens
+//! are. Groupings are printed as the parentheses they were parsed from and
+//! nothing else is parenthesized, so precedence comes out as it went in.
+//! Trees with block or `if` expressions need the `expr-blocks` parse option
+//! to read back, as they did to be parsed in the first place.
+
+use crate::{token::Literal, Expr, FunStmt, Stmt};
+use alloc::{string::String, vec::Vec};
+
+const INDENT: &str = "    ";
+
+/// Formats one statement. Nested statements are indented by four spaces.
+pub fn format_stmt(stmt: &Stmt) -> String {
+    format_stmts(core::slice::from_ref(stmt))
+}
+
+/// Formats a list of statements, such as a whole program, one per line.
+pub fn format_stmts(stmts: &[Stmt]) -> String {
+    let mut printer = Printer::default();
+    printer.stmts(stmts);
+    let mut source = printer.out;
+    // Every statement starts on a new line, including the first.
+    if source.starts_with('\n') {
+        source.remove(0);
+    }
+    source.push('\n');
+    source
+}
+
+pub fn format_expr(expr: &Expr) -> String {
+    let mut printer = Printer::default();
+    printer.expr(expr);
+    printer.out
+}
+
+#[derive(Default)]
+struct Printer {
+    out: String,
+    depth: usize,
+}
+
+impl Printer {
+    fn newline(&mut self) {
+        self.out.push('\n');
+        for _ in 0..self.depth {
+            self.out.push_str(INDENT);
+        }
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.newline();
+            self.stmt(stmt);
+        }
+    }
+
+    /// `{ stmts value }`, with the value of a block expression last.
+    fn block(&mut self, stmts: &[Stmt], value: Option<&Expr>) {
+        if stmts.is_empty() && value.is_none() {
+            self.out.push_str("{}");
+            return;
+        }
+        self.out.push('{');
+        self.depth += 1;
+        self.stmts(stmts);
+        if let Some(value) = value {
+            self.newline();
+            self.expr(value);
+        }
+        self.depth -= 1;
+        self.newline();
+        self.out.push('}');
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Block(block_stmt) => {
+                if !self.for_loop(stmt) {
+                    self.block(&block_stmt.statements, None)
+                }
+            }
+            Stmt::Class(class_stmt) => {
+                self.out.push_str("class ");
+                self.out.push_str(&class_stmt.name.lexeme);
+                if !class_stmt.traits.is_empty() {
+                    self.out.push_str(" with ");
+                    self.list(&class_stmt.traits);
+                }
+                if class_stmt.fields.is_empty() && class_stmt.methods.is_empty() {
+                    self.out.push_str(" {}");
+                    return;
+                }
+                self.out.push_str(" {");
+                self.depth += 1;
+                for field in &class_stmt.fields {
+                    self.newline();
+                    self.stmt(&Stmt::Var(field.clone()));
+                }
+                for method in &class_stmt.methods {
+                    self.newline();
+                    self.function(method);
+                }
+                self.depth -= 1;
+                self.newline();
+                self.out.push('}');
+            }
+            Stmt::Destructure(destructure_stmt) => {
+                let names: Vec<&str> = destructure_stmt
+                    .names
+                    .iter()
+                    .map(|name| name.lexeme.as_str())
+                    .collect();
+                let (open, close) = if destructure_stmt.is_array() {
+                    ("var [", "]")
+                } else {
+                    ("var {", "}")
+                };
+                self.out.push_str(open);
+                self.out.push_str(&names.join(", "));
+                self.out.push_str(close);
+                self.out.push_str(" = ");
+                self.expr(&destructure_stmt.initializer);
+                self.out.push(';');
+            }
+            Stmt::Expr(expr_stmt) => {
+                self.expr(&expr_stmt.expression);
+                // Like the parser, an `if` or block expression ends itself.
+                if !matches!(expr_stmt.expression, Expr::If(_) | Expr::Block(_)) {
+                    self.out.push(';');
+                }
+            }
+            Stmt::Fun(fun_stmt) => {
+                self.out.push_str("fun ");
+                self.function(fun_stmt);
+            }
+            Stmt::If(if_stmt) => {
+                self.out.push_str("if (");
+                self.expr(&if_stmt.condition);
+                self.out.push_str(") ");
+                self.stmt(&if_stmt.then_branch);
+                if let Some(else_branch) = &if_stmt.else_branch {
+                    self.out.push_str(" else ");
+                    self.stmt(else_branch);
+                }
+            }
+            Stmt::Print(print_stmt) => {
+                self.out.push_str("print ");
+                self.list(&print_stmt.expressions);
+                self.out.push(';');
+            }
+            Stmt::Return(return_stmt) => {
+                self.out.push_str("return");
+                if let Some(value) = &return_stmt.value {
+                    self.out.push(' ');
+                    self.expr(value);
+                }
+                self.out.push(';');
+            }
+            Stmt::Trait(trait_stmt) => {
+                self.out.push_str("trait ");
+                self.out.push_str(&trait_stmt.name.lexeme);
+                if trait_stmt.methods.is_empty() {
+                    self.out.push_str(" {}");
+                    return;
+                }
+                self.out.push_str(" {");
+                self.depth += 1;
+                for method in &trait_stmt.methods {
+                    self.newline();
+                    self.function(method);
+                }
+                self.depth -= 1;
+                self.newline();
+                self.out.push('}');
+            }
+            Stmt::Var(var_stmt) => {
+                self.out.push_str("var ");
+                self.out.push_str(&var_stmt.name.lexeme);
+                if let Some(initializer) = &var_stmt.initializer {
+                    self.out.push_str(" = ");
+                    self.expr(initializer);
+                }
+                self.out.push(';');
+            }
+            Stmt::While(while_stmt) => {
+                if self.for_loop(stmt) {
+                    return;
+                }
+                self.out.push_str("while (");
+                self.expr(&while_stmt.condition);
+                self.out.push_str(") ");
+                self.stmt(&while_stmt.body);
+            }
+        }
+    }
+
+    /// A `for` loop is parsed into `{ init; while (cond) { body; inc; } }`,
+    /// which doesn't always read back the same: under `expr-blocks` a block
+    /// or `if` inside a block is an expression, and a body starting with
+    /// `fun` would become a declaration. So a block of an initializer and a loop,
+    /// and a loop whose body is one of those followed by an increment, are
+    /// printed as `for` loops. Returns whether it printed one.
+    fn for_loop(&mut self, stmt: &Stmt) -> bool {
+        let (init, while_stmt) = match stmt {
+            Stmt::Block(block_stmt) => match block_stmt.statements.as_slice() {
+                [init @ Stmt::Var(_), Stmt::While(while_stmt)] => (Some(init), while_stmt),
+                [init @ Stmt::Expr(expr_stmt), Stmt::While(while_stmt)]
+                    if !matches!(expr_stmt.expression, Expr::If(_) | Expr::Block(_)) =>
+                {
+                    (Some(init), while_stmt)
+                }
+                _ => return false,
+            },
+            Stmt::While(while_stmt) => (None, while_stmt),
+            _ => return false,
+        };
+        let (body, increment) = match &*while_stmt.body {
+            Stmt::Block(block_stmt) => match block_stmt.statements.as_slice() {
+                [body, Stmt::Expr(increment)] => (body, Some(&increment.expression)),
+                _ => (&*while_stmt.body, None),
+            },
+            body => (body, None),
+        };
+        // A loop body can't be a bare declaration, so one that's only in
+        // the while's block stays a while loop.
+        if matches!(
+            body,
+            Stmt::Var(_) | Stmt::Fun(_) | Stmt::Class(_) | Stmt::Trait(_)
+        ) {
+            return false;
+        }
+        if init.is_none() {
+            let awkward = match body {
+                Stmt::Block(_) | Stmt::If(_) => true,
+                Stmt::Expr(expr_stmt) => starts_with_function(&expr_stmt.expression),
+                _ => false,
+            };
+            if increment.is_none() || !awkward {
+                return false;
+            }
+        }
+
+        self.out.push_str("for (");
+        match init {
+            Some(init) => self.stmt(init),
+            None => self.out.push(';'),
+        }
+        self.out.push(' ');
+        self.expr(&while_stmt.condition);
+        self.out.push(';');
+        if let Some(increment) = increment {
+            self.out.push(' ');
+            self.expr(increment);
+        }
+        self.out.push_str(") ");
+        self.stmt(body);
+        true
+    }
+
+    /// `name(params) { body }`, as in a declaration or method.
+    fn function(&mut self, fun: &FunStmt) {
+        self.out.push_str(&fun.name.lexeme);
+        self.parameters(fun);
+        self.out.push(' ');
+        self.block(&fun.body, None);
+    }
+
+    fn parameters(&mut self, fun: &FunStmt) {
+        let mut params: Vec<String> = fun.parameters.iter().map(|p| p.lexeme.clone()).collect();
+        if let Some(rest) = &fun.rest {
+            params.push(alloc::format!("...{}", rest.lexeme));
+        }
+        self.out.push('(');
+        self.out.push_str(&params.join(", "));
+        self.out.push(')');
+    }
+
+    fn list(&mut self, exprs: &[Expr]) {
+        for (i, expr) in exprs.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.expr(expr);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Array(array_expr) => {
+                self.out.push('[');
+                self.list(&array_expr.elements);
+                self.out.push(']');
+            }
+            Expr::Assign(assign_expr) => {
+                self.out.push_str(&assign_expr.name.lexeme);
+                self.out.push_str(" = ");
+                self.expr(&assign_expr.value);
+            }
+            Expr::Binary(binary_expr) => {
+                self.expr(&binary_expr.left);
+                self.out.push(' ');
+                self.out.push_str(binary_expr.operator.symbol());
+                self.out.push(' ');
+                self.expr(&binary_expr.right);
+            }
+            Expr::Block(block_expr) => {
+                self.block(&block_expr.statements, block_expr.value.as_deref())
+            }
+            Expr::Call(call_expr) => {
+                self.expr(&call_expr.callee);
+                self.out.push('(');
+                self.list(&call_expr.arguments);
+                for (i, (name, value)) in call_expr.keyword_arguments.iter().enumerate() {
+                    if i > 0 || !call_expr.arguments.is_empty() {
+                        self.out.push_str(", ");
+                    }
+                    self.out.push_str(&name.lexeme);
+                    self.out.push_str(": ");
+                    self.expr(value);
+                }
+                self.out.push(')');
+            }
+            Expr::Function(fun_expr) => {
+                self.out.push_str("fun ");
+                self.function(&fun_expr.declaration);
+            }
+            Expr::Get(get_expr) => {
+                self.expr(&get_expr.object);
+                self.out.push_str(if get_expr.safe { "?." } else { "." });
+                self.out.push_str(&get_expr.name.lexeme);
+            }
+            Expr::Grouping(group_expr) => {
+                self.out.push('(');
+                self.expr(&group_expr.expression);
+                self.out.push(')');
+            }
+            Expr::If(if_expr) => {
+                self.out.push_str("if (");
+                self.expr(&if_expr.condition);
+                self.out.push_str(") ");
+                self.expr(&if_expr.then_branch);
+                if let Some(else_branch) = &if_expr.else_branch {
+                    self.out.push_str(" else ");
+                    self.expr(else_branch);
+                }
+            }
+            Expr::Index(index_expr) => {
+                self.expr(&index_expr.object);
+                self.out.push('[');
+                self.expr(&index_expr.index);
+                self.out.push(']');
+            }
+            Expr::Literal(lit_expr) => match &lit_expr.value {
+                Literal::String(s) => {
+                    self.out.push('"');
+                    self.out.push_str(s);
+                    self.out.push('"');
+                }
+                literal => self.out.push_str(&alloc::format!("{}", literal)),
+            },
+            Expr::Logical(log_expr) => {
+                self.expr(&log_expr.left);
+                self.out.push(' ');
+                self.out.push_str(log_expr.operator.symbol());
+                self.out.push(' ');
+                self.expr(&log_expr.right);
+            }
+            Expr::Set(set_expr) => {
+                self.expr(&set_expr.object);
+                self.out.push('.');
+                self.out.push_str(&set_expr.name.lexeme);
+                self.out.push_str(" = ");
+                self.expr(&set_expr.value);
+            }
+            Expr::SetIndex(set_expr) => {
+                self.expr(&set_expr.object);
+                self.out.push('[');
+                self.expr(&set_expr.index);
+                self.out.push_str("] = ");
+                self.expr(&set_expr.value);
+            }
+            Expr::Spread(spread_expr) => {
+                self.out.push_str("...");
+                self.expr(&spread_expr.expression);
+            }
+            Expr::This(_) => self.out.push_str("this"),
+            Expr::Unary(unary_expr) => {
+                self.out.push_str(unary_expr.operator.symbol());
+                self.expr(&unary_expr.right);
+            }
+            Expr::Variable(var_expr) => self.out.push_str(&var_expr.name.lexeme),
+        }
+    }
+}
+
+/// Whether the source for `expr` starts with `fun`, which at the start of a
+/// declaration would be read as a function declaration.
+fn starts_with_function(expr: &Expr) -> bool {
+    match expr {
+        Expr::Function(_) => true,
+        Expr::Binary(binary_expr) => starts_with_function(&binary_expr.left),
+        Expr::Call(call_expr) => starts_with_function(&call_expr.callee),
+        Expr::Get(get_expr) => starts_with_function(&get_expr.object),
+        Expr::Index(index_expr) => starts_with_function(&index_expr.object),
+        Expr::Logical(logical_expr) => starts_with_function(&logical_expr.left),
+        Expr::Set(set_expr) => starts_with_function(&set_expr.object),
+        Expr::SetIndex(set_expr) => starts_with_function(&set_expr.object),
+        _ => false,
+    }
+}
@@ -1,40 +1,132 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+#![forbid(unsafe_code)]
+
+extern crate alloc;
+
+pub mod format;
+pub mod limits;
 pub mod token;
+pub mod version;
 pub mod visitor;
 
-use crate::token::{Literal, Token};
-use snowflake::ProcessUniqueId;
-use std::fmt;
+use crate::token::{Literal, Position, Span, Token, TokenType};
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
-pub type ScopeId = ProcessUniqueId;
+/// Identifies an expression that refers to a variable, so the resolver can
+/// record how far away the variable lives. Every call to `new` gives a
+/// different id.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct ScopeId(usize);
+
+impl ScopeId {
+    pub fn new() -> ScopeId {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        ScopeId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl Default for ScopeId {
+    fn default() -> ScopeId {
+        ScopeId::new()
+    }
+}
 
 #[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
 pub enum Stmt {
     Block(BlockStmt),
     Class(ClassStmt),
+    Destructure(DestructureStmt),
     Expr(ExprStmt),
     Fun(FunStmt),
     If(IfStmt),
     Print(PrintStmt),
     Return(ReturnStmt),
+    Trait(TraitStmt),
     Var(VarStmt),
     While(WhileStmt),
 }
 
 impl Stmt {
+    /// Where the statement's own token is, for statements that have one.
+    pub fn position(&self) -> Option<Position> {
+        match self {
+            Stmt::Class(class_stmt) => Some(class_stmt.name.position()),
+            Stmt::Destructure(destructure_stmt) => Some(destructure_stmt.open.position()),
+            Stmt::Fun(fun_stmt) => Some(fun_stmt.name.position()),
+            Stmt::Print(print_stmt) => Some(print_stmt.keyword.position()),
+            Stmt::Return(return_stmt) => Some(return_stmt.keyword.position()),
+            Stmt::Trait(trait_stmt) => Some(trait_stmt.name.position()),
+            Stmt::Var(var_stmt) => Some(var_stmt.name.position()),
+            _ => None,
+        }
+    }
+
+    /// What kind of statement this is, for messages and statistics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Stmt::Block(_) => "block",
+            Stmt::Class(_) => "class",
+            Stmt::Destructure(_) => "destructure",
+            Stmt::Expr(_) => "expression statement",
+            Stmt::Fun(_) => "fun",
+            Stmt::If(_) => "if",
+            Stmt::Print(_) => "print",
+            Stmt::Return(_) => "return",
+            Stmt::Trait(_) => "trait",
+            Stmt::Var(_) => "var",
+            Stmt::While(_) => "while",
+        }
+    }
+
     pub fn block(statements: Vec<Stmt>) -> Stmt {
         Stmt::Block(BlockStmt::new(statements))
     }
 
     pub fn class(name: &Token, methods: Vec<FunStmt>) -> Stmt {
-        Stmt::Class(ClassStmt::new(name.clone(), methods))
+        Stmt::Class(ClassStmt::new(name.clone(), vec![], vec![], methods))
+    }
+
+    /// A class that mixes in `traits`, given as variable expressions naming
+    /// them, and whose `var` fields are set on every new instance, in order,
+    /// before `init` runs.
+    pub fn class_with_members(
+        name: &Token,
+        traits: Vec<Expr>,
+        fields: Vec<VarStmt>,
+        methods: Vec<FunStmt>,
+    ) -> Stmt {
+        Stmt::Class(ClassStmt::new(name.clone(), traits, fields, methods))
+    }
+
+    /// `var [a, b] = value;` or `var {a, b} = value;`, as `open` says:
+    /// declares each of `names` as an element of an array with exactly that
+    /// many, or as the property of the same name of an instance.
+    pub fn destructure(open: &Token, names: Vec<Token>, initializer: Expr) -> Stmt {
+        Stmt::Destructure(DestructureStmt::new(open.clone(), names, initializer))
     }
 
     pub fn expr(expression: Expr) -> Stmt {
         Stmt::Expr(ExprStmt::new(expression))
     }
 
-    pub fn function(name: &Token, params: Vec<Token>, body: Vec<Stmt>) -> Stmt {
-        Stmt::Fun(FunStmt::new(name.clone(), params, body))
+    /// A function declaration. `rest`, if any, is a final `...name`
+    /// parameter that collects the remaining arguments into an array.
+    pub fn function(
+        name: &Token,
+        params: Vec<Token>,
+        rest: Option<Token>,
+        body: Vec<Stmt>,
+    ) -> Stmt {
+        Stmt::Fun(FunStmt::new(name.clone(), params, rest, body))
     }
 
     pub fn if_then(condition: Expr, then_branch: Stmt) -> Stmt {
@@ -49,14 +141,19 @@ impl Stmt {
         ))
     }
 
-    pub fn print(expression: Expr) -> Stmt {
-        Stmt::Print(PrintStmt::new(expression))
+    pub fn print(keyword: &Token, expressions: Vec<Expr>) -> Stmt {
+        Stmt::Print(PrintStmt::new(keyword.clone(), expressions))
     }
 
     pub fn ret(keyword: &Token, value: Option<Expr>) -> Stmt {
         Stmt::Return(ReturnStmt::new(keyword.clone(), value))
     }
 
+    /// A trait: a named set of methods that classes can mix in.
+    pub fn trait_decl(name: &Token, methods: Vec<FunStmt>) -> Stmt {
+        Stmt::Trait(TraitStmt::new(name.clone(), methods))
+    }
+
     pub fn var(name: &Token) -> Stmt {
         Stmt::Var(VarStmt::new(name.clone(), None))
     }
@@ -84,12 +181,47 @@ impl BlockStmt {
 #[derive(Clone, Debug, PartialEq)]
 pub struct ClassStmt {
     pub name: Token,
+    pub traits: Vec<Expr>,
+    pub fields: Vec<VarStmt>,
     pub methods: Vec<FunStmt>,
 }
 
 impl ClassStmt {
-    fn new(name: Token, methods: Vec<FunStmt>) -> ClassStmt {
-        ClassStmt { name, methods }
+    fn new(
+        name: Token,
+        traits: Vec<Expr>,
+        fields: Vec<VarStmt>,
+        methods: Vec<FunStmt>,
+    ) -> ClassStmt {
+        ClassStmt {
+            name,
+            traits,
+            fields,
+            methods,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DestructureStmt {
+    /// The `[` or `{` starting the pattern.
+    pub open: Token,
+    pub names: Vec<Token>,
+    pub initializer: Expr,
+}
+
+impl DestructureStmt {
+    fn new(open: Token, names: Vec<Token>, initializer: Expr) -> DestructureStmt {
+        DestructureStmt {
+            open,
+            names,
+            initializer,
+        }
+    }
+
+    /// Whether the pattern takes apart an array, rather than an instance.
+    pub fn is_array(&self) -> bool {
+        self.open.token_type == TokenType::LeftBracket
     }
 }
 
@@ -108,14 +240,21 @@ impl ExprStmt {
 pub struct FunStmt {
     pub name: Token,
     pub parameters: Vec<Token>,
+    pub rest: Option<Token>,
     pub body: Vec<Stmt>,
 }
 
 impl FunStmt {
-    fn new(name: Token, parameters: Vec<Token>, body: Vec<Stmt>) -> FunStmt {
+    pub fn new(
+        name: Token,
+        parameters: Vec<Token>,
+        rest: Option<Token>,
+        body: Vec<Stmt>,
+    ) -> FunStmt {
         FunStmt {
             name,
             parameters,
+            rest,
             body,
         }
     }
@@ -140,12 +279,16 @@ impl IfStmt {
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct PrintStmt {
-    pub expression: Expr,
+    pub keyword: Token,
+    pub expressions: Vec<Expr>,
 }
 
 impl PrintStmt {
-    fn new(expression: Expr) -> PrintStmt {
-        PrintStmt { expression }
+    fn new(keyword: Token, expressions: Vec<Expr>) -> PrintStmt {
+        PrintStmt {
+            keyword,
+            expressions,
+        }
     }
 }
 
@@ -161,6 +304,18 @@ impl ReturnStmt {
     }
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraitStmt {
+    pub name: Token,
+    pub methods: Vec<FunStmt>,
+}
+
+impl TraitStmt {
+    fn new(name: Token, methods: Vec<FunStmt>) -> TraitStmt {
+        TraitStmt { name, methods }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct VarStmt {
     pub name: Token,
@@ -168,7 +323,7 @@ pub struct VarStmt {
 }
 
 impl VarStmt {
-    fn new(name: Token, initializer: Option<Expr>) -> VarStmt {
+    pub fn new(name: Token, initializer: Option<Expr>) -> VarStmt {
         VarStmt { name, initializer }
     }
 }
@@ -189,13 +344,24 @@ impl WhileStmt {
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
 pub enum Expr {
+    Array(ArrayExpr),
     Assign(AssignExpr),
     Binary(BinaryExpr),
+    Block(BlockExpr),
     Call(CallExpr),
+    Function(FunctionExpr),
+    Get(GetExpr),
     Grouping(GroupingExpr),
+    If(IfExpr),
+    Index(IndexExpr),
     Literal(LiteralExpr),
     Logical(LogicalExpr),
+    Set(SetExpr),
+    SetIndex(SetIndexExpr),
+    Spread(SpreadExpr),
+    This(ThisExpr),
     Unary(UnaryExpr),
     Variable(VariableExpr),
 }
@@ -203,40 +369,102 @@ pub enum Expr {
 impl fmt::Display for Expr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            &Expr::Assign(ref assign_expr) => {
+            Expr::Array(array_expr) => write!(
+                f,
+                "{}",
+                parenthesize("array", array_expr.elements.iter().collect())
+            ),
+            Expr::Assign(assign_expr) => {
                 write!(f, "(= {} {})", assign_expr.name.lexeme, assign_expr.value)
             }
-            &Expr::Binary(ref bin_expr) => write!(
+            Expr::Binary(bin_expr) => write!(
                 f,
                 "{}",
                 parenthesize(
-                    &bin_expr.operator.lexeme,
+                    bin_expr.operator.symbol(),
                     vec![&bin_expr.left, &bin_expr.right]
                 )
             ),
-            &Expr::Call(ref call_expr) => write!(
+            Expr::Block(block_expr) => {
+                let dots = if block_expr.statements.is_empty() {
+                    ""
+                } else {
+                    " ..."
+                };
+                match &block_expr.value {
+                    Some(value) => write!(f, "(block{} {})", dots, value),
+                    None => write!(f, "(block{})", dots),
+                }
+            }
+            Expr::Call(call_expr) => write!(
                 f,
                 "{}",
-                parenthesize_call(&call_expr.callee.to_string(), &call_expr.arguments)
+                parenthesize_call(
+                    &call_expr.callee.to_string(),
+                    &call_expr.arguments,
+                    &call_expr.keyword_arguments
+                )
             ),
-            &Expr::Grouping(ref group_expr) => {
+            Expr::Function(fun_expr) => match fun_expr.name() {
+                Some(name) => write!(f, "(fun {})", name.lexeme),
+                None => write!(f, "(fun)"),
+            },
+            Expr::Get(get_expr) => {
+                let dot = if get_expr.safe { "?." } else { "." };
+                write!(f, "({} {} {})", dot, get_expr.object, get_expr.name.lexeme)
+            }
+            Expr::Grouping(group_expr) => {
                 write!(f, "{}", parenthesize("group", vec![&group_expr.expression]))
             }
-            &Expr::Literal(ref lit_expr) => write!(f, "{}", &lit_expr.value.to_string()),
-            &Expr::Logical(ref log_expr) => write!(
+            Expr::If(if_expr) => match &if_expr.else_branch {
+                Some(else_branch) => write!(
+                    f,
+                    "{}",
+                    parenthesize(
+                        "if",
+                        vec![&if_expr.condition, &if_expr.then_branch, else_branch]
+                    )
+                ),
+                None => write!(
+                    f,
+                    "{}",
+                    parenthesize("if", vec![&if_expr.condition, &if_expr.then_branch])
+                ),
+            },
+            Expr::Index(index_expr) => write!(
+                f,
+                "{}",
+                parenthesize("[]", vec![&index_expr.object, &index_expr.index])
+            ),
+            Expr::Literal(lit_expr) => write!(f, "{}", &lit_expr.value.to_string()),
+            Expr::Logical(log_expr) => write!(
                 f,
                 "{}",
                 parenthesize(
-                    &log_expr.operator.lexeme,
+                    log_expr.operator.symbol(),
                     vec![&log_expr.left, &log_expr.right]
                 )
             ),
-            &Expr::Unary(ref unary_expr) => write!(
+            Expr::Set(set_expr) => write!(
+                f,
+                "(= (. {} {}) {})",
+                set_expr.object, set_expr.name.lexeme, set_expr.value
+            ),
+            Expr::SetIndex(set_expr) => write!(
+                f,
+                "(= ([] {} {}) {})",
+                set_expr.object, set_expr.index, set_expr.value
+            ),
+            Expr::Spread(spread_expr) => {
+                write!(f, "{}", parenthesize("...", vec![&spread_expr.expression]))
+            }
+            Expr::This(_) => write!(f, "this"),
+            Expr::Unary(unary_expr) => write!(
                 f,
                 "{}",
-                parenthesize(&unary_expr.operator.lexeme, vec![&unary_expr.right])
+                parenthesize(unary_expr.operator.symbol(), vec![&unary_expr.right])
             ),
-            &Expr::Variable(ref var_expr) => write!(f, "{}", &var_expr.name.lexeme),
+            Expr::Variable(var_expr) => write!(f, "{}", &var_expr.name.lexeme),
         }
     }
 }
@@ -245,51 +473,209 @@ fn parenthesize(name: &str, exprs: Vec<&Expr>) -> String {
     let mut result = String::from("(");
     result.push_str(name);
     for expr in exprs {
-        result.push_str(" ");
+        result.push(' ');
         result.push_str(&expr.to_string());
     }
-    result.push_str(")");
+    result.push(')');
     result
 }
 
-fn parenthesize_call(callee: &str, args: &Vec<Expr>) -> String {
+fn parenthesize_call(callee: &str, args: &Vec<Expr>, keyword_args: &[(Token, Expr)]) -> String {
     let mut result = String::from("(");
     result.push_str(callee);
     for expr in args {
-        result.push_str(" ");
+        result.push(' ');
+        result.push_str(&expr.to_string());
+    }
+    for (name, expr) in keyword_args {
+        result.push(' ');
+        result.push_str(&name.lexeme);
+        result.push_str(": ");
         result.push_str(&expr.to_string());
     }
-    result.push_str(")");
+    result.push(')');
     result
 }
 
 impl Expr {
+    /// Where the expression's own token is, such as its operator. Literals
+    /// and groupings don't have one.
+    pub fn position(&self) -> Option<Position> {
+        match self {
+            Expr::Array(array_expr) => Some(array_expr.bracket.position()),
+            Expr::Assign(assign_expr) => Some(assign_expr.name.position()),
+            Expr::Binary(binary_expr) => Some(binary_expr.span.start),
+            Expr::Block(block_expr) => Some(block_expr.brace.position()),
+            Expr::Call(call_expr) => Some(call_expr.paren.position()),
+            Expr::Function(fun_expr) => Some(fun_expr.keyword.position()),
+            Expr::Get(get_expr) => Some(get_expr.name.position()),
+            Expr::If(if_expr) => Some(if_expr.keyword.position()),
+            Expr::Index(index_expr) => Some(index_expr.bracket.position()),
+            Expr::Logical(logical_expr) => Some(logical_expr.span.start),
+            Expr::Set(set_expr) => Some(set_expr.name.position()),
+            Expr::SetIndex(set_expr) => Some(set_expr.bracket.position()),
+            Expr::Spread(spread_expr) => Some(spread_expr.ellipsis.position()),
+            Expr::This(this_expr) => Some(this_expr.keyword.position()),
+            Expr::Unary(unary_expr) => Some(unary_expr.span.start),
+            Expr::Variable(var_expr) => Some(var_expr.name.position()),
+            Expr::Grouping(_) | Expr::Literal(_) => None,
+        }
+    }
+
+    /// What kind of expression this is, for messages and statistics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Expr::Array(_) => "array",
+            Expr::Assign(_) => "assign",
+            Expr::Binary(_) => "binary",
+            Expr::Block(_) => "block expression",
+            Expr::Call(_) => "call",
+            Expr::Function(_) => "function",
+            Expr::Get(_) => "get",
+            Expr::Grouping(_) => "grouping",
+            Expr::If(_) => "if expression",
+            Expr::Index(_) => "index",
+            Expr::Literal(_) => "literal",
+            Expr::Logical(_) => "logical",
+            Expr::Set(_) => "set",
+            Expr::SetIndex(_) => "set index",
+            Expr::Spread(_) => "spread",
+            Expr::This(_) => "this",
+            Expr::Unary(_) => "unary",
+            Expr::Variable(_) => "variable",
+        }
+    }
+
+    pub fn array(bracket: &Token, elements: Vec<Expr>) -> Expr {
+        Expr::Array(ArrayExpr::new(bracket.clone(), elements))
+    }
+
     pub fn assign(name: &Token, value: Expr) -> Expr {
         Expr::Assign(AssignExpr::new(name.clone(), value))
     }
 
-    pub fn binary(left: Expr, operator: &Token, right: Expr) -> Expr {
-        Expr::Binary(BinaryExpr::new(left, operator.clone(), right))
+    /// `left operator right`, built rather than parsed, so it isn't
+    /// anywhere in the source.
+    pub fn binary(left: Expr, operator: BinaryOp, right: Expr) -> Expr {
+        Expr::binary_at(left, operator, Span::default(), right)
+    }
+
+    /// `left operator right`, with the operator at `span`.
+    pub fn binary_at(left: Expr, operator: BinaryOp, span: Span, right: Expr) -> Expr {
+        Expr::Binary(BinaryExpr::new(left, operator, span, right))
+    }
+
+    /// `{ statements value }`, whose value is that of its trailing
+    /// expression, or nil without one.
+    pub fn block(brace: &Token, statements: Vec<Stmt>, value: Option<Expr>) -> Expr {
+        Expr::Block(BlockExpr::new(brace.clone(), statements, value))
     }
 
     pub fn call(callee: Expr, paren: &Token, args: Vec<Expr>) -> Expr {
-        Expr::Call(CallExpr::new(callee, paren.clone(), args))
+        Expr::Call(CallExpr::new(callee, paren.clone(), args, vec![]))
+    }
+
+    /// A call with `name: value` arguments after the positional ones.
+    pub fn call_with_keywords(
+        callee: Expr,
+        paren: &Token,
+        args: Vec<Expr>,
+        keyword_args: Vec<(Token, Expr)>,
+    ) -> Expr {
+        Expr::Call(CallExpr::new(callee, paren.clone(), args, keyword_args))
+    }
+
+    pub fn function(
+        keyword: &Token,
+        name: Option<&Token>,
+        params: Vec<Token>,
+        rest: Option<Token>,
+        body: Vec<Stmt>,
+    ) -> Expr {
+        // An anonymous function's declaration is named by an empty token.
+        let name = name.cloned().unwrap_or_else(|| Token {
+            lexeme: String::new(),
+            ..keyword.clone()
+        });
+        Expr::Function(FunctionExpr {
+            keyword: keyword.clone(),
+            declaration: FunStmt::new(name, params, rest, body),
+        })
+    }
+
+    pub fn get(object: Expr, name: &Token) -> Expr {
+        Expr::Get(GetExpr::new(object, name.clone(), false))
+    }
+
+    /// `object?.name`, which is nil when `object` is. The property reads,
+    /// calls and indexes after it in the same chain are then skipped too.
+    pub fn safe_get(object: Expr, name: &Token) -> Expr {
+        Expr::Get(GetExpr::new(object, name.clone(), true))
+    }
+
+    /// `...expr` in an argument list, passing each element of an array as
+    /// its own argument.
+    pub fn spread(ellipsis: &Token, expr: Expr) -> Expr {
+        Expr::Spread(SpreadExpr {
+            ellipsis: ellipsis.clone(),
+            expression: Box::new(expr),
+        })
     }
 
     pub fn grouping(expr: Expr) -> Expr {
         Expr::Grouping(GroupingExpr::new(expr))
     }
 
+    /// `if (condition) { ... } else { ... }` used as a value. Without an
+    /// else branch it is nil when the condition is false.
+    pub fn if_else(
+        keyword: &Token,
+        condition: Expr,
+        then_branch: Expr,
+        else_branch: Option<Expr>,
+    ) -> Expr {
+        Expr::If(IfExpr::new(
+            keyword.clone(),
+            condition,
+            then_branch,
+            else_branch,
+        ))
+    }
+
+    pub fn index(object: Expr, bracket: &Token, index: Expr) -> Expr {
+        Expr::Index(IndexExpr::new(object, bracket.clone(), index))
+    }
+
     pub fn literal(lit: Literal) -> Expr {
         Expr::Literal(LiteralExpr::new(lit))
     }
 
-    pub fn logical(left: Expr, operator: &Token, right: Expr) -> Expr {
-        Expr::Logical(LogicalExpr::new(left, operator.clone(), right))
+    pub fn logical(left: Expr, operator: LogicalOp, right: Expr) -> Expr {
+        Expr::logical_at(left, operator, Span::default(), right)
+    }
+
+    pub fn logical_at(left: Expr, operator: LogicalOp, span: Span, right: Expr) -> Expr {
+        Expr::Logical(LogicalExpr::new(left, operator, span, right))
+    }
+
+    pub fn set(object: Expr, name: &Token, value: Expr) -> Expr {
+        Expr::Set(SetExpr::new(object, name.clone(), value))
+    }
+
+    pub fn set_index(object: Expr, bracket: &Token, index: Expr, value: Expr) -> Expr {
+        Expr::SetIndex(SetIndexExpr::new(object, bracket.clone(), index, value))
     }
 
-    pub fn unary(operator: &Token, right: Expr) -> Expr {
-        Expr::Unary(UnaryExpr::new(operator.clone(), right))
+    pub fn this(keyword: &Token) -> Expr {
+        Expr::This(ThisExpr::new(keyword.clone()))
+    }
+
+    pub fn unary(operator: UnaryOp, right: Expr) -> Expr {
+        Expr::unary_at(operator, Span::default(), right)
+    }
+
+    pub fn unary_at(operator: UnaryOp, span: Span, right: Expr) -> Expr {
+        Expr::Unary(UnaryExpr::new(operator, span, right))
     }
 
     pub fn variable(name: &Token) -> Expr {
@@ -297,6 +683,18 @@ impl Expr {
     }
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArrayExpr {
+    pub bracket: Token,
+    pub elements: Vec<Expr>,
+}
+
+impl ArrayExpr {
+    fn new(bracket: Token, elements: Vec<Expr>) -> ArrayExpr {
+        ArrayExpr { bracket, elements }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct AssignExpr {
     pub name: Token,
@@ -317,33 +715,150 @@ impl AssignExpr {
 #[derive(Clone, Debug, PartialEq)]
 pub struct BinaryExpr {
     pub left: Box<Expr>,
-    pub operator: Token,
+    pub operator: BinaryOp,
+    /// Where the operator is.
+    pub span: Span,
     pub right: Box<Expr>,
 }
 
 impl BinaryExpr {
-    fn new(left: Expr, operator: Token, right: Expr) -> BinaryExpr {
+    fn new(left: Expr, operator: BinaryOp, span: Span, right: Expr) -> BinaryExpr {
         BinaryExpr {
             left: Box::new(left),
             operator,
+            span,
             right: Box::new(right),
         }
     }
 }
 
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    /// `value is Class`, whether an instance is of a class or mixes in a
+    /// trait.
+    Is,
+}
+
+impl BinaryOp {
+    /// The operator a token stands for, if it's a binary one.
+    pub fn from_token_type(token_type: TokenType) -> Option<BinaryOp> {
+        Some(match token_type {
+            TokenType::Plus => BinaryOp::Add,
+            TokenType::Minus => BinaryOp::Subtract,
+            TokenType::Star => BinaryOp::Multiply,
+            TokenType::Slash => BinaryOp::Divide,
+            TokenType::EqualEqual => BinaryOp::Equal,
+            TokenType::BangEqual => BinaryOp::NotEqual,
+            TokenType::Less => BinaryOp::Less,
+            TokenType::LessEqual => BinaryOp::LessEqual,
+            TokenType::Greater => BinaryOp::Greater,
+            TokenType::GreaterEqual => BinaryOp::GreaterEqual,
+            TokenType::Is => BinaryOp::Is,
+            _ => return None,
+        })
+    }
+
+    /// How the operator is written.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Subtract => "-",
+            BinaryOp::Multiply => "*",
+            BinaryOp::Divide => "/",
+            BinaryOp::Equal => "==",
+            BinaryOp::NotEqual => "!=",
+            BinaryOp::Less => "<",
+            BinaryOp::LessEqual => "<=",
+            BinaryOp::Greater => ">",
+            BinaryOp::GreaterEqual => ">=",
+            BinaryOp::Is => "is",
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct CallExpr {
     pub callee: Box<Expr>,
     pub paren: Token,
     pub arguments: Vec<Expr>,
+    /// `name: value` arguments, in source order, with distinct names.
+    pub keyword_arguments: Vec<(Token, Expr)>,
 }
 
 impl CallExpr {
-    fn new(callee: Expr, paren: Token, arguments: Vec<Expr>) -> CallExpr {
+    fn new(
+        callee: Expr,
+        paren: Token,
+        arguments: Vec<Expr>,
+        keyword_arguments: Vec<(Token, Expr)>,
+    ) -> CallExpr {
         CallExpr {
             callee: Box::new(callee),
             paren,
             arguments,
+            keyword_arguments,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GetExpr {
+    pub object: Box<Expr>,
+    pub name: Token,
+    pub safe: bool,
+}
+
+impl GetExpr {
+    fn new(object: Expr, name: Token, safe: bool) -> GetExpr {
+        GetExpr {
+            object: Box::new(object),
+            name,
+            safe,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockExpr {
+    pub brace: Token,
+    pub statements: Vec<Stmt>,
+    pub value: Option<Box<Expr>>,
+}
+
+impl BlockExpr {
+    fn new(brace: Token, statements: Vec<Stmt>, value: Option<Expr>) -> BlockExpr {
+        BlockExpr {
+            brace,
+            statements,
+            value: value.map(Box::new),
+        }
+    }
+}
+
+/// A function used as a value. If it has a name, the name is only bound
+/// inside the function's own body, so it can call itself.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FunctionExpr {
+    pub keyword: Token,
+    pub declaration: FunStmt,
+}
+
+impl FunctionExpr {
+    pub fn name(&self) -> Option<&Token> {
+        if self.declaration.name.lexeme.is_empty() {
+            None
+        } else {
+            Some(&self.declaration.name)
         }
     }
 }
@@ -361,6 +876,47 @@ impl GroupingExpr {
     }
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub struct IfExpr {
+    pub keyword: Token,
+    pub condition: Box<Expr>,
+    pub then_branch: Box<Expr>,
+    pub else_branch: Option<Box<Expr>>,
+}
+
+impl IfExpr {
+    fn new(
+        keyword: Token,
+        condition: Expr,
+        then_branch: Expr,
+        else_branch: Option<Expr>,
+    ) -> IfExpr {
+        IfExpr {
+            keyword,
+            condition: Box::new(condition),
+            then_branch: Box::new(then_branch),
+            else_branch: else_branch.map(Box::new),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexExpr {
+    pub object: Box<Expr>,
+    pub bracket: Token,
+    pub index: Box<Expr>,
+}
+
+impl IndexExpr {
+    fn new(object: Expr, bracket: Token, index: Expr) -> IndexExpr {
+        IndexExpr {
+            object: Box::new(object),
+            bracket,
+            index: Box::new(index),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct LiteralExpr {
     pub value: Literal,
@@ -375,35 +931,150 @@ impl LiteralExpr {
 #[derive(Clone, Debug, PartialEq)]
 pub struct LogicalExpr {
     pub left: Box<Expr>,
-    pub operator: Token,
+    pub operator: LogicalOp,
+    /// Where the operator is.
+    pub span: Span,
     pub right: Box<Expr>,
 }
 
 impl LogicalExpr {
-    fn new(left: Expr, operator: Token, right: Expr) -> LogicalExpr {
+    fn new(left: Expr, operator: LogicalOp, span: Span, right: Expr) -> LogicalExpr {
         LogicalExpr {
             left: Box::new(left),
             operator,
+            span,
             right: Box::new(right),
         }
     }
 }
 
+/// An operator that only evaluates its right operand when the left one
+/// doesn't decide the result.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum LogicalOp {
+    And,
+    Or,
+    /// `??`, the right operand when the left one is nil.
+    Coalesce,
+}
+
+impl LogicalOp {
+    pub fn from_token_type(token_type: TokenType) -> Option<LogicalOp> {
+        match token_type {
+            TokenType::And => Some(LogicalOp::And),
+            TokenType::Or => Some(LogicalOp::Or),
+            TokenType::QuestionQuestion => Some(LogicalOp::Coalesce),
+            _ => None,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            LogicalOp::And => "and",
+            LogicalOp::Or => "or",
+            LogicalOp::Coalesce => "??",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SetExpr {
+    pub object: Box<Expr>,
+    pub name: Token,
+    pub value: Box<Expr>,
+}
+
+impl SetExpr {
+    fn new(object: Expr, name: Token, value: Expr) -> SetExpr {
+        SetExpr {
+            object: Box::new(object),
+            name,
+            value: Box::new(value),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SetIndexExpr {
+    pub object: Box<Expr>,
+    pub bracket: Token,
+    pub index: Box<Expr>,
+    pub value: Box<Expr>,
+}
+
+impl SetIndexExpr {
+    fn new(object: Expr, bracket: Token, index: Expr, value: Expr) -> SetIndexExpr {
+        SetIndexExpr {
+            object: Box::new(object),
+            bracket,
+            index: Box::new(index),
+            value: Box::new(value),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpreadExpr {
+    pub ellipsis: Token,
+    pub expression: Box<Expr>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ThisExpr {
+    pub keyword: Token,
+    pub scope_id: ScopeId,
+}
+
+impl ThisExpr {
+    fn new(keyword: Token) -> ThisExpr {
+        ThisExpr {
+            keyword,
+            scope_id: ScopeId::new(),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct UnaryExpr {
-    pub operator: Token,
+    pub operator: UnaryOp,
+    /// Where the operator is.
+    pub span: Span,
     pub right: Box<Expr>,
 }
 
 impl UnaryExpr {
-    fn new(operator: Token, right: Expr) -> UnaryExpr {
+    fn new(operator: UnaryOp, span: Span, right: Expr) -> UnaryExpr {
         UnaryExpr {
             operator,
+            span,
             right: Box::new(right),
         }
     }
 }
 
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum UnaryOp {
+    Negate,
+    Not,
+}
+
+impl UnaryOp {
+    pub fn from_token_type(token_type: TokenType) -> Option<UnaryOp> {
+        match token_type {
+            TokenType::Minus => Some(UnaryOp::Negate),
+            TokenType::Bang => Some(UnaryOp::Not),
+            _ => None,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            UnaryOp::Negate => "-",
+            UnaryOp::Not => "!",
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct VariableExpr {
     pub name: Token,
@@ -431,4 +1102,45 @@ mod tests {
 
         assert_eq!(expr, expr.clone());
     }
+
+    #[test]
+    fn built_with_ops() {
+        let sum = Expr::binary(
+            Expr::literal(Literal::Number(1.0)),
+            BinaryOp::Add,
+            Expr::unary(UnaryOp::Negate, Expr::variable(&Token::identifier("x"))),
+        );
+        let expr = Expr::logical(sum, LogicalOp::Or, Expr::literal(Literal::Nil));
+
+        assert_eq!(format::format_expr(&expr), "1 + -x or nil");
+        assert_eq!(expr.to_string(), "(or (+ 1 (- x)) nil)");
+        assert_eq!(expr.kind(), "logical");
+        assert_eq!(expr.position(), Some(Position::default()));
+    }
+
+    #[test]
+    fn operators_from_tokens() {
+        assert_eq!(
+            BinaryOp::from_token_type(TokenType::Star),
+            Some(BinaryOp::Multiply)
+        );
+        assert_eq!(BinaryOp::from_token_type(TokenType::And), None);
+        assert_eq!(
+            LogicalOp::from_token_type(TokenType::QuestionQuestion),
+            Some(LogicalOp::Coalesce)
+        );
+        assert_eq!(UnaryOp::from_token_type(TokenType::Plus), None);
+    }
+
+    #[test]
+    fn token_lexemes() {
+        assert_eq!(TokenType::keyword("while"), Some(TokenType::While));
+        assert_eq!(TokenType::keyword("While"), None);
+        assert_eq!(TokenType::While.lexeme(), Some("while"));
+        assert_eq!(TokenType::QuestionDot.lexeme(), Some("?."));
+        assert_eq!(TokenType::Identifier.lexeme(), None);
+        for (keyword, token_type) in token::KEYWORDS {
+            assert_eq!(token_type.lexeme(), Some(*keyword));
+        }
+    }
 }
@@ -0,0 +1,33 @@
+/// Limits on the size of a program and the values it builds, shared by the
+/// parser and the interpreter so both report the same numbers. The
+/// defaults are the limits the language has always had.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LanguageLimits {
+    /// How many arguments a call can pass, positional and keyword together.
+    pub max_arguments: usize,
+    /// How many parameters a function can declare, not counting a `...rest`
+    /// parameter.
+    pub max_parameters: usize,
+    /// How deeply statements and expressions may nest. The parser recurses
+    /// once per level, so without a limit machine-generated code can
+    /// overflow the stack.
+    pub max_nesting: usize,
+    /// How many characters a string can have, whether written as a literal
+    /// or built by concatenation.
+    pub max_string_length: usize,
+}
+
+impl LanguageLimits {
+    pub const DEFAULT: LanguageLimits = LanguageLimits {
+        max_arguments: 8,
+        max_parameters: 8,
+        max_nesting: 200,
+        max_string_length: usize::MAX,
+    };
+}
+
+impl Default for LanguageLimits {
+    fn default() -> LanguageLimits {
+        LanguageLimits::DEFAULT
+    }
+}
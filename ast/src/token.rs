@@ -1,4 +1,5 @@
-use std::fmt;
+use alloc::string::{String, ToString};
+use core::{fmt, ops::Range};
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum TokenType {
@@ -7,6 +8,9 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
+    Colon,
     Comma,
     Dot,
     Minus,
@@ -24,6 +28,9 @@ pub enum TokenType {
     GreaterEqual,
     Less,
     LessEqual,
+    QuestionDot,
+    QuestionQuestion,
+    DotDotDot,
 
     // Literals
     Identifier,
@@ -38,19 +45,151 @@ pub enum TokenType {
     Fun,
     For,
     If,
+    Is,
     Nil,
     Or,
     Print,
     Return,
     Super,
     This,
+    Trait,
     True,
     Var,
     While,
+    With,
 
     Eof,
 }
 
+/// The reserved words, with the token each one scans to.
+pub const KEYWORDS: &[(&str, TokenType)] = &[
+    ("and", TokenType::And),
+    ("class", TokenType::Class),
+    ("else", TokenType::Else),
+    ("false", TokenType::False),
+    ("for", TokenType::For),
+    ("fun", TokenType::Fun),
+    ("if", TokenType::If),
+    ("is", TokenType::Is),
+    ("nil", TokenType::Nil),
+    ("or", TokenType::Or),
+    ("print", TokenType::Print),
+    ("return", TokenType::Return),
+    ("super", TokenType::Super),
+    ("this", TokenType::This),
+    ("trait", TokenType::Trait),
+    ("true", TokenType::True),
+    ("var", TokenType::Var),
+    ("while", TokenType::While),
+    ("with", TokenType::With),
+];
+
+/// What kind of word or symbol a token is, for tools such as syntax
+/// highlighters that treat whole groups of token types alike.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TokenClass {
+    Keyword,
+    Identifier,
+    String,
+    Number,
+    /// Operators such as `+` and `==`, and the `.`, `?.` and `...` that
+    /// reach into or spread a value.
+    Operator,
+    /// Brackets and separators.
+    Punctuation,
+    /// The end of the source.
+    Eof,
+}
+
+impl TokenType {
+    /// The keyword spelled `text`, if it is one.
+    pub fn keyword(text: &str) -> Option<TokenType> {
+        KEYWORDS
+            .iter()
+            .find(|(keyword, _)| *keyword == text)
+            .map(|(_, token_type)| *token_type)
+    }
+
+    /// How a token of this type is written, for the types that are always
+    /// written the same way: punctuation, operators and keywords.
+    pub fn lexeme(self) -> Option<&'static str> {
+        let lexeme = match self {
+            TokenType::LeftParen => "(",
+            TokenType::RightParen => ")",
+            TokenType::LeftBrace => "{",
+            TokenType::RightBrace => "}",
+            TokenType::LeftBracket => "[",
+            TokenType::RightBracket => "]",
+            TokenType::Colon => ":",
+            TokenType::Comma => ",",
+            TokenType::Dot => ".",
+            TokenType::Minus => "-",
+            TokenType::Plus => "+",
+            TokenType::Semicolon => ";",
+            TokenType::Slash => "/",
+            TokenType::Star => "*",
+            TokenType::Bang => "!",
+            TokenType::BangEqual => "!=",
+            TokenType::Equal => "=",
+            TokenType::EqualEqual => "==",
+            TokenType::Greater => ">",
+            TokenType::GreaterEqual => ">=",
+            TokenType::Less => "<",
+            TokenType::LessEqual => "<=",
+            TokenType::QuestionDot => "?.",
+            TokenType::QuestionQuestion => "??",
+            TokenType::DotDotDot => "...",
+            TokenType::Identifier | TokenType::String | TokenType::Number | TokenType::Eof => {
+                return None
+            }
+            keyword => {
+                return KEYWORDS
+                    .iter()
+                    .find(|(_, token_type)| *token_type == keyword)
+                    .map(|(text, _)| *text)
+            }
+        };
+        Some(lexeme)
+    }
+}
+
+impl TokenType {
+    pub fn class(self) -> TokenClass {
+        match self {
+            TokenType::LeftParen
+            | TokenType::RightParen
+            | TokenType::LeftBrace
+            | TokenType::RightBrace
+            | TokenType::LeftBracket
+            | TokenType::RightBracket
+            | TokenType::Colon
+            | TokenType::Comma
+            | TokenType::Semicolon => TokenClass::Punctuation,
+            TokenType::Identifier => TokenClass::Identifier,
+            TokenType::String => TokenClass::String,
+            TokenType::Number => TokenClass::Number,
+            TokenType::Eof => TokenClass::Eof,
+            TokenType::Dot
+            | TokenType::Minus
+            | TokenType::Plus
+            | TokenType::Slash
+            | TokenType::Star
+            | TokenType::Bang
+            | TokenType::BangEqual
+            | TokenType::Equal
+            | TokenType::EqualEqual
+            | TokenType::Greater
+            | TokenType::GreaterEqual
+            | TokenType::Less
+            | TokenType::LessEqual
+            | TokenType::QuestionDot
+            | TokenType::QuestionQuestion
+            | TokenType::DotDotDot => TokenClass::Operator,
+            _ => TokenClass::Keyword,
+        }
+    }
+}
+
 impl fmt::Display for TokenType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:?}", self)
@@ -69,8 +208,8 @@ pub enum Literal {
 impl fmt::Display for Literal {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            &Literal::String(ref val) => write!(f, "{}", val),
-            &Literal::Number(ref val) => write!(f, "{}", val),
+            Literal::String(val) => write!(f, "{}", val),
+            Literal::Number(val) => write!(f, "{}", val),
             &Literal::True => write!(f, "true"),
             &Literal::False => write!(f, "false"),
             &Literal::Nil => write!(f, "nil"),
@@ -78,15 +217,73 @@ impl fmt::Display for Literal {
     }
 }
 
+/// Which loaded source a token came from. Whoever loads sources numbers
+/// them; a program parsed on its own is file 0.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct FileId(pub usize);
+
+/// A place in the source. Lines count from 1, and so do columns, which
+/// count characters rather than bytes.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Position {
+    pub file: FileId,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Position {
+    pub fn new(line: usize, column: usize) -> Position {
+        Position {
+            file: FileId::default(),
+            line,
+            column,
+        }
+    }
+
+    pub fn in_file(mut self, file: FileId) -> Position {
+        self.file = file;
+        self
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// The stretch of source from `start` up to, but not including, `end`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    pub fn contains(&self, position: Position) -> bool {
+        self.start <= position && position < self.end
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
     pub line: usize,
+    /// Column of the token's first character, or 0 when it didn't come from
+    /// the scanner.
+    pub column: usize,
+    /// Byte offsets of the token's text in the source it was scanned from,
+    /// `start..end`. Both are 0 when it didn't come from the scanner.
+    pub start: usize,
+    pub end: usize,
+    pub file: FileId,
     pub literal: Option<Literal>,
 }
 
 impl Token {
+    /// A token at `line` and nowhere more exact, as tests and generated
+    /// programs make them. The scanner adds the column and offsets.
     pub fn new(
         token_type: TokenType,
         lexeme: String,
@@ -98,8 +295,60 @@ impl Token {
             lexeme,
             literal,
             line,
+            column: 0,
+            start: 0,
+            end: 0,
+            file: FileId::default(),
         }
     }
+
+    /// A name that didn't come from the scanner, for building programs.
+    pub fn identifier(name: &str) -> Token {
+        Token::new(TokenType::Identifier, name.to_string(), None, 0)
+    }
+
+    pub fn with_column(mut self, column: usize) -> Token {
+        self.column = column;
+        self
+    }
+
+    pub fn with_offsets(mut self, start: usize, end: usize) -> Token {
+        self.start = start;
+        self.end = end;
+        self
+    }
+
+    pub fn with_file(mut self, file: FileId) -> Token {
+        self.file = file;
+        self
+    }
+
+    /// The byte range of the token in its source, for slicing it back out.
+    pub fn source_range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    pub fn position(&self) -> Position {
+        Position::new(self.line, self.column).in_file(self.file)
+    }
+
+    /// Where the token's text is, going by its lexeme. A string can run
+    /// over several lines.
+    pub fn span(&self) -> Span {
+        let start = self.position();
+        let end = match self.lexeme.rfind('\n') {
+            Some(newline) => Position {
+                line: self.line + self.lexeme.matches('\n').count(),
+                column: self.lexeme[newline + 1..].chars().count() + 1,
+                ..start
+            },
+            None => Position {
+                column: self.column + self.lexeme.chars().count(),
+                ..start
+            },
+        };
+        Span { start, end }
+    }
 }
 
 impl fmt::Display for Token {
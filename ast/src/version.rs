@@ -0,0 +1,44 @@
+use core::fmt;
+
+/// Which version of the language a program is written in. A newer version
+/// can add syntax and deprecate constructs, and older programs keep
+/// running by asking for the version they were written for.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum LanguageVersion {
+    /// The base language, without the syntax added since.
+    V1,
+    /// Adds function expressions and the nil-safety operators `?.` and
+    /// `??`. The default.
+    #[default]
+    V2,
+    /// The next version, still changing: expression blocks are standard
+    /// rather than an extension, and `print` statements are deprecated.
+    V3,
+}
+
+impl LanguageVersion {
+    pub const LATEST: LanguageVersion = LanguageVersion::V3;
+
+    pub fn from_number(number: u32) -> Option<LanguageVersion> {
+        match number {
+            1 => Some(LanguageVersion::V1),
+            2 => Some(LanguageVersion::V2),
+            3 => Some(LanguageVersion::V3),
+            _ => None,
+        }
+    }
+
+    pub fn number(self) -> u32 {
+        match self {
+            LanguageVersion::V1 => 1,
+            LanguageVersion::V2 => 2,
+            LanguageVersion::V3 => 3,
+        }
+    }
+}
+
+impl fmt::Display for LanguageVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.number())
+    }
+}
@@ -0,0 +1,48 @@
+//! Times the front end and the interpreter on a generated 50k-statement
+//! program. Run with `cargo bench --bench large_program`.
+
+use interpreter::Interpreter;
+use parser::{parse_program, ParseOptions};
+use std::time::{Duration, Instant};
+
+const STATEMENTS: usize = 50_000;
+const RUNS: u32 = 5;
+
+/// Builds a program out of functions, loops and nested arithmetic so the
+/// AST has a realistic mix of statement and expression nodes.
+fn generate(statements: usize) -> String {
+    let mut source = String::new();
+    let mut i = 0;
+    while i < statements {
+        source.push_str(&format!(
+            "var a{i} = ({i} + 1) * (2 - {i} / 3) + -(4 * 5);\n\
+             fun f{i}(x, y) {{ if (x > y) {{ return x - y; }} return (y - x) * 2; }}\n\
+             var b{i} = f{i}(a{i}, {i}) + a{i};\n\
+             {{ var c = 0; while (c < 3) {{ c = c + 1; }} }}\n\
+             for (var d = 0; d < 2; d = d + 1) b{i} = b{i} + d;\n",
+            i = i
+        ));
+        i += 5;
+    }
+    source
+}
+
+fn main() {
+    let source = generate(STATEMENTS);
+    let mut parse_time = Duration::default();
+    let mut run_time = Duration::default();
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        let program = parse_program(source.clone(), ParseOptions::default()).unwrap();
+        parse_time += start.elapsed();
+
+        // Includes resolving, which the interpreter does before running.
+        let start = Instant::now();
+        Interpreter::new().run(program).unwrap();
+        run_time += start.elapsed();
+    }
+
+    println!("{} statements, {} bytes", STATEMENTS, source.len());
+    println!("scan + parse:    {:>10.2?}", parse_time / RUNS);
+    println!("resolve + run:   {:>10.2?}", run_time / RUNS);
+}
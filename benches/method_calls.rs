@@ -0,0 +1,53 @@
+//! Times a method-heavy loop: property reads, field writes and method
+//! calls on a few instances, the workload per-site caches of property
+//! lookups would target. Run with `cargo bench --bench method_calls`.
+
+use interpreter::Interpreter;
+use parser::{parse_program, ParseOptions};
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+const ITERATIONS: usize = 100_000;
+const RUNS: u32 = 5;
+
+fn program(iterations: usize) -> String {
+    format!(
+        "class Counter {{
+           init(step) {{ this.count = 0; this.step = step; }}
+           bump() {{ this.count = this.count + this.step; return this; }}
+           value() {{ return this.count; }}
+         }}
+         class Pair {{
+           init(a, b) {{ this.a = a; this.b = b; }}
+           sum() {{ return this.a.value() + this.b.value(); }}
+         }}
+         var pair = Pair(Counter(1), Counter(2));
+         var total = 0;
+         for (var i = 0; i < {}; i = i + 1) {{
+           pair.a.bump();
+           pair.b.bump().bump();
+           total = total + pair.sum() + pair.a.step;
+         }}
+         print total;",
+        iterations
+    )
+}
+
+fn main() {
+    let source = program(ITERATIONS);
+    let mut run_time = Duration::default();
+    for _ in 0..RUNS {
+        let program = parse_program(source.clone(), ParseOptions::default()).unwrap();
+        let start = Instant::now();
+        Interpreter::new()
+            .with_output(io::sink())
+            .run(program)
+            .unwrap();
+        run_time += start.elapsed();
+    }
+
+    println!("{} iterations", ITERATIONS);
+    println!("resolve + run:   {:>10.2?}", run_time / RUNS);
+}
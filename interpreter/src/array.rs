@@ -0,0 +1,108 @@
+use crate::{
+    stats::{Kind, Tracked},
+    value::Value,
+};
+use snowflake::ProcessUniqueId;
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+/// A growable list of values. Like instances, clones share the same
+/// elements.
+#[derive(Clone, Debug)]
+pub struct LoxArray {
+    pub id: ProcessUniqueId,
+    elements: Rc<RefCell<Vec<Value>>>,
+    frozen: Rc<Cell<bool>>,
+    _live: Rc<Tracked>,
+}
+
+impl LoxArray {
+    pub fn new(elements: Vec<Value>) -> LoxArray {
+        LoxArray {
+            id: ProcessUniqueId::new(),
+            elements: Rc::new(RefCell::new(elements)),
+            frozen: Rc::new(Cell::new(false)),
+            _live: Rc::new(Tracked::new(Kind::Array)),
+        }
+    }
+
+    /// Makes the array read-only for good. Clones share the flag.
+    pub fn freeze(&self) {
+        self.frozen.set(true);
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen.get()
+    }
+
+    pub fn get(&self, index: usize) -> Option<Value> {
+        self.elements.borrow().get(index).cloned()
+    }
+
+    /// Replaces the element at `index`, returning false if it's out of range.
+    pub fn set(&self, index: usize, value: Value) -> bool {
+        match self.elements.borrow_mut().get_mut(index) {
+            Some(element) => {
+                *element = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn push(&self, value: Value) {
+        self.elements.borrow_mut().push(value);
+    }
+
+    /// Removes and returns the element at `index`, shifting the rest down.
+    pub fn remove(&self, index: usize) -> Option<Value> {
+        let mut elements = self.elements.borrow_mut();
+        if index < elements.len() {
+            Some(elements.remove(index))
+        } else {
+            None
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.elements.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elements.borrow().is_empty()
+    }
+
+    /// A snapshot of the elements.
+    pub fn elements(&self) -> Vec<Value> {
+        self.elements.borrow().clone()
+    }
+}
+
+/// Checks that `index` is a whole number in range for an array of length
+/// `len`.
+pub fn check_index(index: &Value, len: usize) -> Result<usize, String> {
+    let n = match index {
+        Value::Number(n) if n.fract() == 0.0 => *n,
+        _ => {
+            return Err(format!(
+                "Array index must be a whole number, got {}.",
+                index
+            ))
+        }
+    };
+    if n < 0.0 || n >= len as f64 {
+        return Err(format!(
+            "Array index {} is out of range for length {}.",
+            n, len
+        ));
+    }
+    Ok(n as usize)
+}
+
+impl PartialEq for LoxArray {
+    fn eq(&self, other: &LoxArray) -> bool {
+        self.id == other.id
+    }
+}
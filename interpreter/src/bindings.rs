@@ -0,0 +1,214 @@
+use crate::{error::ResolverError, resolver::Resolver};
+use ast::{
+    token::{Position, Token, TokenType},
+    Stmt,
+};
+use std::collections::HashMap;
+
+/// Where each name in a program is declared and where it's used, as the
+/// resolver binds them. A name that isn't declared in an enclosing scope
+/// when it's resolved is looked up by name when it runs, so its uses go to
+/// the top-level declaration with that name, if there is one. The label of
+/// a keyword argument is a use of the parameter it names, when the callee
+/// is a variable bound to a function declaration.
+#[derive(Debug, Default)]
+pub struct Bindings {
+    declarations: Vec<Token>,
+    top_level: HashMap<String, usize>,
+    uses: Vec<(Token, Option<usize>)>,
+    // Each function's declaration, if it has one, and its parameters.
+    functions: Vec<(Option<usize>, Vec<Token>)>,
+    // Each keyword argument label and the use of its callee in `uses`, if
+    // the callee is a variable.
+    labels: Vec<(Token, Option<usize>)>,
+}
+
+impl Bindings {
+    /// Resolves a program for its bindings without running it.
+    pub fn resolve(program: &[Stmt]) -> Result<Bindings, Vec<ResolverError>> {
+        let mut resolver = Resolver::new();
+        resolver.bindings = Some(Bindings::default());
+        resolver.resolve(program)?;
+        Ok(resolver.bindings.unwrap_or_default())
+    }
+
+    pub(crate) fn declare(&mut self, name: &Token, top_level: bool) -> usize {
+        let index = self.declarations.len();
+        self.declarations.push(name.clone());
+        if top_level {
+            self.top_level.insert(name.lexeme.clone(), index);
+        }
+        index
+    }
+
+    pub(crate) fn refer(&mut self, name: &Token, declaration: Option<usize>) {
+        // `this` is bound like a variable but isn't a name to look up.
+        if name.token_type == TokenType::Identifier {
+            self.uses.push((name.clone(), declaration));
+        }
+    }
+
+    pub(crate) fn function(&mut self, declaration: Option<usize>, parameters: &[Token]) {
+        self.functions.push((declaration, parameters.to_vec()));
+    }
+
+    /// Records the labels of a call's keyword arguments. Call it right after
+    /// resolving the callee, so a variable callee is the last use.
+    pub(crate) fn labels<'a>(
+        &mut self,
+        labels: impl Iterator<Item = &'a Token>,
+        variable_callee: bool,
+    ) {
+        let callee = if variable_callee {
+            self.uses.len().checked_sub(1)
+        } else {
+            None
+        };
+        self.labels
+            .extend(labels.map(|label| (label.clone(), callee)));
+    }
+
+    fn declaration_index(&self, index: Option<usize>, name: &Token) -> Option<usize> {
+        index.or_else(|| self.top_level.get(&name.lexeme).copied())
+    }
+
+    /// The declaration of the name whose token covers `position`, whether
+    /// that's the declaration itself or a use of it.
+    pub fn declaration_at(&self, position: Position) -> Option<&Token> {
+        if let Some(declaration) = self.declarations.iter().find(|d| covers(d, position)) {
+            return Some(declaration);
+        }
+        if let Some((name, index)) = self.uses.iter().find(|(name, _)| covers(name, position)) {
+            return self
+                .declaration_index(*index, name)
+                .map(|index| &self.declarations[index]);
+        }
+        let (label, callee) = self
+            .labels
+            .iter()
+            .find(|(label, _)| covers(label, position))?;
+        let function = self.callee_declaration(*callee)?;
+        self.functions
+            .iter()
+            .filter(|(declaration, _)| *declaration == Some(function))
+            .flat_map(|(_, parameters)| parameters)
+            .find(|parameter| parameter.lexeme == label.lexeme)
+    }
+
+    /// The function declaration the callee of a call is bound to, if any.
+    fn callee_declaration(&self, callee: Option<usize>) -> Option<usize> {
+        let (name, index) = self.uses.get(callee?)?;
+        self.declaration_index(*index, name)
+    }
+
+    /// The function declaration whose parameter `declaration` is, and
+    /// whether it is one at all.
+    fn parameter_of(&self, declaration: &Token) -> Option<Option<usize>> {
+        self.functions
+            .iter()
+            .find(|(_, parameters)| {
+                parameters
+                    .iter()
+                    .any(|parameter| parameter.position() == declaration.position())
+            })
+            .map(|(function, _)| *function)
+    }
+
+    /// A keyword argument label spelled like the parameter `declaration`
+    /// whose callee isn't known before the program runs, such as a method,
+    /// so it can't be said whether the label names that parameter.
+    pub fn unbound_label(&self, declaration: &Token) -> Option<&Token> {
+        self.parameter_of(declaration)?;
+        self.labels
+            .iter()
+            .filter(|(label, _)| label.lexeme == declaration.lexeme)
+            .find(|(_, callee)| {
+                !self.callee_declaration(*callee).is_some_and(|function| {
+                    self.functions
+                        .iter()
+                        .any(|(declaration, _)| *declaration == Some(function))
+                })
+            })
+            .map(|(label, _)| label)
+    }
+
+    /// The declaration and every use of the name `declaration` declares,
+    /// in the order the resolver met them.
+    pub fn occurrences(&self, declaration: &Token) -> Vec<&Token> {
+        let index = match self
+            .declarations
+            .iter()
+            .position(|d| d.position() == declaration.position())
+        {
+            Some(index) => index,
+            None => return vec![],
+        };
+        let uses = self
+            .uses
+            .iter()
+            .filter(|(name, i)| self.declaration_index(*i, name) == Some(index))
+            .map(|(name, _)| name);
+        let function = self.parameter_of(declaration).flatten();
+        let labels = self
+            .labels
+            .iter()
+            .filter(move |(label, callee)| {
+                function.is_some()
+                    && label.lexeme == declaration.lexeme
+                    && self.callee_declaration(*callee) == function
+            })
+            .map(|(label, _)| label);
+        std::iter::once(&self.declarations[index])
+            .chain(uses)
+            .chain(labels)
+            .collect()
+    }
+}
+
+/// Whether `position` is on one of the characters of `token`.
+fn covers(token: &Token, position: Position) -> bool {
+    token.column > 0 && token.span().contains(position)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bindings(source: &str) -> Bindings {
+        Bindings::resolve(&parser::parse(source.to_string()).unwrap()).unwrap()
+    }
+
+    fn positions(tokens: Vec<&Token>) -> Vec<(usize, usize)> {
+        tokens.iter().map(|t| (t.line, t.column)).collect()
+    }
+
+    #[test]
+    fn shadowed_names_are_separate() {
+        let bindings = bindings("var a = 1;\nfun f(a) {\n  print a;\n}\nprint a + 1;");
+
+        let outer = bindings.declaration_at(Position::new(5, 7)).unwrap();
+        assert_eq!(outer.line, 1);
+        assert_eq!(positions(bindings.occurrences(outer)), vec![(1, 5), (5, 7)]);
+
+        let param = bindings.declaration_at(Position::new(3, 9)).unwrap();
+        assert_eq!(positions(bindings.occurrences(param)), vec![(2, 7), (3, 9)]);
+        assert!(bindings.declaration_at(Position::new(3, 3)).is_none());
+    }
+
+    #[test]
+    fn keyword_argument_labels() {
+        let bindings = bindings("fun f(a) {}\nf(a: 1);\nvar g = f;\ng(a: 2);\nf(1);");
+        let param = bindings.declaration_at(Position::new(2, 3)).unwrap();
+        assert_eq!((param.line, param.column), (1, 7));
+        // `g` is a variable, not the function, so its label isn't bound.
+        assert_eq!(positions(bindings.occurrences(param)), vec![(1, 7), (2, 3)]);
+        assert_eq!(bindings.unbound_label(param).map(|l| l.line), Some(4));
+    }
+
+    #[test]
+    fn later_top_level_declarations() {
+        let bindings = bindings("fun f() { return g(); }\nfun g() { return 1; }");
+        let g = bindings.declaration_at(Position::new(1, 18)).unwrap();
+        assert_eq!(positions(bindings.occurrences(g)), vec![(2, 5), (1, 18)]);
+    }
+}
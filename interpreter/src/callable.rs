@@ -6,8 +6,17 @@ use crate::{
 use ast::token::Token;
 
 pub trait Callable {
+    /// Number of arguments, or the minimum number for a variadic callable.
     fn arity(&self) -> usize;
-    fn call(&self, int: &mut Interpreter, args: Vec<Value>) -> InterpreterResult;
+    /// Whether extra arguments are accepted, as with a `...rest` parameter.
+    fn variadic(&self) -> bool {
+        false
+    }
+    /// Calls with arguments already checked against `arity`. `paren` is the
+    /// call's closing parenthesis, where errors are reported.
+    fn call(&self, int: &mut Interpreter, paren: &Token, args: Vec<Value>) -> InterpreterResult;
+    /// Where the callable comes from, for error messages.
+    fn declared_at(&self) -> String;
 }
 
 pub fn call<T: Callable>(
@@ -16,15 +25,67 @@ pub fn call<T: Callable>(
     int: &mut Interpreter,
     args: Vec<Value>,
 ) -> InterpreterResult {
-    if callee.arity() != args.len() {
+    let (expected, matches) = if callee.variadic() {
+        ("at least ", args.len() >= callee.arity())
+    } else {
+        ("", args.len() == callee.arity())
+    };
+    if !matches {
         return runtime_error_result(
             paren,
             &format!(
-                "Expected {} arguments but got {}.",
+                "Expected {}{} arguments but got {}. ({})",
+                expected,
                 callee.arity(),
-                args.len()
+                args.len(),
+                callee.declared_at()
             ),
         );
     }
-    callee.call(int, args)
+    callee.call(int, paren, args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::function::{LoxFunction, NativeFunction};
+    use ast::token::TokenType;
+    use std::rc::Rc;
+
+    fn token(token_type: TokenType, lexeme: &str, line: usize) -> Token {
+        Token::new(token_type, lexeme.to_string(), None, line)
+    }
+
+    #[test]
+    fn arity_mismatch_names_declaration() {
+        let name = token(TokenType::Identifier, "add", 3);
+        let params = vec![
+            token(TokenType::Identifier, "a", 3),
+            token(TokenType::Identifier, "b", 3),
+        ];
+        let declaration = match ast::Stmt::function(&name, params, None, vec![]) {
+            ast::Stmt::Fun(fun_stmt) => fun_stmt,
+            _ => unreachable!(),
+        };
+        let fun = LoxFunction::new(declaration, None);
+        let paren = token(TokenType::RightParen, ")", 7);
+
+        let err = call(&paren, &fun, &mut Interpreter::new(), vec![Value::Nil]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "[line 7] Error: Expected 2 arguments but got 1. (function 'add' declared at line 3)"
+        );
+    }
+
+    #[test]
+    fn arity_mismatch_native() {
+        let fun = NativeFunction::new("clock".to_string(), 0, Rc::new(|_| Ok(Value::Nil)));
+        let paren = token(TokenType::RightParen, ")", 1);
+
+        let err = call(&paren, &fun, &mut Interpreter::new(), vec![Value::Nil]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "[line 1] Error: Expected 0 arguments but got 1. (native function 'clock')"
+        );
+    }
 }
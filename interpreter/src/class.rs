@@ -1,24 +1,129 @@
+use crate::{
+    callable::Callable,
+    environment::Environment,
+    function::LoxFunction,
+    instance::LoxInstance,
+    interpreter::{Interpreter, InterpreterResult},
+    traits::LoxTrait,
+    value::Value,
+};
+use ast::{token::Token, VarStmt};
 use snowflake::ProcessUniqueId;
-use std::fmt;
+use std::{collections::HashMap, fmt, rc::Rc};
 
 #[derive(Clone, Debug)]
 pub struct LoxClass {
     pub name: String,
     pub id: ProcessUniqueId,
+    methods: Rc<HashMap<String, LoxFunction>>,
+    fields: Rc<Vec<VarStmt>>,
+    traits: Rc<Vec<LoxTrait>>,
+    // Where field initializers are evaluated, like a method's closure.
+    closure: Option<Rc<Environment>>,
 }
 
 impl LoxClass {
-    pub fn new(name: String) -> LoxClass {
+    pub fn new(name: String, methods: HashMap<String, LoxFunction>) -> LoxClass {
         LoxClass {
             name,
             id: ProcessUniqueId::new(),
+            methods: Rc::new(methods),
+            fields: Rc::new(vec![]),
+            traits: Rc::new(vec![]),
+            closure: None,
         }
     }
+
+    /// Adds `var` field declarations, whose initializers are evaluated in
+    /// `closure` for each new instance.
+    pub fn with_fields(
+        mut self,
+        fields: Vec<VarStmt>,
+        closure: Option<Rc<Environment>>,
+    ) -> LoxClass {
+        self.fields = Rc::new(fields);
+        self.closure = closure;
+        self
+    }
+
+    /// Records the traits the class mixes in, for `is`. Their methods are
+    /// expected to be among `methods` already.
+    pub fn with_traits(mut self, traits: Vec<LoxTrait>) -> LoxClass {
+        self.traits = Rc::new(traits);
+        self
+    }
+
+    pub fn mixes_in(&self, t: &LoxTrait) -> bool {
+        self.traits.contains(t)
+    }
+
+    pub fn find_method(&self, name: &str) -> Option<&LoxFunction> {
+        self.methods.get(name)
+    }
+
+    /// Binds keyword arguments against the initializer's parameters.
+    pub fn bind_keywords(
+        &self,
+        args: Vec<Value>,
+        keywords: Vec<(String, Value)>,
+    ) -> Result<Vec<Value>, String> {
+        match (self.find_method("init"), keywords.first()) {
+            (Some(init), _) => init.bind_keywords(args, keywords),
+            (None, Some((name, _))) => Err(format!(
+                "Unknown parameter '{}'. ({})",
+                name,
+                self.declared_at()
+            )),
+            (None, None) => Ok(args),
+        }
+    }
+
+    /// Names of the methods the class declares, sorted.
+    pub fn method_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.methods.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+}
+
+impl Callable for LoxClass {
+    fn arity(&self) -> usize {
+        self.find_method("init").map_or(0, |init| init.arity())
+    }
+
+    fn variadic(&self) -> bool {
+        self.find_method("init").is_some_and(|init| init.variadic())
+    }
+
+    fn call(&self, int: &mut Interpreter, paren: &Token, args: Vec<Value>) -> InterpreterResult {
+        let instance = LoxInstance::new(self.clone());
+        if !self.fields.is_empty() {
+            let environment = Rc::new(Environment::new(self.closure.clone()));
+            environment.define("this".to_string(), Value::Instance(instance.clone()));
+            for field in self.fields.iter() {
+                let value = match field.initializer {
+                    Some(ref initializer) => int
+                        .evaluate_in(initializer, environment.clone())?
+                        .unwrap_or(Value::Nil),
+                    None => Value::Nil,
+                };
+                instance.set(field.name.lexeme.clone(), value);
+            }
+        }
+        if let Some(init) = self.find_method("init") {
+            init.bind(instance.clone()).call(int, paren, args)?;
+        }
+        Ok(Some(Value::Instance(instance)))
+    }
+
+    fn declared_at(&self) -> String {
+        format!("class '{}'", self.name)
+    }
 }
 
 impl fmt::Display for LoxClass {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "<class {}>", self.name)
+        write!(f, "{}", self.name)
     }
 }
 
@@ -0,0 +1,451 @@
+use crate::{
+    error::ConfigError,
+    module::{FileLoader, ModuleLoader},
+};
+use ast::{limits::LanguageLimits, version::LanguageVersion};
+use serde::{Deserialize, Deserializer};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+pub const CONFIG_FILE_NAME: &str = "rlox.toml";
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct InterpreterConfig {
+    pub strict: bool,
+    pub debug_resolver: bool,
+    pub trace_exprs: bool,
+    /// Whether arithmetic warns when its result is too large for integers
+    /// to be exact, or infinite, as the `imprecise-number` lint.
+    pub checked_arithmetic: bool,
+    /// How many environments, innermost first, to snapshot when a runtime
+    /// error escapes a run. See `Interpreter::error_environments`.
+    pub dump_env_on_error: Option<usize>,
+    /// Whether to count how many times each kind of statement and
+    /// expression runs. See `Interpreter::node_counts`.
+    pub count_nodes: bool,
+    /// Whether to keep a summary of how long runs take, how many calls they
+    /// make, how deep their scopes nest and what they allocate. See
+    /// `Interpreter::report`.
+    pub report: bool,
+    /// Whether reading or calling a property an instance doesn't have goes
+    /// to its `method_missing(name, args)` method, if it has one.
+    pub method_missing: bool,
+    /// Whether each native is a global under its own name, such as `len`,
+    /// as well as in its namespace, such as `collections.len`. Programs
+    /// written before natives had namespaces need it.
+    pub flat_natives: bool,
+    /// Which version of the language programs are written in, as a number
+    /// in rlox.toml. Parse with the same version in `ParseOptions`.
+    #[serde(deserialize_with = "language_version")]
+    pub version: LanguageVersion,
+    pub module_paths: Vec<PathBuf>,
+    /// Where modules come from instead of the files under `module_paths`,
+    /// for hosts without a filesystem. It can't be set in rlox.toml.
+    #[serde(skip)]
+    pub module_loader: Option<Rc<dyn ModuleLoader>>,
+    pub capabilities: Capabilities,
+    pub lint: LintConfig,
+    pub limits: ExecutionLimits,
+    /// How large a program and its strings can be. Parse with the same
+    /// limits in `ParseOptions`.
+    #[serde(deserialize_with = "language_limits")]
+    pub language_limits: LanguageLimits,
+}
+
+impl Default for InterpreterConfig {
+    fn default() -> InterpreterConfig {
+        InterpreterConfig {
+            strict: false,
+            debug_resolver: false,
+            trace_exprs: false,
+            checked_arithmetic: false,
+            dump_env_on_error: None,
+            count_nodes: false,
+            report: false,
+            method_missing: true,
+            flat_natives: true,
+            version: LanguageVersion::default(),
+            module_paths: vec![],
+            module_loader: None,
+            capabilities: Capabilities::default(),
+            lint: LintConfig::default(),
+            limits: ExecutionLimits::default(),
+            language_limits: LanguageLimits::default(),
+        }
+    }
+}
+
+impl InterpreterConfig {
+    /// The config for running untrusted code: the default capabilities,
+    /// which leave the environment, files and network alone, and limits
+    /// that stop runaway loops and recursion.
+    pub fn sandbox() -> InterpreterConfig {
+        InterpreterConfig {
+            limits: ExecutionLimits {
+                max_steps: Some(1_000_000),
+                max_call_depth: Some(200),
+                timeout_ms: Some(2_000),
+            },
+            language_limits: LanguageLimits {
+                max_string_length: 1_000_000,
+                ..LanguageLimits::default()
+            },
+            ..InterpreterConfig::default()
+        }
+    }
+
+    pub fn from_toml(source: &str) -> Result<InterpreterConfig, ConfigError> {
+        let config: InterpreterConfig =
+            toml::from_str(source).map_err(|e| ConfigError::new(e.to_string()))?;
+        Ok(config.with_portable_module_paths())
+    }
+
+    /// Loads a config file, resolving relative module paths against the
+    /// directory that contains it.
+    pub fn from_file(path: &Path) -> Result<InterpreterConfig, ConfigError> {
+        let source = fs::read_to_string(path)
+            .map_err(|e| ConfigError::new(format!("{}: {}", path.display(), e)))?;
+        let mut config = InterpreterConfig::from_toml(&source)
+            .map_err(|e| ConfigError::new(format!("{}: {}", path.display(), e)))?;
+        if let Some(dir) = path.parent() {
+            config.module_paths = config
+                .module_paths
+                .into_iter()
+                .map(|p| if p.is_relative() { dir.join(p) } else { p })
+                .collect();
+        }
+        Ok(config)
+    }
+
+    // A config file written on Windows may separate its module paths with
+    // `\`, which other platforms would take as part of a file name.
+    fn with_portable_module_paths(mut self) -> InterpreterConfig {
+        self.module_paths = self
+            .module_paths
+            .iter()
+            .map(|p| match p.to_str() {
+                Some(p) => portable_path(p),
+                None => p.clone(),
+            })
+            .collect();
+        self
+    }
+
+    /// The files a module called `name`, such as `util/strings`, could be
+    /// loaded from, in search order: `util/strings.lox` under each module
+    /// path. Either `/` or `\` separates the parts of the name. A name with
+    /// an empty, `.` or `..` part has none.
+    pub fn module_candidates(&self, name: &str) -> Vec<PathBuf> {
+        FileLoader::new(self.module_paths.clone()).candidates(name)
+    }
+
+    /// The first of `module_candidates(name)` that exists.
+    pub fn find_module(&self, name: &str) -> Option<PathBuf> {
+        self.module_candidates(name)
+            .into_iter()
+            .find(|path| path.is_file())
+    }
+
+    /// What loads modules: the `module_loader` if there is one, and
+    /// otherwise a `FileLoader` over the module paths.
+    pub fn loader(&self) -> Rc<dyn ModuleLoader> {
+        match &self.module_loader {
+            Some(loader) => loader.clone(),
+            None => Rc::new(FileLoader::new(self.module_paths.clone())),
+        }
+    }
+
+    /// Walks up from `dir` looking for an `rlox.toml`.
+    pub fn discover(dir: &Path) -> Option<PathBuf> {
+        let mut current = Some(dir);
+        while let Some(dir) = current {
+            let candidate = dir.join(CONFIG_FILE_NAME);
+            if candidate.is_file() {
+                return Some(candidate);
+            }
+            current = dir.parent();
+        }
+        None
+    }
+}
+
+fn language_version<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<LanguageVersion, D::Error> {
+    let number = u32::deserialize(deserializer)?;
+    LanguageVersion::from_number(number).ok_or_else(|| {
+        serde::de::Error::custom(format!(
+            "unknown language version {}, expected 1 to {}",
+            number,
+            LanguageVersion::LATEST
+        ))
+    })
+}
+
+/// The `[language_limits]` table, where a limit left out keeps its default.
+fn language_limits<'de, D: Deserializer<'de>>(deserializer: D) -> Result<LanguageLimits, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct Table {
+        max_arguments: Option<usize>,
+        max_parameters: Option<usize>,
+        max_nesting: Option<usize>,
+        max_string_length: Option<usize>,
+    }
+
+    let table = Table::deserialize(deserializer)?;
+    let default = LanguageLimits::DEFAULT;
+    Ok(LanguageLimits {
+        max_arguments: table.max_arguments.unwrap_or(default.max_arguments),
+        max_parameters: table.max_parameters.unwrap_or(default.max_parameters),
+        max_nesting: table.max_nesting.unwrap_or(default.max_nesting),
+        max_string_length: table.max_string_length.unwrap_or(default.max_string_length),
+    })
+}
+
+/// `path` with both `/` and `\\` taken as separators, so a path written
+/// for Windows works on other platforms too. Windows reads both already.
+pub fn portable_path(path: &str) -> PathBuf {
+    if cfg!(windows) {
+        return PathBuf::from(path);
+    }
+    let mut portable = PathBuf::new();
+    if path.starts_with(['/', '\\']) {
+        portable.push("/");
+    }
+    portable.extend(path.split(['/', '\\']).filter(|part| !part.is_empty()));
+    portable
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Capabilities {
+    pub clock: bool,
+    pub env: bool,
+    pub fs: bool,
+    pub net: bool,
+}
+
+impl Capabilities {
+    pub fn set(&mut self, name: &str, enabled: bool) -> Result<(), ConfigError> {
+        match name {
+            "clock" => self.clock = enabled,
+            "env" => self.env = enabled,
+            "fs" => self.fs = enabled,
+            "net" => self.net = enabled,
+            _ => return Err(ConfigError::new(format!("Unknown capability '{}'.", name))),
+        }
+        Ok(())
+    }
+}
+
+impl Default for Capabilities {
+    fn default() -> Capabilities {
+        Capabilities {
+            clock: true,
+            env: false,
+            fs: false,
+            net: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LintConfig {
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+}
+
+/// Bounds on how much work a run may do. A step is a loop iteration or a
+/// call; the time limit is checked as steps are taken.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExecutionLimits {
+    pub max_steps: Option<u64>,
+    pub max_call_depth: Option<usize>,
+    pub timeout_ms: Option<u64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_source_is_default() {
+        let config = InterpreterConfig::from_toml("").unwrap();
+        assert_eq!(config, InterpreterConfig::default());
+        assert!(config.capabilities.clock);
+        assert!(config.method_missing);
+        assert!(config.flat_natives);
+    }
+
+    #[test]
+    fn full_config() {
+        let config = InterpreterConfig::from_toml(
+            r#"
+            strict = true
+            method_missing = false
+            flat_natives = false
+            dump_env_on_error = 2
+            checked_arithmetic = true
+            version = 3
+            module_paths = ["lib", "/usr/share/lox"]
+
+            [capabilities]
+            clock = false
+            fs = true
+
+            [lint]
+            allow = ["shadow-native"]
+
+            [limits]
+            max_steps = 1000
+
+            [language_limits]
+            max_arguments = 255
+            "#,
+        )
+        .unwrap();
+
+        assert!(config.strict);
+        assert!(!config.method_missing);
+        assert!(!config.flat_natives);
+        assert_eq!(config.dump_env_on_error, Some(2));
+        assert!(config.checked_arithmetic);
+        assert_eq!(config.version, LanguageVersion::V3);
+        assert_eq!(
+            config.module_paths,
+            vec![PathBuf::from("lib"), PathBuf::from("/usr/share/lox")]
+        );
+        assert!(!config.capabilities.clock);
+        assert!(config.capabilities.fs);
+        assert!(!config.capabilities.net);
+        assert_eq!(config.lint.allow, vec!["shadow-native".to_string()]);
+        assert_eq!(config.limits.max_steps, Some(1000));
+        assert_eq!(config.limits.timeout_ms, None);
+        assert_eq!(config.language_limits.max_arguments, 255);
+        assert_eq!(config.language_limits.max_parameters, 8);
+    }
+
+    #[test]
+    fn windows_module_paths() {
+        let config = InterpreterConfig::from_toml(r#"module_paths = ['lib\std', '\lox']"#).unwrap();
+        assert_eq!(
+            config.module_paths,
+            vec![Path::new("lib").join("std"), PathBuf::from("/lox")]
+        );
+    }
+
+    #[test]
+    fn module_candidates_in_search_order() {
+        let config = InterpreterConfig {
+            module_paths: vec![PathBuf::from("lib"), PathBuf::from("vendor")],
+            ..InterpreterConfig::default()
+        };
+        let expected = vec![
+            Path::new("lib").join("util").join("strings.lox"),
+            Path::new("vendor").join("util").join("strings.lox"),
+        ];
+        assert_eq!(config.module_candidates("util/strings"), expected);
+        assert_eq!(config.module_candidates("util\\strings"), expected);
+        assert_eq!(
+            config.module_candidates("list"),
+            vec![
+                Path::new("lib").join("list.lox"),
+                Path::new("vendor").join("list.lox")
+            ]
+        );
+    }
+
+    #[test]
+    fn module_names_stay_inside_the_module_paths() {
+        let config = InterpreterConfig {
+            module_paths: vec![PathBuf::from("lib")],
+            ..InterpreterConfig::default()
+        };
+        for name in &[
+            "",
+            "../secret",
+            "util\\..\\..\\secret",
+            "/etc/passwd",
+            "a//b",
+            "./a",
+        ] {
+            assert_eq!(
+                config.module_candidates(name),
+                Vec::<PathBuf>::new(),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn find_module_skips_missing_files() {
+        let dir = std::env::temp_dir().join(format!("rlox-modules-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("second").join("util")).unwrap();
+        fs::write(dir.join("second").join("util").join("strings.lox"), "").unwrap();
+        let config = InterpreterConfig {
+            module_paths: vec![dir.join("first"), dir.join("second")],
+            ..InterpreterConfig::default()
+        };
+
+        assert_eq!(
+            config.find_module("util\\strings"),
+            Some(dir.join("second").join("util").join("strings.lox"))
+        );
+        assert_eq!(config.find_module("util/numbers"), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn configured_loader() {
+        let loader: Rc<dyn ModuleLoader> =
+            Rc::new(crate::module::MemoryLoader::new().with_module("util", "var x = 1;"));
+        let config = InterpreterConfig {
+            module_loader: Some(loader.clone()),
+            ..InterpreterConfig::default()
+        };
+        assert!(config.loader() == loader);
+        assert_eq!(
+            config.loader().resolve("util", None),
+            Ok("util".to_string())
+        );
+        assert!(InterpreterConfig::default()
+            .loader()
+            .resolve("util", None)
+            .is_err());
+    }
+
+    #[test]
+    fn portable_paths() {
+        assert_eq!(portable_path("lib\\std"), Path::new("lib").join("std"));
+        assert_eq!(portable_path("lib/std/"), Path::new("lib").join("std"));
+        assert_eq!(portable_path("\\usr\\lox"), PathBuf::from("/usr/lox"));
+        assert_eq!(portable_path("script.lox"), PathBuf::from("script.lox"));
+    }
+
+    #[test]
+    fn unknown_key() {
+        assert!(InterpreterConfig::from_toml("strcit = true").is_err());
+        assert!(InterpreterConfig::from_toml("version = 4")
+            .unwrap_err()
+            .to_string()
+            .contains("unknown language version 4, expected 1 to 3"));
+    }
+
+    #[test]
+    fn set_capability() {
+        let mut caps = Capabilities::default();
+        caps.set("net", true).unwrap();
+        assert!(caps.net);
+        assert!(caps.set("gpu", true).is_err());
+    }
+}
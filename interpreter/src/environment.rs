@@ -1,12 +1,18 @@
-use crate::value::Value;
+use crate::{
+    stats::{Kind, Tracked},
+    value::Value,
+};
 use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
 pub type Scope = HashMap<String, Value>;
 
+/// The variables of one scope, and the scope around it. The outermost
+/// environment holds the globals, natives included.
 #[derive(Debug)]
 pub struct Environment {
     pub enclosing: Option<Rc<Environment>>,
     values: RefCell<Scope>,
+    _live: Tracked,
 }
 
 impl Environment {
@@ -14,6 +20,7 @@ impl Environment {
         Environment {
             enclosing,
             values: RefCell::new(HashMap::new()),
+            _live: Tracked::new(Kind::Environment),
         }
     }
 
@@ -21,6 +28,11 @@ impl Environment {
         self.values.borrow_mut().insert(name, value);
     }
 
+    /// Removes a name from this scope alone.
+    pub(crate) fn undefine(&self, name: &str) {
+        self.values.borrow_mut().remove(name);
+    }
+
     pub fn assign(&self, name: String, value: Value) -> Result<(), String> {
         if self.values.borrow().contains_key(&name) {
             self.values.borrow_mut().insert(name, value);
@@ -58,6 +70,44 @@ impl Environment {
         }
     }
 
+    /// Every name visible from this environment, innermost scope first.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.values.borrow().keys().cloned().collect();
+        names.sort();
+        if let Some(enclosing) = &self.enclosing {
+            for name in enclosing.names() {
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+        names
+    }
+
+    /// How many environments there are from this one out to the globals,
+    /// both included.
+    pub fn depth(&self) -> usize {
+        let mut depth = 1;
+        let mut enclosing = &self.enclosing;
+        while let Some(environment) = enclosing {
+            depth += 1;
+            enclosing = &environment.enclosing;
+        }
+        depth
+    }
+
+    /// The names defined in this scope alone and their values, by name.
+    pub fn bindings(&self) -> Vec<(String, Value)> {
+        let mut bindings: Vec<(String, Value)> = self
+            .values
+            .borrow()
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        bindings.sort_by(|a, b| a.0.cmp(&b.0));
+        bindings
+    }
+
     pub fn get_at(&self, name: &String, distance: usize) -> Option<Value> {
         if distance == 0 {
             self.get(name)
@@ -130,6 +180,19 @@ mod tests {
         assert_eq!(env.get(&key), Some(Value::Number(5.0)));
     }
 
+    #[test]
+    fn names_include_enclosing() {
+        let parent = Environment::new(None);
+        parent.define("foo".to_string(), Value::Nil);
+        parent.define("bar".to_string(), Value::Nil);
+
+        let env = Environment::new(Some(Rc::new(parent)));
+        env.define("foo".to_string(), Value::Nil);
+        env.define("baz".to_string(), Value::Nil);
+
+        assert_eq!(env.names(), vec!["baz", "foo", "bar"]);
+    }
+
     #[test]
     fn shadow_var() {
         let key = String::from("foo");
@@ -1,63 +1,251 @@
 use crate::interpreter::InterpreterResult;
-use ast::token::Token;
+use ast::token::{Position, Span, Token};
 use std::{error::Error, fmt};
 
+/// A `RuntimeError` for something that can't happen unless the interpreter
+/// has a bug, asking the user to report it rather than panicking.
+macro_rules! internal_error {
+    ($position:expr, $($arg:tt)+) => {
+        $crate::error::RuntimeError::internal(
+            $position,
+            $crate::error::internal_error_message(&format!($($arg)+)),
+        )
+    };
+}
+
+pub(crate) use internal_error;
+
+/// The message of an internal error about `what` went wrong.
+pub(crate) fn internal_error_message(what: &str) -> String {
+    format!(
+        "Internal error: {}. This is a bug in rlox; please report it.",
+        what
+    )
+}
+
 #[derive(Debug)]
 pub struct RuntimeError {
     msg: String,
-    line: usize,
+    position: Position,
+    kind: RuntimeErrorKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RuntimeErrorKind {
+    Error,
+    // The host cancelled the run. The message then lists the calls in
+    // progress instead of a line, since the first of them has it.
+    Interrupted,
+    // A bug in the interpreter rather than the program.
+    Internal,
 }
 
 impl RuntimeError {
-    pub fn new(line: usize, msg: String) -> RuntimeError {
-        RuntimeError { msg, line }
+    pub fn new(position: Position, msg: String) -> RuntimeError {
+        log::debug!("runtime error at {}: {}", position, msg);
+        RuntimeError {
+            msg,
+            position,
+            kind: RuntimeErrorKind::Error,
+        }
     }
-}
 
-impl fmt::Display for RuntimeError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "[line {}] Error: {}", self.line, self.description())
+    /// An error that's a bug in the interpreter. See `internal_error!`.
+    pub(crate) fn internal(position: Position, msg: String) -> RuntimeError {
+        RuntimeError {
+            kind: RuntimeErrorKind::Internal,
+            ..RuntimeError::new(position, msg)
+        }
+    }
+
+    /// The run was cancelled at `position`. `backtrace` describes the calls
+    /// in progress, innermost first.
+    pub fn interrupted(position: Position, backtrace: Vec<String>) -> RuntimeError {
+        let mut msg = "Interrupted.".to_string();
+        for frame in backtrace {
+            msg.push_str("\n  ");
+            msg.push_str(&frame);
+        }
+        RuntimeError {
+            msg,
+            position,
+            kind: RuntimeErrorKind::Interrupted,
+        }
+    }
+
+    pub fn line(&self) -> usize {
+        self.position.line
+    }
+
+    pub fn position(&self) -> Position {
+        self.position
     }
-}
 
-impl Error for RuntimeError {
-    fn description(&self) -> &str {
+    /// Where the error is. Runtime errors are known by where they start,
+    /// so the span is empty.
+    pub fn span(&self) -> Span {
+        Span {
+            start: self.position,
+            end: self.position,
+        }
+    }
+
+    pub fn message(&self) -> &str {
         &self.msg
     }
 
-    fn cause(&self) -> Option<&Error> {
-        None
+    /// What kind of error this is, for tools: `runtime`, `interrupted` when
+    /// the host cancelled the run, or `internal` for a bug in rlox.
+    pub fn code(&self) -> &'static str {
+        match self.kind {
+            RuntimeErrorKind::Error => "runtime",
+            RuntimeErrorKind::Interrupted => "interrupted",
+            RuntimeErrorKind::Internal => "internal",
+        }
+    }
+
+    /// Whether the host cancelled the run, rather than the program failing.
+    pub fn is_interrupted(&self) -> bool {
+        self.kind == RuntimeErrorKind::Interrupted
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_interrupted() {
+            write!(f, "{}", self.msg)
+        } else {
+            write!(f, "[line {}] Error: {}", self.position.line, self.msg)
+        }
     }
 }
 
+impl Error for RuntimeError {}
+
 pub fn runtime_error_result(token: &Token, msg: &str) -> InterpreterResult {
-    Result::Err(RuntimeError::new(token.line, String::from(msg)))
+    Result::Err(RuntimeError::new(token.position(), String::from(msg)))
 }
 
 #[derive(Debug)]
 pub struct ResolverError {
     msg: String,
-    line: usize,
+    position: Position,
 }
 
 impl ResolverError {
-    pub fn new(line: usize, msg: String) -> ResolverError {
-        ResolverError { line, msg }
+    pub fn new(position: Position, msg: String) -> ResolverError {
+        log::debug!("resolver error at {}: {}", position, msg);
+        ResolverError { position, msg }
+    }
+
+    pub fn line(&self) -> usize {
+        self.position.line
+    }
+
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    /// Where the error is. Resolver errors are known by where they start,
+    /// so the span is empty.
+    pub fn span(&self) -> Span {
+        Span {
+            start: self.position,
+            end: self.position,
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.msg
+    }
+
+    /// What kind of error this is, for tools: always `resolve`.
+    pub fn code(&self) -> &'static str {
+        "resolve"
     }
 }
 
 impl fmt::Display for ResolverError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "[line {}] Error: {}", self.line, self.description())
+        write!(f, "[line {}] Error: {}", self.position.line, self.msg)
     }
 }
 
-impl Error for ResolverError {
-    fn description(&self) -> &str {
+impl Error for ResolverError {}
+
+/// A diagnostic that doesn't stop the program. `lint` names the check, so it
+/// can be listed under `[lint]` in rlox.toml to allow or deny it.
+#[derive(Debug, PartialEq)]
+pub struct Warning {
+    pub lint: &'static str,
+    msg: String,
+    position: Position,
+}
+
+impl Warning {
+    pub fn new(lint: &'static str, position: Position, msg: String) -> Warning {
+        Warning {
+            lint,
+            msg,
+            position,
+        }
+    }
+
+    pub fn line(&self) -> usize {
+        self.position.line
+    }
+
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    /// Where the warning is, an empty span where it starts.
+    pub fn span(&self) -> Span {
+        Span {
+            start: self.position,
+            end: self.position,
+        }
+    }
+
+    pub fn message(&self) -> &str {
         &self.msg
     }
 
-    fn cause(&self) -> Option<&Error> {
-        None
+    /// The lint that raised the warning, the same as `lint`.
+    pub fn code(&self) -> &'static str {
+        self.lint
+    }
+
+    pub fn into_error(self) -> ResolverError {
+        ResolverError::new(self.position, self.msg)
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "[line {}] Warning: {} [{}]",
+            self.position.line, self.msg, self.lint
+        )
     }
 }
+
+#[derive(Debug)]
+pub struct ConfigError {
+    msg: String,
+}
+
+impl ConfigError {
+    pub fn new(msg: String) -> ConfigError {
+        ConfigError { msg }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Config error: {}", self.msg)
+    }
+}
+
+impl Error for ConfigError {}
@@ -0,0 +1,287 @@
+//! Renders values for `print`.
+//!
+//! Values that contain other values (arrays, sets, and instances through
+//! their fields) are rendered recursively. A value already being rendered further
+//! up shows as `...`, so self-referential structures terminate, and
+//! anything nested deeper than the depth limit is elided. An instance whose
+//! class defines `to_string()` can be shown as what that returns instead,
+//! by a caller able to run it.
+
+use crate::{array::LoxArray, instance::LoxInstance, set::LoxSet, value::Value};
+use snowflake::ProcessUniqueId;
+
+pub const DEFAULT_MAX_DEPTH: usize = 8;
+
+/// How to show an instance other than by its fields, if at all.
+pub type ShowInstance<'a> = dyn FnMut(&LoxInstance) -> Option<String> + 'a;
+
+/// How `print` shows a value: strings bare, everything else as `repr`.
+pub fn display(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.to_string(),
+        _ => repr(value),
+    }
+}
+
+/// Like `display`, but instances are shown as `to_string` gives them, when
+/// it gives anything.
+pub fn display_with(value: &Value, to_string: &mut ShowInstance) -> String {
+    match value {
+        Value::String(s) => s.to_string(),
+        _ => {
+            let mut formatter = ValueFormatter::new(DEFAULT_MAX_DEPTH);
+            formatter.to_string = Some(to_string);
+            formatter.write(value);
+            formatter.out
+        }
+    }
+}
+
+/// Shows a value the way it would be written, with strings quoted. Used
+/// for values nested inside other values.
+pub fn repr(value: &Value) -> String {
+    let mut formatter = ValueFormatter::new(DEFAULT_MAX_DEPTH);
+    formatter.write(value);
+    formatter.out
+}
+
+/// `shown`, the way `value` was shown, in the terminal color for its type:
+/// numbers yellow, strings green, booleans and nil magenta, and what can be
+/// called cyan. Arrays, sets and instances are left as they are.
+pub fn colored(value: &Value, shown: &str) -> String {
+    let color = match value {
+        Value::Number(_) => "33",
+        Value::String(_) => "32",
+        Value::Boolean(_) | Value::Nil => "35",
+        Value::Class(_) | Value::Function(_) | Value::NativeFunction(_) | Value::Trait(_) => "36",
+        Value::Array(_) | Value::Instance(_) | Value::Set(_) => return shown.to_string(),
+    };
+    format!("\x1b[{}m{}\x1b[0m", color, shown)
+}
+
+struct ValueFormatter<'a> {
+    max_depth: usize,
+    to_string: Option<&'a mut ShowInstance<'a>>,
+    // Containers currently being rendered, outermost first.
+    path: Vec<ProcessUniqueId>,
+    out: String,
+}
+
+impl<'a> ValueFormatter<'a> {
+    fn new(max_depth: usize) -> ValueFormatter<'a> {
+        ValueFormatter {
+            max_depth,
+            to_string: None,
+            path: vec![],
+            out: String::new(),
+        }
+    }
+
+    fn write(&mut self, value: &Value) {
+        match value {
+            Value::Nil => self.out.push_str("nil"),
+            Value::Array(array) => self.write_array(array),
+            Value::Boolean(b) => self.out.push_str(&b.to_string()),
+            Value::Class(class) => self.out.push_str(&class.to_string()),
+            Value::Function(fun) => self.out.push_str(&fun.to_string()),
+            Value::Instance(instance) => self.write_instance(instance),
+            Value::NativeFunction(fun) => self.out.push_str(&fun.to_string()),
+            Value::Number(n) => self.out.push_str(&n.to_string()),
+            Value::Set(set) => self.write_set(set),
+            Value::String(s) => self.out.push_str(&format!("{:?}", s)),
+            Value::Trait(t) => self.out.push_str(&t.to_string()),
+        }
+    }
+
+    fn write_array(&mut self, array: &LoxArray) {
+        if self.path.contains(&array.id) {
+            self.out.push_str("...");
+            return;
+        }
+        if array.is_empty() {
+            self.out.push_str("[]");
+            return;
+        }
+        if self.path.len() >= self.max_depth {
+            self.out.push_str("[...]");
+            return;
+        }
+
+        self.path.push(array.id);
+        self.out.push('[');
+        for (i, element) in array.elements().iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.write(element);
+        }
+        self.out.push(']');
+        self.path.pop();
+    }
+
+    fn write_set(&mut self, set: &LoxSet) {
+        if self.path.contains(&set.id) {
+            self.out.push_str("...");
+            return;
+        }
+        if set.is_empty() {
+            self.out.push_str("{}");
+            return;
+        }
+        if self.path.len() >= self.max_depth {
+            self.out.push_str("{...}");
+            return;
+        }
+
+        self.path.push(set.id);
+        self.out.push('{');
+        for (i, member) in set.values().iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.write(member);
+        }
+        self.out.push('}');
+        self.path.pop();
+    }
+
+    fn write_instance(&mut self, instance: &LoxInstance) {
+        if self.path.contains(&instance.id) {
+            self.out.push_str("...");
+            return;
+        }
+        if let Some(shown) = self.to_string.as_mut().and_then(|f| f(instance)) {
+            self.out.push_str(&shown);
+            return;
+        }
+
+        let fields = instance.fields();
+        self.out.push_str(&instance.class.name);
+        if fields.is_empty() {
+            self.out.push_str(" instance");
+            return;
+        }
+        if self.path.len() >= self.max_depth {
+            self.out.push_str(" { ... }");
+            return;
+        }
+
+        self.path.push(instance.id);
+        self.out.push_str(" { ");
+        for (i, (name, value)) in fields.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.out.push_str(name);
+            self.out.push_str(": ");
+            self.write(value);
+        }
+        self.out.push_str(" }");
+        self.path.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::class::LoxClass;
+    use std::collections::HashMap;
+
+    fn instance(class_name: &str) -> LoxInstance {
+        LoxInstance::new(LoxClass::new(class_name.to_string(), HashMap::new()))
+    }
+
+    #[test]
+    fn instance_fields() {
+        let point = instance("Point");
+        assert_eq!(display(&Value::Instance(point.clone())), "Point instance");
+
+        point.set("y".to_string(), Value::Number(2.0));
+        point.set("x".to_string(), Value::Number(1.0));
+        point.set("label".to_string(), Value::from("origin"));
+        assert_eq!(
+            display(&Value::Instance(point)),
+            "Point { label: \"origin\", x: 1, y: 2 }"
+        );
+    }
+
+    #[test]
+    fn self_reference() {
+        let node = instance("Node");
+        node.set("next".to_string(), Value::Instance(node.clone()));
+        assert_eq!(display(&Value::Instance(node)), "Node { next: ... }");
+    }
+
+    #[test]
+    fn indirect_cycle() {
+        let a = instance("A");
+        let b = instance("B");
+        a.set("b".to_string(), Value::Instance(b.clone()));
+        b.set("a".to_string(), Value::Instance(a.clone()));
+        assert_eq!(display(&Value::Instance(a)), "A { b: B { a: ... } }");
+    }
+
+    #[test]
+    fn shared_value_is_not_a_cycle() {
+        let pair = instance("Pair");
+        let leaf = instance("Leaf");
+        leaf.set("v".to_string(), Value::Nil);
+        pair.set("left".to_string(), Value::Instance(leaf.clone()));
+        pair.set("right".to_string(), Value::Instance(leaf));
+        assert_eq!(
+            display(&Value::Instance(pair)),
+            "Pair { left: Leaf { v: nil }, right: Leaf { v: nil } }"
+        );
+    }
+
+    #[test]
+    fn arrays() {
+        let empty = LoxArray::new(vec![]);
+        assert_eq!(display(&Value::Array(empty.clone())), "[]");
+
+        let point = instance("Point");
+        point.set("x".to_string(), Value::Number(1.0));
+        let array = LoxArray::new(vec![
+            Value::Number(1.0),
+            Value::from("two"),
+            Value::Array(empty),
+            Value::Instance(point),
+        ]);
+        assert_eq!(
+            display(&Value::Array(array.clone())),
+            "[1, \"two\", [], Point { x: 1 }]"
+        );
+
+        array.set(2, Value::Array(array.clone()));
+        assert_eq!(
+            display(&Value::Array(array)),
+            "[1, \"two\", ..., Point { x: 1 }]"
+        );
+    }
+
+    #[test]
+    fn sets() {
+        let set = LoxSet::new(vec![]);
+        assert_eq!(display(&Value::Set(set.clone())), "{}");
+
+        set.add(Value::Number(1.0));
+        set.add(Value::from("two"));
+        set.add(Value::Number(1.0));
+        set.add(Value::Set(set.clone()));
+        assert_eq!(display(&Value::Set(set)), "{1, \"two\", ...}");
+    }
+
+    #[test]
+    fn depth_limit() {
+        let mut formatter = ValueFormatter::new(2);
+        let outer = instance("Box");
+        let middle = instance("Box");
+        let inner = instance("Box");
+        inner.set("v".to_string(), Value::Number(1.0));
+        middle.set("v".to_string(), Value::Instance(inner));
+        outer.set("v".to_string(), Value::Instance(middle));
+
+        formatter.write(&Value::Instance(outer));
+        assert_eq!(formatter.out, "Box { v: Box { v: Box { ... } } }");
+    }
+}
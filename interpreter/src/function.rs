@@ -1,9 +1,14 @@
 use crate::{
+    array::LoxArray,
     callable::Callable,
     environment::Environment,
+    error::runtime_error_result,
+    instance::LoxInstance,
     interpreter::{Interpreter, InterpreterResult},
+    stats::{Kind, Tracked},
     value::Value,
 };
+use ast::token::Token;
 use snowflake::ProcessUniqueId;
 use std::{
     fmt,
@@ -13,19 +18,95 @@ use std::{
 
 #[derive(Clone, Debug)]
 pub struct LoxFunction {
-    pub declaration: ast::FunStmt,
+    pub declaration: Rc<ast::FunStmt>,
     pub id: ProcessUniqueId,
     pub closure: Option<Rc<Environment>>,
+    is_initializer: bool,
+    _live: Rc<Tracked>,
 }
 
 impl LoxFunction {
     pub fn new(declaration: ast::FunStmt, closure: Option<Rc<Environment>>) -> LoxFunction {
-        let fun = LoxFunction {
-            declaration,
+        LoxFunction {
+            declaration: Rc::new(declaration),
             id: ProcessUniqueId::new(),
             closure,
-        };
-        fun
+            is_initializer: false,
+            _live: Rc::new(Tracked::new(Kind::Function)),
+        }
+    }
+
+    /// A method declared in a class body. A method named `init` is the
+    /// class's initializer and always returns the instance.
+    pub fn method(declaration: ast::FunStmt, closure: Option<Rc<Environment>>) -> LoxFunction {
+        let is_initializer = declaration.name.lexeme == "init";
+        LoxFunction {
+            is_initializer,
+            ..LoxFunction::new(declaration, closure)
+        }
+    }
+
+    /// Puts `name: value` arguments in the places of the parameters they
+    /// name, after the positional `args`. Only the fixed parameters can be
+    /// named; extra positional arguments still go to a rest parameter.
+    pub fn bind_keywords(
+        &self,
+        args: Vec<Value>,
+        keywords: Vec<(String, Value)>,
+    ) -> Result<Vec<Value>, String> {
+        let params = &self.declaration.parameters;
+        let mut slots: Vec<Option<Value>> = vec![None; params.len()];
+        let mut extra = vec![];
+        for (i, arg) in args.into_iter().enumerate() {
+            match slots.get_mut(i) {
+                Some(slot) => *slot = Some(arg),
+                None => extra.push(arg),
+            }
+        }
+
+        for (name, value) in keywords {
+            let i = params
+                .iter()
+                .position(|param| param.lexeme == name)
+                .ok_or_else(|| format!("Unknown parameter '{}'. ({})", name, self.declared_at()))?;
+            if slots[i].is_some() {
+                return Err(format!(
+                    "Parameter '{}' was given more than once. ({})",
+                    name,
+                    self.declared_at()
+                ));
+            }
+            slots[i] = Some(value);
+        }
+
+        let mut bound = vec![];
+        for (param, slot) in params.iter().zip(slots) {
+            match slot {
+                Some(value) => bound.push(value),
+                None => {
+                    return Err(format!(
+                        "Missing argument for parameter '{}'. ({})",
+                        param.lexeme,
+                        self.declared_at()
+                    ))
+                }
+            }
+        }
+        bound.append(&mut extra);
+        Ok(bound)
+    }
+
+    /// Copy of this method with `this` bound to `instance`.
+    pub fn bind(&self, instance: LoxInstance) -> LoxFunction {
+        let environment = Environment::new(self.closure.clone());
+        environment.define("this".to_string(), Value::Instance(instance));
+        LoxFunction {
+            declaration: self.declaration.clone(),
+            id: ProcessUniqueId::new(),
+            closure: Some(Rc::new(environment)),
+            is_initializer: self.is_initializer,
+            _live: Rc::new(Tracked::new(Kind::Function)),
+        }
     }
 }
 
@@ -34,13 +115,37 @@ impl Callable for LoxFunction {
         self.declaration.parameters.len()
     }
 
-    fn call(&self, int: &mut Interpreter, args: Vec<Value>) -> InterpreterResult {
+    fn variadic(&self) -> bool {
+        self.declaration.rest.is_some()
+    }
+
+    fn call(&self, int: &mut Interpreter, _paren: &Token, args: Vec<Value>) -> InterpreterResult {
         let environment = Environment::new(self.closure.clone());
         for (i, param) in self.declaration.parameters.iter().enumerate() {
             environment.define(param.lexeme.clone(), args[i].clone());
         }
+        if let Some(ref rest) = self.declaration.rest {
+            let extra = args[self.declaration.parameters.len()..].to_vec();
+            environment.define(rest.lexeme.clone(), Value::Array(LoxArray::new(extra)));
+        }
 
-        int.execute_block(&self.declaration.body, Rc::new(environment))
+        // Falling off the end of the body returns nil.
+        let value = int.execute_block(&self.declaration.body, Rc::new(environment))?;
+        if self.is_initializer {
+            let this = self
+                .closure
+                .as_ref()
+                .and_then(|c| c.get_at(&"this".to_string(), 0));
+            return Ok(this);
+        }
+        Ok(Some(value.unwrap_or(Value::Nil)))
+    }
+
+    fn declared_at(&self) -> String {
+        format!(
+            "function '{}' declared at line {}",
+            self.declaration.name.lexeme, self.declaration.name.line
+        )
     }
 }
 
@@ -60,20 +165,48 @@ impl Hash for LoxFunction {
 
 impl fmt::Display for LoxFunction {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "<fun {}>", self.declaration.name.lexeme)
+        match self.declaration.name.lexeme.as_str() {
+            "" => write!(f, "<fn>"),
+            name => write!(f, "<fn {}>", name),
+        }
     }
 }
 
+/// A native returns its error message, which is reported at the call site.
+pub type NativeFn = dyn Fn(Vec<Value>) -> Result<Value, String>;
+
+/// A native that runs Lox code, such as a callable it's given, and so needs
+/// the interpreter. `paren` is where the native was called.
+pub type ReentrantFn = dyn Fn(&mut Interpreter, &Token, Vec<Value>) -> InterpreterResult;
+
+#[derive(Clone)]
+pub enum NativeBody {
+    Plain(Rc<NativeFn>),
+    Reentrant(Rc<ReentrantFn>),
+}
+
 #[derive(Clone)]
 pub struct NativeFunction {
     pub name: String,
     pub arity: usize,
-    pub fun: Rc<dyn Fn(Vec<Value>) -> Value>,
+    pub fun: NativeBody,
 }
 
 impl NativeFunction {
-    pub fn new(name: String, arity: usize, fun: Rc<dyn Fn(Vec<Value>) -> Value>) -> NativeFunction {
-        NativeFunction { name, arity, fun }
+    pub fn new(name: String, arity: usize, fun: Rc<NativeFn>) -> NativeFunction {
+        NativeFunction {
+            name,
+            arity,
+            fun: NativeBody::Plain(fun),
+        }
+    }
+
+    pub fn reentrant(name: String, arity: usize, fun: Rc<ReentrantFn>) -> NativeFunction {
+        NativeFunction {
+            name,
+            arity,
+            fun: NativeBody::Reentrant(fun),
+        }
     }
 }
 
@@ -82,9 +215,18 @@ impl Callable for NativeFunction {
         self.arity
     }
 
-    fn call(&self, _int: &mut Interpreter, args: Vec<Value>) -> InterpreterResult {
-        let value = (self.fun)(args);
-        Ok(Some(value))
+    fn call(&self, int: &mut Interpreter, paren: &Token, args: Vec<Value>) -> InterpreterResult {
+        match &self.fun {
+            NativeBody::Plain(fun) => match fun(args) {
+                Ok(value) => Ok(Some(value)),
+                Err(msg) => runtime_error_result(paren, &msg),
+            },
+            NativeBody::Reentrant(fun) => fun(int, paren, args),
+        }
+    }
+
+    fn declared_at(&self) -> String {
+        format!("native function '{}'", self.name)
     }
 }
 
@@ -100,7 +242,7 @@ impl fmt::Debug for NativeFunction {
 
 impl fmt::Display for NativeFunction {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "<native fun {}>", self.name)
+        write!(f, "<native fn>")
     }
 }
 
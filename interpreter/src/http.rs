@@ -0,0 +1,191 @@
+//! A minimal HTTP/1.0 client for the `http_get` native. It only speaks plain
+//! `http://`, which keeps the interpreter free of TLS dependencies.
+
+use std::{
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+#[derive(Debug, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    /// Header names are lowercased. Repeated headers keep the last value.
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+/// Fetches `url`, waiting at most `timeout` to connect and for each read
+/// or write, or as long as the system does if there's no timeout.
+pub fn get(url: &str, timeout: Option<Duration>) -> Result<Response, String> {
+    let (host, port, path) = parse_url(url)?;
+    let address = (host.as_str(), port)
+        .to_socket_addrs()
+        .map_err(|e| format!("Cannot resolve '{}': {}.", host, e))?
+        .next()
+        .ok_or_else(|| format!("Cannot resolve '{}'.", host))?;
+
+    let connected = match timeout {
+        Some(timeout) => TcpStream::connect_timeout(&address, timeout),
+        None => TcpStream::connect(address),
+    };
+    let mut stream = connected.map_err(|e| format!("Cannot connect to '{}': {}.", host, e))?;
+    stream
+        .set_read_timeout(timeout)
+        .and_then(|_| stream.set_write_timeout(timeout))
+        .map_err(|e| e.to_string())?;
+
+    let request = format!(
+        "GET {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: rlox\r\nConnection: close\r\n\r\n",
+        path, host
+    );
+    let mut raw = vec![];
+    stream
+        .write_all(request.as_bytes())
+        .and_then(|_| stream.read_to_end(&mut raw))
+        .map_err(|e| format!("Request to '{}' failed: {}.", url, e))?;
+
+    parse_response(&raw)
+}
+
+/// Splits `http://host[:port][/path]` into its parts.
+fn parse_url(url: &str) -> Result<(String, u16, String), String> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| format!("Only http:// URLs are supported, got '{}'.", url))?;
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rfind(':') {
+        Some(i) => {
+            let port = authority[i + 1..]
+                .parse()
+                .map_err(|_| format!("Invalid port in '{}'.", url))?;
+            (&authority[..i], port)
+        }
+        None => (authority, 80),
+    };
+    if host.is_empty() {
+        return Err(format!("Missing host in '{}'.", url));
+    }
+    Ok((host.to_string(), port, path.to_string()))
+}
+
+fn parse_response(raw: &[u8]) -> Result<Response, String> {
+    let split = raw
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| "Malformed HTTP response.".to_string())?;
+    let head = String::from_utf8_lossy(&raw[..split]);
+    let body = String::from_utf8_lossy(&raw[split + 4..]).into_owned();
+
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| "Malformed HTTP status line.".to_string())?;
+    let headers = lines
+        .filter_map(|line| {
+            let colon = line.find(':')?;
+            let name = line[..colon].trim().to_lowercase();
+            Some((name, line[colon + 1..].trim().to_string()))
+        })
+        .collect();
+
+    Ok(Response {
+        status,
+        headers,
+        body,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{net::TcpListener, thread};
+
+    #[test]
+    fn urls() {
+        assert_eq!(
+            parse_url("http://example.com").unwrap(),
+            ("example.com".to_string(), 80, "/".to_string())
+        );
+        assert_eq!(
+            parse_url("http://localhost:8080/a?b=c").unwrap(),
+            ("localhost".to_string(), 8080, "/a?b=c".to_string())
+        );
+        assert!(parse_url("https://example.com").is_err());
+        assert!(parse_url("http://:80/").is_err());
+    }
+
+    #[test]
+    fn get_from_local_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let n = stream.read(&mut request).unwrap();
+            let request = String::from_utf8_lossy(&request[..n]).into_owned();
+            stream
+                .write_all(b"HTTP/1.0 404 Not Found\r\nContent-Type: text/plain\r\n\r\nnope")
+                .unwrap();
+            request
+        });
+
+        let response = get(&format!("http://127.0.0.1:{}/missing", port), None).unwrap();
+        assert_eq!(
+            response,
+            Response {
+                status: 404,
+                headers: vec![("content-type".to_string(), "text/plain".to_string())],
+                body: "nope".to_string(),
+            }
+        );
+        assert!(server
+            .join()
+            .unwrap()
+            .starts_with("GET /missing HTTP/1.0\r\n"));
+    }
+
+    #[test]
+    fn connection_failure_is_an_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+        assert!(get(&format!("http://127.0.0.1:{}/", port), None).is_err());
+    }
+
+    #[test]
+    fn request_ends_with_the_run_time_limit() {
+        use crate::{
+            config::{Capabilities, ExecutionLimits, InterpreterConfig},
+            interpreter::Interpreter,
+        };
+
+        // The connection is queued but never answered.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let config = InterpreterConfig {
+            capabilities: Capabilities {
+                net: true,
+                ..Capabilities::default()
+            },
+            limits: ExecutionLimits {
+                timeout_ms: Some(100),
+                ..ExecutionLimits::default()
+            },
+            ..InterpreterConfig::default()
+        };
+        let source = format!("http_get(\"http://127.0.0.1:{}/\");", port);
+        let program = parser::parse(source).unwrap();
+        let errors = Interpreter::with_config(config).run(program).unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 1] Error: Execution time limit of 100ms exceeded."
+        );
+        drop(listener);
+    }
+}
@@ -0,0 +1,81 @@
+use crate::{
+    class::LoxClass,
+    stats::{Kind, Tracked},
+    value::Value,
+};
+use snowflake::ProcessUniqueId;
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::Rc,
+};
+
+/// An instance of a class. Clones share the same fields, so an instance is
+/// passed around by reference like in jlox.
+#[derive(Clone, Debug)]
+pub struct LoxInstance {
+    pub class: LoxClass,
+    pub id: ProcessUniqueId,
+    fields: Rc<RefCell<HashMap<String, Value>>>,
+    frozen: Rc<Cell<bool>>,
+    _live: Rc<Tracked>,
+}
+
+impl LoxInstance {
+    pub fn new(class: LoxClass) -> LoxInstance {
+        LoxInstance {
+            class,
+            id: ProcessUniqueId::new(),
+            fields: Rc::new(RefCell::new(HashMap::new())),
+            frozen: Rc::new(Cell::new(false)),
+            _live: Rc::new(Tracked::new(Kind::Instance)),
+        }
+    }
+
+    /// Makes the instance's fields read-only for good. Clones share the flag.
+    pub fn freeze(&self) {
+        self.frozen.set(true);
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen.get()
+    }
+
+    /// Looks up a field, then a method bound to this instance.
+    pub fn get(&self, name: &str) -> Option<Value> {
+        if let Some(value) = self.fields.borrow().get(name) {
+            return Some(value.clone());
+        }
+        self.class
+            .find_method(name)
+            .map(|method| Value::Function(method.bind(self.clone())))
+    }
+
+    pub fn set(&self, name: String, value: Value) {
+        self.fields.borrow_mut().insert(name, value);
+    }
+
+    /// Removes a field, returning its value if it was set. Methods are
+    /// unaffected.
+    pub fn remove(&self, name: &str) -> Option<Value> {
+        self.fields.borrow_mut().remove(name)
+    }
+
+    /// The instance's fields, sorted by name.
+    pub fn fields(&self) -> Vec<(String, Value)> {
+        let mut fields: Vec<(String, Value)> = self
+            .fields
+            .borrow()
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        fields.sort_by(|a, b| a.0.cmp(&b.0));
+        fields
+    }
+}
+
+impl PartialEq for LoxInstance {
+    fn eq(&self, other: &LoxInstance) -> bool {
+        self.id == other.id
+    }
+}
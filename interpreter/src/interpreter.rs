@@ -1,61 +1,248 @@
 use crate::{
+    array::{check_index, LoxArray},
     callable::call,
     class::LoxClass,
+    config::InterpreterConfig,
     environment::Environment,
-    error::{runtime_error_result, RuntimeError},
+    error::{internal_error, internal_error_message, runtime_error_result, RuntimeError, Warning},
+    format,
     function::LoxFunction,
-    native::define_native_functions,
+    instance::LoxInstance,
+    native::{define_native_functions, is_native},
+    output::{OutputCallback, OutputEvent},
+    replay::{SharedTape, Tape},
     resolver::Resolver,
+    stats::{created_counts, NodeCounts, RunReport},
+    suggest::{did_you_mean, similar_names},
+    traits::LoxTrait,
     value::Value,
 };
 use ast::{
-    token::{Literal, Token, TokenType},
+    token::{Literal, Position, Token, TokenType},
     visitor::Visitor,
-    Expr, ScopeId, Stmt,
+    BinaryExpr, BinaryOp, ClassStmt, DestructureStmt, Expr, LogicalOp, ScopeId, Stmt, UnaryOp,
+};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    error::Error,
+    io::{self, Write},
+    mem,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
-use std::{error::Error, rc::Rc};
 
 pub type InterpreterResult = Result<Option<Value>, RuntimeError>;
 
+/// Arithmetic whose result is past 2^53, where not every integer can be
+/// represented, or infinite. Only checked when the config's
+/// `checked_arithmetic` is set.
+pub const IMPRECISE_NUMBER: &str = "imprecise-number";
+
+// The largest integer whose neighbours are exact too, 2^53 - 1.
+const MAX_EXACT_INTEGER: f64 = 9_007_199_254_740_991.0;
+
 pub struct Interpreter {
     pub environment: Option<Rc<Environment>>,
+    pub config: InterpreterConfig,
     resolver: Resolver,
+    // The calls in progress and where they were made, for expression
+    // traces and the backtrace of an interrupted run.
+    calls: Vec<(Value, Position)>,
+    pub output: Box<dyn Write>,
+    /// Takes prints, warnings, errors and traces instead of `output`,
+    /// `warnings` and stderr, when the host gives one.
+    pub on_output: Option<OutputCallback>,
+    pub warnings: Vec<Warning>,
+    /// Where natives that read from outside get their inputs.
+    pub tape: SharedTape,
+    /// What's run so far, when counting.
+    pub node_counts: Option<NodeCounts>,
+    /// What the runs so far did, when reporting.
+    pub report: Option<RunReport>,
+    // Where the last expression evaluated is, for errors raised by limits.
+    position: Position,
+    /// Set by the host to stop the run at the next step.
+    pub cancelled: Arc<AtomicBool>,
+    steps: u64,
+    deadline: Option<Instant>,
+    /// The innermost environments when the last run's error was raised,
+    /// when the config's `dump_env_on_error` is set.
+    pub error_environments: Option<Vec<Vec<(String, String)>>>,
+    /// Whether `print` colors each value by its type, for a terminal.
+    pub color: bool,
+    // Set just before evaluating the `object.name` callee of a call, so a
+    // missing method can be forwarded with the call's arguments.
+    calling: bool,
+    // The method the callee just evaluated didn't find, when the callee is
+    // the instance's `method_missing` instead.
+    missing_method: Option<String>,
+    // The property read, call or index a `?.` cut short, until the next link
+    // of its chain sees it and is skipped too.
+    short_circuited: Option<*const Expr>,
 }
 
 impl Interpreter {
     pub fn new() -> Interpreter {
+        Interpreter::with_config(InterpreterConfig::default())
+    }
+
+    pub fn with_config(config: InterpreterConfig) -> Interpreter {
         let mut globals = Environment::new(None);
-        define_native_functions(&mut globals);
+        let tape = Rc::new(RefCell::new(Tape::Live));
+        define_native_functions(
+            &mut globals,
+            &config.capabilities,
+            &tape,
+            config.flat_natives,
+        );
+        let mut resolver = Resolver::new();
+        resolver.trace = config.debug_resolver;
+        resolver.strict = config.strict;
+        resolver.lint = config.lint.clone();
+        resolver.version = config.version;
+        resolver.natives = globals
+            .bindings()
+            .into_iter()
+            .filter(|(_, value)| matches!(value, Value::NativeFunction(_)))
+            .map(|(name, _)| name)
+            .collect();
+        let node_counts = if config.count_nodes {
+            Some(NodeCounts::default())
+        } else {
+            None
+        };
+        let report = if config.report {
+            Some(RunReport::default())
+        } else {
+            None
+        };
         Interpreter {
             environment: Some(Rc::new(globals)),
-            resolver: Resolver::new(),
+            config,
+            resolver,
+            calls: vec![],
+            output: Box::new(io::stdout()),
+            on_output: None,
+            warnings: vec![],
+            tape,
+            node_counts,
+            report,
+            position: Position::default(),
+            cancelled: Arc::new(AtomicBool::new(false)),
+            steps: 0,
+            deadline: None,
+            error_environments: None,
+            color: false,
+            calling: false,
+            missing_method: None,
+            short_circuited: None,
         }
     }
 
-    pub fn run(&mut self, stmts: Vec<Stmt>) -> Result<(), Vec<Box<Error>>> {
-        self.resolver.resolve(&stmts).map_err(|errs| {
-            let mut boxed: Vec<Box<Error>> = vec![];
+    /// Runs a program, returning the value of a top-level `return` or, if
+    /// there is none, of a trailing expression statement.
+    pub fn run(&mut self, stmts: Vec<Stmt>) -> Result<Option<Value>, Vec<Box<dyn Error>>> {
+        self.resolve(&stmts)?;
+        self.run_resolved(stmts)
+    }
+
+    /// Resolves a program before it's run, reporting its warnings.
+    pub fn resolve(&mut self, stmts: &[Stmt]) -> Result<(), Vec<Box<dyn Error>>> {
+        let resolved = self.resolver.resolve(stmts);
+        for warning in mem::take(&mut self.resolver.warnings) {
+            self.warn(warning);
+        }
+        resolved.map_err(|errs| {
+            let mut boxed: Vec<Box<dyn Error>> = vec![];
             for e in errs {
                 boxed.push(Box::new(e));
             }
+            self.report_errors(&boxed);
             boxed
-        })?;
+        })
+    }
+
+    /// Runs a program that `resolve` has accepted.
+    pub fn run_resolved(&mut self, stmts: Vec<Stmt>) -> Result<Option<Value>, Vec<Box<dyn Error>>> {
+        log::debug!("running {} statements", stmts.len());
+        self.steps = 0;
+        self.calls.clear();
+        self.error_environments = None;
+        self.cancelled.store(false, Ordering::Relaxed);
+        self.deadline = self
+            .config
+            .limits
+            .timeout_ms
+            .map(|ms| Instant::now() + Duration::from_millis(ms));
 
-        let mut errors: Vec<Box<Error>> = vec![];
-        for stmt in stmts.iter() {
-            match self.visit_stmt(stmt) {
-                Ok(_) => (),
-                Err(err) => errors.push(Box::new(err)),
+        let started = match (&mut self.report, &self.environment) {
+            (Some(report), Some(environment)) => {
+                let depth = environment.depth();
+                report.peak_environment_depth = report.peak_environment_depth.max(depth);
+                Some((Instant::now(), created_counts()))
+            }
+            _ => None,
+        };
+        let mut errors: Vec<Box<dyn Error>> = vec![];
+        let mut result = None;
+        for (i, stmt) in stmts.iter().enumerate() {
+            let outcome = match stmt {
+                Stmt::Expr(expr_stmt) if i == stmts.len() - 1 => {
+                    self.evaluate(&expr_stmt.expression)
+                }
+                _ => self.visit_stmt(stmt),
+            };
+            match outcome {
+                Ok(None) => (),
+                Ok(Some(value)) => {
+                    result = Some(value);
+                    if !matches!(stmt, Stmt::Expr(_)) {
+                        break;
+                    }
+                }
+                Err(err) => {
+                    self.dump_environments();
+                    errors.push(Box::new(err));
+                }
             }
         }
 
-        if errors.len() == 0 {
-            Ok(())
+        if let (Some(report), Some((start, created))) = (&mut self.report, started) {
+            report.add_run(start.elapsed(), &created);
+        }
+
+        if errors.is_empty() {
+            Ok(result)
         } else {
+            self.report_errors(&errors);
             Err(errors)
         }
     }
 
+    /// Hands a warning to the output callback, or keeps it for the host to
+    /// take.
+    pub(crate) fn warn(&mut self, warning: Warning) {
+        match &mut self.on_output {
+            Some(on_output) => {
+                let _ = on_output(OutputEvent::Warning(&warning));
+            }
+            None => self.warnings.push(warning),
+        }
+    }
+
+    fn report_errors(&mut self, errors: &[Box<dyn Error>]) {
+        if let Some(on_output) = &mut self.on_output {
+            for error in errors {
+                let _ = on_output(OutputEvent::Error(error.as_ref()));
+            }
+        }
+    }
+
     fn look_up_var(&mut self, name: &String, scope_id: &ScopeId) -> Option<Value> {
         if let Some(environment) = self.environment.take() {
             let value;
@@ -77,25 +264,28 @@ impl Interpreter {
         value: Value,
         scope_id: Option<&ScopeId>,
     ) -> Result<(), String> {
-        let environment = self.environment.take().unwrap();
-        let result;
-        if let Some(scope_id) = scope_id {
-            if let Some(distance) = self.resolver.locals.get(scope_id) {
-                result = environment.assign_at(name, value, *distance);
-            } else {
-                result = environment.assign(name, value);
-            }
-        } else {
-            result = environment.assign(name, value);
+        let environment = match &self.environment {
+            Some(environment) => environment,
+            None => return Err(internal_error_message("no environment to assign in")),
+        };
+        match scope_id.and_then(|scope_id| self.resolver.locals.get(scope_id)) {
+            Some(distance) => environment.assign_at(name, value, *distance),
+            None => environment.assign(name, value),
         }
-        self.environment = Some(environment);
-        result
     }
 
-    pub fn define_var(&mut self, name: String, value: Value) {
-        let environment = self.environment.take().unwrap();
-        environment.define(name, value);
-        self.environment = Some(environment);
+    pub fn define_var(&mut self, name: String, value: Value) -> Result<(), RuntimeError> {
+        match &self.environment {
+            Some(environment) => {
+                environment.define(name, value);
+                Ok(())
+            }
+            None => Err(internal_error!(
+                self.position,
+                "no environment to define '{}' in",
+                name
+            )),
+        }
     }
 
     pub fn execute_block(
@@ -114,6 +304,7 @@ impl Interpreter {
                     break;
                 }
                 Err(err) => {
+                    self.dump_environments();
                     result = Err(err);
                     break;
                 }
@@ -123,7 +314,513 @@ impl Interpreter {
         result
     }
 
+    /// Adds the methods of the traits a class mixes in to the ones it
+    /// declares. The class's own methods win; two traits providing the same
+    /// method is an error, since neither is the obvious choice.
+    fn mix_in_traits(
+        &mut self,
+        class_stmt: &ClassStmt,
+        methods: &mut HashMap<String, LoxFunction>,
+    ) -> Result<Vec<LoxTrait>, RuntimeError> {
+        let mut traits = vec![];
+        let mut providers: HashMap<String, String> = HashMap::new();
+        for expr in &class_stmt.traits {
+            let t = match self.value_of(expr)? {
+                Value::Trait(t) => t,
+                value => {
+                    return Err(RuntimeError::new(
+                        class_stmt.name.position(),
+                        format!("Can only mix in traits, got {}.", value),
+                    ))
+                }
+            };
+            for (name, method) in t.methods() {
+                if class_stmt.methods.iter().any(|m| &m.name.lexeme == name) {
+                    continue;
+                }
+                if let Some(other) = providers.insert(name.clone(), t.name.clone()) {
+                    return Err(RuntimeError::new(
+                        class_stmt.name.position(),
+                        format!(
+                            "Method '{}' is provided by both '{}' and '{}'; declare it in the class to choose.",
+                            name, other, t.name
+                        ),
+                    ));
+                }
+                methods.insert(name.clone(), method.clone());
+            }
+            traits.push(t);
+        }
+        Ok(traits)
+    }
+
+    /// Evaluates the callee of a call. For `object.name(...)` where the
+    /// instance has no `name` but does have `method_missing`, that's the
+    /// callee instead, returned with the missing name so the call can pass
+    /// it the name and an array of the arguments.
+    fn callee(&mut self, expr: &Expr) -> Result<Option<(Value, Option<String>)>, RuntimeError> {
+        self.calling = matches!(expr, Expr::Get(_));
+        let callee = self.chain_object(expr)?;
+        Ok(callee.map(|callee| (callee, self.missing_method.take())))
+    }
+
+    /// Evaluates what a property read, call or index applies to. `None` if
+    /// a `?.` earlier in the chain found nil, so this link is skipped too.
+    fn chain_object(&mut self, expr: &Expr) -> Result<Option<Value>, RuntimeError> {
+        let value = self.value_of(expr)?;
+        match self.short_circuited.take() {
+            Some(cut) if std::ptr::eq(cut, expr) => Ok(None),
+            _ => Ok(Some(value)),
+        }
+    }
+
+    /// Ends the chain link `expr` with nil, and the links after it.
+    fn cut_short(&mut self, expr: &Expr) -> InterpreterResult {
+        self.short_circuited = Some(expr);
+        Ok(Some(Value::Nil))
+    }
+
+    /// A property `instance` doesn't have. With `method_missing` on and a
+    /// `method_missing(name, args)` method on the instance, it's what that
+    /// method returns for the name and no arguments, or, if the property is
+    /// being called, the method itself, for the call to pass its arguments.
+    #[cold]
+    #[inline(never)]
+    fn missing_property(
+        &mut self,
+        name: &Token,
+        instance: &LoxInstance,
+        calling: bool,
+    ) -> InterpreterResult {
+        let handler = match instance.get("method_missing") {
+            Some(handler) if self.config.method_missing => handler,
+            _ => return Err(undefined_property(name)),
+        };
+        if calling {
+            self.missing_method = Some(name.lexeme.clone());
+            return Ok(Some(handler));
+        }
+        let args = vec![
+            Value::from(name.lexeme.as_str()),
+            Value::Array(LoxArray::new(vec![])),
+        ];
+        self.call_value(name, handler, args)
+    }
+
+    /// The values a destructuring declaration gives its names, in order: the
+    /// elements of an array with exactly as many, or the properties of the
+    /// same names of an instance.
+    #[inline(never)]
+    fn destructured(
+        &mut self,
+        destructure_stmt: &DestructureStmt,
+        value: Value,
+    ) -> Result<Vec<Value>, RuntimeError> {
+        let open = &destructure_stmt.open;
+        let names = &destructure_stmt.names;
+        if destructure_stmt.is_array() {
+            return match value {
+                Value::Array(array) if array.len() == names.len() => Ok(array.elements()),
+                Value::Array(array) => Err(RuntimeError::new(
+                    open.position(),
+                    format!(
+                        "Expected {} values to destructure, got {}.",
+                        names.len(),
+                        array.len()
+                    ),
+                )),
+                value => Err(RuntimeError::new(
+                    open.position(),
+                    format!("Can only destructure arrays with '[...]', got {}.", value),
+                )),
+            };
+        }
+        let instance = match value {
+            Value::Instance(instance) => instance,
+            _ => {
+                return Err(RuntimeError::new(
+                    open.position(),
+                    "Only instances have properties.".to_string(),
+                ))
+            }
+        };
+        names
+            .iter()
+            .map(|name| match instance.get(&name.lexeme) {
+                Some(value) => Ok(value),
+                None => Ok(self
+                    .missing_property(name, &instance, false)?
+                    .unwrap_or(Value::Nil)),
+            })
+            .collect()
+    }
+
+    /// Evaluates an expression in `environment` rather than the current one.
+    pub(crate) fn evaluate_in(
+        &mut self,
+        expr: &Expr,
+        environment: Rc<Environment>,
+    ) -> InterpreterResult {
+        let previous = self.environment.take();
+        self.push_scope(environment);
+        let result = self.evaluate(expr);
+        self.pop_scope(previous);
+        result
+    }
+
+    /// Runs the statements of a block expression and evaluates its trailing
+    /// expression. The resolver rejects `return` in a block expression, so
+    /// the statements never produce a value.
+    fn block_value(&mut self, statements: &[Stmt], value: &Option<Box<Expr>>) -> InterpreterResult {
+        for statement in statements {
+            self.visit_stmt(statement)?;
+        }
+        match value {
+            Some(value) => self.evaluate(value),
+            None => Ok(Some(Value::Nil)),
+        }
+    }
+
+    /// "Did you mean ...?" hint listing visible names close to `name`.
+    fn suggest_names(&self, name: &Token) -> String {
+        let candidates = match self.environment {
+            Some(ref environment) => environment.names(),
+            None => vec![],
+        };
+        did_you_mean(&similar_names(&name.lexeme, &candidates))
+    }
+
+    /// Evaluates an expression for its value. Every expression has one, so
+    /// not getting one is a bug in the interpreter.
+    // Kept out of line: inlined at each of its uses in `visit_expr`, it
+    // makes that frame, and so every level of recursion, much bigger.
+    #[inline(never)]
+    fn value_of(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+        match self.evaluate(expr)? {
+            Some(value) => Ok(value),
+            None => Err(self.no_value(expr)),
+        }
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn no_value(&self, expr: &Expr) -> RuntimeError {
+        internal_error!(self.position, "{} gave no value", expr.kind())
+    }
+
+    /// Evaluates an expression, printing it and its value to stderr when
+    /// expression tracing is on.
+    fn evaluate(&mut self, expr: &Expr) -> InterpreterResult {
+        if let Some(position) = expr.position() {
+            self.position = position;
+        }
+        let result = self.visit_expr(expr);
+        match result {
+            Ok(Some(ref value)) if self.config.trace_exprs => self.trace(expr, value),
+            Err(_) => self.dump_environments(),
+            _ => (),
+        }
+        result
+    }
+
+    /// Keeps the names and values in the innermost environments, as `repr`
+    /// shows them, the first time an error is seen on its way out of a run,
+    /// while the scope it was raised in is still current. Natives are left
+    /// out of the globals, and scopes that define nothing else are skipped.
+    #[cold]
+    #[inline(never)]
+    fn dump_environments(&mut self) {
+        let depth = match self.config.dump_env_on_error {
+            Some(depth) if self.error_environments.is_none() => depth,
+            _ => return,
+        };
+        let mut environments = vec![];
+        let mut environment = self.environment.clone();
+        while let Some(scope) = environment {
+            if environments.len() == depth {
+                break;
+            }
+            let bindings: Vec<(String, String)> = scope
+                .bindings()
+                .into_iter()
+                .filter(|(_, value)| !is_native(value))
+                .map(|(name, value)| (name, format::repr(&value)))
+                .collect();
+            if !bindings.is_empty() {
+                environments.push(bindings);
+            }
+            environment = scope.enclosing.clone();
+        }
+        self.error_environments = Some(environments);
+    }
+
+    /// Counts a loop iteration or call against the step and time limits,
+    /// and stops if the host has cancelled the run.
+    fn step(&mut self) -> Result<(), RuntimeError> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            self.cancelled.store(false, Ordering::Relaxed);
+            return Err(RuntimeError::interrupted(self.position, self.backtrace()));
+        }
+        self.steps += 1;
+        if let Some(max_steps) = self.config.limits.max_steps {
+            if self.steps > max_steps {
+                return Err(RuntimeError::new(
+                    self.position,
+                    format!("Execution limit of {} steps exceeded.", max_steps),
+                ));
+            }
+        }
+        // Reading the clock on every step would dominate tight loops.
+        if let Some(deadline) = self.deadline {
+            if self.steps.is_multiple_of(1024) && Instant::now() > deadline {
+                return Err(self.time_limit_exceeded());
+            }
+        }
+        Ok(())
+    }
+
+    /// How long the run may still take under its time limit, if it has
+    /// one, or the limit's error once the time is up.
+    #[cfg(feature = "net")]
+    pub(crate) fn time_left(&self) -> Result<Option<Duration>, RuntimeError> {
+        match self.deadline {
+            None => Ok(None),
+            Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                Some(left) if !left.is_zero() => Ok(Some(left)),
+                _ => Err(self.time_limit_exceeded()),
+            },
+        }
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn time_limit_exceeded(&self) -> RuntimeError {
+        RuntimeError::new(
+            self.position,
+            format!(
+                "Execution time limit of {}ms exceeded.",
+                self.config.limits.timeout_ms.unwrap_or_default()
+            ),
+        )
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn trace(&mut self, expr: &Expr, value: &Value) {
+        let line = trace_line(self.calls.len(), expr, value);
+        match &mut self.on_output {
+            Some(on_output) => {
+                let _ = on_output(OutputEvent::Trace(&line));
+            }
+            None => eprintln!("{}", line),
+        }
+    }
+
+    fn print_values(&mut self, expressions: &[Expr]) -> InterpreterResult {
+        let mut printed = vec![];
+        for expr in expressions {
+            let value = self.value_of(expr)?;
+            let shown = self.stringify(&value)?;
+            printed.push(if self.color {
+                format::colored(&value, &shown)
+            } else {
+                shown
+            });
+        }
+        let printed = printed.join(" ");
+        match &mut self.on_output {
+            Some(on_output) => on_output(OutputEvent::Print(&printed)),
+            None => writeln!(self.output, "{}", printed),
+        }
+        .map_err(|e| RuntimeError::new(self.position, format!("Cannot print: {}.", e)))?;
+        Ok(None)
+    }
+
+    /// `value` as `print` shows it, with instances whose class defines
+    /// `to_string()` shown as the string it returns.
+    pub(crate) fn stringify(&mut self, value: &Value) -> Result<String, RuntimeError> {
+        let mut error = None;
+        let printed = format::display_with(value, &mut |instance| {
+            if error.is_some() {
+                return None;
+            }
+            match self.call_to_string(instance) {
+                Ok(shown) => shown,
+                Err(e) => {
+                    error = Some(e);
+                    None
+                }
+            }
+        });
+        match error {
+            Some(e) => Err(e),
+            None => Ok(printed),
+        }
+    }
+
+    /// What the instance's `to_string()` returns, if its class has one.
+    fn call_to_string(&mut self, instance: &LoxInstance) -> Result<Option<String>, RuntimeError> {
+        let method = match instance.class.find_method("to_string") {
+            Some(method) => method.bind(instance.clone()),
+            None => return Ok(None),
+        };
+        let position = self.position;
+        let paren = Token::new(
+            TokenType::Identifier,
+            "to_string".to_string(),
+            None,
+            position.line,
+        )
+        .with_column(self.position.column)
+        .with_file(self.position.file);
+        match self.call_value(&paren, Value::Function(method), vec![])? {
+            Some(Value::String(s)) => Ok(Some(s.into_string())),
+            value => Err(RuntimeError::new(
+                position,
+                format!(
+                    "to_string() must return a string, got {}.",
+                    value.unwrap_or(Value::Nil)
+                ),
+            )),
+        }
+    }
+
+    /// Evaluates a binary operator. Adding a string to an instance whose
+    /// class defines `to_string()`, or the other way round, concatenates
+    /// the string it returns.
+    fn eval_binary(
+        &mut self,
+        bin_expr: &BinaryExpr,
+        left: Value,
+        right: Value,
+    ) -> InterpreterResult {
+        let concatenates = |value: &Value| match value {
+            Value::Instance(instance) => {
+                bin_expr.operator == BinaryOp::Add
+                    && instance.class.find_method("to_string").is_some()
+            }
+            _ => false,
+        };
+        let (left, right) = match (left, right) {
+            (Value::String(l), right) if concatenates(&right) => {
+                let r = self.stringify(&right)?;
+                (Value::String(l), Value::from(r))
+            }
+            (left, Value::String(r)) if concatenates(&left) => {
+                let l = self.stringify(&left)?;
+                (Value::from(l), Value::String(r))
+            }
+            operands => operands,
+        };
+        let operands = match (&left, &right) {
+            (Value::Number(l), Value::Number(r)) if self.config.checked_arithmetic => {
+                Some((*l, *r))
+            }
+            _ => None,
+        };
+        let max_string_length = self.config.language_limits.max_string_length;
+        let result = eval_binary_expr(bin_expr, left, right, max_string_length)?;
+        if let (Some(operands), Some(Value::Number(n))) = (operands, &result) {
+            self.check_precision(bin_expr, operands, *n)?;
+        }
+        Ok(result)
+    }
+
+    /// Warns when arithmetic on finite operands gives infinity, or on exact
+    /// integers gives a number past 2^53, as a counting loop that runs too
+    /// long would. Operands that were already past it aren't warned about
+    /// again. It's an error in strict mode or when the lint is denied.
+    #[cold]
+    #[inline(never)]
+    fn check_precision(
+        &mut self,
+        bin_expr: &BinaryExpr,
+        (l, r): (f64, f64),
+        result: f64,
+    ) -> Result<(), RuntimeError> {
+        let exact = |n: f64| n.abs() <= MAX_EXACT_INTEGER;
+        let msg = if result.is_infinite() && l.is_finite() && r.is_finite() {
+            format!("Result of '{}' is infinite.", bin_expr.operator.symbol())
+        } else if exact(l) && exact(r) && !exact(result) && result.is_finite() {
+            format!(
+                "Result of '{}' is past 2^53, where integers are no longer exact.",
+                bin_expr.operator.symbol()
+            )
+        } else {
+            return Ok(());
+        };
+        let lint = IMPRECISE_NUMBER.to_string();
+        if self.config.lint.allow.contains(&lint) {
+            Ok(())
+        } else if self.config.strict || self.config.lint.deny.contains(&lint) {
+            Err(RuntimeError::new(bin_expr.span.start, msg))
+        } else {
+            self.warn(Warning::new(IMPRECISE_NUMBER, bin_expr.span.start, msg));
+            Ok(())
+        }
+    }
+
+    /// Calls `callee` with `args`, as a call expression ending at `paren`
+    /// does once it has its arguments, or as a native calls back into the
+    /// program.
+    pub fn call_value(
+        &mut self,
+        paren: &Token,
+        callee: Value,
+        args: Vec<Value>,
+    ) -> InterpreterResult {
+        self.step()?;
+        if let Some(max_call_depth) = self.config.limits.max_call_depth {
+            if self.calls.len() >= max_call_depth {
+                return runtime_error_result(paren, "Stack overflow.");
+            }
+        }
+        self.calls.push((callee.clone(), paren.position()));
+        if let Some(report) = &mut self.report {
+            report.calls += 1;
+        }
+        log::trace!(
+            "enter call frame {} at line {}",
+            self.calls.len(),
+            paren.line
+        );
+        let result = match callee {
+            Value::Function(fun) => call(paren, &fun, self, args),
+            Value::NativeFunction(fun) => call(paren, &fun, self, args),
+            Value::Class(class) => call(paren, &class, self, args),
+            _ => runtime_error_result(paren, "Can only call functions and classes."),
+        };
+        log::trace!("exit call frame {}", self.calls.len());
+        self.calls.pop();
+        result
+    }
+
+    /// Where each call in progress is, innermost first and ending with the
+    /// top-level code. Only the innermost few are kept.
+    fn backtrace(&self) -> Vec<String> {
+        const SHOWN: usize = 8;
+        let mut frames = vec![];
+        let mut line = self.position.line;
+        for (callee, call_site) in self.calls.iter().rev() {
+            frames.push(format!("in {}() at line {}", callee_name(callee), line));
+            line = call_site.line;
+        }
+        frames.push(format!("in the script at line {}", line));
+        if frames.len() > SHOWN + 1 {
+            let hidden = frames.len() - SHOWN - 1;
+            let script = frames.pop();
+            frames.truncate(SHOWN);
+            frames.push(format!("... {} more calls", hidden));
+            frames.extend(script);
+        }
+        frames
+    }
+
     fn push_scope(&mut self, environment: Rc<Environment>) {
+        if let Some(report) = &mut self.report {
+            let depth = environment.depth();
+            report.peak_environment_depth = report.peak_environment_depth.max(depth);
+        }
         self.environment = Some(environment);
     }
 
@@ -134,152 +831,335 @@ impl Interpreter {
 
 impl Visitor<InterpreterResult> for Interpreter {
     fn visit_stmt(&mut self, stmt: &Stmt) -> InterpreterResult {
+        if let Some(position) = stmt.position() {
+            self.position = position;
+        }
+        if let Some(counts) = &mut self.node_counts {
+            counts.stmt(stmt);
+        }
         match stmt {
             Stmt::Block(block_stmt) => {
                 let environment = Environment::new(self.environment.clone());
                 self.execute_block(&block_stmt.statements, Rc::new(environment))
             }
             Stmt::Class(class_stmt) => {
-                self.define_var(class_stmt.name.lexeme.clone(), Value::Nil);
-                let class = Value::Class(LoxClass::new(class_stmt.name.lexeme.clone()));
+                self.define_var(class_stmt.name.lexeme.clone(), Value::Nil)?;
+                let mut methods = HashMap::new();
+                for method in &class_stmt.methods {
+                    let fun = LoxFunction::method(method.clone(), self.environment.clone());
+                    methods.insert(method.name.lexeme.clone(), fun);
+                }
+                let traits = self.mix_in_traits(class_stmt, &mut methods)?;
+                let class = LoxClass::new(class_stmt.name.lexeme.clone(), methods)
+                    .with_fields(class_stmt.fields.clone(), self.environment.clone())
+                    .with_traits(traits);
+                let class = Value::Class(class);
                 self.assign_var(class_stmt.name.lexeme.clone(), class, None)
                     .map(|_| None)
-                    .map_err(|msg| RuntimeError::new(class_stmt.name.line, msg))
+                    .map_err(|msg| RuntimeError::new(class_stmt.name.position(), msg))
             }
             Stmt::Expr(expr_stmt) => {
-                self.visit_expr(&expr_stmt.expression)?;
+                self.evaluate(&expr_stmt.expression)?;
                 Ok(None)
             }
             Stmt::Fun(fun_stmt) => {
+                // Closes over the environment the declaration runs in, which
+                // inside a block or loop body is a new one each time through.
                 let fun = LoxFunction::new(fun_stmt.clone(), self.environment.clone());
-                self.define_var(fun_stmt.name.lexeme.clone(), Value::Function(fun));
+                self.define_var(fun_stmt.name.lexeme.clone(), Value::Function(fun))?;
                 Ok(None)
             }
             Stmt::If(if_stmt) => {
-                if let Some(condition) = self.visit_expr(&if_stmt.condition)? {
-                    if is_truthy(&condition) {
-                        Ok(self.visit_stmt(&if_stmt.then_branch)?)
-                    } else {
-                        if let Some(ref else_branch) = if_stmt.else_branch {
-                            Ok(self.visit_stmt(else_branch)?)
-                        } else {
-                            Ok(None)
-                        }
-                    }
+                let condition = self.value_of(&if_stmt.condition)?;
+                if is_truthy(&condition) {
+                    Ok(self.visit_stmt(&if_stmt.then_branch)?)
+                } else if let Some(ref else_branch) = if_stmt.else_branch {
+                    Ok(self.visit_stmt(else_branch)?)
                 } else {
-                    panic!("Expression should always return a value");
+                    Ok(None)
                 }
             }
-            Stmt::Print(print_stmt) => {
-                let expr_result = (self.visit_expr(&print_stmt.expression)?).unwrap();
-                println!("{}", expr_result.print());
-                Ok(None)
-            }
+            Stmt::Print(print_stmt) => self.print_values(&print_stmt.expressions),
             Stmt::Return(ret_stmt) => {
                 let value = if let Some(ref expr) = ret_stmt.value {
-                    self.visit_expr(expr)?
+                    self.evaluate(expr)?
                 } else {
                     Some(Value::Nil)
                 };
                 Ok(value)
             }
+            Stmt::Trait(trait_stmt) => {
+                let mut methods = HashMap::new();
+                for method in &trait_stmt.methods {
+                    let fun = LoxFunction::method(method.clone(), self.environment.clone());
+                    methods.insert(method.name.lexeme.clone(), fun);
+                }
+                let t = LoxTrait::new(trait_stmt.name.lexeme.clone(), methods);
+                self.define_var(trait_stmt.name.lexeme.clone(), Value::Trait(t))?;
+                Ok(None)
+            }
+            Stmt::Destructure(destructure_stmt) => {
+                let value = self.value_of(&destructure_stmt.initializer)?;
+                let values = self.destructured(destructure_stmt, value)?;
+                for (name, value) in destructure_stmt.names.iter().zip(values) {
+                    self.define_var(name.lexeme.clone(), value)?;
+                }
+                Ok(None)
+            }
             Stmt::Var(var_stmt) => {
                 let value = if let Some(ref initializer) = var_stmt.initializer {
-                    (self.visit_expr(initializer)?).unwrap()
+                    self.value_of(initializer)?
                 } else {
                     Value::Nil
                 };
-                self.define_var(var_stmt.name.lexeme.clone(), value);
+                self.define_var(var_stmt.name.lexeme.clone(), value)?;
                 Ok(None)
             }
             Stmt::While(while_stmt) => {
-                let mut condition = (self.visit_expr(&while_stmt.condition)?).unwrap();
+                let mut condition = self.value_of(&while_stmt.condition)?;
                 while is_truthy(&condition) {
-                    match self.visit_stmt(&while_stmt.body)? {
-                        Some(v) => return Ok(Some(v)),
-                        None => (),
+                    self.step()?;
+                    if let Some(v) = self.visit_stmt(&while_stmt.body)? {
+                        return Ok(Some(v));
                     }
-                    condition = (self.visit_expr(&while_stmt.condition)?).unwrap();
+                    condition = self.value_of(&while_stmt.condition)?;
                 }
                 Ok(None)
             }
-            // _ => unimplemented!(),
+            _ => Err(RuntimeError::new(
+                self.position,
+                format!("Unsupported syntax: {}.", stmt.kind()),
+            )),
         }
     }
 
     fn visit_expr(&mut self, expr: &Expr) -> InterpreterResult {
+        if let Some(counts) = &mut self.node_counts {
+            counts.expr(expr);
+        }
         match expr {
+            Expr::Array(array_expr) => {
+                let mut elements = vec![];
+                for element in &array_expr.elements {
+                    elements.push(self.value_of(element)?);
+                }
+                Ok(Some(Value::Array(LoxArray::new(elements))))
+            }
             Expr::Assign(assign_expr) => {
                 let name = &assign_expr.name;
-                let value = self.visit_expr(&assign_expr.value)?;
+                let value = self.value_of(&assign_expr.value)?;
                 match self.assign_var(
                     name.lexeme.clone(),
-                    value.clone().unwrap(),
+                    value.clone(),
                     Some(&assign_expr.scope_id),
                 ) {
-                    Ok(_) => Ok(value),
-                    Err(msg) => runtime_error_result(name, &msg),
+                    Ok(_) => Ok(Some(value)),
+                    Err(msg) => {
+                        runtime_error_result(name, &format!("{}{}", msg, self.suggest_names(name)))
+                    }
                 }
             }
             Expr::Binary(bin_expr) => {
-                let left = self.visit_expr(&bin_expr.left)?;
-                let right = self.visit_expr(&bin_expr.right)?;
-                let operator = &bin_expr.operator;
-                eval_binary_expr(operator, left.unwrap(), right.unwrap())
+                let left = self.value_of(&bin_expr.left)?;
+                let right = self.value_of(&bin_expr.right)?;
+                self.eval_binary(bin_expr, left, right)
+            }
+            Expr::Block(block_expr) => {
+                let previous = self.environment.take();
+                self.push_scope(Rc::new(Environment::new(previous.clone())));
+                let result = self.block_value(&block_expr.statements, &block_expr.value);
+                self.pop_scope(previous);
+                result
             }
             Expr::Call(call_expr) => {
-                let callee = self.visit_expr(&call_expr.callee)?;
+                let (callee, missing) = match self.callee(&call_expr.callee)? {
+                    Some(callee) => callee,
+                    None => return self.cut_short(expr),
+                };
                 let mut arguments = vec![];
                 for arg in &call_expr.arguments {
-                    arguments.push((self.visit_expr(arg)?).unwrap());
+                    match arg {
+                        Expr::Spread(spread_expr) => {
+                            match self.value_of(&spread_expr.expression)? {
+                                Value::Array(array) => arguments.extend(array.elements()),
+                                value => {
+                                    return runtime_error_result(
+                                        &spread_expr.ellipsis,
+                                        &format!("Can only spread arrays, got {}.", value),
+                                    )
+                                }
+                            }
+                        }
+                        _ => arguments.push(self.value_of(arg)?),
+                    }
                 }
-                match callee.unwrap() {
-                    Value::Function(fun) => call(&call_expr.paren, &fun, self, arguments),
-                    Value::NativeFunction(fun) => call(&call_expr.paren, &fun, self, arguments),
-                    _ => runtime_error_result(
-                        &call_expr.paren,
-                        "Can only call functions and classes.",
-                    ),
+                if let Some(name) = missing {
+                    if !call_expr.keyword_arguments.is_empty() {
+                        return runtime_error_result(
+                            &call_expr.paren,
+                            "Keyword arguments can't be passed to method_missing.",
+                        );
+                    }
+                    let rest = Value::Array(LoxArray::new(arguments));
+                    arguments = vec![Value::from(name), rest];
+                }
+                if !call_expr.keyword_arguments.is_empty() {
+                    let mut keywords = vec![];
+                    for (name, arg) in &call_expr.keyword_arguments {
+                        keywords.push((name.lexeme.clone(), self.value_of(arg)?));
+                    }
+                    let bound = match callee {
+                        Value::Function(ref fun) => fun.bind_keywords(arguments, keywords),
+                        Value::Class(ref class) => class.bind_keywords(arguments, keywords),
+                        Value::NativeFunction(_) => {
+                            Err("Native functions don't take keyword arguments.".to_string())
+                        }
+                        _ => Err("Can only call functions and classes.".to_string()),
+                    };
+                    arguments =
+                        bound.map_err(|msg| RuntimeError::new(call_expr.paren.position(), msg))?;
+                }
+                self.call_value(&call_expr.paren, callee, arguments)
+            }
+            Expr::Function(fun_expr) => {
+                let declaration = fun_expr.declaration.clone();
+                let fun = match fun_expr.name() {
+                    Some(name) => {
+                        let environment = Rc::new(Environment::new(self.environment.clone()));
+                        let fun = LoxFunction::new(declaration, Some(environment.clone()));
+                        environment.define(name.lexeme.clone(), Value::Function(fun.clone()));
+                        fun
+                    }
+                    None => LoxFunction::new(declaration, self.environment.clone()),
+                };
+                Ok(Some(Value::Function(fun)))
+            }
+            Expr::Get(get_expr) => {
+                let calling = mem::take(&mut self.calling);
+                let object = match self.chain_object(&get_expr.object)? {
+                    Some(object) => object,
+                    None => return self.cut_short(expr),
+                };
+                match object {
+                    Value::Instance(ref instance) => match instance.get(&get_expr.name.lexeme) {
+                        Some(value) => Ok(Some(value)),
+                        None => self.missing_property(&get_expr.name, instance, calling),
+                    },
+                    Value::Nil if get_expr.safe => self.cut_short(expr),
+                    _ => runtime_error_result(&get_expr.name, "Only instances have properties."),
+                }
+            }
+            Expr::Grouping(group_expr) => self.evaluate(&group_expr.expression),
+            Expr::If(if_expr) => {
+                let condition = self.value_of(&if_expr.condition)?;
+                if is_truthy(&condition) {
+                    self.evaluate(&if_expr.then_branch)
+                } else if let Some(ref else_branch) = if_expr.else_branch {
+                    self.evaluate(else_branch)
+                } else {
+                    Ok(Some(Value::Nil))
+                }
+            }
+            Expr::Index(index_expr) => {
+                let object = match self.chain_object(&index_expr.object)? {
+                    Some(object) => object,
+                    None => return self.cut_short(expr),
+                };
+                let index = self.value_of(&index_expr.index)?;
+                match object {
+                    Value::Array(array) => {
+                        let i = array_index(&index_expr.bracket, &index, array.len())?;
+                        Ok(array.get(i))
+                    }
+                    _ => runtime_error_result(&index_expr.bracket, "Only arrays can be indexed."),
                 }
             }
-            Expr::Grouping(group_expr) => self.visit_expr(&group_expr.expression),
             Expr::Literal(lit_expr) => match &lit_expr.value {
                 Literal::Nil => Ok(Some(Value::Nil)),
                 Literal::True => Ok(Some(Value::Boolean(true))),
                 Literal::False => Ok(Some(Value::Boolean(false))),
                 Literal::Number(n) => Ok(Some(Value::Number(*n))),
-                Literal::String(s) => Ok(Some(Value::String(s.clone()))),
+                Literal::String(s) => Ok(Some(Value::from(s.as_str()))),
             },
             Expr::Logical(logical_expr) => {
-                let left = (self.visit_expr(&logical_expr.left)?).unwrap();
-                match &logical_expr.operator.token_type {
-                    TokenType::Or => {
+                let left = self.value_of(&logical_expr.left)?;
+                match logical_expr.operator {
+                    LogicalOp::Or => {
                         if is_truthy(&left) {
                             Ok(Some(left))
                         } else {
-                            self.visit_expr(&logical_expr.right)
+                            self.evaluate(&logical_expr.right)
                         }
                     }
-                    TokenType::And => {
+                    LogicalOp::And => {
                         if !is_truthy(&left) {
                             Ok(Some(left))
                         } else {
-                            self.visit_expr(&logical_expr.right)
+                            self.evaluate(&logical_expr.right)
                         }
                     }
-                    _ => panic!("Invalid logical expression. This is an uncaught parse error."),
+                    LogicalOp::Coalesce => match left {
+                        Value::Nil => self.evaluate(&logical_expr.right),
+                        _ => Ok(Some(left)),
+                    },
+                }
+            }
+            Expr::Set(set_expr) => match self.value_of(&set_expr.object)? {
+                Value::Instance(instance) => {
+                    let value = self.value_of(&set_expr.value)?;
+                    if instance.is_frozen() {
+                        return runtime_error_result(
+                            &set_expr.name,
+                            "Cannot modify a frozen instance.",
+                        );
+                    }
+                    instance.set(set_expr.name.lexeme.clone(), value.clone());
+                    Ok(Some(value))
+                }
+                _ => runtime_error_result(&set_expr.name, "Only instances have fields."),
+            },
+            Expr::SetIndex(set_expr) => {
+                let object = self.value_of(&set_expr.object)?;
+                let index = self.value_of(&set_expr.index)?;
+                match object {
+                    Value::Array(array) => {
+                        let i = array_index(&set_expr.bracket, &index, array.len())?;
+                        let value = self.value_of(&set_expr.value)?;
+                        if array.is_frozen() {
+                            return runtime_error_result(
+                                &set_expr.bracket,
+                                "Cannot modify a frozen array.",
+                            );
+                        }
+                        array.set(i, value.clone());
+                        Ok(Some(value))
+                    }
+                    _ => runtime_error_result(&set_expr.bracket, "Only arrays can be indexed."),
+                }
+            }
+            // Call arguments handle their own spreads; the parser doesn't
+            // produce them anywhere else.
+            Expr::Spread(spread_expr) => runtime_error_result(
+                &spread_expr.ellipsis,
+                "Can only spread arguments of a call.",
+            ),
+            Expr::This(this_expr) => {
+                match self.look_up_var(&this_expr.keyword.lexeme, &this_expr.scope_id) {
+                    Some(this) => Ok(Some(this)),
+                    None => runtime_error_result(&this_expr.keyword, "Undefined 'this'."),
                 }
             }
             Expr::Unary(unary_expr) => {
-                let right = (self.visit_expr(&unary_expr.right)?).unwrap();
-                let operator = &unary_expr.operator;
-                match operator.token_type {
-                    TokenType::Minus => match right {
+                let right = self.value_of(&unary_expr.right)?;
+                match unary_expr.operator {
+                    UnaryOp::Negate => match right {
                         Value::Number(n) => Ok(Some(Value::Number(-n))),
-                        _ => runtime_error_result(operator, "Operand must be a number."),
+                        _ => Err(RuntimeError::new(
+                            unary_expr.span.start,
+                            "Operand must be a number.".to_string(),
+                        )),
                     },
-                    TokenType::Bang => Ok(Some(Value::Boolean(!is_truthy(&right)))),
-                    _ => panic!("Invalid unary expression. This is an uncaught parse error."),
+                    UnaryOp::Not => Ok(Some(Value::Boolean(!is_truthy(&right)))),
                 }
             }
             Expr::Variable(var_expr) => {
@@ -287,81 +1167,1573 @@ impl Visitor<InterpreterResult> for Interpreter {
                 if let Some(val) = self.look_up_var(&name.lexeme, &var_expr.scope_id) {
                     Ok(Some(val))
                 } else {
-                    runtime_error_result(name, &format!("Undefined variable '{}'", name.lexeme))
+                    let msg = format!(
+                        "Undefined variable '{}'.{}",
+                        name.lexeme,
+                        self.suggest_names(name)
+                    );
+                    runtime_error_result(name, &msg)
                 }
             }
+            _ => Err(RuntimeError::new(
+                self.position,
+                format!("Unsupported syntax: {}.", expr.kind()),
+            )),
         }
     }
 }
 
+fn callee_name(callee: &Value) -> String {
+    match callee {
+        Value::Function(fun) if fun.declaration.name.lexeme.is_empty() => "<fn>".to_string(),
+        Value::Function(fun) => fun.declaration.name.lexeme.clone(),
+        Value::NativeFunction(fun) => fun.name.clone(),
+        Value::Class(class) => class.name.clone(),
+        value => value.to_string(),
+    }
+}
+
+fn trace_line(depth: usize, expr: &Expr, value: &Value) -> String {
+    format!("[trace] {}{} => {}", "  ".repeat(depth), expr, value)
+}
+
+/// Checks that `index` is a whole number within an array of length `len`.
+fn array_index(bracket: &Token, index: &Value, len: usize) -> Result<usize, RuntimeError> {
+    check_index(index, len).map_err(|msg| RuntimeError::new(bracket.position(), msg))
+}
+
 fn is_truthy(val: &Value) -> bool {
-    match val {
-        &Value::Nil => false,
-        &Value::Boolean(b) => b,
+    match *val {
+        Value::Nil => false,
+        Value::Boolean(b) => b,
         _ => true,
     }
 }
 
-fn is_equal(a: Value, b: Value) -> bool {
-    match a {
-        Value::Boolean(a_bool) => match b {
-            Value::Boolean(b_bool) => a_bool == b_bool,
-            _ => false,
-        },
-        Value::Class(a_class) => match b {
-            Value::Class(b_class) => a_class == b_class,
-            _ => false,
-        },
-        Value::Function(a_fun) => match b {
-            Value::Function(b_fun) => &a_fun == &b_fun,
-            _ => false,
-        },
-        Value::Nil => match b {
-            Value::Nil => true,
-            _ => false,
-        },
-        Value::Number(a_num) => match b {
-            Value::Number(b_num) => a_num == b_num,
-            _ => false,
-        },
-        Value::String(a_str) => match b {
-            Value::String(b_str) => a_str.eq(&b_str),
-            _ => false,
-        },
-        Value::NativeFunction(a_fun) => match b {
-            Value::NativeFunction(b_fun) => &a_fun == &b_fun,
-            _ => false,
-        },
+/// A property of something that isn't an instance: nil for `nil?.name`,
+/// and an error otherwise.
+fn undefined_property(name: &Token) -> RuntimeError {
+    RuntimeError::new(
+        name.position(),
+        format!("Undefined property '{}'.", name.lexeme),
+    )
+}
+
+/// `value is Class` is true for instances of the class, and `value is Trait`
+/// for instances of classes that mix the trait in. Anything that isn't an
+/// instance isn't an instance of anything.
+fn is_instance(position: Position, value: Value, class: Value) -> InterpreterResult {
+    let instance = match value {
+        Value::Instance(instance) => Some(instance),
+        _ => None,
+    };
+    let result = match class {
+        Value::Class(class) => instance.is_some_and(|i| i.class == class),
+        Value::Trait(t) => instance.is_some_and(|i| i.class.mixes_in(&t)),
+        value => {
+            return Err(RuntimeError::new(
+                position,
+                format!(
+                    "Right operand of 'is' must be a class or trait, got {}.",
+                    value
+                ),
+            ))
+        }
+    };
+    Ok(Some(Value::Boolean(result)))
+}
+
+fn eval_binary_expr(
+    bin_expr: &BinaryExpr,
+    left: Value,
+    right: Value,
+    max_string_length: usize,
+) -> InterpreterResult {
+    let arithmetic: fn(f64, f64) -> Value = match bin_expr.operator {
+        BinaryOp::Equal => return Ok(Some(Value::Boolean(left == right))),
+        BinaryOp::NotEqual => return Ok(Some(Value::Boolean(left != right))),
+        BinaryOp::Is => return is_instance(bin_expr.span.start, left, right),
+        BinaryOp::Add => |l, r| Value::Number(l + r),
+        BinaryOp::Subtract => |l, r| Value::Number(l - r),
+        BinaryOp::Multiply => |l, r| Value::Number(l * r),
+        BinaryOp::Divide => |l, r| Value::Number(l / r),
+        BinaryOp::Greater => |l, r| Value::Boolean(l > r),
+        BinaryOp::GreaterEqual => |l, r| Value::Boolean(l >= r),
+        BinaryOp::Less => |l, r| Value::Boolean(l < r),
+        BinaryOp::LessEqual => |l, r| Value::Boolean(l <= r),
+    };
+    let error = |msg: &str| Err(RuntimeError::new(bin_expr.span.start, msg.to_string()));
+    match (left, right) {
+        (Value::Number(l_num), Value::Number(r_num)) => Ok(Some(arithmetic(l_num, r_num))),
+        (Value::String(l_str), Value::String(r_str)) if bin_expr.operator == BinaryOp::Add => {
+            // Counting characters is only needed when the bytes are over.
+            if l_str.len() + r_str.len() > max_string_length
+                && l_str.chars().count() + r_str.chars().count() > max_string_length
+            {
+                return error(&format!(
+                    "String is longer than the limit of {} characters.",
+                    max_string_length
+                ));
+            }
+            Ok(Some(Value::from(format!("{}{}", l_str, r_str))))
+        }
+        (Value::Number(_), _) => error("Right operand must be a Number."),
+        (Value::String(_), Value::String(_)) => error("Operands must be numbers."),
+        (Value::String(_), _) => error("Right operand must be a String."),
+        _ => error("Left operand must be a Number or a String."),
     }
 }
 
-fn eval_binary_expr<'a>(operator: &Token, left: Value, right: Value) -> InterpreterResult {
-    match operator.token_type {
-        TokenType::EqualEqual => Ok(Some(Value::Boolean(is_equal(left, right)))),
-        TokenType::BangEqual => Ok(Some(Value::Boolean(!is_equal(left, right)))),
-        _ => match left {
-            Value::Number(l_num) => match right {
-                Value::Number(r_num) => match operator.token_type {
-                    TokenType::Plus => Ok(Some(Value::Number(l_num + r_num))),
-                    TokenType::Minus => Ok(Some(Value::Number(l_num - r_num))),
-                    TokenType::Star => Ok(Some(Value::Number(l_num * r_num))),
-                    TokenType::Slash => Ok(Some(Value::Number(l_num / r_num))),
-                    TokenType::Greater => Ok(Some(Value::Boolean(l_num > r_num))),
-                    TokenType::GreaterEqual => Ok(Some(Value::Boolean(l_num >= r_num))),
-                    TokenType::Less => Ok(Some(Value::Boolean(l_num < r_num))),
-                    TokenType::LessEqual => Ok(Some(Value::Boolean(l_num <= r_num))),
-                    _ => panic!("Invalid binary expression. This is an uncaught parse error"),
-                },
-                _ => runtime_error_result(operator, "Right operand must be a Number."),
-            },
-            Value::String(l_str) => match right {
-                Value::String(r_str) => match operator.token_type {
-                    TokenType::Plus => Ok(Some(Value::String(format!("{}{}", l_str, r_str)))),
-                    _ => panic!("Invalid binary expression. This is an uncaught parse error"),
-                },
-                _ => runtime_error_result(operator, "Right operand must be a String."),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{callable::Callable, config::ExecutionLimits, function::NativeFunction};
+    use ast::limits::LanguageLimits;
+    use std::cell::RefCell;
+
+    fn run(source: &str) -> Interpreter {
+        let mut interpreter = Interpreter::new();
+        let program = parser::parse(source.to_string()).unwrap();
+        interpreter.run(program).unwrap();
+        interpreter
+    }
+
+    fn global(interpreter: &Interpreter, name: &str) -> Value {
+        let environment = interpreter.environment.as_ref().unwrap();
+        environment.get(&name.to_string()).unwrap()
+    }
+
+    #[test]
+    fn class_declaration() {
+        let interpreter = run("
+            class Breakfast {
+              cook() { return \"Eggs\"; }
+              serve(who) { return who; }
+            }
+        ");
+
+        match global(&interpreter, "Breakfast") {
+            Value::Class(class) => {
+                assert_eq!(class.to_string(), "Breakfast");
+                assert!(class.find_method("cook").is_some());
+                assert_eq!(class.find_method("serve").unwrap().arity(), 1);
+                assert!(class.find_method("eat").is_none());
+            }
+            value => panic!("Expected a class, got {:?}", value),
+        }
+    }
+
+    #[test]
+    fn instances() {
+        let interpreter = run("
+            class Counter {
+              init(start) { this.count = start; }
+              add(n) { this.count = this.count + n; return this; }
+            }
+            var counter = Counter(1);
+            var same = counter.add(2).add(3) == counter;
+            var count = counter.count;
+            var printed = counter;
+        ");
+
+        assert_eq!(global(&interpreter, "count"), Value::Number(6.0));
+        assert_eq!(global(&interpreter, "same"), Value::Boolean(true));
+        assert_eq!(
+            global(&interpreter, "printed").print(),
+            "Counter { count: 6 }"
+        );
+    }
+
+    #[test]
+    fn this_and_initializer_errors() {
+        for source in &[
+            "print this;",
+            "fun f() { return this; }",
+            "class A { init() { return 1; } }",
+        ] {
+            let program = parser::parse(source.to_string()).unwrap();
+            assert!(Interpreter::new().run(program).is_err(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn operators() {
+        let interpreter = run("
+            var sum = 1 + 2 * 3 - 4 / 2;
+            var text = \"a\" + \"b\";
+            var compared = 1 < 2 and 2 >= 2 and !(1 == 2) and 1 != 2;
+            var fallback = nil ?? -1;
+        ");
+        assert_eq!(global(&interpreter, "sum"), Value::Number(5.0));
+        assert_eq!(global(&interpreter, "text"), Value::from("ab"));
+        assert_eq!(global(&interpreter, "compared"), Value::Boolean(true));
+        assert_eq!(global(&interpreter, "fallback"), Value::Number(-1.0));
+
+        for (source, error) in &[
+            (
+                "\"a\" - \"b\";",
+                "[line 1] Error: Operands must be numbers.",
+            ),
+            (
+                "\n1 < \"b\";",
+                "[line 2] Error: Right operand must be a Number.",
+            ),
+            (
+                "nil * 2;",
+                "[line 1] Error: Left operand must be a Number or a String.",
+            ),
+        ] {
+            let program = parser::parse(source.to_string()).unwrap();
+            let errors = Interpreter::new().run(program).unwrap_err();
+            assert_eq!(errors[0].to_string(), *error);
+        }
+    }
+
+    #[test]
+    fn arrays() {
+        let interpreter = run("
+            var a = [1, 2, [3]];
+            a[0] = a[2][0] + a[1];
+            var first = a[0];
+            var alias = a;
+            alias[1] = nil;
+            var second = a[1];
+        ");
+        assert_eq!(global(&interpreter, "first"), Value::Number(5.0));
+        assert_eq!(global(&interpreter, "second"), Value::Nil);
+
+        for (source, error) in &[
+            ("[1][1];", "Array index 1 is out of range for length 1."),
+            ("[1][0.5];", "Array index must be a whole number, got 0.5."),
+            (
+                "[1][\"0\"] = 2;",
+                "Array index must be a whole number, got \"0\".",
+            ),
+            ("\"abc\"[0];", "Only arrays can be indexed."),
+        ] {
+            let program = parser::parse(source.to_string()).unwrap();
+            let errors = Interpreter::new().run(program).unwrap_err();
+            assert_eq!(errors[0].to_string(), format!("[line 1] Error: {}", error));
+        }
+    }
+
+    #[test]
+    fn character_natives() {
+        let interpreter = run("
+            var letters = chars(\"añ日\");
+            var count = len(letters);
+            var middle = letters[1];
+            var bytes_ignored = len(\"日本\");
+            var code = ord(\"日\");
+            var back = chr(code);
+            var empty = len(chars(\"\"));
+        ");
+        assert_eq!(global(&interpreter, "count"), Value::Number(3.0));
+        assert_eq!(global(&interpreter, "middle"), Value::from("ñ"));
+        assert_eq!(global(&interpreter, "bytes_ignored"), Value::Number(2.0));
+        assert_eq!(global(&interpreter, "code"), Value::Number(26085.0));
+        assert_eq!(global(&interpreter, "back"), Value::from("日"));
+        assert_eq!(global(&interpreter, "empty"), Value::Number(0.0));
+
+        for source in &[
+            "ord(\"ab\");",
+            "ord(\"\");",
+            "chr(-1);",
+            "chr(55296);",
+            "chr(1.5);",
+        ] {
+            let program = parser::parse(source.to_string()).unwrap();
+            assert!(Interpreter::new().run(program).is_err(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn removal_natives() {
+        let interpreter = run("
+            class Point {}
+            var p = Point();
+            p.x = 1;
+            p.y = 2;
+            var removed = delattr(p, \"x\");
+            var again = delattr(p, \"x\");
+            var has_y = p.y;
+
+            var a = [1, 2, 3];
+            var middle = remove(a, 1);
+            var rest = a;
+        ");
+        assert_eq!(global(&interpreter, "removed"), Value::Boolean(true));
+        assert_eq!(global(&interpreter, "again"), Value::Boolean(false));
+        assert_eq!(global(&interpreter, "has_y"), Value::Number(2.0));
+        assert_eq!(global(&interpreter, "middle"), Value::Number(2.0));
+        assert_eq!(global(&interpreter, "rest").print(), "[1, 3]");
+
+        for (source, error) in &[
+            (
+                "class A {} var a = A(); a.x = 1; delattr(a, \"x\"); a.x;",
+                "[line 1] Error: Undefined property 'x'.",
+            ),
+            (
+                "delattr(1, \"x\");",
+                "[line 1] Error: delattr() expects an instance, got 1.",
+            ),
+            (
+                "class A {} delattr(A(), 1);",
+                "[line 1] Error: delattr() expects 'field' to be a string, got 1.",
+            ),
+            (
+                "remove([1], 1);",
+                "[line 1] Error: Array index 1 is out of range for length 1.",
+            ),
+        ] {
+            let program = parser::parse(source.to_string()).unwrap();
+            let errors = Interpreter::new().run(program).unwrap_err();
+            assert_eq!(&errors[0].to_string(), error);
+        }
+    }
+
+    #[test]
+    fn sets() {
+        let interpreter = run("
+            var s = set();
+            var first = add(s, 1);
+            var again = add(s, 1);
+            add(s, \"one\");
+            add(s, nil);
+            var has_one = has(s, 1);
+            var has_two = has(s, 2);
+            var removed = remove(s, nil);
+            var size = len(s);
+
+            var t = set();
+            add(t, \"one\");
+            add(t, 3);
+            var u = union(s, t);
+            var i = intersect(s, t);
+            var same = s == s;
+            var different = s == u;
+        ");
+        assert_eq!(global(&interpreter, "first"), Value::Boolean(true));
+        assert_eq!(global(&interpreter, "again"), Value::Boolean(false));
+        assert_eq!(global(&interpreter, "has_one"), Value::Boolean(true));
+        assert_eq!(global(&interpreter, "has_two"), Value::Boolean(false));
+        assert_eq!(global(&interpreter, "removed"), Value::Boolean(true));
+        assert_eq!(global(&interpreter, "size"), Value::Number(2.0));
+        assert_eq!(global(&interpreter, "s").print(), "{1, \"one\"}");
+        assert_eq!(global(&interpreter, "u").print(), "{1, \"one\", 3}");
+        assert_eq!(global(&interpreter, "i").print(), "{\"one\"}");
+        assert_eq!(global(&interpreter, "same"), Value::Boolean(true));
+        assert_eq!(global(&interpreter, "different"), Value::Boolean(false));
+
+        let program = parser::parse("union(set(), [1]);".to_string()).unwrap();
+        let errors = Interpreter::new().run(program).unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 1] Error: union() expects two sets, got {} and [1]."
+        );
+    }
+
+    #[test]
+    fn equals_and_compare() {
+        let interpreter = run("
+            var a = [1];
+            var same = equals(a, a);
+            var copy = equals(a, [1]);
+            var nan = equals(0 / 0, 0 / 0);
+            var zeros = compare(-0, 0);
+            var mixed = compare(\"1\", 1);
+            var strings = compare(\"a\", \"a\");
+            var nils = compare(nil, false);
+        ");
+        assert_eq!(global(&interpreter, "same"), Value::Boolean(true));
+        assert_eq!(global(&interpreter, "copy"), Value::Boolean(false));
+        assert_eq!(global(&interpreter, "nan"), Value::Boolean(true));
+        assert_eq!(global(&interpreter, "zeros"), Value::Number(-1.0));
+        assert_eq!(global(&interpreter, "mixed"), Value::Number(1.0));
+        assert_eq!(global(&interpreter, "strings"), Value::Number(0.0));
+        assert_eq!(global(&interpreter, "nils"), Value::Number(-1.0));
+    }
+
+    #[test]
+    fn expression_blocks() {
+        let options = parser::ParseOptions {
+            expr_blocks: true,
+            ..parser::ParseOptions::default()
+        };
+        let run = |source: &str| {
+            let program = parser::parse_with_options(source.to_string(), options).unwrap();
+            Interpreter::new().run(program)
+        };
+
+        let source = "
+            var a = \"outer\";
+            fun sign(n) {
+              return if (n > 0) { 1 } else if (n < 0) { -1 } else { 0 };
+            }
+            var b = { var a = \"inner\"; a };
+            [sign(5), sign(-5), sign(0), if (false) { 1 }, b, a];
+        ";
+        assert_eq!(
+            run(source).unwrap().unwrap().print(),
+            "[1, -1, 0, nil, \"inner\", \"outer\"]"
+        );
+
+        let errors = run("fun f() { var x = { return 1; }; }").unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 1] Error: Cannot return from inside a block expression."
+        );
+        assert!(run("var f = { fun g() { return 1; } g() };").is_ok());
+
+        // An if or block whose value isn't used is a statement, which
+        // return can leave.
+        let source = "
+            fun first(x) { if (x) { return 1; } return 2; }
+            fun last(x) { if (x) { return 1; } else { { return 2; } } }
+            fun find(n) {
+              var i = 0;
+              while (true) { if (i * i >= n) { return i; } i = i + 1; }
+            }
+            [first(true), first(false), last(true), last(false), find(10)];
+        ";
+        assert_eq!(run(source).unwrap().unwrap().print(), "[1, 2, 1, 2, 4]");
+    }
+
+    #[test]
+    fn function_expressions() {
+        let interpreter = run("
+            var fact = fun fact(n) {
+              if (n <= 1) return 1;
+              return n * fact(n - 1);
+            };
+            var renamed = fact;
+            fact = nil;
+            var result = renamed(5);
+
+            var twice = fun (f, x) { return f(f(x)); };
+            var inc = fun (n) { return n + 1; };
+            var added = twice(inc, 1);
+
+            // Parameters and locals shadow the function's own name.
+            var param = fun g(g) { return g; }(\"param\");
+            var local = fun h() { var h = \"local\"; return h; }();
+            var shown = fun () {};
+        ");
+        assert_eq!(global(&interpreter, "result"), Value::Number(120.0));
+        assert_eq!(global(&interpreter, "added"), Value::Number(3.0));
+        assert_eq!(global(&interpreter, "param"), Value::from("param"));
+        assert_eq!(global(&interpreter, "local"), Value::from("local"));
+        assert_eq!(global(&interpreter, "shown").print(), "<fn>");
+        assert_eq!(global(&interpreter, "renamed").print(), "<fn fact>");
+
+        // The name isn't visible outside the function.
+        let program = parser::parse("var f = fun inner() {}; inner;".to_string()).unwrap();
+        let errors = Interpreter::new().run(program).unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 1] Error: Undefined variable 'inner'."
+        );
+    }
+
+    #[test]
+    fn variadic_functions_and_spread() {
+        let interpreter = run("
+            fun collect(first, ...rest) { return [first, rest]; }
+            var none = collect(1);
+            var some = collect(1, 2, 3);
+
+            fun add(a, b, c) { return a + b + c; }
+            var parts = [2, 3];
+            var spread = add(1, ...parts);
+            var forwarded = collect(...parts, ...[], 4);
+
+            class Point {
+              init(...coords) { this.coords = coords; }
+            }
+            var point = Point(1, 2);
+            var min_arity = arity(collect);
+        ");
+        assert_eq!(global(&interpreter, "none").print(), "[1, []]");
+        assert_eq!(global(&interpreter, "some").print(), "[1, [2, 3]]");
+        assert_eq!(global(&interpreter, "spread"), Value::Number(6.0));
+        assert_eq!(global(&interpreter, "forwarded").print(), "[2, [3, 4]]");
+        assert_eq!(
+            global(&interpreter, "point").print(),
+            "Point { coords: [1, 2] }"
+        );
+        assert_eq!(global(&interpreter, "min_arity"), Value::Number(1.0));
+
+        for (source, error) in &[
+            (
+                "fun f(a, ...b) {} f();",
+                "[line 1] Error: Expected at least 1 arguments but got 0. (function 'f' declared at line 1)",
+            ),
+            (
+                "fun f(a) {} f(...[1, 2]);",
+                "[line 1] Error: Expected 1 arguments but got 2. (function 'f' declared at line 1)",
+            ),
+            (
+                "fun f(a) {} f(...\"ab\");",
+                "[line 1] Error: Can only spread arrays, got \"ab\".",
+            ),
+        ] {
+            let program = parser::parse(source.to_string()).unwrap();
+            let errors = Interpreter::new().run(program).unwrap_err();
+            assert_eq!(&errors[0].to_string(), error);
+        }
+    }
+
+    #[test]
+    fn keyword_arguments() {
+        let interpreter = run("
+            fun point(x, y, ...rest) { return [x, y, rest]; }
+            var named = point(y: 2, x: 1);
+            var mixed = point(1, y: 2);
+            var extra = point(1, 2, 3);
+
+            class Range {
+              init(start, end) { this.start = start; this.end = end; }
+            }
+            var range = Range(end: 10, start: 0);
+        ");
+        assert_eq!(global(&interpreter, "named").print(), "[1, 2, []]");
+        assert_eq!(global(&interpreter, "mixed").print(), "[1, 2, []]");
+        assert_eq!(global(&interpreter, "extra").print(), "[1, 2, [3]]");
+        assert_eq!(
+            global(&interpreter, "range").print(),
+            "Range { end: 10, start: 0 }"
+        );
+
+        for (source, error) in &[
+            (
+                "fun f(a) {} f(b: 1);",
+                "[line 1] Error: Unknown parameter 'b'. (function 'f' declared at line 1)",
+            ),
+            (
+                "fun f(a) {} f(1, a: 2);",
+                "[line 1] Error: Parameter 'a' was given more than once. (function 'f' declared at line 1)",
+            ),
+            (
+                "fun f(a, b) {} f(b: 2);",
+                "[line 1] Error: Missing argument for parameter 'a'. (function 'f' declared at line 1)",
+            ),
+            (
+                "fun f(a, ...b) {} f(1, 2, b: 3);",
+                "[line 1] Error: Unknown parameter 'b'. (function 'f' declared at line 1)",
+            ),
+            (
+                "class A {} A(x: 1);",
+                "[line 1] Error: Unknown parameter 'x'. (class 'A')",
+            ),
+            (
+                "len(value: [1]);",
+                "[line 1] Error: Native functions don't take keyword arguments.",
+            ),
+        ] {
+            let program = parser::parse(source.to_string()).unwrap();
+            let errors = Interpreter::new().run(program).unwrap_err();
+            assert_eq!(&errors[0].to_string(), error);
+        }
+    }
+
+    #[test]
+    fn destructuring() {
+        let interpreter = run("
+            var calls = 0;
+            fun pair() { calls = calls + 1; return [1, 2]; }
+            var [a, b] = pair();
+
+            class Point {}
+            var point = Point();
+            point.x = 3;
+            point.y = 4;
+            var sum;
+            {
+              var {x, y} = point;
+              var [c] = [x + y];
+              sum = c;
+            }
+        ");
+        assert_eq!(global(&interpreter, "calls"), Value::Number(1.0));
+        assert_eq!(global(&interpreter, "a"), Value::Number(1.0));
+        assert_eq!(global(&interpreter, "b"), Value::Number(2.0));
+        assert_eq!(global(&interpreter, "sum"), Value::Number(7.0));
+        let environment = interpreter.environment.as_ref().unwrap();
+        assert!(environment.names().iter().all(|name| !name.contains('[')));
+
+        for (source, error) in &[
+            (
+                "var [a, b] = [1];",
+                "[line 1] Error: Expected 2 values to destructure, got 1.",
+            ),
+            (
+                "var [a] = [1, 2];",
+                "[line 1] Error: Expected 1 values to destructure, got 2.",
+            ),
+            (
+                "var [a] = 1;",
+                "[line 1] Error: Can only destructure arrays with '[...]', got 1.",
+            ),
+            (
+                "class P {} var {x} = P();",
+                "[line 1] Error: Undefined property 'x'.",
+            ),
+            (
+                "var {x} = 1;",
+                "[line 1] Error: Only instances have properties.",
+            ),
+        ] {
+            let program = parser::parse(source.to_string()).unwrap();
+            let errors = Interpreter::new().run(program).unwrap_err();
+            assert_eq!(&errors[0].to_string(), error);
+        }
+    }
+
+    #[test]
+    fn class_fields() {
+        let interpreter = run("
+            var made = 0;
+            class Point {
+              var x = 0;
+              var y;
+              var id = made = made + 1;
+              var double = fun () { return this.x * 2; };
+              init(x) { this.x = x; }
+            }
+            var a = Point(3);
+            var b = Point(4);
+            var twice = a.double();
+        ");
+        assert_eq!(global(&interpreter, "made"), Value::Number(2.0));
+        assert_eq!(
+            global(&interpreter, "a").print(),
+            "Point { double: <fn>, id: 1, x: 3, y: nil }"
+        );
+        assert!(global(&interpreter, "b").print().contains("id: 2"));
+        assert_eq!(global(&interpreter, "twice"), Value::Number(6.0));
+
+        let program = parser::parse("class A { var x = missing; } A();".to_string()).unwrap();
+        let errors = Interpreter::new().run(program).unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 1] Error: Undefined variable 'missing'."
+        );
+    }
+
+    #[test]
+    fn traits() {
+        let interpreter = run("
+            trait Named {
+              describe() { return \"I am \" + this.name; }
+              greet() { return \"hello\"; }
+            }
+            trait Counted {
+              count() { return 1; }
+            }
+            class Dog with Named, Counted {
+              init(name) { this.name = name; }
+              greet() { return \"woof\"; }
+            }
+            var dog = Dog(\"Rex\");
+            var described = dog.describe();
+            var greeting = dog.greet();
+            var count = dog.count();
+            var shown = Named;
+            var n = methods(Dog);
+        ");
+        assert_eq!(global(&interpreter, "described"), Value::from("I am Rex"));
+        assert_eq!(global(&interpreter, "greeting"), Value::from("woof"));
+        assert_eq!(global(&interpreter, "count"), Value::Number(1.0));
+        assert_eq!(global(&interpreter, "shown").print(), "<trait Named>");
+        assert_eq!(global(&interpreter, "n"), Value::Number(4.0));
+
+        for (source, error) in &[
+            (
+                "trait A { f() {} } trait B { f() {} } class C with A, B {}",
+                "[line 1] Error: Method 'f' is provided by both 'A' and 'B'; declare it in the class to choose.",
+            ),
+            (
+                "class A {} class B with A {}",
+                "[line 1] Error: Can only mix in traits, got A.",
+            ),
+            (
+                "trait T { init() {} }",
+                "[line 1] Error: A trait cannot declare an initializer.",
+            ),
+        ] {
+            let program = parser::parse(source.to_string()).unwrap();
+            let errors = Interpreter::new().run(program).unwrap_err();
+            assert_eq!(&errors[0].to_string(), error);
+        }
+
+        // A class can resolve a conflict by declaring the method itself.
+        run("trait A { f() {} } trait B { f() {} } class C with A, B { f() {} }");
+    }
+
+    #[test]
+    fn is_operator() {
+        let interpreter = run("
+            trait Walks {}
+            class Dog with Walks {}
+            class Cat {}
+            var dog = Dog();
+            var results = [
+              dog is Dog, dog is Cat, dog is Walks, Cat() is Walks,
+              1 is Dog, nil is Dog, Dog is Dog
+            ];
+        ");
+        assert_eq!(
+            global(&interpreter, "results").print(),
+            "[true, false, true, false, false, false, false]"
+        );
+
+        let program = parser::parse("var a = 1; 1 is a;".to_string()).unwrap();
+        let errors = Interpreter::new().run(program).unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 1] Error: Right operand of 'is' must be a class or trait, got 1."
+        );
+    }
+
+    #[test]
+    fn method_missing() {
+        let source = "
+            class Proxy {
+              init(target) { this.target = target; }
+              known() { return \"known\"; }
+              method_missing(name, args) { return [name, args]; }
+            }
+            var proxy = Proxy(nil);
+            var forwarded = proxy.anything(1, 2);
+            var read = proxy.field;
+            var known = proxy.known();
+        ";
+        let interpreter = run(source);
+        assert_eq!(
+            global(&interpreter, "forwarded").print(),
+            "[\"anything\", [1, 2]]"
+        );
+        assert_eq!(global(&interpreter, "read").print(), "[\"field\", []]");
+        assert_eq!(global(&interpreter, "known"), Value::from("known"));
+
+        // Nothing is forwarded when it's disabled.
+        let disabled = InterpreterConfig {
+            method_missing: false,
+            ..InterpreterConfig::default()
+        };
+        for (config, source, error) in &[
+            (
+                InterpreterConfig::default(),
+                "class A {} A().missing;",
+                "[line 1] Error: Undefined property 'missing'.",
+            ),
+            (
+                disabled.clone(),
+                "class A { method_missing(name, args) {} } A().missing;",
+                "[line 1] Error: Undefined property 'missing'.",
+            ),
+            (
+                disabled,
+                "class A { method_missing(name, args) {} } A().missing();",
+                "[line 1] Error: Undefined property 'missing'.",
+            ),
+        ] {
+            let program = parser::parse(source.to_string()).unwrap();
+            let errors = Interpreter::with_config(config.clone())
+                .run(program)
+                .unwrap_err();
+            assert_eq!(&errors[0].to_string(), error);
+        }
+        // A forwarded call is traced like any other.
+        let traces = Rc::new(RefCell::new(vec![]));
+        let collected = traces.clone();
+        let mut interp = Interpreter::with_config(InterpreterConfig {
+            trace_exprs: true,
+            ..InterpreterConfig::default()
+        });
+        interp.on_output = Some(Box::new(move |event| {
+            if let OutputEvent::Trace(line) = event {
+                collected.borrow_mut().push(line.to_string());
+            }
+            Ok(())
+        }));
+        let program = parser::parse(
+            "class A { method_missing(name, args) { return name; } }\nvar a = A();\na.b(1);"
+                .to_string(),
+        )
+        .unwrap();
+        interp.run(program).unwrap();
+        let traces = traces.borrow();
+        assert_eq!(
+            traces[traces.len() - 5..],
+            [
+                "[trace] a => A instance",
+                "[trace] (. a b) => <fn method_missing>",
+                "[trace] 1 => 1",
+                "[trace]   name => \"b\"",
+                "[trace] ((. a b) 1) => \"b\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn frozen_values() {
+        let interpreter = run("
+            class Config {}
+            var config = Config();
+            config.debug = true;
+            var same = freeze(config) == config;
+            var inner = [1];
+            var list = freeze([inner, 2]);
+            inner[0] = 3;
+            var frozen = [is_frozen(config), is_frozen(list), is_frozen(inner), is_frozen(1)];
+        ");
+        assert_eq!(global(&interpreter, "same"), Value::Boolean(true));
+        assert_eq!(global(&interpreter, "list").print(), "[[3], 2]");
+        assert_eq!(
+            global(&interpreter, "frozen").print(),
+            "[true, true, false, true]"
+        );
+
+        for (source, error) in &[
+            (
+                "class A {} var a = freeze(A()); a.x = 1;",
+                "[line 1] Error: Cannot modify a frozen instance.",
+            ),
+            (
+                "var a = freeze([1]); a[0] = 2;",
+                "[line 1] Error: Cannot modify a frozen array.",
+            ),
+            (
+                "remove(freeze([1]), 0);",
+                "[line 1] Error: Cannot modify a frozen array.",
+            ),
+            (
+                "add(freeze(set()), 1);",
+                "[line 1] Error: Cannot modify a frozen set.",
+            ),
+            (
+                "class A {} var a = A(); a.x = 1; delattr(freeze(a), \"x\");",
+                "[line 1] Error: Cannot modify a frozen instance.",
+            ),
+        ] {
+            let program = parser::parse(source.to_string()).unwrap();
+            let errors = Interpreter::new().run(program).unwrap_err();
+            assert_eq!(&errors[0].to_string(), error);
+        }
+    }
+
+    #[test]
+    fn native_namespaces() {
+        let interpreter = run("
+            var code = str.ord(\"a\");
+            var size = collections.len([1, 2]);
+            var same = collections.len == len;
+            var namespace = str;
+        ");
+        assert_eq!(global(&interpreter, "code"), Value::Number(97.0));
+        assert_eq!(global(&interpreter, "size"), Value::Number(2.0));
+        assert_eq!(global(&interpreter, "same"), Value::Boolean(true));
+        assert!(global(&interpreter, "namespace").is_frozen());
+
+        let mut interpreter = Interpreter::with_config(InterpreterConfig {
+            flat_natives: false,
+            ..InterpreterConfig::default()
+        });
+        let program = parser::parse("var now = time.clock();\nlen([]);".to_string()).unwrap();
+        let errors = interpreter.run(program).unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 2] Error: Undefined variable 'len'."
+        );
+        assert!(matches!(global(&interpreter, "now"), Value::Number(_)));
+
+        let program = parser::parse("str.ord = nil;".to_string()).unwrap();
+        let errors = Interpreter::new().run(program).unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 1] Error: Cannot modify a frozen instance."
+        );
+    }
+
+    #[test]
+    fn memory_stats_counts_live_objects() {
+        let interpreter = run("
+            class Node {}
+            fun instances() { return memory_stats().instances; }
+            fun cycle() { var n = Node(); n.f = fun() { return n; }; }
+            fun noCycle() { var n = Node(); n.x = [1]; }
+
+            var start = instances();
+            for (var i = 0; i < 10; i = i + 1) cycle();
+            var leaked = instances() - start;
+            for (var i = 0; i < 10; i = i + 1) noCycle();
+            var kept = instances() - start - leaked;
+
+            fun strings() { return memory_stats().strings; }
+            var before = strings();
+            var letters = chars(\"abcde\");
+            var held = strings() - before;
+            letters = nil;
+            var freed = strings() - before;
+            var stats = memory_stats();
+        ");
+        assert_eq!(global(&interpreter, "leaked"), Value::Number(10.0));
+        assert_eq!(global(&interpreter, "kept"), Value::Number(0.0));
+        assert_eq!(global(&interpreter, "held"), Value::Number(5.0));
+        assert_eq!(global(&interpreter, "freed"), Value::Number(0.0));
+        let stats = global(&interpreter, "stats").print();
+        for field in &[
+            "environments",
+            "functions",
+            "instances",
+            "arrays",
+            "sets",
+            "strings",
+        ] {
+            assert!(stats.contains(field), "{}", stats);
+        }
+    }
+
+    #[test]
+    fn nil_safety_operators() {
+        let interpreter = run("
+            class Box {}
+            var full = Box();
+            full.value = 1;
+            var empty;
+            var a = empty?.value;
+            var b = full?.value;
+            var c = empty ?? \"default\";
+            var d = false ?? \"default\";
+            var e = empty?.value ?? 2;
+            var f = empty?.method();
+            var g = empty?.a.b;
+            var h = empty?.a[0].b();
+        ");
+        assert_eq!(global(&interpreter, "a"), Value::Nil);
+        assert_eq!(global(&interpreter, "b"), Value::Number(1.0));
+        assert_eq!(global(&interpreter, "c"), Value::from("default"));
+        assert_eq!(global(&interpreter, "d"), Value::Boolean(false));
+        assert_eq!(global(&interpreter, "e"), Value::Number(2.0));
+        assert_eq!(global(&interpreter, "f"), Value::Nil);
+        assert_eq!(global(&interpreter, "g"), Value::Nil);
+        assert_eq!(global(&interpreter, "h"), Value::Nil);
+
+        // Only the chain after the `?.` is skipped.
+        for (source, error) in &[
+            (
+                "var a; (a?.b).c;",
+                "[line 1] Error: Only instances have properties.",
+            ),
+            (
+                "var a; fun f(x) { return x; } f(a?.b).c;",
+                "[line 1] Error: Only instances have properties.",
+            ),
+            (
+                "var a; [a?.b][0].c;",
+                "[line 1] Error: Only instances have properties.",
+            ),
+            (
+                "var a; a.b?.c;",
+                "[line 1] Error: Only instances have properties.",
+            ),
+        ] {
+            let program = parser::parse(source.to_string()).unwrap();
+            let errors = Interpreter::new().run(program).unwrap_err();
+            assert_eq!(&errors[0].to_string(), error);
+        }
+    }
+
+    #[test]
+    fn introspection_natives() {
+        let interpreter = run("
+            fun add(a, b) { return a + b; }
+            class Point {
+              init(x, y) { this.x = x; this.y = y; }
+              norm() { return this.x + this.y; }
+            }
+            var add_arity = arity(add);
+            var point_arity = arity(Point);
+            var clock_arity = arity(clock);
+            var point_name = name(Point);
+            var add_name = name(add);
+            var point_methods = methods(Point);
+        ");
+        assert_eq!(global(&interpreter, "add_arity"), Value::Number(2.0));
+        assert_eq!(global(&interpreter, "point_arity"), Value::Number(2.0));
+        assert_eq!(global(&interpreter, "clock_arity"), Value::Number(0.0));
+        assert_eq!(global(&interpreter, "point_name"), Value::from("Point"));
+        assert_eq!(global(&interpreter, "add_name"), Value::from("add"));
+        assert_eq!(global(&interpreter, "point_methods"), Value::Number(2.0));
+
+        let program = parser::parse("arity(\"add\");".to_string()).unwrap();
+        let errors = Interpreter::new().run(program).unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 1] Error: arity() expects a function or class, got \"add\"."
+        );
+    }
+
+    #[test]
+    fn deep_program_is_rejected() {
+        // The depth limit is sized for a main thread's 8MB stack; test
+        // threads get 2MB, which a debug build outgrows before the limit.
+        let rejected = std::thread::Builder::new()
+            .stack_size(8 * 1024 * 1024)
+            .spawn(|| {
+                let source = format!("print {};", vec!["1"; 1000].join(" + "));
+                let program = parser::parse(source).unwrap();
+                let errors = Interpreter::new().run(program).unwrap_err();
+                errors[0].to_string()
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(rejected, "[line 1] Error: Program is nested too deeply.");
+    }
+
+    #[test]
+    fn execution_limits() {
+        let run = |limits, source: &str| {
+            let config = InterpreterConfig {
+                limits,
+                ..InterpreterConfig::default()
+            };
+            let program = parser::parse(source.to_string()).unwrap();
+            let errors = Interpreter::with_config(config).run(program).unwrap_err();
+            errors[0].to_string()
+        };
+
+        let steps = ExecutionLimits {
+            max_steps: Some(100),
+            ..ExecutionLimits::default()
+        };
+        assert_eq!(
+            run(steps, "var i = 0;\nwhile (true) i = i + 1;"),
+            "[line 2] Error: Execution limit of 100 steps exceeded."
+        );
+
+        let depth = ExecutionLimits {
+            max_call_depth: Some(50),
+            ..ExecutionLimits::default()
+        };
+        assert_eq!(
+            run(depth, "fun f(n) {\n  return f(n + 1);\n}\nf(0);"),
+            "[line 2] Error: Stack overflow."
+        );
+
+        let time = ExecutionLimits {
+            timeout_ms: Some(10),
+            ..ExecutionLimits::default()
+        };
+        assert_eq!(
+            run(time, "var i = 0;\nwhile (i >= 0) { i = i + 1; }"),
+            "[line 2] Error: Execution time limit of 10ms exceeded."
+        );
+    }
+
+    #[test]
+    fn string_length_limit() {
+        let config = InterpreterConfig {
+            language_limits: LanguageLimits {
+                max_string_length: 4,
+                ..LanguageLimits::default()
             },
-            _ => runtime_error_result(operator, "Left operand must be a Number or a String."),
-        },
+            ..InterpreterConfig::default()
+        };
+        let mut interp = Interpreter::with_config(config);
+        let program = parser::parse("var s = \"ab\" + \"ïé\";".to_string()).unwrap();
+        interp.run(program).unwrap();
+        assert_eq!(global(&interp, "s"), Value::from("abïé"));
+
+        let program = parser::parse("s = s +\n\"c\";".to_string()).unwrap();
+        let errors = interp.run(program).unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 1] Error: String is longer than the limit of 4 characters."
+        );
+    }
+
+    #[test]
+    fn checked_arithmetic() {
+        let source = "
+            var big = 9007199254740990;
+            for (var i = 0; i < 4; i = i + 1) big = big + 1;
+            var huge = big * 2;
+            var inf = huge;
+            for (var j = 0; j < 60; j = j + 1) inf = inf *
+              1000000;
+            var fine = inf + 1;
+            var ratio = 1 / 0;
+        ";
+        let mut config = InterpreterConfig {
+            checked_arithmetic: true,
+            ..InterpreterConfig::default()
+        };
+        let mut interp = Interpreter::with_config(config.clone());
+        interp
+            .run(parser::parse(source.to_string()).unwrap())
+            .unwrap();
+        let warnings: Vec<String> = interp.warnings.iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            warnings,
+            vec![
+                "[line 3] Warning: Result of '+' is past 2^53, where integers are no longer exact. [imprecise-number]",
+                "[line 6] Warning: Result of '*' is infinite. [imprecise-number]",
+                "[line 9] Warning: Result of '/' is infinite. [imprecise-number]",
+            ]
+        );
+
+        config.strict = true;
+        let mut interp = Interpreter::with_config(config.clone());
+        let errors = interp
+            .run(parser::parse(source.to_string()).unwrap())
+            .unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 3] Error: Result of '+' is past 2^53, where integers are no longer exact."
+        );
+
+        config.lint.allow = vec![IMPRECISE_NUMBER.to_string()];
+        let mut interp = Interpreter::with_config(config);
+        interp
+            .run(parser::parse(source.to_string()).unwrap())
+            .unwrap();
+        assert!(interp.warnings.is_empty());
+
+        let mut interp = Interpreter::new();
+        interp
+            .run(parser::parse(source.to_string()).unwrap())
+            .unwrap();
+        assert!(interp.warnings.is_empty());
+    }
+
+    #[test]
+    fn warnings_before_running() {
+        let mut interp = Interpreter::new();
+        let program = parser::parse("var clock = 1;".to_string()).unwrap();
+        interp.resolve(&program).unwrap();
+        assert_eq!(interp.warnings.len(), 1);
+        assert!(matches!(global(&interp, "clock"), Value::NativeFunction(_)));
+        interp.run_resolved(program).unwrap();
+        assert_eq!(global(&interp, "clock"), Value::Number(1.0));
+    }
+
+    #[test]
+    fn strict_mode_allows_native_names() {
+        let config = InterpreterConfig {
+            strict: true,
+            ..InterpreterConfig::default()
+        };
+        let mut interp = Interpreter::with_config(config);
+        interp
+            .run(parser::parse("var name = 1; fun add(a, b) {}".to_string()).unwrap())
+            .unwrap();
+        let warnings: Vec<String> = interp.warnings.iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            warnings,
+            vec![
+                "[line 1] Warning: 'name' hides the native function of that name. [shadow-native]",
+                "[line 1] Warning: 'add' hides the native function of that name. [shadow-native]",
+            ]
+        );
+    }
+
+    #[test]
+    fn environments_at_an_error() {
+        let config = InterpreterConfig {
+            dump_env_on_error: Some(3),
+            ..InterpreterConfig::default()
+        };
+        let mut interp = Interpreter::with_config(config);
+        let program = parser::parse(
+            "
+            var total = 0;
+            fun add(items) {
+              for (var i = 0; i < 3; i = i + 1) {
+                var item = items[i];
+                total = total + item;
+              }
+            }
+            add([1, \"two\"]);
+            "
+            .to_string(),
+        )
+        .unwrap();
+        assert!(interp.run(program).is_err());
+        let bindings = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+            pairs
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect()
+        };
+        assert_eq!(
+            interp.error_environments,
+            Some(vec![
+                bindings(&[("item", "\"two\"")]),
+                bindings(&[("i", "1")]),
+                bindings(&[("items", "[1, \"two\"]")]),
+            ])
+        );
+
+        let program = parser::parse("print -\"a\";".to_string()).unwrap();
+        assert!(interp.run(program).is_err());
+        assert_eq!(
+            interp.error_environments,
+            Some(vec![bindings(&[("add", "<fn add>"), ("total", "1")])])
+        );
+
+        interp
+            .run(parser::parse("1;".to_string()).unwrap())
+            .unwrap();
+        assert_eq!(interp.error_environments, None);
+
+        let mut interp = Interpreter::new();
+        assert!(interp
+            .run(parser::parse("-\"a\";".to_string()).unwrap())
+            .is_err());
+        assert_eq!(interp.error_environments, None);
+    }
+
+    #[test]
+    fn to_string_protocol() {
+        let interpreter = run("
+            class Point {
+              init(x, y) { this.x = x; this.y = y; }
+              to_string() { return \"(\" + str(this.x) + \", \" + str(this.y) + \")\"; }
+            }
+            fun str(n) { return n == 1 and \"1\" or \"2\"; }
+            class Plain {}
+            var p = Point(1, 2);
+            var printed = capture(fun () { print p, [p, Plain()]; });
+            var joined = \"at \" + p;
+            var reversed = p + \"!\";
+        ");
+        assert_eq!(
+            global(&interpreter, "printed"),
+            Value::from("(1, 2) [(1, 2), Plain instance]\n")
+        );
+        assert_eq!(global(&interpreter, "joined"), Value::from("at (1, 2)"));
+        assert_eq!(global(&interpreter, "reversed"), Value::from("(1, 2)!"));
+
+        let program =
+            parser::parse("class A { to_string() { return 1; } }\nprint A();".to_string()).unwrap();
+        let errors = Interpreter::new().run(program).unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 2] Error: to_string() must return a string, got 1."
+        );
+    }
+
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn print_output() {
+        let buffer = Rc::new(RefCell::new(vec![]));
+        let mut interpreter = Interpreter::new();
+        interpreter.output = Box::new(Shared(buffer.clone()));
+        let program = parser::parse("print 1, \"two\"; print nil;".to_string()).unwrap();
+        interpreter.run(program).unwrap();
+        assert_eq!(buffer.borrow().as_slice(), b"1 two\nnil\n");
+    }
+
+    #[test]
+    fn output_events() {
+        let events = Rc::new(RefCell::new(vec![]));
+        let collected = events.clone();
+        let mut interp = Interpreter::with_config(InterpreterConfig {
+            checked_arithmetic: true,
+            trace_exprs: true,
+            ..InterpreterConfig::default()
+        });
+        interp.on_output = Some(Box::new(move |event| {
+            let shown = match event {
+                OutputEvent::Print(line) => format!("print: {}", line),
+                OutputEvent::Warning(warning) => format!("warning: {}", warning),
+                OutputEvent::Error(error) => format!("error: {}", error),
+                OutputEvent::Trace(line) => format!("trace: {}", line),
+            };
+            collected.borrow_mut().push(shown);
+            Ok(())
+        }));
+        let program = parser::parse("print 1 / 0; -nil;".to_string()).unwrap();
+        assert!(interp.run(program).is_err());
+        assert!(interp.warnings.is_empty());
+        assert_eq!(
+            *events.borrow(),
+            vec![
+                "trace: [trace] 1 => 1",
+                "trace: [trace] 0 => 0",
+                "warning: [line 1] Warning: Result of '/' is infinite. [imprecise-number]",
+                "trace: [trace] (/ 1 0) => inf",
+                "print: inf",
+                "trace: [trace] nil => nil",
+                "error: [line 1] Error: Operand must be a number.",
+            ]
+        );
+    }
+
+    #[test]
+    fn colored_output() {
+        let buffer = Rc::new(RefCell::new(vec![]));
+        let mut interpreter = Interpreter::new();
+        interpreter.output = Box::new(Shared(buffer.clone()));
+        interpreter.color = true;
+        let program = parser::parse(
+            "print 1, \"two\", [nil]; print capture(fun () { print true; });".to_string(),
+        )
+        .unwrap();
+        interpreter.run(program).unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&buffer.borrow()),
+            "\x1b[33m1\x1b[0m \x1b[32mtwo\x1b[0m [nil]\n\x1b[32mtrue\n\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn capture_output() {
+        let interpreter = run("
+            fun greet(who) { print \"hello\", who; }
+            var greeting = capture(fun () { greet(\"you\"); print 1; });
+            var nested = capture(fun () {
+              print \"outer\";
+              print capture(fun () { print \"inner\"; });
+            });
+        ");
+
+        assert_eq!(
+            global(&interpreter, "greeting"),
+            Value::from("hello you\n1\n")
+        );
+        assert_eq!(
+            global(&interpreter, "nested"),
+            Value::from("outer\ninner\n\n")
+        );
+    }
+
+    #[test]
+    fn capture_restores_output_on_error() {
+        let buffer = Rc::new(RefCell::new(vec![]));
+        let mut interpreter = Interpreter::new();
+        interpreter.output = Box::new(Shared(buffer.clone()));
+        let mut run = |source: &str| {
+            let program = parser::parse(source.to_string()).unwrap();
+            interpreter
+                .run(program)
+                .map_err(|errors| errors[0].to_string())
+        };
+
+        assert_eq!(
+            run("capture(fun () { print 1; print -\"a\"; });"),
+            Err("[line 1] Error: Operand must be a number.".to_string())
+        );
+        assert_eq!(
+            run("capture(1);"),
+            Err("[line 1] Error: Can only call functions and classes.".to_string())
+        );
+        run("print 2;").unwrap();
+        assert_eq!(buffer.borrow().as_slice(), b"2\n");
+    }
+
+    #[test]
+    fn run_result() {
+        let mut interpreter = Interpreter::new();
+        let mut run = |source: &str| {
+            let program = parser::parse(source.to_string()).unwrap();
+            interpreter.run(program).unwrap()
+        };
+
+        assert_eq!(run("var a = 1;"), None);
+        assert_eq!(run("a + 1;"), Some(Value::Number(2.0)));
+        assert_eq!(run("a + 1; var b = 2;"), None);
+        assert_eq!(
+            run("if (a == 1) return \"one\"; print \"unreachable\";"),
+            Some(Value::from("one"))
+        );
+        assert_eq!(run("fun f() { return 3; } f();"), Some(Value::Number(3.0)));
+    }
+
+    #[test]
+    fn trace_line_indents_by_call_depth() {
+        let program = parser::parse("1 + 2;".to_string()).unwrap();
+        let expr = match &program[0] {
+            Stmt::Expr(expr_stmt) => &expr_stmt.expression,
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            trace_line(2, expr, &Value::Number(3.0)),
+            "[trace]     (+ 1 2) => 3"
+        );
+    }
+
+    #[test]
+    fn cancellation_stops_with_a_backtrace() {
+        let mut interpreter = Interpreter::new();
+        let cancelled = interpreter.cancelled.clone();
+        let cancel = NativeFunction::new(
+            "cancel".to_string(),
+            0,
+            Rc::new(move |_| {
+                cancelled.store(true, Ordering::Relaxed);
+                Ok(Value::Nil)
+            }),
+        );
+        interpreter
+            .define_var("cancel".to_string(), Value::NativeFunction(cancel))
+            .unwrap();
+        let program = parser::parse(
+            "fun spin() {\n  while (true) {\n    cancel();\n  }\n}\nfun outer() { spin(); }\nouter();"
+                .to_string(),
+        )
+        .unwrap();
+
+        let errors = interpreter.run(program).unwrap_err();
+        let error = errors[0].downcast_ref::<RuntimeError>().unwrap();
+        assert!(error.is_interrupted());
+        assert_eq!(
+            error.to_string(),
+            "Interrupted.\n  in spin() at line 3\n  in outer() at line 6\n  in the script at line 7"
+        );
+        assert!(!interpreter.cancelled.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn equality_matches_jlox() {
+        // Expected results are what jlox prints for the same comparisons.
+        let interpreter = run("
+            fun f() {}
+            fun g() {}
+            class A {}
+            var alias = f;
+            var nan = 0 / 0;
+            var results = \"\";
+            fun check(result) {
+              if (result) results = results + \"t\"; else results = results + \"f\";
+            }
+            check(nil == nil);
+            check(nil == false);
+            check(true == true);
+            check(true == 1);
+            check(1 == 1);
+            check(1 == \"1\");
+            check(nan == nan);
+            check(0 == -0);
+            check(\"a\" + \"b\" == \"ab\");
+            check(f == alias);
+            check(f == g);
+            check(A == A);
+            check(clock == clock);
+        ");
+
+        assert_eq!(
+            global(&interpreter, "results"),
+            Value::from("tftftftfttftt")
+        );
+    }
+
+    #[test]
+    fn loop_variable_is_shared_by_closures() {
+        let interpreter = run("
+            var f1; var f2; var f3;
+            for (var i = 1; i < 4; i = i + 1) {
+              var j = i;
+              fun f() { return i * 10 + j; }
+              if (j == 1) f1 = f; else if (j == 2) f2 = f; else f3 = f;
+            }
+            var r1 = f1(); var r2 = f2(); var r3 = f3();
+        ");
+
+        assert_eq!(global(&interpreter, "r1"), Value::Number(41.0));
+        assert_eq!(global(&interpreter, "r2"), Value::Number(42.0));
+        assert_eq!(global(&interpreter, "r3"), Value::Number(43.0));
+    }
+
+    #[test]
+    fn function_in_loop_body_is_fresh_per_iteration() {
+        let interpreter = run("
+            var first; var second;
+            var i = 0;
+            while (i < 2) {
+              fun f() {}
+              if (i == 0) first = f; else second = f;
+              i = i + 1;
+            }
+            var same = first == second;
+        ");
+
+        assert_eq!(global(&interpreter, "same"), Value::Boolean(false));
+    }
+
+    #[test]
+    fn function_in_block_is_block_scoped() {
+        let interpreter = run("
+            fun f() { return \"outer\"; }
+            var inner;
+            {
+              fun f() { return \"inner\"; }
+              inner = f();
+            }
+            var outer = f();
+        ");
+
+        assert_eq!(global(&interpreter, "inner"), Value::from("inner"));
+        assert_eq!(global(&interpreter, "outer"), Value::from("outer"));
+    }
+
+    #[test]
+    fn counts_nodes_when_asked() {
+        let program = parser::parse("var n = 0; while (n < 3) n = n + 1;".to_string()).unwrap();
+        let mut interpreter = Interpreter::with_config(InterpreterConfig {
+            count_nodes: true,
+            ..InterpreterConfig::default()
+        });
+        interpreter.run(program.clone()).unwrap();
+        let counts = interpreter.node_counts.as_ref().unwrap().sorted();
+        assert_eq!(
+            &counts[..3],
+            &[("literal", 8), ("binary", 7), ("variable", 7)]
+        );
+
+        let mut interpreter = Interpreter::new();
+        interpreter.run(program).unwrap();
+        assert!(interpreter.node_counts.is_none());
+    }
+
+    #[test]
+    fn reports_when_asked() {
+        let program = parser::parse(
+            "
+            fun pair(n) { { return [n, n]; } }
+            for (var i = 0; i < 3; i = i + 1) pair(i);
+            "
+            .to_string(),
+        )
+        .unwrap();
+        let mut interpreter = Interpreter::with_config(InterpreterConfig {
+            report: true,
+            ..InterpreterConfig::default()
+        });
+        interpreter.run(program.clone()).unwrap();
+        interpreter.run(program.clone()).unwrap();
+        let report = interpreter.report.as_ref().unwrap();
+        assert_eq!(report.calls, 6);
+        // The globals, the function's parameters and the block inside it.
+        assert_eq!(report.peak_environment_depth, 3);
+        assert!(report.allocations.contains(&("arrays", 6)));
+        assert!(report.allocations.contains(&("functions", 2)));
+
+        let mut interpreter = Interpreter::new();
+        interpreter.run(program).unwrap();
+        assert!(interpreter.report.is_none());
     }
 }
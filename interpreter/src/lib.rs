@@ -1,21 +1,75 @@
+#![forbid(unsafe_code)]
+
 extern crate ast;
-use std::error::Error;
+use crate::{
+    native::{define_expect_snapshot, define_pure_natives},
+    replay::Tape,
+    snapshot::Snapshots,
+    stats::NodeCounts,
+};
+use std::{
+    error::Error,
+    io::{self, Write},
+    path::PathBuf,
+    rc::Rc,
+    sync::{atomic::AtomicBool, Arc},
+};
 
+mod array;
+mod bindings;
 mod callable;
 mod class;
+mod config;
 mod environment;
 mod error;
+mod format;
 mod function;
+#[cfg(feature = "net")]
+mod http;
+mod instance;
 mod interpreter;
+mod module;
 mod native;
+mod native_args;
+mod output;
+mod replay;
 mod resolver;
+mod set;
+mod snapshot;
+mod stats;
+mod string;
+mod suggest;
+mod traits;
 mod value;
 
+pub use crate::{
+    bindings::Bindings,
+    config::{
+        portable_path, Capabilities, ExecutionLimits, InterpreterConfig, LintConfig,
+        CONFIG_FILE_NAME,
+    },
+    environment::Environment,
+    error::{ConfigError, ResolverError, RuntimeError, Warning},
+    module::{FileLoader, MemoryLoader, ModuleLoader},
+    native_args::{check_native_arity, native_arg, NativeArg},
+    output::OutputEvent,
+    replay::{Input, Recording},
+    stats::RunReport,
+    string::LoxString,
+    value::Value,
+};
+
 // Public interface
 pub struct Interpreter {
     internal: interpreter::Interpreter,
 }
 
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Interpreter {
     pub fn new() -> Interpreter {
         Interpreter {
@@ -23,7 +77,181 @@ impl Interpreter {
         }
     }
 
-    pub fn run(&mut self, program: Vec<ast::Stmt>) -> Result<(), Vec<Box<Error>>> {
+    pub fn with_config(config: InterpreterConfig) -> Interpreter {
+        Interpreter {
+            internal: interpreter::Interpreter::with_config(config),
+        }
+    }
+
+    /// An interpreter for untrusted expressions that must not have side
+    /// effects, such as config values. Its globals are only the natives
+    /// that read nothing from outside and change nothing, output is
+    /// discarded and the sandbox limits apply. It doesn't stop a program
+    /// from declaring or assigning variables; see `rlox::evaluate_pure`.
+    pub fn pure() -> Interpreter {
+        let mut internal = interpreter::Interpreter::with_config(InterpreterConfig::sandbox());
+        let globals = Environment::new(None);
+        define_pure_natives(&globals);
+        internal.environment = Some(Rc::new(globals));
+        internal.output = Box::new(io::sink());
+        Interpreter { internal }
+    }
+
+    /// Sends `print` output somewhere other than stdout.
+    pub fn with_output(mut self, output: impl Write + 'static) -> Interpreter {
+        self.internal.output = Box::new(output);
+        self
+    }
+
+    /// Hands what the program prints, and its warnings, errors and traces,
+    /// to `on_output` as they happen instead of writing them out, so a host
+    /// can show each kind its own way. Warnings given to it aren't kept
+    /// for `take_warnings`, and errors are still returned by `run`.
+    pub fn with_output_callback(
+        mut self,
+        on_output: impl FnMut(OutputEvent) -> io::Result<()> + 'static,
+    ) -> Interpreter {
+        self.internal.on_output = Some(Box::new(on_output));
+        self
+    }
+
+    /// Colors each value `print` shows by its type, for a terminal.
+    pub fn with_color(mut self) -> Interpreter {
+        self.internal.color = true;
+        self
+    }
+
+    /// Records what natives read from outside the program, such as the
+    /// time, so the run can be replayed. See `recording`.
+    pub fn with_recording(self) -> Interpreter {
+        *self.internal.tape.borrow_mut() = Tape::Recording(vec![]);
+        self
+    }
+
+    /// Gives natives the inputs in `recording`, in order, instead of
+    /// reading them afresh. A native that reads more than was recorded, or
+    /// in a different order, fails with a runtime error.
+    pub fn with_replay(self, recording: Recording) -> Interpreter {
+        *self.internal.tape.borrow_mut() = Tape::Replaying(recording.inputs.into());
+        self
+    }
+
+    /// What's been recorded so far, when recording.
+    pub fn recording(&self) -> Option<Recording> {
+        match &*self.internal.tape.borrow() {
+            Tape::Recording(inputs) => Some(Recording {
+                inputs: inputs.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Defines `expect_snapshot(name, value)`, for golden tests. It compares
+    /// the value, as `print` shows it, with the file `<name>.snap` in `dir`
+    /// and fails with a runtime error if they differ or there's no such
+    /// file. With `update`, it writes the value to the file instead.
+    pub fn with_snapshots(self, dir: impl Into<PathBuf>, update: bool) -> Interpreter {
+        if let Some(globals) = &self.internal.environment {
+            define_expect_snapshot(globals, Snapshots::new(dir.into(), update));
+        }
+        self
+    }
+
+    /// A flag that stops the running program when set, from another thread
+    /// or a signal handler. The program stops at its next loop iteration or
+    /// call with an "Interrupted." runtime error listing the calls in
+    /// progress, and the flag is cleared so the interpreter can run again.
+    pub fn cancellation(&self) -> Arc<AtomicBool> {
+        self.internal.cancelled.clone()
+    }
+
+    /// How many times each kind of statement and expression has run, most
+    /// often first, when the config's `count_nodes` is set. The counts add
+    /// up over every run of this interpreter.
+    pub fn node_counts(&self) -> Option<Vec<(&'static str, u64)>> {
+        self.internal.node_counts.as_ref().map(NodeCounts::sorted)
+    }
+
+    /// The environments in scope where the last run's runtime error was
+    /// raised, innermost first and at most the config's
+    /// `dump_env_on_error` of them, as each name and its value shown the
+    /// way it would be written. `None` if the last run had no runtime error
+    /// or the config doesn't ask for them. Scopes that define nothing but
+    /// natives are left out.
+    pub fn error_environments(&self) -> Option<&[Vec<(String, String)>]> {
+        self.internal.error_environments.as_deref()
+    }
+
+    /// How long the runs so far took, how many calls they made, how deep
+    /// their scopes nested and how many objects they created, when the
+    /// config's `report` is set.
+    pub fn report(&self) -> Option<RunReport> {
+        self.internal.report.clone()
+    }
+
+    /// Warnings from the runs so far that haven't been taken yet. They don't
+    /// stop a run, so the host decides whether and where to show them.
+    pub fn take_warnings(&mut self) -> Vec<Warning> {
+        self.internal.warnings.drain(..).collect()
+    }
+
+    /// The environment the next run starts in, which holds the globals.
+    /// Pass it to `evaluate_expr` to evaluate against what's been run so
+    /// far.
+    pub fn environment(&self) -> Rc<Environment> {
+        match &self.internal.environment {
+            Some(environment) => environment.clone(),
+            None => Rc::new(Environment::new(None)),
+        }
+    }
+
+    pub fn config(&self) -> &InterpreterConfig {
+        &self.internal.config
+    }
+
+    /// Runs a program. The result is the value of a top-level `return` or
+    /// of a trailing expression statement, so scripts can compute a value
+    /// for the host.
+    pub fn run(&mut self, program: Vec<ast::Stmt>) -> Result<Option<Value>, Vec<Box<dyn Error>>> {
         self.internal.run(program)
     }
+
+    /// The first half of `run`: checks a program without running it. Its
+    /// warnings can then be taken, and shown, before the program prints
+    /// anything.
+    pub fn resolve(&mut self, program: &[ast::Stmt]) -> Result<(), Vec<Box<dyn Error>>> {
+        self.internal.resolve(program)
+    }
+
+    /// The second half of `run`: runs a program that `resolve` has accepted.
+    /// A program that hasn't been resolved looks up every name as a global.
+    pub fn run_resolved(
+        &mut self,
+        program: Vec<ast::Stmt>,
+    ) -> Result<Option<Value>, Vec<Box<dyn Error>>> {
+        self.internal.run_resolved(program)
+    }
+}
+
+/// Evaluates one expression against `env` and its enclosing environments,
+/// for tools such as watch windows and tests that have an environment at
+/// hand rather than a program. The expression isn't resolved first, so
+/// every name is looked up by name, innermost environment first. It runs
+/// with the default config, and only sees the natives `env` can reach.
+///
+/// ```
+/// use interpreter::{evaluate_expr, Interpreter, Value};
+///
+/// let mut interpreter = Interpreter::new();
+/// interpreter.run(parser::parse("var x = 20;".to_string()).unwrap()).unwrap();
+/// let expr = parser::parse_expression("x + len([1, 2])".to_string()).unwrap();
+/// assert_eq!(
+///     evaluate_expr(&expr, &interpreter.environment()).unwrap(),
+///     Value::Number(22.0)
+/// );
+/// ```
+pub fn evaluate_expr(expr: &ast::Expr, env: &Rc<Environment>) -> Result<Value, RuntimeError> {
+    let mut internal = interpreter::Interpreter::new();
+    let value = internal.evaluate_in(expr, env.clone())?;
+    Ok(value.unwrap_or(Value::Nil))
 }
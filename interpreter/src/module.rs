@@ -0,0 +1,309 @@
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+    ptr,
+};
+
+/// Finds modules by name, such as `util/strings`, and reads their source,
+/// so imports work where there's no filesystem, as in the browser, or where
+/// the host keeps modules itself.
+pub trait ModuleLoader: fmt::Debug {
+    /// Where the module called `name` is, such as the path of its file.
+    /// Names that mean the same module resolve to the same place, so it's
+    /// what a module is known by.
+    ///
+    /// A name starting with `./` or `../` is relative to `from`, the
+    /// location of the module that names it, or to where the program
+    /// started without one. Any other name is package-style and is looked
+    /// for in the loader's search paths, where being found in more than
+    /// one is an error rather than a guess.
+    fn resolve(&self, name: &str, from: Option<&str>) -> Result<String, String>;
+
+    /// The source of the module `resolve` found at `location`.
+    fn load(&self, location: &str) -> Result<String, String>;
+}
+
+// Loaders are equal when they're the same loader, so configs holding one
+// can still be compared.
+impl PartialEq for dyn ModuleLoader {
+    fn eq(&self, other: &dyn ModuleLoader) -> bool {
+        ptr::eq(
+            self as *const dyn ModuleLoader as *const u8,
+            other as *const dyn ModuleLoader as *const u8,
+        )
+    }
+}
+
+/// The parts of a module name, such as `["util", "strings"]` for
+/// `util/strings`. Either `/` or `\` separates them. A name with an empty,
+/// `.` or `..` part has none, so it can't reach outside where modules are
+/// kept.
+fn module_name_parts(name: &str) -> Option<Vec<&str>> {
+    let parts: Vec<&str> = name.split(['/', '\\']).collect();
+    if parts.iter().any(|part| matches!(*part, "" | "." | "..")) {
+        None
+    } else {
+        Some(parts)
+    }
+}
+
+/// Whether `name` is relative to the module that names it, like
+/// `./strings` or `../util/strings`, rather than package-style.
+fn is_relative(name: &str) -> bool {
+    ["./", ".\\", "../", "..\\"]
+        .iter()
+        .any(|prefix| name.starts_with(prefix))
+}
+
+/// The parts of a relative name, such as `["..", "util", "strings"]` for
+/// `../util/strings`: some `..` parts, then a module name.
+fn relative_name_parts(name: &str) -> Option<Vec<&str>> {
+    let parts: Vec<&str> = name
+        .split(['/', '\\'])
+        .filter(|part| *part != ".")
+        .collect();
+    let ups = parts.iter().take_while(|part| **part == "..").count();
+    module_name_parts(&parts[ups..].join("/"))?;
+    Some(parts)
+}
+
+fn not_found(name: &str) -> String {
+    format!("Cannot find module '{}'.", name)
+}
+
+/// Loads a module called `util/strings` from the `util/strings.lox` under
+/// one of its module paths, and `./strings` from the `strings.lox` next to
+/// the module that names it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FileLoader {
+    module_paths: Vec<PathBuf>,
+}
+
+impl FileLoader {
+    pub fn new(module_paths: Vec<PathBuf>) -> FileLoader {
+        FileLoader { module_paths }
+    }
+
+    /// The files a module called `name` could be loaded from, in search
+    /// order.
+    pub fn candidates(&self, name: &str) -> Vec<PathBuf> {
+        let mut parts = match module_name_parts(name) {
+            Some(parts) => parts,
+            None => return vec![],
+        };
+        let file = format!("{}.lox", parts.pop().unwrap_or_default());
+        let relative: PathBuf = parts.iter().collect::<PathBuf>().join(file);
+        self.module_paths
+            .iter()
+            .map(|dir| dir.join(&relative))
+            .collect()
+    }
+}
+
+impl ModuleLoader for FileLoader {
+    fn resolve(&self, name: &str, from: Option<&str>) -> Result<String, String> {
+        if is_relative(name) {
+            let dir = from
+                .and_then(|from| Path::new(from).parent())
+                .unwrap_or_else(|| Path::new("."));
+            let parts = relative_name_parts(name).ok_or_else(|| not_found(name))?;
+            let path = dir.join(format!("{}.lox", parts.join("/")));
+            return if path.is_file() {
+                Ok(path.display().to_string())
+            } else {
+                Err(not_found(name))
+            };
+        }
+        let found: Vec<PathBuf> = self
+            .candidates(name)
+            .into_iter()
+            .filter(|path| path.is_file())
+            .collect();
+        match found.as_slice() {
+            [] => Err(not_found(name)),
+            [path] => Ok(path.display().to_string()),
+            paths => {
+                let paths: Vec<String> = paths.iter().map(|p| p.display().to_string()).collect();
+                Err(format!(
+                    "Module '{}' is in more than one module path: {}.",
+                    name,
+                    paths.join(", ")
+                ))
+            }
+        }
+    }
+
+    fn load(&self, location: &str) -> Result<String, String> {
+        fs::read_to_string(location)
+            .map(|source| source.replace("\r\n", "\n"))
+            .map_err(|e| format!("{}: {}", location, e))
+    }
+}
+
+/// Loads modules from sources the host gives it, by name, for tests and
+/// embedding.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MemoryLoader {
+    modules: HashMap<String, String>,
+}
+
+impl MemoryLoader {
+    pub fn new() -> MemoryLoader {
+        MemoryLoader::default()
+    }
+
+    /// Adds the module called `name`, replacing any module of that name.
+    pub fn with_module(mut self, name: &str, source: impl Into<String>) -> MemoryLoader {
+        if let Some(parts) = module_name_parts(name) {
+            self.modules.insert(parts.join("/"), source.into());
+        }
+        self
+    }
+}
+
+impl ModuleLoader for MemoryLoader {
+    fn resolve(&self, name: &str, from: Option<&str>) -> Result<String, String> {
+        let location = if is_relative(name) {
+            let mut location: Vec<&str> = from.map_or(vec![], |from| from.split('/').collect());
+            location.pop();
+            for part in relative_name_parts(name).ok_or_else(|| not_found(name))? {
+                if part == ".." {
+                    location.pop().ok_or_else(|| not_found(name))?;
+                } else {
+                    location.push(part);
+                }
+            }
+            location.join("/")
+        } else {
+            module_name_parts(name)
+                .ok_or_else(|| not_found(name))?
+                .join("/")
+        };
+        if self.modules.contains_key(&location) {
+            Ok(location)
+        } else {
+            Err(not_found(name))
+        }
+    }
+
+    fn load(&self, location: &str) -> Result<String, String> {
+        self.modules
+            .get(location)
+            .cloned()
+            .ok_or_else(|| not_found(location))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    #[test]
+    fn memory_loader() {
+        let loader = MemoryLoader::new()
+            .with_module("util/strings", "var x = 1;")
+            .with_module("util/numbers", "var y = 2;")
+            .with_module("main", "var z = 3;")
+            .with_module("../escape", "var w = 4;");
+        assert_eq!(
+            loader.resolve("util\\strings", None),
+            Ok("util/strings".to_string())
+        );
+        assert_eq!(loader.load("util/strings"), Ok("var x = 1;".to_string()));
+        assert_eq!(
+            loader.resolve("./numbers", Some("util/strings")),
+            Ok("util/numbers".to_string())
+        );
+        assert_eq!(
+            loader.resolve("../main", Some("util/strings")),
+            Ok("main".to_string())
+        );
+        assert_eq!(
+            loader.resolve("./util/strings", None),
+            Ok("util/strings".to_string())
+        );
+        for (name, from) in &[
+            ("../escape", None),
+            ("../../main", Some("util/strings")),
+            ("./numbers", Some("main")),
+            ("util/", None),
+        ] {
+            assert_eq!(
+                loader.resolve(name, *from),
+                Err(format!("Cannot find module '{}'.", name))
+            );
+        }
+        assert_eq!(
+            loader.load("util/lists"),
+            Err("Cannot find module 'util/lists'.".to_string())
+        );
+    }
+
+    #[test]
+    fn file_loader() {
+        let dir = std::env::temp_dir().join(format!("rlox-loader-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (path, source) in &[
+            ("first/util/strings.lox", "var x = 1;\r\n"),
+            ("first/util/numbers.lox", ""),
+            ("second/util/numbers.lox", ""),
+            ("first/main.lox", ""),
+        ] {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        }
+        let loader = FileLoader::new(vec![
+            dir.join("missing"),
+            dir.join("first"),
+            dir.join("second"),
+        ]);
+
+        let location = loader.resolve("util/strings", None).unwrap();
+        assert_eq!(
+            PathBuf::from(&location),
+            dir.join("first").join("util").join("strings.lox")
+        );
+        assert_eq!(loader.load(&location), Ok("var x = 1;\n".to_string()));
+        assert_eq!(
+            loader.resolve("util/lists", None),
+            Err("Cannot find module 'util/lists'.".to_string())
+        );
+        assert_eq!(
+            loader.resolve("util/numbers", None),
+            Err(format!(
+                "Module 'util/numbers' is in more than one module path: {}, {}.",
+                dir.join("first").join("util").join("numbers.lox").display(),
+                dir.join("second")
+                    .join("util")
+                    .join("numbers.lox")
+                    .display()
+            ))
+        );
+
+        let main = loader
+            .resolve("../main", Some(&location))
+            .map(PathBuf::from)
+            .unwrap();
+        assert_eq!(
+            main.canonicalize().unwrap(),
+            dir.join("first").join("main.lox").canonicalize().unwrap()
+        );
+        assert!(loader.resolve("./main", Some(&location)).is_err());
+        assert!(loader
+            .load(&dir.join("nope.lox").display().to_string())
+            .is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn loaders_compare_by_identity() {
+        let a: Rc<dyn ModuleLoader> = Rc::new(MemoryLoader::new());
+        let b: Rc<dyn ModuleLoader> = Rc::new(MemoryLoader::new());
+        assert!(a == a.clone());
+        assert!(a != b);
+    }
+}
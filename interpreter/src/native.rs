@@ -1,16 +1,499 @@
-use crate::{environment::Environment, function::NativeFunction, value::Value};
+#[cfg(feature = "net")]
+use crate::http;
+use crate::{
+  array::{check_index, LoxArray},
+  callable::Callable,
+  class::LoxClass,
+  config::Capabilities,
+  environment::Environment,
+  error::runtime_error_result,
+  function::{NativeFn, NativeFunction, ReentrantFn},
+  instance::LoxInstance,
+  interpreter::{Interpreter, InterpreterResult},
+  native_args,
+  replay::{Input, SharedTape, Tape},
+  set::LoxSet,
+  snapshot::Snapshots,
+  stats,
+  value::Value,
+};
+use ast::token::Token;
+#[cfg(feature = "net")]
+use std::time::Duration;
 use std::{
+  cell::RefCell,
+  collections::HashMap,
+  io::{self, Write},
+  mem,
   rc::Rc,
   time::{SystemTime, UNIX_EPOCH},
 };
 
-pub fn define_native_functions(environment: &mut Environment) {
-  let clock_fun = NativeFunction::new("clock".to_string(), 0, Rc::new(clock));
-  environment.define(clock_fun.name.clone(), Value::NativeFunction(clock_fun));
+/// The namespaces natives are grouped in, such as `str.ord`, and the natives
+/// in each.
+const NAMESPACES: [(&str, &[&str]); 7] = [
+  ("time", &["clock"]),
+  ("net", &["http_get"]),
+  ("str", &["chars", "ord", "chr"]),
+  (
+    "collections",
+    &["len", "set", "add", "has", "remove", "union", "intersect"],
+  ),
+  ("values", &["equals", "compare", "freeze", "is_frozen"]),
+  ("reflect", &["arity", "name", "methods", "delattr"]),
+  ("debug", &["memory_stats", "capture"]),
+];
+
+/// Natives that read from outside the program get their inputs from
+/// `tape`, so they can be recorded and replayed. Each native is defined in
+/// its namespace and, if `flat` is set, as a global of its own too.
+pub fn define_native_functions(
+  environment: &mut Environment,
+  capabilities: &Capabilities,
+  tape: &SharedTape,
+  flat: bool,
+) {
+  if capabilities.clock {
+    let tape = tape.clone();
+    define(environment, "clock", 0, Rc::new(move |_| clock(&tape)));
+  }
+  #[cfg(feature = "net")]
+  {
+    if capabilities.net {
+      define_reentrant(environment, "http_get", 1, Rc::new(http_get));
+    }
+  }
+  define_pure_natives(environment);
+  define(environment, "remove", 2, Rc::new(remove));
+  define(environment, "add", 2, Rc::new(add));
+  define(environment, "delattr", 2, Rc::new(delattr));
+  define(environment, "freeze", 1, Rc::new(freeze));
+  define(environment, "memory_stats", 0, Rc::new(memory_stats));
+  define_reentrant(environment, "capture", 1, Rc::new(capture));
+  define_namespaces(environment, flat);
+}
+
+/// Whether `value` is a native function or one of the namespaces they're
+/// grouped in.
+pub fn is_native(value: &Value) -> bool {
+  match value {
+    Value::NativeFunction(_) => true,
+    Value::Instance(instance) => {
+      instance.is_frozen()
+        && instance.class.method_names().is_empty()
+        && NAMESPACES
+          .iter()
+          .any(|(namespace, _)| *namespace == instance.class.name)
+    }
+    _ => false,
+  }
+}
+
+/// Gathers the natives defined in `environment` into read-only namespace
+/// instances, leaving out those the capabilities didn't define. Unless
+/// `flat` is set, the natives are then only reachable through them.
+fn define_namespaces(environment: &Environment, flat: bool) {
+  for (namespace, names) in NAMESPACES.iter() {
+    let instance = LoxInstance::new(LoxClass::new(namespace.to_string(), HashMap::new()));
+    for name in names.iter() {
+      if let Some(native) = environment.get(&name.to_string()) {
+        instance.set(name.to_string(), native);
+        if !flat {
+          environment.undefine(name);
+        }
+      }
+    }
+    instance.freeze();
+    environment.define(namespace.to_string(), Value::Instance(instance));
+  }
+}
+
+/// Defines the natives that neither read from outside the program nor
+/// change their arguments, so their result depends only on what they're
+/// given.
+pub fn define_pure_natives(environment: &Environment) {
+  define(environment, "arity", 1, Rc::new(arity));
+  define(environment, "name", 1, Rc::new(name));
+  define(environment, "methods", 1, Rc::new(methods));
+  define(environment, "len", 1, Rc::new(len));
+  define(environment, "set", 0, Rc::new(set));
+  define(environment, "has", 2, Rc::new(has));
+  define(environment, "union", 2, Rc::new(union));
+  define(environment, "intersect", 2, Rc::new(intersect));
+  define(environment, "is_frozen", 1, Rc::new(is_frozen));
+  define(environment, "chars", 1, Rc::new(chars));
+  define(environment, "ord", 1, Rc::new(ord));
+  define(environment, "chr", 1, Rc::new(chr));
+  define(environment, "equals", 2, Rc::new(equals));
+  define(environment, "compare", 2, Rc::new(compare));
+}
+
+/// Defines `expect_snapshot(name, value)`, which checks `value` as printed
+/// against `snapshots`.
+pub fn define_expect_snapshot(environment: &Environment, snapshots: Snapshots) {
+  define(
+    environment,
+    "expect_snapshot",
+    2,
+    Rc::new(move |args| expect_snapshot(&snapshots, args)),
+  );
+}
+
+fn define(environment: &Environment, name: &str, arity: usize, fun: Rc<NativeFn>) {
+  let native = NativeFunction::new(name.to_string(), arity, fun);
+  environment.define(native.name.clone(), Value::NativeFunction(native));
+}
+
+fn define_reentrant(environment: &Environment, name: &str, arity: usize, fun: Rc<ReentrantFn>) {
+  let native = NativeFunction::reentrant(name.to_string(), arity, fun);
+  environment.define(native.name.clone(), Value::NativeFunction(native));
+}
+
+fn clock(tape: &RefCell<Tape>) -> Result<Value, String> {
+  let input = tape.borrow_mut().input("clock", || {
+    // A clock set before 1970 reads as 0 rather than failing.
+    let since_the_epoch = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .unwrap_or_default();
+    Input::Clock {
+      millis: since_the_epoch.as_millis() as f64,
+    }
+  })?;
+  match input {
+    Input::Clock { millis } => Ok(Value::Number(millis)),
+    input => Err(format!(
+      "Expected a clock() input, found {}().",
+      input.native()
+    )),
+  }
+}
+
+fn expect_snapshot(snapshots: &Snapshots, args: Vec<Value>) -> Result<Value, String> {
+  native_args!("expect_snapshot", args => name: String, value: Value);
+  snapshots.check(&name, &value.print())?;
+  Ok(Value::Nil)
+}
+
+/// Number of arguments a function, native or class takes.
+fn arity(args: Vec<Value>) -> Result<Value, String> {
+  native_args!("arity", args => callable: Value);
+  let arity = match &callable {
+    Value::Function(fun) => fun.arity(),
+    Value::NativeFunction(fun) => fun.arity(),
+    Value::Class(class) => class.arity(),
+    value => {
+      return Err(format!(
+        "arity() expects a function or class, got {}.",
+        value
+      ))
+    }
+  };
+  Ok(Value::Number(arity as f64))
+}
+
+/// Declared name of a function, native, class or trait.
+fn name(args: Vec<Value>) -> Result<Value, String> {
+  native_args!("name", args => declared: Value);
+  let name = match &declared {
+    Value::Function(fun) => fun.declaration.name.lexeme.clone(),
+    Value::NativeFunction(fun) => fun.name.clone(),
+    Value::Class(class) => class.name.clone(),
+    Value::Trait(t) => t.name.clone(),
+    value => {
+      return Err(format!(
+        "name() expects a function, class or trait, got {}.",
+        value
+      ))
+    }
+  };
+  Ok(Value::from(name))
+}
+
+/// Number of methods a class has, including `init` and those mixed in from
+/// traits, or that a trait declares.
+fn methods(args: Vec<Value>) -> Result<Value, String> {
+  native_args!("methods", args => declared: Value);
+  match &declared {
+    Value::Class(class) => Ok(Value::Number(class.method_names().len() as f64)),
+    Value::Trait(t) => Ok(Value::Number(t.method_names().len() as f64)),
+    value => Err(format!(
+      "methods() expects a class or trait, got {}.",
+      value
+    )),
+  }
+}
+
+/// Length of an array or set, or of a string in characters (not bytes).
+fn len(args: Vec<Value>) -> Result<Value, String> {
+  native_args!("len", args => collection: Value);
+  match &collection {
+    Value::Array(array) => Ok(Value::Number(array.len() as f64)),
+    Value::Set(set) => Ok(Value::Number(set.len() as f64)),
+    Value::String(s) => Ok(Value::Number(s.chars().count() as f64)),
+    value => Err(format!(
+      "len() expects an array, set or string, got {}.",
+      value
+    )),
+  }
+}
+
+/// Whether two values are equal, exactly as `==` decides.
+fn equals(args: Vec<Value>) -> Result<Value, String> {
+  native_args!("equals", args => a: Value, b: Value);
+  Ok(Value::Boolean(a == b))
+}
+
+/// -1, 0 or 1 as the first value comes before, is equal to or comes after
+/// the second. Any two values can be compared, unlike with `<`; see
+/// `Value::total_cmp` for the order.
+fn compare(args: Vec<Value>) -> Result<Value, String> {
+  native_args!("compare", args => a: Value, b: Value);
+  let ordering = a.total_cmp(&b);
+  Ok(Value::Number(ordering as i8 as f64))
+}
+
+/// Removes and returns an array element, shifting later elements down. The
+/// index must be in range, as when indexing. For a set, removes a member and
+/// returns whether it was there.
+fn remove(args: Vec<Value>) -> Result<Value, String> {
+  native_args!("remove", args => collection: Value, key: Value);
+  collection.check_mutable()?;
+  match &collection {
+    Value::Array(array) => {
+      let index = check_index(&key, array.len())?;
+      Ok(array.remove(index).unwrap_or(Value::Nil))
+    }
+    Value::Set(set) => Ok(Value::Boolean(set.remove(&key))),
+    value => Err(format!("remove() expects an array or set, got {}.", value)),
+  }
+}
+
+/// A new, empty set.
+fn set(_args: Vec<Value>) -> Result<Value, String> {
+  Ok(Value::Set(LoxSet::new(vec![])))
 }
 
-fn clock(_args: Vec<Value>) -> Value {
-  let start = SystemTime::now();
-  let since_the_epoch = start.duration_since(UNIX_EPOCH).unwrap();
-  Value::Number(since_the_epoch.as_millis() as f64)
+/// Adds a member to a set, returning false if it was already there.
+fn add(args: Vec<Value>) -> Result<Value, String> {
+  native_args!("add", args => set: Value, member: Value);
+  set.check_mutable()?;
+  match set {
+    Value::Set(set) => Ok(Value::Boolean(set.add(member))),
+    value => Err(format!("add() expects a set, got {}.", value)),
+  }
+}
+
+fn has(args: Vec<Value>) -> Result<Value, String> {
+  native_args!("has", args => set: Value, member: Value);
+  match set {
+    Value::Set(set) => Ok(Value::Boolean(set.has(&member))),
+    value => Err(format!("has() expects a set, got {}.", value)),
+  }
+}
+
+/// A new set with the members of both sets.
+fn union(args: Vec<Value>) -> Result<Value, String> {
+  native_args!("union", args => a: Value, b: Value);
+  match (&a, &b) {
+    (Value::Set(a), Value::Set(b)) => Ok(Value::Set(a.union(b))),
+    (a, b) => Err(format!("union() expects two sets, got {} and {}.", a, b)),
+  }
+}
+
+/// A new set with the members the two sets have in common.
+fn intersect(args: Vec<Value>) -> Result<Value, String> {
+  native_args!("intersect", args => a: Value, b: Value);
+  match (&a, &b) {
+    (Value::Set(a), Value::Set(b)) => Ok(Value::Set(a.intersect(b))),
+    (a, b) => Err(format!(
+      "intersect() expects two sets, got {} and {}.",
+      a, b
+    )),
+  }
+}
+
+/// Removes a field from an instance. Returns whether the field was there, so
+/// deleting a missing field is not an error.
+fn delattr(args: Vec<Value>) -> Result<Value, String> {
+  native_args!("delattr", args => object: Value, field: String);
+  object.check_mutable()?;
+  match object {
+    Value::Instance(instance) => Ok(Value::Boolean(instance.remove(&field).is_some())),
+    object => Err(format!("delattr() expects an instance, got {}.", object)),
+  }
+}
+
+/// Stops an instance's fields, or an array's or set's members, from
+/// changing, and returns the value. Other values are left as they are.
+fn freeze(args: Vec<Value>) -> Result<Value, String> {
+  native_args!("freeze", args => value: Value);
+  value.freeze();
+  Ok(value)
+}
+
+fn is_frozen(args: Vec<Value>) -> Result<Value, String> {
+  native_args!("is_frozen", args => value: Value);
+  Ok(Value::Boolean(value.is_frozen()))
+}
+
+/// Splits a string into an array of one-character strings.
+fn chars(args: Vec<Value>) -> Result<Value, String> {
+  native_args!("chars", args => text: String);
+  let chars = text.chars().map(|c| Value::from(c.to_string())).collect();
+  Ok(Value::Array(LoxArray::new(chars)))
+}
+
+/// Unicode code point of a one-character string.
+fn ord(args: Vec<Value>) -> Result<Value, String> {
+  native_args!("ord", args => character: String);
+  let mut chars = character.chars();
+  match (chars.next(), chars.next()) {
+    (Some(c), None) => Ok(Value::Number(c as u32 as f64)),
+    _ => Err(format!(
+      "ord() expects a one-character string, got {}.",
+      Value::from(character)
+    )),
+  }
+}
+
+/// One-character string for a Unicode code point.
+fn chr(args: Vec<Value>) -> Result<Value, String> {
+  native_args!("chr", args => code: f64);
+  if code.fract() == 0.0 && code >= 0.0 && code <= u32::MAX as f64 {
+    if let Some(c) = std::char::from_u32(code as u32) {
+      return Ok(Value::from(c.to_string()));
+    }
+  }
+  Err(format!(
+    "chr() expects a code point, got {}.",
+    Value::Number(code)
+  ))
+}
+
+/// Counts of the live environments, functions, instances, arrays, sets and
+/// strings, as a `MemoryStats` instance with one field per kind. Counts
+/// that keep growing while a loop runs point at values kept alive by
+/// closure cycles.
+fn memory_stats(_args: Vec<Value>) -> Result<Value, String> {
+  // Read before creating the result, so it isn't counted.
+  let counts = stats::live_counts();
+  let result = LoxInstance::new(LoxClass::new("MemoryStats".to_string(), HashMap::new()));
+  for (name, count) in counts {
+    result.set(name.to_string(), Value::Number(count as f64));
+  }
+  Ok(Value::Instance(result))
+}
+
+/// Calls `callable` with what it prints going to a string instead, and
+/// returns the string, without colors. If the call fails, what it printed
+/// is dropped.
+fn capture(int: &mut Interpreter, paren: &Token, args: Vec<Value>) -> InterpreterResult {
+  let callable = match capture_args(args) {
+    Ok(callable) => callable,
+    Err(msg) => return runtime_error_result(paren, &msg),
+  };
+  let captured = Captured::default();
+  let output = mem::replace(&mut int.output, Box::new(captured.clone()));
+  let color = mem::replace(&mut int.color, false);
+  let on_output = int.on_output.take();
+  let result = int.call_value(paren, callable, vec![]);
+  int.output = output;
+  int.color = color;
+  int.on_output = on_output;
+  result?;
+  let printed = String::from_utf8_lossy(&captured.0.borrow()).into_owned();
+  Ok(Some(Value::from(printed)))
+}
+
+fn capture_args(args: Vec<Value>) -> Result<Value, String> {
+  native_args!("capture", args => callable: Value);
+  Ok(callable)
+}
+
+#[derive(Clone, Default)]
+struct Captured(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Captured {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    self.0.borrow_mut().extend_from_slice(buf);
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+/// Fetches a URL, returning a `Response` instance with `status`, `headers`
+/// (an instance with one field per lowercased header name) and `body`.
+/// Network failures are runtime errors. The request may take as long as
+/// the run has left under its time limit, and no longer; a request cut
+/// short by the limit fails with the limit's error.
+#[cfg(feature = "net")]
+fn http_get(int: &mut Interpreter, paren: &Token, args: Vec<Value>) -> InterpreterResult {
+  let timeout = int.time_left()?;
+  let response = fetch(&int.tape, args, timeout);
+  int.time_left()?;
+  match response {
+    Ok(response) => Ok(Some(response)),
+    Err(msg) => runtime_error_result(paren, &msg),
+  }
+}
+
+#[cfg(feature = "net")]
+fn fetch(
+  tape: &RefCell<Tape>,
+  args: Vec<Value>,
+  timeout: Option<Duration>,
+) -> Result<Value, String> {
+  native_args!("http_get", args => url: String);
+  let input = tape
+    .borrow_mut()
+    .input("http_get", || match http::get(&url, timeout) {
+      Ok(response) => Input::HttpGet {
+        url: url.clone(),
+        status: response.status,
+        headers: response.headers,
+        body: response.body,
+        error: None,
+      },
+      Err(error) => Input::HttpGet {
+        url: url.clone(),
+        status: 0,
+        headers: vec![],
+        body: String::new(),
+        error: Some(error),
+      },
+    })?;
+  let response = match input {
+    Input::HttpGet {
+      error: Some(error), ..
+    } => return Err(error),
+    Input::HttpGet {
+      status,
+      headers,
+      body,
+      ..
+    } => http::Response {
+      status,
+      headers,
+      body,
+    },
+    input => {
+      return Err(format!(
+        "Expected an http_get() input, found {}().",
+        input.native()
+      ))
+    }
+  };
+
+  let headers = LoxInstance::new(LoxClass::new("Headers".to_string(), HashMap::new()));
+  for (name, value) in response.headers {
+    headers.set(name, Value::from(value));
+  }
+  let result = LoxInstance::new(LoxClass::new("Response".to_string(), HashMap::new()));
+  result.set("status".to_string(), Value::Number(response.status as f64));
+  result.set("headers".to_string(), Value::Instance(headers));
+  result.set("body".to_string(), Value::from(response.body));
+  Ok(Value::Instance(result))
 }
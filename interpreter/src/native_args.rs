@@ -0,0 +1,137 @@
+use crate::value::Value;
+
+/// A type a native can take an argument as, with `native_args!`.
+pub trait NativeArg: Sized {
+    /// What an argument has to be to convert, such as "a number", for
+    /// errors.
+    const EXPECTED: &'static str;
+
+    /// The argument as `Self`, or back as it was when it's something else.
+    fn from_value(value: Value) -> Result<Self, Value>;
+}
+
+impl NativeArg for f64 {
+    const EXPECTED: &'static str = "a number";
+
+    fn from_value(value: Value) -> Result<f64, Value> {
+        match value {
+            Value::Number(n) => Ok(n),
+            value => Err(value),
+        }
+    }
+}
+
+impl NativeArg for bool {
+    const EXPECTED: &'static str = "a boolean";
+
+    fn from_value(value: Value) -> Result<bool, Value> {
+        match value {
+            Value::Boolean(b) => Ok(b),
+            value => Err(value),
+        }
+    }
+}
+
+impl NativeArg for String {
+    const EXPECTED: &'static str = "a string";
+
+    fn from_value(value: Value) -> Result<String, Value> {
+        match value {
+            Value::String(s) => Ok(s.into_string()),
+            value => Err(value),
+        }
+    }
+}
+
+/// The array's elements as they are now; later changes to the array
+/// aren't seen in the `Vec`.
+impl NativeArg for Vec<Value> {
+    const EXPECTED: &'static str = "an array";
+
+    fn from_value(value: Value) -> Result<Vec<Value>, Value> {
+        match value {
+            Value::Array(array) => Ok(array.elements()),
+            value => Err(value),
+        }
+    }
+}
+
+/// Any value, as it is.
+impl NativeArg for Value {
+    const EXPECTED: &'static str = "a value";
+
+    fn from_value(value: Value) -> Result<Value, Value> {
+        Ok(value)
+    }
+}
+
+/// Checks a native's arguments and binds each one, converted, to a
+/// variable named for its parameter. `native` is the native's name, for
+/// errors. The calling function returns a `String` error when there are
+/// too many or too few arguments, or one can't convert:
+///
+/// ```
+/// use interpreter::{native_args, Value};
+///
+/// fn repeat(args: Vec<Value>) -> Result<Value, String> {
+///     native_args!("repeat", args => text: String, times: f64);
+///     Ok(Value::from(text.repeat(times as usize)))
+/// }
+///
+/// assert_eq!(
+///     repeat(vec![Value::from("ab"), Value::from(2.0)]),
+///     Ok(Value::from("abab"))
+/// );
+/// assert_eq!(
+///     repeat(vec![Value::from("ab"), Value::Nil]),
+///     Err("repeat() expects 'times' to be a number, got nil.".to_string())
+/// );
+/// assert_eq!(
+///     repeat(vec![]),
+///     Err("repeat() expects 2 arguments, got 0.".to_string())
+/// );
+/// ```
+#[macro_export]
+macro_rules! native_args {
+    ($native:expr, $args:expr => $($name:ident: $ty:ty),+ $(,)?) => {
+        let args: ::std::vec::Vec<$crate::Value> = $args;
+        $crate::check_native_arity($native, 0 $(+ { let _ = stringify!($name); 1 })+, args.len())?;
+        let mut args = args.into_iter();
+        $(
+            let $name: $ty = $crate::native_arg($native, stringify!($name), args.next())?;
+        )+
+    };
+}
+
+#[doc(hidden)]
+pub fn check_native_arity(native: &str, expected: usize, got: usize) -> Result<(), String> {
+    if expected == got {
+        return Ok(());
+    }
+    let arguments = if expected == 1 {
+        "argument"
+    } else {
+        "arguments"
+    };
+    Err(format!(
+        "{}() expects {} {}, got {}.",
+        native, expected, arguments, got
+    ))
+}
+
+#[doc(hidden)]
+pub fn native_arg<T: NativeArg>(
+    native: &str,
+    param: &str,
+    value: Option<Value>,
+) -> Result<T, String> {
+    T::from_value(value.unwrap_or(Value::Nil)).map_err(|value| {
+        format!(
+            "{}() expects '{}' to be {}, got {}.",
+            native,
+            param,
+            T::EXPECTED,
+            value
+        )
+    })
+}
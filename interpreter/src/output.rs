@@ -0,0 +1,21 @@
+use crate::error::Warning;
+use std::{error::Error, io};
+
+/// Something a run has to show, for hosts that show each kind differently,
+/// such as a GUI that puts errors beside the program's output.
+#[derive(Debug)]
+pub enum OutputEvent<'a> {
+    /// A line the program printed, without its newline.
+    Print(&'a str),
+    /// A warning, as soon as it's found.
+    Warning(&'a Warning),
+    /// An error that stopped the run, or some of it; the run returns it
+    /// too.
+    Error(&'a (dyn Error + 'static)),
+    /// An expression and its value, when expression tracing is on.
+    Trace(&'a str),
+}
+
+/// Takes a run's output events. An error returned for a `Print` fails the
+/// print, as a failing writer would; for anything else it's ignored.
+pub type OutputCallback = Box<dyn FnMut(OutputEvent) -> io::Result<()>>;
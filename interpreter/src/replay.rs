@@ -0,0 +1,132 @@
+use serde::{Deserialize, Serialize};
+use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+/// What natives read from outside the program during a run, such as the
+/// time, in the order they read it. Replaying a recording feeds the same
+/// inputs back, so a time- or network-dependent run can be reproduced.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Recording {
+    pub inputs: Vec<Input>,
+}
+
+impl Recording {
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).unwrap_or_default()
+    }
+
+    pub fn from_toml(source: &str) -> Result<Recording, String> {
+        toml::from_str(source).map_err(|e| format!("Invalid recording: {}", e))
+    }
+}
+
+/// One value a native read, tagged with the native's name.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "native", rename_all = "snake_case")]
+pub enum Input {
+    Clock {
+        millis: f64,
+    },
+    /// A response, or the error that stopped the request.
+    HttpGet {
+        url: String,
+        #[serde(default)]
+        status: u16,
+        #[serde(default)]
+        headers: Vec<(String, String)>,
+        #[serde(default)]
+        body: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+}
+
+impl Input {
+    pub fn native(&self) -> &'static str {
+        match self {
+            Input::Clock { .. } => "clock",
+            Input::HttpGet { .. } => "http_get",
+        }
+    }
+}
+
+/// Where natives that read from outside get their inputs. The natives and
+/// the interpreter share it, so recording or replay can be turned on after
+/// the natives are defined.
+#[derive(Debug)]
+pub enum Tape {
+    Live,
+    Recording(Vec<Input>),
+    Replaying(VecDeque<Input>),
+}
+
+pub type SharedTape = Rc<RefCell<Tape>>;
+
+impl Tape {
+    /// Reads an input with `live`, keeping a copy when recording, or takes
+    /// the next recorded one when replaying.
+    pub fn input(&mut self, native: &str, live: impl FnOnce() -> Input) -> Result<Input, String> {
+        match self {
+            Tape::Live => Ok(live()),
+            Tape::Recording(inputs) => {
+                let input = live();
+                inputs.push(input.clone());
+                Ok(input)
+            }
+            Tape::Replaying(inputs) => match inputs.pop_front() {
+                Some(input) if input.native() == native => Ok(input),
+                Some(input) => Err(format!(
+                    "The recording has a call to {}() here, not {}().",
+                    input.native(),
+                    native
+                )),
+                None => Err(format!(
+                    "The recording has no more inputs, but {}() was called.",
+                    native
+                )),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toml_round_trip() {
+        let recording = Recording {
+            inputs: vec![
+                Input::Clock { millis: 1.5e12 },
+                Input::HttpGet {
+                    url: "http://example.com/".to_string(),
+                    status: 200,
+                    headers: vec![("content-type".to_string(), "text/plain".to_string())],
+                    body: "hi".to_string(),
+                    error: None,
+                },
+                Input::HttpGet {
+                    url: "http://nowhere/".to_string(),
+                    status: 0,
+                    headers: vec![],
+                    body: String::new(),
+                    error: Some("Cannot resolve 'nowhere'.".to_string()),
+                },
+            ],
+        };
+        assert_eq!(Recording::from_toml(&recording.to_toml()), Ok(recording));
+        assert!(Recording::from_toml("[[inputs]]\nnative = \"random\"").is_err());
+    }
+
+    #[test]
+    fn replay_in_order() {
+        let mut tape = Tape::Replaying(vec![Input::Clock { millis: 7.0 }].into());
+        assert_eq!(
+            tape.input("clock", || panic!("read live")),
+            Ok(Input::Clock { millis: 7.0 })
+        );
+        assert_eq!(
+            tape.input("clock", || panic!("read live")),
+            Err("The recording has no more inputs, but clock() was called.".to_string())
+        );
+    }
+}
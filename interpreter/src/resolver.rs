@@ -1,29 +1,109 @@
-use crate::error::ResolverError;
-use ast::{token::Token, visitor::Visitor, Expr, FunStmt, ScopeId, Stmt};
+use crate::{
+    bindings::Bindings,
+    config::LintConfig,
+    error::{ResolverError, Warning},
+};
+use ast::{
+    token::{Position, Token},
+    version::LanguageVersion,
+    visitor::Visitor,
+    Expr, FunStmt, ScopeId, Stmt,
+};
 use std::collections::HashMap;
 
-type Scope = HashMap<String, bool>;
+type Scope = HashMap<String, Local>;
 type ResolverResult = Result<(), ResolverError>;
+
+/// How deep the resolver will walk into the AST. The parser already limits
+/// nesting, but left-associative chains like `1 + 1 + ... + 1` are parsed
+/// in a loop and still build a deep tree. The resolver and the interpreter
+/// both recurse through it, so a tree that would overflow the stack is
+/// rejected here, before it runs.
+const MAX_DEPTH: usize = 512;
+
+/// `if (x = 1)` and friends, which are usually a typo for `==`.
+pub const ASSIGN_IN_CONDITION: &str = "assign-in-condition";
+
+/// Constructs that the language version in use deprecates.
+pub const DEPRECATED: &str = "deprecated";
+
+/// A top-level declaration with the name of a native function, such as
+/// `clock`, which hides the native from the whole program. Locals and
+/// parameters only hide it where they're visible, so they're left alone.
+/// Natives such as `name` and `add` are common names, so this stays a
+/// warning in strict mode unless it's denied.
+pub const SHADOW_NATIVE: &str = "shadow-native";
+
+struct Local {
+    defined: bool,
+    // Where the name was declared in `Resolver::bindings`, when recording.
+    declaration: Option<usize>,
+}
+
+impl Local {
+    fn this() -> Local {
+        Local {
+            defined: true,
+            declaration: None,
+        }
+    }
+}
+#[derive(PartialEq)]
 enum FunType {
     Function,
+    Initializer,
+    Method,
+}
+
+enum ClassType {
+    Class,
+    Trait,
 }
 
 pub struct Resolver {
     current_fun: Option<FunType>,
+    current_class: Option<ClassType>,
+    // Inside a block expression, where `return` has nowhere to go.
+    in_block_expr: bool,
     scopes: Vec<Scope>,
+    depth: usize,
+    // Where the last token seen is, for errors on nodes without one.
+    position: Position,
     pub locals: HashMap<ScopeId, usize>,
+    pub trace: bool,
+    /// Turns warnings into errors unless their lint is allowed.
+    pub strict: bool,
+    pub lint: LintConfig,
+    /// Which constructs to warn are deprecated.
+    pub version: LanguageVersion,
+    /// Names of the native functions, to warn when a declaration hides one.
+    pub natives: Vec<String>,
+    pub warnings: Vec<Warning>,
+    /// Records every declaration and use of a name when set, for tooling.
+    pub bindings: Option<Bindings>,
 }
 
 impl Resolver {
     pub fn new() -> Resolver {
         Resolver {
             current_fun: None,
+            current_class: None,
+            in_block_expr: false,
             scopes: vec![],
+            depth: 0,
+            position: Position::default(),
             locals: HashMap::new(),
+            trace: false,
+            strict: false,
+            lint: LintConfig::default(),
+            version: LanguageVersion::default(),
+            natives: vec![],
+            warnings: vec![],
+            bindings: None,
         }
     }
 
-    pub fn resolve(&mut self, stmts: &Vec<Stmt>) -> Result<(), Vec<ResolverError>> {
+    pub fn resolve(&mut self, stmts: &[Stmt]) -> Result<(), Vec<ResolverError>> {
         let mut errors = vec![];
         self.push_scope();
         for stmt in stmts {
@@ -34,7 +114,7 @@ impl Resolver {
         }
         self.pop_scope();
 
-        if errors.len() > 0 {
+        if !errors.is_empty() {
             Err(errors)
         } else {
             Ok(())
@@ -42,67 +122,218 @@ impl Resolver {
     }
 
     fn resolve_stmt(&mut self, stmt: &Stmt) -> ResolverResult {
-        self.visit_stmt(stmt)
+        if let Some(position) = stmt.position() {
+            self.position = position;
+        }
+        self.enter()?;
+        let result = self.visit_stmt(stmt);
+        self.depth -= 1;
+        result
     }
 
     fn resolve_expr(&mut self, expr: &Expr) -> ResolverResult {
-        self.visit_expr(expr)
+        if let Some(position) = expr.position() {
+            self.position = position;
+        }
+        self.enter()?;
+        let result = self.visit_expr(expr);
+        self.depth -= 1;
+        result
+    }
+
+    fn enter(&mut self) -> ResolverResult {
+        if self.depth >= MAX_DEPTH {
+            return Err(ResolverError::new(
+                self.position,
+                "Program is nested too deeply.".to_string(),
+            ));
+        }
+        self.depth += 1;
+        Ok(())
     }
 
     fn resolve_function(&mut self, function: &FunStmt, fun_type: FunType) -> ResolverResult {
+        // A named function's name is declared in the scope around it.
+        let declaration = match fun_type {
+            FunType::Function => self
+                .scopes
+                .last()
+                .and_then(|scope| scope.get(&function.name.lexeme))
+                .and_then(|local| local.declaration),
+            _ => None,
+        };
+        if let Some(bindings) = self.bindings.as_mut() {
+            bindings.function(declaration, &function.parameters);
+        }
         let enclosing_fun = self.current_fun.take();
+        let enclosing_block_expr = self.in_block_expr;
         self.current_fun = Some(fun_type);
+        self.in_block_expr = false;
         self.push_scope();
-        for param in &function.parameters {
-            self.declare(&param)?;
-            self.define(&param);
+        for param in function.parameters.iter().chain(&function.rest) {
+            self.declare(param)?;
+            self.define(param);
         }
         for statement in &function.body {
-            self.resolve_stmt(&statement)?;
+            self.resolve_stmt(statement)?;
         }
         self.pop_scope();
         self.current_fun = enclosing_fun;
+        self.in_block_expr = enclosing_block_expr;
         Ok(())
     }
 
+    fn warn(&mut self, warning: Warning) -> ResolverResult {
+        let lint = warning.lint.to_string();
+        if self.lint.allow.contains(&lint) {
+            Ok(())
+        } else if (self.strict && warning.lint != SHADOW_NATIVE) || self.lint.deny.contains(&lint) {
+            Err(warning.into_error())
+        } else {
+            self.warnings.push(warning);
+            Ok(())
+        }
+    }
+
+    /// Warns about an assignment used directly as an `if` or `while`
+    /// condition. Wrapping it in parentheses says it's intended.
+    fn check_condition(&mut self, condition: &Expr) -> ResolverResult {
+        let name = match condition {
+            Expr::Assign(assign_expr) => &assign_expr.name,
+            Expr::Set(set_expr) => &set_expr.name,
+            _ => return Ok(()),
+        };
+        self.warn(Warning::new(
+            ASSIGN_IN_CONDITION,
+            name.position(),
+            "Assignment used as a condition; did you mean '=='?".to_string(),
+        ))
+    }
+
     fn push_scope(&mut self) {
         self.scopes.push(HashMap::new());
+        log::trace!("push scope {}", self.scopes.len());
+        if self.trace {
+            eprintln!("[resolver] push scope {}", self.scopes.len());
+        }
     }
 
     fn pop_scope(&mut self) {
+        log::trace!("pop scope {}", self.scopes.len());
+        if self.trace {
+            eprintln!("[resolver] pop scope {}", self.scopes.len());
+        }
         self.scopes.pop();
     }
 
     fn declare(&mut self, token: &Token) -> ResolverResult {
+        let top_level = self.scopes.len() == 1;
+        if top_level && self.natives.contains(&token.lexeme) {
+            self.warn(Warning::new(
+                SHADOW_NATIVE,
+                token.position(),
+                format!("'{}' hides the native function of that name.", token.lexeme),
+            ))?;
+        }
         if let Some(scope) = self.scopes.last_mut() {
             if scope.contains_key(&token.lexeme) {
                 return Err(ResolverError::new(
-                    token.line,
+                    token.position(),
                     "Variable with this name already declared in this scope.".to_string(),
                 ));
             }
-            scope.insert(token.lexeme.clone(), false);
+            let declaration = self
+                .bindings
+                .as_mut()
+                .map(|bindings| bindings.declare(token, top_level));
+            scope.insert(
+                token.lexeme.clone(),
+                Local {
+                    defined: false,
+                    declaration,
+                },
+            );
         }
         Ok(())
     }
 
     fn define(&mut self, token: &Token) {
-        if let Some(scope) = self.scopes.last_mut() {
-            scope.insert(token.lexeme.clone(), true);
+        if let Some(local) = self
+            .scopes
+            .last_mut()
+            .and_then(|scope| scope.get_mut(&token.lexeme))
+        {
+            local.defined = true;
         }
     }
 
-    fn resolve_local(&mut self, scope_id: ScopeId, name: &String) {
+    fn resolve_local(&mut self, scope_id: ScopeId, name: &Token) {
         let mut i = (self.scopes.len() - 1) as isize;
         while i >= 0 {
             let index = i as usize;
-            if self.scopes[index].contains_key(name) {
-                self.locals.insert(scope_id, self.scopes.len() - 1 - index);
+            if let Some(local) = self.scopes[index].get(&name.lexeme) {
+                if let Some(bindings) = self.bindings.as_mut() {
+                    bindings.refer(name, local.declaration);
+                }
+                let depth = self.scopes.len() - 1 - index;
+                if self.trace {
+                    eprintln!(
+                        "[resolver] [line {}] '{}' -> depth {} (scope {})",
+                        name.line,
+                        name.lexeme,
+                        depth,
+                        index + 1
+                    );
+                }
+                self.locals.insert(scope_id, depth);
                 return;
             }
             i -= 1;
         }
+        if let Some(bindings) = self.bindings.as_mut() {
+            bindings.refer(name, None);
+        }
+        if self.trace {
+            eprintln!(
+                "[resolver] [line {}] '{}' -> global",
+                name.line, name.lexeme
+            );
+        }
+    }
+}
+
+/// Rejects a class or trait body that declares the same name twice, where
+/// the second method would silently replace the first, or a field would
+/// hide a method.
+fn check_duplicate_members<'a>(
+    members: impl Iterator<Item = (&'static str, &'a Token)>,
+) -> ResolverResult {
+    let mut seen: HashMap<&str, (&str, usize)> = HashMap::new();
+    for (kind, name) in members {
+        if let Some((first_kind, first)) = seen.insert(&name.lexeme, (kind, name.line)) {
+            let msg = if first_kind == kind {
+                format!(
+                    "{} '{}' is declared twice, at lines {} and {}.",
+                    kind, name.lexeme, first, name.line
+                )
+            } else {
+                format!(
+                    "'{}' is declared as both a {}, at line {}, and a {}, at line {}.",
+                    name.lexeme,
+                    first_kind.to_lowercase(),
+                    first,
+                    kind.to_lowercase(),
+                    name.line
+                )
+            };
+            return Err(ResolverError::new(name.position(), msg));
+        }
     }
+    Ok(())
+}
+
+fn methods(methods: &[FunStmt]) -> impl Iterator<Item = (&'static str, &Token)> {
+    methods.iter().map(|method| ("Method", &method.name))
 }
 
 impl Visitor<ResolverResult> for Resolver {
@@ -111,13 +342,50 @@ impl Visitor<ResolverResult> for Resolver {
             Stmt::Block(block_stmt) => {
                 self.push_scope();
                 for statement in &block_stmt.statements {
-                    self.resolve_stmt(&statement)?;
+                    self.resolve_stmt(statement)?;
                 }
                 self.pop_scope();
             }
             Stmt::Class(class_stmt) => {
                 self.declare(&class_stmt.name)?;
                 self.define(&class_stmt.name);
+
+                let fields = class_stmt.fields.iter().map(|field| ("Field", &field.name));
+                check_duplicate_members(fields.chain(methods(&class_stmt.methods)))?;
+                for t in &class_stmt.traits {
+                    self.resolve_expr(t)?;
+                }
+
+                let enclosing_class = self.current_class.replace(ClassType::Class);
+                self.push_scope();
+                if let Some(scope) = self.scopes.last_mut() {
+                    scope.insert("this".to_string(), Local::this());
+                }
+                // Field initializers run with `this` bound to the new
+                // instance, in the same scope as the methods' `this`.
+                let mut result = Ok(());
+                for field in &class_stmt.fields {
+                    if let Some(ref initializer) = field.initializer {
+                        result = self.resolve_expr(initializer);
+                        if result.is_err() {
+                            break;
+                        }
+                    }
+                }
+                for method in &class_stmt.methods {
+                    if result.is_err() {
+                        break;
+                    }
+                    let fun_type = if method.name.lexeme == "init" {
+                        FunType::Initializer
+                    } else {
+                        FunType::Method
+                    };
+                    result = self.resolve_function(method, fun_type);
+                }
+                self.pop_scope();
+                self.current_class = enclosing_class;
+                result?;
             }
             Stmt::Expr(expr_stmt) => self.resolve_expr(&expr_stmt.expression)?,
             Stmt::Fun(fun_stmt) => {
@@ -126,29 +394,89 @@ impl Visitor<ResolverResult> for Resolver {
                 self.resolve_function(fun_stmt, FunType::Function)?;
             }
             Stmt::If(if_stmt) => {
+                self.check_condition(&if_stmt.condition)?;
                 self.resolve_expr(&if_stmt.condition)?;
                 self.resolve_stmt(&if_stmt.then_branch)?;
                 if let Some(else_branch) = &if_stmt.else_branch {
                     self.resolve_stmt(else_branch)?;
                 }
             }
-            Stmt::Print(print_stmt) => self.resolve_expr(&print_stmt.expression)?,
+            Stmt::Print(print_stmt) => {
+                if self.version >= LanguageVersion::V3 {
+                    self.warn(Warning::new(
+                        DEPRECATED,
+                        print_stmt.keyword.position(),
+                        format!(
+                            "The print statement is deprecated in language version {}.",
+                            self.version
+                        ),
+                    ))?;
+                }
+                for expr in &print_stmt.expressions {
+                    self.resolve_expr(expr)?;
+                }
+            }
             Stmt::Return(return_stmt) => {
-                if let None = self.current_fun {
+                if self.in_block_expr {
                     return Err(ResolverError::new(
-                        return_stmt.keyword.line,
-                        "Cannot return from top-level code.".to_string(),
+                        return_stmt.keyword.position(),
+                        "Cannot return from inside a block expression.".to_string(),
                     ));
                 }
-
+                // A return at the top level ends the script and becomes the
+                // result of Interpreter::run.
                 if let Some(value) = &return_stmt.value {
+                    if self.current_fun == Some(FunType::Initializer) {
+                        return Err(ResolverError::new(
+                            return_stmt.keyword.position(),
+                            "Cannot return a value from an initializer.".to_string(),
+                        ));
+                    }
                     self.resolve_expr(value)?;
                 }
             }
             Stmt::While(while_stmt) => {
+                self.check_condition(&while_stmt.condition)?;
                 self.resolve_expr(&while_stmt.condition)?;
                 self.resolve_stmt(&while_stmt.body)?;
             }
+            Stmt::Trait(trait_stmt) => {
+                self.declare(&trait_stmt.name)?;
+                self.define(&trait_stmt.name);
+
+                check_duplicate_members(methods(&trait_stmt.methods))?;
+                if let Some(init) = trait_stmt.methods.iter().find(|m| m.name.lexeme == "init") {
+                    return Err(ResolverError::new(
+                        init.name.position(),
+                        "A trait cannot declare an initializer.".to_string(),
+                    ));
+                }
+
+                let enclosing_class = self.current_class.replace(ClassType::Trait);
+                self.push_scope();
+                if let Some(scope) = self.scopes.last_mut() {
+                    scope.insert("this".to_string(), Local::this());
+                }
+                let mut result = Ok(());
+                for method in &trait_stmt.methods {
+                    result = self.resolve_function(method, FunType::Method);
+                    if result.is_err() {
+                        break;
+                    }
+                }
+                self.pop_scope();
+                self.current_class = enclosing_class;
+                result?;
+            }
+            Stmt::Destructure(destructure_stmt) => {
+                for name in &destructure_stmt.names {
+                    self.declare(name)?;
+                }
+                self.resolve_expr(&destructure_stmt.initializer)?;
+                for name in &destructure_stmt.names {
+                    self.define(name);
+                }
+            }
             Stmt::Var(var_stmt) => {
                 self.declare(&var_stmt.name)?;
                 if let Some(initializer) = &var_stmt.initializer {
@@ -156,51 +484,264 @@ impl Visitor<ResolverResult> for Resolver {
                 }
                 self.define(&var_stmt.name);
             }
-            // _ => unimplemented!(),
+            _ => {
+                return Err(ResolverError::new(
+                    self.position,
+                    format!("Unsupported syntax: {}.", stmt.kind()),
+                ))
+            }
         }
         Ok(())
     }
 
     fn visit_expr(&mut self, expr: &Expr) -> ResolverResult {
         match expr {
+            Expr::Array(array_expr) => {
+                for element in &array_expr.elements {
+                    self.resolve_expr(element)?;
+                }
+            }
             Expr::Assign(assign_expr) => {
                 self.resolve_expr(&assign_expr.value)?;
-                self.resolve_local(assign_expr.scope_id, &assign_expr.name.lexeme);
+                self.resolve_local(assign_expr.scope_id, &assign_expr.name);
             }
             Expr::Binary(binary_expr) => {
                 self.resolve_expr(&binary_expr.left)?;
                 self.resolve_expr(&binary_expr.right)?;
             }
+            Expr::Block(block_expr) => {
+                let enclosing_block_expr = self.in_block_expr;
+                self.in_block_expr = true;
+                self.push_scope();
+                let mut result = Ok(());
+                for statement in &block_expr.statements {
+                    result = self.resolve_stmt(statement);
+                    if result.is_err() {
+                        break;
+                    }
+                }
+                if let (Ok(()), Some(value)) = (&result, &block_expr.value) {
+                    result = self.resolve_expr(value);
+                }
+                self.pop_scope();
+                self.in_block_expr = enclosing_block_expr;
+                result?;
+            }
             Expr::Call(call_expr) => {
                 self.resolve_expr(&call_expr.callee)?;
+                if let Some(bindings) = self.bindings.as_mut() {
+                    bindings.labels(
+                        call_expr.keyword_arguments.iter().map(|(label, _)| label),
+                        matches!(*call_expr.callee, Expr::Variable(_)),
+                    );
+                }
                 for arg in &call_expr.arguments {
                     self.resolve_expr(arg)?;
                 }
+                for (_, arg) in &call_expr.keyword_arguments {
+                    self.resolve_expr(arg)?;
+                }
+            }
+            Expr::Function(fun_expr) => {
+                // The name of a named function expression lives in a scope
+                // of its own, between the enclosing code and the parameters.
+                let name = fun_expr.name();
+                if let Some(name) = name {
+                    self.push_scope();
+                    self.declare(name)?;
+                    self.define(name);
+                }
+                let result = self.resolve_function(&fun_expr.declaration, FunType::Function);
+                if name.is_some() {
+                    self.pop_scope();
+                }
+                result?;
             }
+            Expr::Get(get_expr) => self.resolve_expr(&get_expr.object)?,
             Expr::Grouping(grouping_expr) => {
                 self.resolve_expr(&grouping_expr.expression)?;
             }
+            Expr::If(if_expr) => {
+                self.check_condition(&if_expr.condition)?;
+                self.resolve_expr(&if_expr.condition)?;
+                self.resolve_expr(&if_expr.then_branch)?;
+                if let Some(else_branch) = &if_expr.else_branch {
+                    self.resolve_expr(else_branch)?;
+                }
+            }
+            Expr::Index(index_expr) => {
+                self.resolve_expr(&index_expr.object)?;
+                self.resolve_expr(&index_expr.index)?;
+            }
             Expr::Literal(_) => (),
             Expr::Logical(logical_expr) => {
                 self.resolve_expr(&logical_expr.left)?;
                 self.resolve_expr(&logical_expr.right)?;
             }
+            Expr::Set(set_expr) => {
+                self.resolve_expr(&set_expr.value)?;
+                self.resolve_expr(&set_expr.object)?;
+            }
+            Expr::SetIndex(set_expr) => {
+                self.resolve_expr(&set_expr.value)?;
+                self.resolve_expr(&set_expr.object)?;
+                self.resolve_expr(&set_expr.index)?;
+            }
+            Expr::Spread(spread_expr) => self.resolve_expr(&spread_expr.expression)?,
+            Expr::This(this_expr) => {
+                if self.current_class.is_none() {
+                    return Err(ResolverError::new(
+                        this_expr.keyword.position(),
+                        "Cannot use 'this' outside of a class.".to_string(),
+                    ));
+                }
+                self.resolve_local(this_expr.scope_id, &this_expr.keyword);
+            }
             Expr::Unary(unary_expr) => {
                 self.resolve_expr(&unary_expr.right)?;
             }
             Expr::Variable(var_expr) => {
                 let name = &var_expr.name.lexeme;
                 if let Some(scope) = self.scopes.last() {
-                    if scope.get(name) == Some(&false) {
+                    if scope.get(name).is_some_and(|local| !local.defined) {
                         return Err(ResolverError::new(
-                            var_expr.name.line,
+                            var_expr.name.position(),
                             "Cannot read local variable in its own intializer.".to_string(),
                         ));
                     }
                 }
-                self.resolve_local(var_expr.scope_id, name);
+                self.resolve_local(var_expr.scope_id, &var_expr.name);
+            }
+            _ => {
+                return Err(ResolverError::new(
+                    self.position,
+                    format!("Unsupported syntax: {}.", expr.kind()),
+                ))
             }
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(source: &str, configure: impl FnOnce(&mut Resolver)) -> Resolver {
+        let program = parser::parse(source.to_string()).unwrap();
+        let mut resolver = Resolver::new();
+        configure(&mut resolver);
+        let _ = resolver.resolve(&program);
+        resolver
+    }
+
+    #[test]
+    fn assignment_in_condition() {
+        let source = "var a; if (a = 1) print a; while ((a = nil)) print a;";
+
+        let resolver = resolve(source, |_| ());
+        let warnings: Vec<String> = resolver.warnings.iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            warnings,
+            vec!["[line 1] Warning: Assignment used as a condition; did you mean '=='? [assign-in-condition]"]
+        );
+
+        let program = parser::parse(source.to_string()).unwrap();
+        let mut resolver = Resolver::new();
+        resolver.strict = true;
+        let errors = resolver.resolve(&program).unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 1] Error: Assignment used as a condition; did you mean '=='?"
+        );
+
+        let resolver = resolve(source, |r| {
+            r.strict = true;
+            r.lint.allow.push(ASSIGN_IN_CONDITION.to_string());
+        });
+        assert!(resolver.warnings.is_empty());
+    }
+
+    #[test]
+    fn deprecated_print() {
+        let source = "var a = 1;\nprint a;";
+        assert!(resolve(source, |_| ()).warnings.is_empty());
+
+        let resolver = resolve(source, |r| r.version = LanguageVersion::V3);
+        let warnings: Vec<String> = resolver.warnings.iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            warnings,
+            vec!["[line 2] Warning: The print statement is deprecated in language version 3. [deprecated]"]
+        );
+
+        let resolver = resolve(source, |r| {
+            r.version = LanguageVersion::V3;
+            r.lint.allow.push(DEPRECATED.to_string());
+        });
+        assert!(resolver.warnings.is_empty());
+    }
+
+    #[test]
+    fn shadowed_natives() {
+        let source = "var clock = 1;\nfun f(len) { var clock; }\nfun len() {}";
+        let natives = |r: &mut Resolver| r.natives = vec!["clock".to_string(), "len".to_string()];
+        let resolver = resolve(source, natives);
+        let warnings: Vec<String> = resolver.warnings.iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            warnings,
+            vec![
+                "[line 1] Warning: 'clock' hides the native function of that name. [shadow-native]",
+                "[line 3] Warning: 'len' hides the native function of that name. [shadow-native]",
+            ]
+        );
+
+        let resolver = resolve(source, |r| {
+            natives(r);
+            r.lint.allow.push(SHADOW_NATIVE.to_string());
+        });
+        assert!(resolver.warnings.is_empty());
+
+        let resolver = resolve(source, |r| {
+            natives(r);
+            r.strict = true;
+        });
+        assert_eq!(resolver.warnings.len(), 2);
+
+        let program = parser::parse(source.to_string()).unwrap();
+        let mut resolver = Resolver::new();
+        natives(&mut resolver);
+        resolver.lint.deny.push(SHADOW_NATIVE.to_string());
+        let errors = resolver.resolve(&program).unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 1] Error: 'clock' hides the native function of that name."
+        );
+    }
+
+    #[test]
+    fn duplicate_members() {
+        let program = parser::parse(
+            "class A {\n  get() { return 1; }\n  set(x) {}\n  get() { return 2; }\n}".to_string(),
+        )
+        .unwrap();
+        let errors = Resolver::new().resolve(&program).unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 4] Error: Method 'get' is declared twice, at lines 2 and 4."
+        );
+
+        let program = parser::parse("class A {\n  var x;\n  x() {}\n}".to_string()).unwrap();
+        let errors = Resolver::new().resolve(&program).unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 3] Error: 'x' is declared as both a field, at line 2, and a method, at line 3."
+        );
+
+        let program = parser::parse("class A {\n  var x;\n  var x = 1;\n}".to_string()).unwrap();
+        let errors = Resolver::new().resolve(&program).unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 3] Error: Field 'x' is declared twice, at lines 2 and 3."
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use crate::{
+    stats::{Kind, Tracked},
+    value::Value,
+};
+use snowflake::ProcessUniqueId;
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashSet,
+    rc::Rc,
+};
+
+/// An unordered collection of distinct values, using the same equality as
+/// `==`. Members are kept in insertion order so printing is predictable.
+/// Like arrays, clones share the same members.
+#[derive(Clone, Debug)]
+pub struct LoxSet {
+    pub id: ProcessUniqueId,
+    members: Rc<RefCell<Members>>,
+    frozen: Rc<Cell<bool>>,
+    _live: Rc<Tracked>,
+}
+
+#[derive(Debug, Default)]
+struct Members {
+    order: Vec<Value>,
+    index: HashSet<Value>,
+}
+
+impl LoxSet {
+    pub fn new(values: impl IntoIterator<Item = Value>) -> LoxSet {
+        let set = LoxSet {
+            id: ProcessUniqueId::new(),
+            members: Rc::new(RefCell::new(Members::default())),
+            frozen: Rc::new(Cell::new(false)),
+            _live: Rc::new(Tracked::new(Kind::Set)),
+        };
+        for value in values {
+            set.add(value);
+        }
+        set
+    }
+
+    /// Makes the set read-only for good. Clones share the flag.
+    pub fn freeze(&self) {
+        self.frozen.set(true);
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen.get()
+    }
+
+    /// Adds a value, returning false if it was already a member.
+    pub fn add(&self, value: Value) -> bool {
+        let mut members = self.members.borrow_mut();
+        if !members.index.insert(value.clone()) {
+            return false;
+        }
+        members.order.push(value);
+        true
+    }
+
+    pub fn has(&self, value: &Value) -> bool {
+        self.members.borrow().index.contains(value)
+    }
+
+    /// Removes a value, returning false if it wasn't a member.
+    pub fn remove(&self, value: &Value) -> bool {
+        let mut members = self.members.borrow_mut();
+        if !members.index.remove(value) {
+            return false;
+        }
+        members.order.retain(|member| member != value);
+        true
+    }
+
+    /// Members of either set: this set's first, then the other's.
+    pub fn union(&self, other: &LoxSet) -> LoxSet {
+        LoxSet::new(self.values().into_iter().chain(other.values()))
+    }
+
+    /// Members of both sets, in this set's order.
+    pub fn intersect(&self, other: &LoxSet) -> LoxSet {
+        LoxSet::new(self.values().into_iter().filter(|value| other.has(value)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.borrow().order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.borrow().order.is_empty()
+    }
+
+    /// A snapshot of the members in insertion order.
+    pub fn values(&self) -> Vec<Value> {
+        self.members.borrow().order.clone()
+    }
+}
+
+impl PartialEq for LoxSet {
+    fn eq(&self, other: &LoxSet) -> bool {
+        self.id == other.id
+    }
+}
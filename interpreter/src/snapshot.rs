@@ -0,0 +1,85 @@
+use std::{fs, path::PathBuf};
+
+/// Where `expect_snapshot(name, value)` keeps its snapshots: one
+/// `<name>.snap` file per snapshot, holding the value as `print` shows it.
+#[derive(Clone, Debug)]
+pub struct Snapshots {
+    dir: PathBuf,
+    update: bool,
+}
+
+impl Snapshots {
+    /// When `update` is set, values are written to their snapshots instead
+    /// of being compared with them.
+    pub fn new(dir: PathBuf, update: bool) -> Snapshots {
+        Snapshots { dir, update }
+    }
+
+    /// Compares `printed` with the snapshot called `name`, or writes it
+    /// there when updating.
+    pub fn check(&self, name: &str, printed: &str) -> Result<(), String> {
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if !valid {
+            return Err(format!(
+                "Snapshot names are made of letters, digits, '_' and '-', not '{}'.",
+                name
+            ));
+        }
+        let path = self.dir.join(format!("{}.snap", name));
+        if self.update {
+            return fs::create_dir_all(&self.dir)
+                .and_then(|_| fs::write(&path, format!("{}\n", printed)))
+                .map_err(|e| format!("Cannot write {}: {}.", path.display(), e));
+        }
+        let expected = match fs::read_to_string(&path) {
+            Ok(expected) => expected,
+            Err(_) => {
+                return Err(format!(
+                    "There's no snapshot '{}' yet. Run with --update-snapshots to record it.",
+                    name
+                ))
+            }
+        };
+        let expected = expected.strip_suffix('\n').unwrap_or(&expected);
+        if expected == printed {
+            Ok(())
+        } else {
+            Err(format!(
+                "Snapshot '{}' doesn't match.\n  expected: {}\n    actual: {}",
+                name, expected, printed
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn update_then_compare() {
+        let dir = env::temp_dir().join(format!("rlox-snapshots-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let checking = Snapshots::new(dir.clone(), false);
+        assert!(checking.check("point", "[1, 2]").is_err());
+        Snapshots::new(dir.clone(), true)
+            .check("point", "[1, 2]")
+            .unwrap();
+        assert_eq!(checking.check("point", "[1, 2]"), Ok(()));
+        assert_eq!(
+            checking.check("point", "[2, 1]"),
+            Err(
+                "Snapshot 'point' doesn't match.\n  expected: [1, 2]\n    actual: [2, 1]"
+                    .to_string()
+            )
+        );
+        assert!(checking.check("../point", "[1, 2]").is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,124 @@
+use ast::{Expr, Stmt};
+use std::{cell::Cell, collections::HashMap, time::Duration};
+
+/// The kinds of heap object `memory_stats()` reports on.
+#[derive(Clone, Copy, Debug)]
+pub enum Kind {
+    Environment,
+    Function,
+    Instance,
+    Array,
+    Set,
+    String,
+}
+
+const KINDS: [(Kind, &str); 6] = [
+    (Kind::Environment, "environments"),
+    (Kind::Function, "functions"),
+    (Kind::Instance, "instances"),
+    (Kind::Array, "arrays"),
+    (Kind::Set, "sets"),
+    (Kind::String, "strings"),
+];
+
+thread_local! {
+    static LIVE: [Cell<usize>; 6] = Default::default();
+    static CREATED: [Cell<u64>; 6] = Default::default();
+}
+
+/// Counts as one live object of its kind until it's dropped. Types whose
+/// clones share their contents keep it in an `Rc`, so the object is counted
+/// once however many copies there are.
+#[derive(Debug)]
+pub struct Tracked(Kind);
+
+impl Tracked {
+    pub fn new(kind: Kind) -> Tracked {
+        LIVE.with(|live| live[kind as usize].set(live[kind as usize].get() + 1));
+        CREATED.with(|created| created[kind as usize].set(created[kind as usize].get() + 1));
+        Tracked(kind)
+    }
+}
+
+impl Drop for Tracked {
+    fn drop(&mut self) {
+        let kind = self.0 as usize;
+        // The counters may already be gone when values are dropped as the
+        // thread exits.
+        let _ = LIVE.try_with(|live| live[kind].set(live[kind].get() - 1));
+    }
+}
+
+/// How many objects of each kind are alive on this thread, by name.
+pub fn live_counts() -> Vec<(&'static str, usize)> {
+    LIVE.with(|live| {
+        KINDS
+            .iter()
+            .map(|&(kind, name)| (name, live[kind as usize].get()))
+            .collect()
+    })
+}
+
+/// How many objects of each kind have been created on this thread, by name,
+/// whether or not they're still alive.
+pub fn created_counts() -> Vec<(&'static str, u64)> {
+    CREATED.with(|created| {
+        KINDS
+            .iter()
+            .map(|&(kind, name)| (name, created[kind as usize].get()))
+            .collect()
+    })
+}
+
+/// A summary of what the runs of an interpreter did, kept when
+/// `InterpreterConfig::report` is set. Like the node counts, it adds up
+/// over every run.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RunReport {
+    /// How long the runs took.
+    pub elapsed: Duration,
+    /// How many functions, natives and classes were called.
+    pub calls: u64,
+    /// The most environments that were ever in scope at once, the globals
+    /// included.
+    pub peak_environment_depth: usize,
+    /// How many objects of each kind the runs created, by name.
+    pub allocations: Vec<(&'static str, u64)>,
+}
+
+impl RunReport {
+    /// Adds a run that took `elapsed`, and started when `created_counts()`
+    /// was `before`.
+    pub fn add_run(&mut self, elapsed: Duration, before: &[(&'static str, u64)]) {
+        self.elapsed += elapsed;
+        for ((name, after), (_, before)) in created_counts().into_iter().zip(before) {
+            match self.allocations.iter_mut().find(|(kind, _)| *kind == name) {
+                Some((_, count)) => *count += after - before,
+                None => self.allocations.push((name, after - before)),
+            }
+        }
+    }
+}
+
+/// How many times each kind of statement and expression has run, kept when
+/// `InterpreterConfig::count_nodes` is set.
+#[derive(Debug, Default)]
+pub struct NodeCounts(HashMap<&'static str, u64>);
+
+impl NodeCounts {
+    pub fn stmt(&mut self, stmt: &Stmt) {
+        *self.0.entry(stmt.kind()).or_default() += 1;
+    }
+
+    pub fn expr(&mut self, expr: &Expr) {
+        *self.0.entry(expr.kind()).or_default() += 1;
+    }
+
+    /// The kinds that have run, most often first.
+    pub fn sorted(&self) -> Vec<(&'static str, u64)> {
+        let mut counts: Vec<(&'static str, u64)> =
+            self.0.iter().map(|(kind, count)| (*kind, *count)).collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        counts
+    }
+}
@@ -0,0 +1,96 @@
+use crate::stats::{Kind, Tracked};
+use std::{
+    cmp::Ordering,
+    fmt,
+    hash::{Hash, Hasher},
+    ops::Deref,
+};
+
+/// The text of a string value. Strings are value types, so a clone is a
+/// copy of the text, and each copy counts as a live string in
+/// `memory_stats()` until it's dropped.
+pub struct LoxString {
+    text: String,
+    _live: Tracked,
+}
+
+impl LoxString {
+    pub fn new(text: String) -> LoxString {
+        LoxString {
+            text,
+            _live: Tracked::new(Kind::String),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    pub fn into_string(self) -> String {
+        self.text
+    }
+}
+
+impl Clone for LoxString {
+    fn clone(&self) -> LoxString {
+        LoxString::new(self.text.clone())
+    }
+}
+
+impl Deref for LoxString {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.text
+    }
+}
+
+impl From<String> for LoxString {
+    fn from(text: String) -> LoxString {
+        LoxString::new(text)
+    }
+}
+
+impl From<&str> for LoxString {
+    fn from(text: &str) -> LoxString {
+        LoxString::new(text.to_string())
+    }
+}
+
+impl PartialEq for LoxString {
+    fn eq(&self, other: &LoxString) -> bool {
+        self.text == other.text
+    }
+}
+
+impl Eq for LoxString {}
+
+impl Hash for LoxString {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.text.hash(state);
+    }
+}
+
+impl PartialOrd for LoxString {
+    fn partial_cmp(&self, other: &LoxString) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for LoxString {
+    fn cmp(&self, other: &LoxString) -> Ordering {
+        self.text.cmp(&other.text)
+    }
+}
+
+impl fmt::Debug for LoxString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.text, f)
+    }
+}
+
+impl fmt::Display for LoxString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
@@ -0,0 +1,92 @@
+/// Edit distance between two strings, counted in chars. Swapping two
+/// adjacent chars counts as a single edit since that is the most common typo.
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            let mut distance = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = distance;
+        }
+    }
+
+    rows[a.len()][b.len()]
+}
+
+/// Picks up to three candidates close enough to `name` to be likely typos,
+/// closest first.
+pub fn similar_names(name: &str, candidates: &[String]) -> Vec<String> {
+    let max_distance = (name.chars().count() / 3).max(1);
+    let mut scored: Vec<(usize, &String)> = candidates
+        .iter()
+        .filter(|candidate| candidate.as_str() != name)
+        .map(|candidate| (levenshtein(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect();
+    scored.sort();
+    scored
+        .into_iter()
+        .take(3)
+        .map(|(_, candidate)| candidate.clone())
+        .collect()
+}
+
+/// Formats suggestions as a sentence to append to an error message, or an
+/// empty string when there is nothing to suggest.
+pub fn did_you_mean(suggestions: &[String]) -> String {
+    let quoted: Vec<String> = suggestions.iter().map(|s| format!("'{}'", s)).collect();
+    match quoted.split_last() {
+        None => String::new(),
+        Some((last, [])) => format!(" Did you mean {}?", last),
+        Some((last, rest)) => format!(" Did you mean {} or {}?", rest.join(", "), last),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distance() {
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(levenshtein("clock", "clock"), 0);
+        assert_eq!(levenshtein("coutn", "count"), 1);
+    }
+
+    #[test]
+    fn suggestions() {
+        let names: Vec<String> = vec!["clock", "count", "counter", "total"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+
+        assert_eq!(similar_names("cloc", &names), vec!["clock"]);
+        assert_eq!(similar_names("countr", &names), vec!["count", "counter"]);
+        assert!(similar_names("xyz", &names).is_empty());
+    }
+
+    #[test]
+    fn message() {
+        assert_eq!(did_you_mean(&[]), "");
+        assert_eq!(did_you_mean(&["a".to_string()]), " Did you mean 'a'?");
+        assert_eq!(
+            did_you_mean(&["a".to_string(), "b".to_string(), "c".to_string()]),
+            " Did you mean 'a', 'b' or 'c'?"
+        );
+    }
+}
@@ -0,0 +1,45 @@
+use crate::function::LoxFunction;
+use snowflake::ProcessUniqueId;
+use std::{collections::HashMap, fmt, rc::Rc};
+
+/// A named set of methods that classes mix in with `class A with T`. The
+/// methods are copied into each class's method table when it's declared.
+#[derive(Clone, Debug)]
+pub struct LoxTrait {
+    pub name: String,
+    pub id: ProcessUniqueId,
+    methods: Rc<HashMap<String, LoxFunction>>,
+}
+
+impl LoxTrait {
+    pub fn new(name: String, methods: HashMap<String, LoxFunction>) -> LoxTrait {
+        LoxTrait {
+            name,
+            id: ProcessUniqueId::new(),
+            methods: Rc::new(methods),
+        }
+    }
+
+    pub fn methods(&self) -> &HashMap<String, LoxFunction> {
+        &self.methods
+    }
+
+    /// Names of the methods the trait declares, sorted.
+    pub fn method_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.methods.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+}
+
+impl fmt::Display for LoxTrait {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<trait {}>", self.name)
+    }
+}
+
+impl PartialEq for LoxTrait {
+    fn eq(&self, other: &LoxTrait) -> bool {
+        self.id == other.id
+    }
+}
@@ -1,44 +1,453 @@
 use crate::{
+    array::LoxArray,
     class::LoxClass,
+    format,
     function::{LoxFunction, NativeFunction},
+    instance::LoxInstance,
+    native_args::NativeArg,
+    set::LoxSet,
+    string::LoxString,
+    traits::LoxTrait,
+};
+use snowflake::ProcessUniqueId;
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    convert::TryFrom,
+    fmt,
+    hash::{Hash, Hasher},
+    mem,
 };
-use std::fmt;
 
-#[derive(Clone, Debug, PartialEq)]
+/// A Lox value. Nil, booleans, numbers and strings are value types: a clone
+/// is independent of the original. Arrays, sets and instances are reference
+/// types whose clones share their contents, so a change through one is seen
+/// through every other; `deep_clone` copies them instead. Classes, traits
+/// and functions are shared too, but can't be changed once made.
+#[derive(Clone, Debug)]
 pub enum Value {
     Nil,
+    Array(LoxArray),
     Boolean(bool),
     Class(LoxClass),
     Function(LoxFunction),
+    Instance(LoxInstance),
     NativeFunction(NativeFunction),
     Number(f64),
-    String(String),
+    Set(LoxSet),
+    String(LoxString),
+    Trait(LoxTrait),
 }
 
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", format::repr(self))
+    }
+}
+
+/// Equality as seen by Lox's `==`, following jlox (which defers to Java's
+/// `equals`):
+///
+/// - values of different types are never equal;
+/// - nil, booleans and strings compare by value;
+/// - numbers compare bit for bit, except that every NaN equals every other
+///   NaN. So `0 / 0 == 0 / 0` is true while `0 == -0` is false;
+/// - functions, classes, traits, instances, arrays and sets compare by
+///   identity: two declarations with the same source are still different
+///   values, and so are two instances with the same fields or two arrays
+///   with the same elements. Natives compare by name.
+///
+/// Any reference type added later compares by identity as well.
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Array(a), Value::Array(b)) => a == b,
+            (Value::Boolean(a), Value::Boolean(b)) => a == b,
+            (Value::Class(a), Value::Class(b)) => a == b,
+            (Value::Function(a), Value::Function(b)) => a == b,
+            (Value::Instance(a), Value::Instance(b)) => a == b,
+            (Value::NativeFunction(a), Value::NativeFunction(b)) => a == b,
+            (Value::Number(a), Value::Number(b)) => number_bits(*a) == number_bits(*b),
+            (Value::Set(a), Value::Set(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Trait(a), Value::Trait(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
         match self {
-            Value::Nil => write!(f, "nil"),
-            Value::Boolean(b) => write!(f, "{}", b),
-            Value::Class(ref class) => write!(f, "{}", class),
-            Value::Function(ref fun) => write!(f, "{}", fun),
-            Value::Number(n) => write!(f, "{}", n),
-            Value::String(ref s) => write!(f, "\"{}\"", s),
-            Value::NativeFunction(ref fun) => write!(f, "{}", fun),
+            Value::Nil => (),
+            Value::Array(array) => array.id.hash(state),
+            Value::Boolean(b) => b.hash(state),
+            Value::Class(class) => class.id.hash(state),
+            Value::Function(fun) => fun.hash(state),
+            Value::Instance(instance) => instance.id.hash(state),
+            Value::NativeFunction(fun) => fun.name.hash(state),
+            Value::Number(n) => number_bits(*n).hash(state),
+            Value::Set(set) => set.id.hash(state),
+            Value::String(s) => s.hash(state),
+            Value::Trait(t) => t.id.hash(state),
         }
     }
 }
 
+/// Bit pattern used to compare and hash numbers, with every NaN collapsed
+/// into one.
+fn number_bits(n: f64) -> u64 {
+    if n.is_nan() {
+        f64::NAN.to_bits()
+    } else {
+        n.to_bits()
+    }
+}
+
 impl Value {
     pub fn print(&self) -> String {
+        format::display(self)
+    }
+
+    /// A total order over all values that agrees with `==`: two values are
+    /// `Equal` exactly when they're equal. Values of different types are
+    /// ordered by type: nil, booleans, numbers, strings, arrays, sets,
+    /// instances, classes, traits, functions and natives. Within a type,
+    /// false comes before true, numbers go by value with -0 before 0 and
+    /// NaN last, strings go by their characters, natives by name, and the
+    /// other reference types by when they were made.
+    pub fn total_cmp(&self, other: &Value) -> Ordering {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => Ordering::Equal,
+            (Value::Array(a), Value::Array(b)) => a.id.cmp(&b.id),
+            (Value::Boolean(a), Value::Boolean(b)) => a.cmp(b),
+            (Value::Class(a), Value::Class(b)) => a.id.cmp(&b.id),
+            (Value::Function(a), Value::Function(b)) => a.id.cmp(&b.id),
+            (Value::Instance(a), Value::Instance(b)) => a.id.cmp(&b.id),
+            (Value::NativeFunction(a), Value::NativeFunction(b)) => {
+                (&a.name, a.arity).cmp(&(&b.name, b.arity))
+            }
+            (Value::Number(a), Value::Number(b)) => match (a.is_nan(), b.is_nan()) {
+                (false, false) => a.total_cmp(b),
+                (a_nan, b_nan) => a_nan.cmp(&b_nan),
+            },
+            (Value::Set(a), Value::Set(b)) => a.id.cmp(&b.id),
+            (Value::String(a), Value::String(b)) => a.cmp(b),
+            (Value::Trait(a), Value::Trait(b)) => a.id.cmp(&b.id),
+            _ => self.type_rank().cmp(&other.type_rank()),
+        }
+    }
+
+    // Where values of this type go in `total_cmp`.
+    fn type_rank(&self) -> u8 {
+        match self {
+            Value::Nil => 0,
+            Value::Boolean(_) => 1,
+            Value::Number(_) => 2,
+            Value::String(_) => 3,
+            Value::Array(_) => 4,
+            Value::Set(_) => 5,
+            Value::Instance(_) => 6,
+            Value::Class(_) => 7,
+            Value::Trait(_) => 8,
+            Value::Function(_) => 9,
+            Value::NativeFunction(_) => 10,
+        }
+    }
+
+    /// Freezes an instance, array or set. Freezing is shallow: the values
+    /// inside can still change unless they're frozen too.
+    pub fn freeze(&self) {
+        match self {
+            Value::Array(array) => array.freeze(),
+            Value::Instance(instance) => instance.freeze(),
+            Value::Set(set) => set.freeze(),
+            _ => (),
+        }
+    }
+
+    /// Whether the value can't be changed, which is true of every value
+    /// other than an unfrozen instance, array or set.
+    pub fn is_frozen(&self) -> bool {
         match self {
-            Value::Nil => format!("nil"),
-            Value::Boolean(b) => format!("{}", b),
-            Value::Class(class) => format!("{}", class),
-            Value::Function(fun) => format!("{}", fun),
-            Value::Number(n) => format!("{}", n),
-            Value::String(s) => format!("{}", s),
-            Value::NativeFunction(fun) => format!("{}", fun),
+            Value::Array(array) => array.is_frozen(),
+            Value::Instance(instance) => instance.is_frozen(),
+            Value::Set(set) => set.is_frozen(),
+            _ => true,
+        }
+    }
+
+    /// A copy that shares no arrays, sets or instances with the original,
+    /// so changes to one can't be seen through the other. Values reachable
+    /// more than once, including through a cycle, are copied once and stay
+    /// shared within the copy. Frozen values are copied frozen, while
+    /// classes, traits and functions are shared as they are.
+    pub fn deep_clone(&self) -> Value {
+        let mut cloner = DeepCloner::default();
+        let copy = cloner.copy(self);
+        // Copies are filled in a loop rather than by recursion, so a long
+        // chain of instances can't overflow the stack.
+        while let Some((original, copy)) = cloner.pending.pop() {
+            cloner.fill(&original, &copy);
+        }
+        copy
+    }
+
+    /// The error for an attempt to change a frozen value, if it is one.
+    pub fn check_mutable(&self) -> Result<(), String> {
+        let kind = match self {
+            Value::Array(_) => "array",
+            Value::Instance(_) => "instance",
+            Value::Set(_) => "set",
+            _ => return Ok(()),
+        };
+        if self.is_frozen() {
+            Err(format!("Cannot modify a frozen {}.", kind))
+        } else {
+            Ok(())
         }
     }
 }
+
+// Conversions for hosts, such as natives taking their arguments apart. A
+// failed conversion says what was expected in the style of a native's
+// error, so `?` can pass it on.
+
+fn convert<T: NativeArg>(value: Value) -> Result<T, String> {
+    T::from_value(value).map_err(|value| format!("Expected {}, got {}.", T::EXPECTED, value))
+}
+
+impl TryFrom<Value> for f64 {
+    type Error = String;
+
+    fn try_from(value: Value) -> Result<f64, String> {
+        convert(value)
+    }
+}
+
+impl TryFrom<Value> for bool {
+    type Error = String;
+
+    fn try_from(value: Value) -> Result<bool, String> {
+        convert(value)
+    }
+}
+
+impl TryFrom<Value> for String {
+    type Error = String;
+
+    fn try_from(value: Value) -> Result<String, String> {
+        convert(value)
+    }
+}
+
+impl TryFrom<Value> for Vec<Value> {
+    type Error = String;
+
+    fn try_from(value: Value) -> Result<Vec<Value>, String> {
+        convert(value)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Value {
+        Value::Number(n)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Value {
+        Value::Boolean(b)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Value {
+        Value::String(LoxString::from(s))
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Value {
+        Value::String(LoxString::new(s))
+    }
+}
+
+/// A new array with `elements`.
+impl From<Vec<Value>> for Value {
+    fn from(elements: Vec<Value>) -> Value {
+        Value::Array(LoxArray::new(elements))
+    }
+}
+
+#[derive(Default)]
+struct DeepCloner {
+    // The copy of each array, set and instance met so far.
+    copies: HashMap<ProcessUniqueId, Value>,
+    // Copies made but not filled in yet, with their originals.
+    pending: Vec<(Value, Value)>,
+}
+
+impl DeepCloner {
+    /// The copy of `value`, made empty and left to be filled in if it's the
+    /// first time it's been met.
+    fn copy(&mut self, value: &Value) -> Value {
+        let (id, copy) = match value {
+            Value::Array(array) => (array.id, Value::Array(LoxArray::new(vec![]))),
+            Value::Instance(instance) => (
+                instance.id,
+                Value::Instance(LoxInstance::new(instance.class.clone())),
+            ),
+            Value::Set(set) => (set.id, Value::Set(LoxSet::new(vec![]))),
+            _ => return value.clone(),
+        };
+        if let Some(existing) = self.copies.get(&id) {
+            return existing.clone();
+        }
+        self.copies.insert(id, copy.clone());
+        self.pending.push((value.clone(), copy.clone()));
+        copy
+    }
+
+    fn fill(&mut self, original: &Value, copy: &Value) {
+        match (original, copy) {
+            (Value::Array(original), Value::Array(copy)) => {
+                for element in original.elements() {
+                    copy.push(self.copy(&element));
+                }
+            }
+            (Value::Instance(original), Value::Instance(copy)) => {
+                for (name, value) in original.fields() {
+                    copy.set(name, self.copy(&value));
+                }
+            }
+            (Value::Set(original), Value::Set(copy)) => {
+                for member in original.values() {
+                    copy.add(self.copy(&member));
+                }
+            }
+            _ => (),
+        }
+        if original.is_frozen() {
+            copy.freeze();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::DefaultHasher;
+
+    fn hash_of(value: &Value) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn number_equality() {
+        assert_eq!(Value::Number(f64::NAN), Value::Number(-f64::NAN));
+        assert_ne!(Value::Number(0.0), Value::Number(-0.0));
+        assert_ne!(Value::Number(1.0), Value::from("1"));
+    }
+
+    #[test]
+    fn hash_agrees_with_eq() {
+        let nan = Value::Number(f64::NAN);
+        let negative_nan = Value::Number(-f64::NAN);
+        assert_eq!(hash_of(&nan), hash_of(&negative_nan));
+
+        let a = Value::from("lox");
+        let b = Value::from("lox");
+        assert_eq!(hash_of(&a), hash_of(&b));
+
+        assert_ne!(hash_of(&Value::Number(1.0)), hash_of(&Value::Boolean(true)));
+    }
+
+    #[test]
+    fn total_order_agrees_with_eq() {
+        let values = vec![
+            Value::Nil,
+            Value::Boolean(false),
+            Value::Boolean(true),
+            Value::Number(f64::NEG_INFINITY),
+            Value::Number(-0.0),
+            Value::Number(0.0),
+            Value::Number(2.0),
+            Value::Number(f64::NAN),
+            Value::from("a"),
+            Value::from("b"),
+            Value::Array(LoxArray::new(vec![])),
+            Value::Array(LoxArray::new(vec![])),
+            Value::Set(LoxSet::new(vec![])),
+        ];
+        for (i, a) in values.iter().enumerate() {
+            for (j, b) in values.iter().enumerate() {
+                assert_eq!(a.total_cmp(b), i.cmp(&j), "{} and {}", a, b);
+                assert_eq!(a.total_cmp(b) == Ordering::Equal, a == b);
+            }
+        }
+        assert_eq!(
+            Value::Number(f64::NAN).total_cmp(&Value::Number(-f64::NAN)),
+            Ordering::Equal
+        );
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(f64::try_from(Value::from(1.5)), Ok(1.5));
+        assert_eq!(bool::try_from(Value::from(true)), Ok(true));
+        assert_eq!(String::try_from(Value::from("lox")), Ok("lox".to_string()));
+        let elements = Vec::<Value>::try_from(Value::from(vec![Value::from(1.0), Value::Nil]));
+        assert_eq!(elements, Ok(vec![Value::Number(1.0), Value::Nil]));
+
+        assert_eq!(
+            f64::try_from(Value::from("1")),
+            Err("Expected a number, got \"1\".".to_string())
+        );
+        assert_eq!(
+            bool::try_from(Value::Nil),
+            Err("Expected a boolean, got nil.".to_string())
+        );
+        assert_eq!(
+            String::try_from(Value::from(2.0)),
+            Err("Expected a string, got 2.".to_string())
+        );
+        assert_eq!(
+            Vec::<Value>::try_from(Value::from("ab")),
+            Err("Expected an array, got \"ab\".".to_string())
+        );
+    }
+
+    #[test]
+    fn deep_clone() {
+        let instance = LoxInstance::new(LoxClass::new("Point".to_string(), HashMap::new()));
+        instance.set("x".to_string(), Value::Number(1.0));
+        let array = LoxArray::new(vec![Value::Instance(instance.clone())]);
+        array.push(Value::Instance(instance.clone()));
+        array.push(Value::Array(array.clone()));
+        array.push(Value::Set(LoxSet::new(vec![Value::from("a")])));
+        array.freeze();
+
+        let copy = match Value::Array(array.clone()).deep_clone() {
+            Value::Array(copy) => copy,
+            value => panic!("Expected an array, got {}", value),
+        };
+        assert_ne!(copy, array);
+        assert!(copy.is_frozen());
+        assert_eq!(copy.get(2), Some(Value::Array(copy.clone())));
+        assert_eq!(copy.get(0), copy.get(1));
+        assert_ne!(copy.get(0), Some(Value::Instance(instance.clone())));
+
+        instance.set("x".to_string(), Value::Number(2.0));
+        assert_eq!(
+            format::repr(&Value::Array(copy)),
+            "[Point { x: 1 }, Point { x: 1 }, ..., {\"a\"}]"
+        );
+    }
+}
@@ -1,45 +1,239 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+#![forbid(unsafe_code)]
+
+extern crate alloc;
 extern crate ast;
 
 mod scanner;
 
 use crate::scanner::Scanner;
-use ast::token::{Literal, Token, TokenType};
+use alloc::{
+    borrow::Cow,
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use ast::{
+    limits::LanguageLimits,
+    token::{FileId, Literal, Position, Span, Token, TokenType},
+    version::LanguageVersion,
+    BinaryOp, LogicalOp, UnaryOp,
+};
+use core::cell::Cell;
+use core::fmt;
+use core::ops::Deref;
+#[cfg(feature = "std")]
 use std::error::Error;
-use std::fmt;
 
-// enum StmtResult<'a> {
-//     Ok(ast::Stmt, usize),
+type StmtResult = Result<(ast::Stmt, usize), SyntaxError>;
+type ExprResult = Result<(ast::Expr, usize), SyntaxError>;
+/// A grammar rule for an expression, parsing from a position.
+type Rule = fn(&Tokens, usize) -> ExprResult;
+type ConsumeResult<'a> = Result<(&'a Token, usize), SyntaxError>;
+type FunStmtResult = Result<(ast::FunStmt, usize), SyntaxError>;
+type VarStmtResult = Result<(ast::VarStmt, usize), SyntaxError>;
+/// Positional and keyword arguments of a call.
+type ArgumentsResult = Result<(Vec<ast::Expr>, Vec<(Token, ast::Expr)>, usize), SyntaxError>;
+/// Parameters, rest parameter and body of a function.
+type FunctionResult = Result<(Vec<Token>, Option<Token>, Vec<ast::Stmt>, usize), SyntaxError>;
 
-//     Err(&'a str, usize),
-// }
+/// Switches for grammar variations. The default is the standard Lox grammar.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ParseOptions {
+    /// Let a line break (or the end of input) end a statement that is
+    /// missing its `;`, as long as the statement is already complete. A
+    /// line starting with `.`, `?.`, `(` or `[` still continues the
+    /// expression before it, so call chains can be split across lines.
+    pub optional_semicolons: bool,
+    /// The `expr-blocks` extension: blocks and `if` can be used as
+    /// expressions, taking the value of their trailing expression, as in
+    /// `var x = if (c) { 1 } else { 2 };`. Standard from version 3.
+    pub expr_blocks: bool,
+    /// Which version of the language to accept. Syntax added after it is
+    /// an error.
+    pub version: LanguageVersion,
+    /// How many arguments, parameters and levels of nesting to accept, and
+    /// how long a string literal can be.
+    pub limits: LanguageLimits,
+}
 
-// enum ExprResult< {
-//     Ok(ast::Expr, usize),
-//     Err(&'a str, usize),
-// }
+impl ParseOptions {
+    /// Whether blocks and `if` can be used as expressions, by extension or
+    /// because the version has them.
+    pub fn allows_expr_blocks(&self) -> bool {
+        self.expr_blocks || self.version >= LanguageVersion::V3
+    }
+}
 
-type StmtResult = Result<(ast::Stmt, usize), (&'static str, usize)>;
-type ExprResult = Result<(ast::Expr, usize), (&'static str, usize)>;
-type ConsumeResult<'a> = Result<(&'a Token, usize), &'static str>;
+/// The token stream together with the options it's parsed under.
+struct Tokens {
+    tokens: Vec<Token>,
+    options: ParseOptions,
+    depth: Cell<usize>,
+    // The nesting depth of the condition being parsed, if any, so that an
+    // assignment at its top can be taken for a comparison.
+    condition: Cell<Option<usize>>,
+}
+
+impl Tokens {
+    fn new(tokens: Vec<Token>, options: ParseOptions) -> Tokens {
+        Tokens {
+            tokens,
+            options,
+            depth: Cell::new(0),
+            condition: Cell::new(None),
+        }
+    }
+}
+
+impl Deref for Tokens {
+    type Target = [Token];
+
+    fn deref(&self) -> &[Token] {
+        &self.tokens
+    }
+}
+
+struct SyntaxError {
+    msg: Cow<'static, str>,
+    pos: usize,
+    // The token that would have let the parse continue, used to build fix-its.
+    expected: Option<TokenType>,
+}
+
+fn error(msg: impl Into<Cow<'static, str>>, pos: usize) -> SyntaxError {
+    SyntaxError {
+        msg: msg.into(),
+        pos,
+        expected: None,
+    }
+}
+
+fn expected(token_type: TokenType, msg: &'static str, pos: usize) -> SyntaxError {
+    SyntaxError {
+        msg: msg.into(),
+        pos,
+        expected: Some(token_type),
+    }
+}
+
+/// Runs `parse` one nesting level deeper, failing once the limit is hit.
+fn nested<T>(
+    tokens: &Tokens,
+    pos: usize,
+    parse: impl FnOnce(&Tokens, usize) -> Result<T, SyntaxError>,
+) -> Result<T, SyntaxError> {
+    let depth = tokens.depth.get();
+    let max_nesting = tokens.options.limits.max_nesting;
+    if depth >= max_nesting {
+        let msg = format!("Cannot nest more than {} levels deep.", max_nesting);
+        return Err(error(msg, pos));
+    }
+    tokens.depth.set(depth + 1);
+    let result = parse(tokens, pos);
+    tokens.depth.set(depth);
+    result
+}
+
+/// The name being declared at `pos`, failing with `msg` if there isn't
+/// one.
+fn declared_name<'a>(tokens: &'a Tokens, pos: usize, msg: &'static str) -> ConsumeResult<'a> {
+    match tokens[pos].token_type {
+        TokenType::Identifier => Ok((&tokens[pos], pos + 1)),
+        _ => Err(name_error(tokens, pos, msg)),
+    }
+}
+
+/// The error for a missing name at `pos`: `msg`, unless the token there is
+/// a reserved word, in which case the error says so.
+fn name_error(tokens: &Tokens, pos: usize, msg: &'static str) -> SyntaxError {
+    let token = &tokens[pos];
+    if is_reserved(token) {
+        error(format!("'{}' is a reserved word.", token.lexeme), pos)
+    } else {
+        error(msg, pos)
+    }
+}
+
+fn is_reserved(token: &Token) -> bool {
+    TokenType::keyword(&token.lexeme).is_some()
+}
 
 fn consume<'a>(
     expect: TokenType,
-    tokens: &'a Vec<Token>,
+    tokens: &'a [Token],
     pos: usize,
     err_msg: &'static str,
 ) -> ConsumeResult<'a> {
     if tokens[pos].token_type == expect {
         Ok((&tokens[pos], pos + 1))
     } else {
-        Err(err_msg)
+        Err(expected(expect, err_msg, pos))
     }
 }
 
-pub fn parse(source: String) -> Result<Vec<ast::Stmt>, Vec<Box<Error>>> {
-    let scanner = Scanner::new(source);
-    let tokens = scanner.scan_tokens()?;
+/// Like `consume`, for when the token itself isn't needed.
+fn expect(
+    tokens: &Tokens,
+    pos: usize,
+    token_type: TokenType,
+    err_msg: &'static str,
+) -> Result<usize, SyntaxError> {
+    consume(token_type, tokens, pos, err_msg).map(|(_, pos)| pos)
+}
+
+/// Parses `( inner )`, as around a condition, with the message for each
+/// missing paren.
+fn parenthesized<T>(
+    tokens: &Tokens,
+    pos: usize,
+    messages: (&'static str, &'static str),
+    inner: impl FnOnce(&Tokens, usize) -> Result<(T, usize), SyntaxError>,
+) -> Result<(T, usize), SyntaxError> {
+    let (open_msg, close_msg) = messages;
+    let pos = expect(tokens, pos, TokenType::LeftParen, open_msg)?;
+    let (value, pos) = inner(tokens, pos)?;
+    let pos = expect(tokens, pos, TokenType::RightParen, close_msg)?;
+    Ok((value, pos))
+}
+
+#[cfg(feature = "std")]
+pub fn parse(source: String) -> Result<Vec<ast::Stmt>, Vec<Box<dyn Error>>> {
+    parse_with_options(source, ParseOptions::default())
+}
+
+#[cfg(feature = "std")]
+pub fn parse_with_options(
+    source: String,
+    options: ParseOptions,
+) -> Result<Vec<ast::Stmt>, Vec<Box<dyn Error>>> {
+    parse_program(source, options).map_err(boxed)
+}
+
+/// Parses a whole program. This is what `parse` and `parse_with_options`
+/// build on, and the entry point to use without the `std` feature.
+pub fn parse_program(
+    source: String,
+    options: ParseOptions,
+) -> Result<Vec<ast::Stmt>, Vec<ParseError>> {
+    parse_file(source, FileId::default(), options)
+}
+
+/// Parses a program loaded as `file`, so its tokens, and the errors
+/// reported against them, say which source they came from.
+pub fn parse_file(
+    source: String,
+    file: FileId,
+    options: ParseOptions,
+) -> Result<Vec<ast::Stmt>, Vec<ParseError>> {
+    let scanner = Scanner::new(source)
+        .in_file(file)
+        .with_max_string_length(options.limits.max_string_length);
+    let tokens = Tokens::new(scanner.scan_tokens()?, options);
     let mut statements: Vec<ast::Stmt> = vec![];
-    let mut errors: Vec<Box<Error>> = vec![];
+    let mut errors: Vec<ParseError> = vec![];
     let mut pos = 0;
     while tokens[pos].token_type != TokenType::Eof {
         match declaration(&tokens, pos) {
@@ -47,10 +241,9 @@ pub fn parse(source: String) -> Result<Vec<ast::Stmt>, Vec<Box<Error>>> {
                 statements.push(stmt);
                 pos = next_pos;
             }
-            Err((msg, mut next_pos)) => {
-                let token = &tokens[next_pos];
-                let error = ParseError::new(token.line, token.lexeme.clone(), String::from(msg));
-                errors.push(Box::new(error));
+            Err(err) => {
+                let mut next_pos = err.pos;
+                errors.push(parse_error(&tokens, err));
 
                 // fast forward to next statement
                 if tokens[next_pos].token_type != TokenType::Eof {
@@ -65,6 +258,7 @@ pub fn parse(source: String) -> Result<Vec<ast::Stmt>, Vec<Box<Error>>> {
 
                     match next_tok.token_type {
                         TokenType::Class
+                        | TokenType::Trait
                         | TokenType::Fun
                         | TokenType::Var
                         | TokenType::For
@@ -85,571 +279,943 @@ pub fn parse(source: String) -> Result<Vec<ast::Stmt>, Vec<Box<Error>>> {
         }
     }
 
-    if errors.len() > 0 {
+    if !errors.is_empty() {
         Err(errors)
     } else {
         Ok(statements)
     }
 }
 
-fn declaration(tokens: &Vec<Token>, pos: usize) -> StmtResult {
+/// Splits `source` into tokens, ending with an `Eof` token. Comments and
+/// whitespace aren't tokens; what's between two tokens' byte offsets is
+/// one or the other.
+pub fn scan(source: String) -> Result<Vec<Token>, Vec<ParseError>> {
+    Scanner::new(source).scan_tokens()
+}
+
+/// Parses a single expression, rejecting anything left over after it.
+pub fn parse_expression(source: String) -> Result<ast::Expr, Vec<ParseError>> {
+    let scanner = Scanner::new(source);
+    let tokens = Tokens::new(scanner.scan_tokens()?, ParseOptions::default());
+    let (expr, pos) = expression(&tokens, 0).map_err(|err| vec![parse_error(&tokens, err)])?;
+    if tokens[pos].token_type != TokenType::Eof {
+        let err = error("Expect end of expression.", pos);
+        return Err(vec![parse_error(&tokens, err)]);
+    }
+    Ok(expr)
+}
+
+fn parse_error(tokens: &[Token], err: SyntaxError) -> ParseError {
+    let token = &tokens[err.pos];
+    log::debug!(
+        "syntax error at {:?} '{}': {}",
+        token.position(),
+        token.lexeme,
+        err.msg
+    );
+    let fix_it = suggest_fix(tokens, &err);
+    let mut error = ParseError::new(token.position(), token.lexeme.clone(), err.msg.into_owned())
+        .with_fix_it(fix_it);
+    error.at_end = token.token_type == TokenType::Eof;
+    error
+}
+
+#[cfg(feature = "std")]
+fn boxed(errors: Vec<ParseError>) -> Vec<Box<dyn Error>> {
+    errors
+        .into_iter()
+        .map(|e| Box::new(e) as Box<dyn Error>)
+        .collect()
+}
+
+fn declaration(tokens: &Tokens, pos: usize) -> StmtResult {
     match tokens[pos].token_type {
         TokenType::Class => class_declaration(tokens, pos + 1),
+        TokenType::Trait => trait_declaration(tokens, pos + 1),
         TokenType::Fun => fun_declaration(tokens, pos + 1),
+        TokenType::Var
+            if match_type(
+                &tokens[pos + 1],
+                vec![TokenType::LeftBracket, TokenType::LeftBrace],
+            ) =>
+        {
+            destructuring_declaration(tokens, pos + 1)
+        }
         TokenType::Var => var_declaration(tokens, pos + 1),
         _ => statement(tokens, pos),
     }
 }
 
-fn class_declaration(tokens: &Vec<Token>, pos: usize) -> StmtResult {
-    let (name, pos) = consume(TokenType::Identifier, tokens, pos, "Expect class name.")
-        .map_err(|msg| (msg, pos))?;
-    let (_, mut pos) = consume(
-        TokenType::LeftBrace,
+fn class_declaration(tokens: &Tokens, pos: usize) -> StmtResult {
+    let (name, mut pos) = declared_name(tokens, pos, "Expect class name.")?;
+    let mut traits: Vec<ast::Expr> = Vec::new();
+    if match_type(&tokens[pos], vec![TokenType::With]) {
+        loop {
+            let (name, next_pos) =
+                consume(TokenType::Identifier, tokens, pos + 1, "Expect trait name.")?;
+            traits.push(ast::Expr::variable(name));
+            pos = next_pos;
+            if !match_type(&tokens[pos], vec![TokenType::Comma]) {
+                break;
+            }
+        }
+    }
+    let mut pos = expect(
         tokens,
         pos,
+        TokenType::LeftBrace,
         "Expect '{' before class body.",
-    )
-    .map_err(|msg| (msg, pos))?;
+    )?;
+    let mut fields: Vec<ast::VarStmt> = Vec::new();
     let mut methods: Vec<ast::FunStmt> = Vec::new();
-    while !check_token(&tokens[pos], TokenType::RightBrace) {
-        let (stmt, next_pos) = fun_declaration(tokens, pos)?;
-        pos = next_pos;
-        if let ast::Stmt::Fun(fun) = stmt {
-            methods.push(fun);
+    while !check_token(&tokens[pos], TokenType::RightBrace)
+        && tokens[pos].token_type != TokenType::Eof
+    {
+        if check_token(&tokens[pos], TokenType::Var) {
+            let (field, next_pos) = variable(tokens, pos + 1)?;
+            fields.push(field);
+            pos = next_pos;
+        } else {
+            let (method, next_pos) = member_function(tokens, pos)?;
+            methods.push(method);
+            pos = next_pos;
         }
     }
-    let (_, pos) = consume(
-        TokenType::RightBrace,
+    let pos = expect(
         tokens,
         pos,
+        TokenType::RightBrace,
         "Expect '}' after class body.",
-    )
-    .map_err(|msg| (msg, pos))?;
+    )?;
+
+    Ok((
+        ast::Stmt::class_with_members(name, traits, fields, methods),
+        pos,
+    ))
+}
+
+fn trait_declaration(tokens: &Tokens, pos: usize) -> StmtResult {
+    let (name, pos) = declared_name(tokens, pos, "Expect trait name.")?;
+    let mut pos = expect(
+        tokens,
+        pos,
+        TokenType::LeftBrace,
+        "Expect '{' before trait body.",
+    )?;
+    let mut methods: Vec<ast::FunStmt> = Vec::new();
+    while !check_token(&tokens[pos], TokenType::RightBrace)
+        && tokens[pos].token_type != TokenType::Eof
+    {
+        let (method, next_pos) = member_function(tokens, pos)?;
+        methods.push(method);
+        pos = next_pos;
+    }
+    let pos = expect(
+        tokens,
+        pos,
+        TokenType::RightBrace,
+        "Expect '}' after trait body.",
+    )?;
+
+    Ok((ast::Stmt::trait_decl(name, methods), pos))
+}
 
-    Ok((ast::Stmt::class(name, methods), pos))
+fn fun_declaration(tokens: &Tokens, pos: usize) -> StmtResult {
+    let (fun, pos) = function(tokens, pos)?;
+    Ok((ast::Stmt::Fun(fun), pos))
 }
 
-fn fun_declaration<'a>(tokens: &'a Vec<Token>, mut pos: usize) -> StmtResult {
+/// A method in a class or trait body, which is declared without `fun`.
+fn member_function(tokens: &Tokens, pos: usize) -> FunStmtResult {
+    match tokens[pos].token_type {
+        TokenType::Identifier => function(tokens, pos),
+        TokenType::Fun => Err(error("Methods are declared without 'fun'.", pos)),
+        // Only a trait body gets here with `var`, and traits have no fields.
+        TokenType::Var => Err(error("Expect method name.", pos)),
+        _ => Err(name_error(tokens, pos, "Expect method name.")),
+    }
+}
+
+/// A named function, starting at its name.
+fn function(tokens: &Tokens, pos: usize) -> FunStmtResult {
     match tokens[pos].token_type {
         TokenType::Identifier => {
             let name = &tokens[pos];
-            pos += 1;
-            match tokens[pos].token_type {
+            match tokens[pos + 1].token_type {
                 TokenType::LeftParen => {
-                    pos += 1;
-                    let mut params: Vec<Token> = vec![];
-                    if !check_token(&tokens[pos], TokenType::RightParen) {
-                        loop {
-                            if params.len() >= 8 {
-                                return Err(("Cannot have more than 8 arguments.", pos));
-                            }
-
-                            match tokens[pos].token_type {
-                                TokenType::Identifier => params.push(tokens[pos].clone()),
-                                _ => return Err(("Expect parameter name.", pos)),
-                            };
-
-                            pos += 1;
-                            match tokens[pos].token_type {
-                                TokenType::Comma => pos = pos + 1,
-                                _ => break,
-                            }
-                        }
-                    }
+                    let (params, rest, body, pos) = function_body(tokens, pos + 2)?;
+                    Ok((ast::FunStmt::new(name.clone(), params, rest, body), pos))
+                }
+                _ => Err(expected(
+                    TokenType::LeftParen,
+                    "Expect '(' after function or method name.",
+                    pos + 1,
+                )),
+            }
+        }
+        _ => Err(name_error(tokens, pos, "Expect function or method name.")),
+    }
+}
+
+/// `fun (params) { body }` or `fun name(params) { body }` in an expression.
+fn function_expression(tokens: &Tokens, pos: usize) -> ExprResult {
+    if tokens.options.version < LanguageVersion::V2 {
+        return Err(error(
+            "Function expressions need language version 2 or later.",
+            pos,
+        ));
+    }
+    let keyword = &tokens[pos];
+    let (name, pos) = match tokens[pos + 1].token_type {
+        TokenType::Identifier => (Some(&tokens[pos + 1]), pos + 2),
+        _ if is_reserved(&tokens[pos + 1]) => {
+            return Err(name_error(tokens, pos + 1, "Expect function name."))
+        }
+        _ => (None, pos + 1),
+    };
+    let pos = expect(tokens, pos, TokenType::LeftParen, "Expect '(' after 'fun'.")?;
+    let (params, rest, body, pos) = function_body(tokens, pos)?;
+    Ok((ast::Expr::function(keyword, name, params, rest, body), pos))
+}
+
+/// Parses a parameter list and body, starting after the `(`. The last
+/// parameter may be a `...rest` parameter.
+fn function_body(tokens: &Tokens, mut pos: usize) -> FunctionResult {
+    let mut params: Vec<Token> = vec![];
+    let mut rest = None;
+    if !check_token(&tokens[pos], TokenType::RightParen) {
+        loop {
+            let max_parameters = tokens.options.limits.max_parameters;
+            if params.len() >= max_parameters {
+                let msg = format!("Cannot have more than {} parameters.", max_parameters);
+                return Err(error(msg, pos));
+            }
 
-                    match tokens[pos].token_type {
-                        TokenType::RightParen => match tokens[pos + 1].token_type {
-                            TokenType::LeftBrace => match block_statement(tokens, pos + 2) {
-                                Ok((body, pos)) => match body {
-                                    ast::Stmt::Block(block_stmt) => Ok((
-                                        ast::Stmt::function(name, params, block_stmt.statements),
-                                        pos,
-                                    )),
-                                    _ => Err(("Expect block.", pos)),
-                                },
-                                Err(err) => Err(err),
-                            },
-                            _ => Err(("Expect '{{' before function or method body.", pos)),
-                        },
-                        _ => Err(("Expect ')' after parameters.", pos)),
+            match tokens[pos].token_type {
+                TokenType::Identifier => params.push(tokens[pos].clone()),
+                TokenType::DotDotDot => {
+                    let (name, next_pos) =
+                        declared_name(tokens, pos + 1, "Expect parameter name after '...'.")?;
+                    rest = Some(name.clone());
+                    let (next_pos, more) = separator(tokens, next_pos, TokenType::RightParen);
+                    if more {
+                        return Err(error(
+                            "A '...' parameter must be the last one.",
+                            next_pos - 1,
+                        ));
                     }
+                    pos = next_pos;
+                    break;
                 }
-                _ => Err(("Expect '(' after function or method name.", pos)),
+                _ => return Err(name_error(tokens, pos, "Expect parameter name.")),
+            };
+
+            let (next_pos, more) = separator(tokens, pos + 1, TokenType::RightParen);
+            pos = next_pos;
+            if !more {
+                break;
             }
         }
-        _ => Err(("Expect function or method name.", pos)),
     }
+
+    let pos = expect(
+        tokens,
+        pos,
+        TokenType::RightParen,
+        "Expect ')' after parameters.",
+    )?;
+    let pos = expect(
+        tokens,
+        pos,
+        TokenType::LeftBrace,
+        "Expect '{' before function or method body.",
+    )?;
+    match block_statement(tokens, pos)? {
+        (ast::Stmt::Block(block_stmt), pos) => Ok((params, rest, block_stmt.statements, pos)),
+        (_, pos) => Err(error("Expect block.", pos)),
+    }
+}
+
+fn var_declaration(tokens: &Tokens, pos: usize) -> StmtResult {
+    let (var, pos) = variable(tokens, pos)?;
+    Ok((ast::Stmt::Var(var), pos))
 }
 
-fn var_declaration(tokens: &Vec<Token>, mut pos: usize) -> StmtResult {
+/// A variable or field declaration, starting after `var`.
+fn variable(tokens: &Tokens, mut pos: usize) -> VarStmtResult {
     if match_type(&tokens[pos], vec![TokenType::Identifier]) {
-        let name = &tokens[pos];
+        let name = tokens[pos].clone();
         pos += 1;
         if match_type(&tokens[pos], vec![TokenType::Equal]) {
             let (initializer, pos) = expression(tokens, pos + 1)?;
-            if match_type(&tokens[pos], vec![TokenType::Semicolon]) {
-                let stmt = ast::Stmt::var_init(name, initializer);
-                Ok((stmt, pos + 1))
-            } else {
-                Err(("Expect ';' after variable declaration.", pos))
-            }
+            let pos = end_statement(tokens, pos, "Expect ';' after variable declaration.")?;
+            Ok((ast::VarStmt::new(name, Some(initializer)), pos))
         } else {
-            if match_type(&tokens[pos], vec![TokenType::Semicolon]) {
-                let stmt = ast::Stmt::var(name);
-                Ok((stmt, pos + 1))
-            } else {
-                Err(("Expect ';' after variable declaration.", pos))
-            }
+            let pos = end_statement(tokens, pos, "Expect ';' after variable declaration.")?;
+            Ok((ast::VarStmt::new(name, None), pos))
         }
     } else {
-        Err(("Expect variable name.", pos))
+        Err(name_error(tokens, pos, "Expect variable name."))
     }
 }
 
-fn statement(tokens: &Vec<Token>, pos: usize) -> StmtResult {
+fn statement(tokens: &Tokens, pos: usize) -> StmtResult {
+    nested(tokens, pos, statement_kind)
+}
+
+fn statement_kind(tokens: &Tokens, pos: usize) -> StmtResult {
     match tokens[pos].token_type {
         TokenType::If => if_statement(tokens, pos + 1),
         TokenType::For => for_statement(tokens, pos + 1),
         TokenType::LeftBrace => block_statement(tokens, pos + 1),
-        TokenType::Print => print_statement(tokens, pos + 1),
+        TokenType::Print => print_statement(tokens, pos),
         TokenType::Return => return_statement(tokens, pos + 1),
         TokenType::While => while_statement(tokens, pos + 1),
         _ => expression_statement(tokens, pos),
     }
 }
 
-fn if_statement(tokens: &Vec<Token>, pos: usize) -> StmtResult {
-    match tokens[pos].token_type {
-        TokenType::LeftParen => match expression(tokens, pos + 1) {
-            Ok((condition, pos)) => match tokens[pos].token_type {
-                TokenType::RightParen => match statement(tokens, pos + 1) {
-                    Ok((then_branch, pos)) => match tokens[pos].token_type {
-                        TokenType::Else => match statement(tokens, pos + 1) {
-                            Ok((else_branch, pos)) => Ok((
-                                ast::Stmt::if_then_else(condition, then_branch, else_branch),
-                                pos,
-                            )),
-                            Err(err) => Err(err),
-                        },
-                        _ => Ok((ast::Stmt::if_then(condition, then_branch), pos)),
-                    },
-                    Err(err) => Err(err),
-                },
-                _ => Err(("Expect ')' after if condition.", pos)),
-            },
-            Err(err) => Err(err),
-        },
-        _ => Err(("Expect '(' after 'if'.", pos)),
+fn if_statement(tokens: &Tokens, pos: usize) -> StmtResult {
+    let (condition, pos) = parenthesized(
+        tokens,
+        pos,
+        ("Expect '(' after 'if'.", "Expect ')' after if condition."),
+        condition,
+    )?;
+    let (then_branch, pos) = statement(tokens, pos)?;
+    if !check_token(&tokens[pos], TokenType::Else) {
+        return Ok((ast::Stmt::if_then(condition, then_branch), pos));
     }
+    let (else_branch, pos) = statement(tokens, pos + 1)?;
+    Ok((
+        ast::Stmt::if_then_else(condition, then_branch, else_branch),
+        pos,
+    ))
 }
 
-fn for_statement(tokens: &Vec<Token>, mut pos: usize) -> StmtResult {
-    let mut initializer = None;
-    let mut condition = None;
-    let mut increment = None;
-    match tokens[pos].token_type {
-        TokenType::LeftParen => {
-            if let Some(stmt_result) = for_stmt_initializer(tokens, pos + 1) {
-                match stmt_result {
-                    Ok((init, next_pos)) => {
-                        initializer = Some(init);
-                        pos = next_pos;
-                    }
-                    Err(err) => return Err(err),
-                }
-            } else {
-                pos = pos + 2;
-            }
-
-            if let Some(expr_result) = for_stmt_condition(tokens, pos) {
-                match expr_result {
-                    Ok((cond, next_pos)) => {
-                        condition = Some(cond);
-                        pos = next_pos;
-                    }
-                    Err(err) => return Err(err),
-                }
-            }
+/// Desugars `for (init; cond; inc) body` into `{ init; while (cond) { body; inc; } }`.
+///
+/// As in jlox, the loop variable lives in the outer block and is shared by
+/// every iteration, so closures created in the body all see its latest value.
+/// The body itself is still a fresh scope on each pass: functions and
+/// variables declared inside it are new for every iteration.
+fn for_statement(tokens: &Tokens, pos: usize) -> StmtResult {
+    let pos = expect(tokens, pos, TokenType::LeftParen, "Expect '(' after 'for'.")?;
+    let (initializer, pos) = match tokens[pos].token_type {
+        TokenType::Semicolon => (None, pos + 1),
+        TokenType::Var => {
+            let (init, pos) = var_declaration(tokens, pos + 1)?;
+            (Some(init), pos)
+        }
+        _ => {
+            let (init, pos) = expression_statement(tokens, pos)?;
+            (Some(init), pos)
+        }
+    };
+    let (condition, pos) = optional_expression(tokens, pos, TokenType::Semicolon, condition)?;
+    let pos = expect(
+        tokens,
+        pos,
+        TokenType::Semicolon,
+        "Expect ';' after loop condition.",
+    )?;
+    let (increment, pos) = optional_expression(tokens, pos, TokenType::RightParen, expression)?;
+    let pos = expect(
+        tokens,
+        pos,
+        TokenType::RightParen,
+        "Expect ')' after for clauses.",
+    )?;
+    let (body, pos) = statement(tokens, pos)?;
 
-            match tokens[pos].token_type {
-                TokenType::Semicolon => pos = pos + 1,
-                _ => return Err(("Expect ';' after loop condition.", pos)),
-            }
+    let mut tree = match increment {
+        Some(inc_expr) => ast::Stmt::block(vec![body, ast::Stmt::expr(inc_expr)]),
+        None => body,
+    };
+    let condition = condition.unwrap_or_else(|| ast::Expr::literal(Literal::True));
+    tree = ast::Stmt::while_loop(condition, tree);
+    match initializer {
+        Some(init_stmt) => Ok((ast::Stmt::block(vec![init_stmt, tree]), pos)),
+        None => Ok((tree, pos)),
+    }
+}
 
-            if let Some(expr_result) = for_stmt_increment(tokens, pos) {
-                match expr_result {
-                    Ok((inc, next_pos)) => {
-                        increment = Some(inc);
-                        pos = next_pos;
-                    }
-                    Err(err) => return Err(err),
-                }
-            }
+/// An expression parsed with `parse`, unless the next token is `end`, as in
+/// an empty `for` clause.
+fn optional_expression(
+    tokens: &Tokens,
+    pos: usize,
+    end: TokenType,
+    parse: fn(&Tokens, usize) -> ExprResult,
+) -> Result<(Option<ast::Expr>, usize), SyntaxError> {
+    if tokens[pos].token_type == end {
+        return Ok((None, pos));
+    }
+    let (expr, pos) = parse(tokens, pos)?;
+    Ok((Some(expr), pos))
+}
 
-            match tokens[pos].token_type {
-                TokenType::RightParen => pos = pos + 1,
-                _ => return Err(("Expect ')' after for clauses.", pos)),
-            }
+fn while_statement(tokens: &Tokens, pos: usize) -> StmtResult {
+    let (condition, pos) = parenthesized(
+        tokens,
+        pos,
+        (
+            "Expect '(' after 'while'.",
+            "Expect ')' after while condition.",
+        ),
+        condition,
+    )?;
+    let (body, pos) = statement(tokens, pos)?;
+    Ok((ast::Stmt::while_loop(condition, body), pos))
+}
 
-            match statement(tokens, pos) {
-                Ok((body, pos)) => {
-                    let mut tree = match increment {
-                        Some(inc_expr) => ast::Stmt::block(vec![body, ast::Stmt::expr(inc_expr)]),
-                        None => body,
-                    };
-                    tree = match condition {
-                        Some(cond_expr) => ast::Stmt::while_loop(cond_expr, tree),
-                        None => ast::Stmt::while_loop(ast::Expr::literal(Literal::True), tree),
-                    };
-                    match initializer {
-                        Some(init_stmt) => Ok((ast::Stmt::block(vec![init_stmt, tree]), pos)),
-                        None => Ok((tree, pos)),
-                    }
-                }
-                Err(err) => Err(err),
-            }
-        }
-        _ => Err(("Expect '(' after 'for'.", pos)),
+fn print_statement(tokens: &Tokens, pos: usize) -> StmtResult {
+    let keyword = &tokens[pos];
+    let (expr, mut pos) = expression(tokens, pos + 1)?;
+    let mut exprs = vec![expr];
+    while tokens[pos].token_type == TokenType::Comma {
+        let (expr, next_pos) = expression(tokens, pos + 1)?;
+        exprs.push(expr);
+        pos = next_pos;
     }
+    let pos = end_statement(tokens, pos, "Expect ';' after value.")?;
+    Ok((ast::Stmt::print(keyword, exprs), pos))
 }
 
-fn for_stmt_initializer(tokens: &Vec<Token>, pos: usize) -> Option<StmtResult> {
-    match tokens[pos].token_type {
-        TokenType::Semicolon => None,
-        TokenType::Var => Some(var_declaration(tokens, pos + 1)),
-        _ => Some(expression_statement(tokens, pos + 1)),
-    }
+fn return_statement(tokens: &Tokens, pos: usize) -> StmtResult {
+    let keyword = &tokens[pos - 1];
+    let (value, pos) =
+        if check_token(&tokens[pos], TokenType::Semicolon) || implicit_semicolon(tokens, pos) {
+            (None, pos)
+        } else {
+            let (expr, pos) = expression(tokens, pos)?;
+            (Some(expr), pos)
+        };
+    let pos = end_statement(tokens, pos, "Expect ';' after return value.")?;
+    Ok((ast::Stmt::ret(keyword, value), pos))
 }
 
-fn for_stmt_condition(tokens: &Vec<Token>, pos: usize) -> Option<ExprResult> {
-    match tokens[pos].token_type {
-        TokenType::Semicolon => None,
-        _ => Some(expression(tokens, pos)),
+/// Consumes the `;` ending a statement, returning the position after it. In
+/// optional-semicolon mode a line break or the end of input also ends it.
+fn end_statement(tokens: &Tokens, pos: usize, err_msg: &'static str) -> Result<usize, SyntaxError> {
+    if tokens[pos].token_type == TokenType::Semicolon {
+        Ok(pos + 1)
+    } else if implicit_semicolon(tokens, pos) {
+        Ok(pos)
+    } else {
+        Err(expected(TokenType::Semicolon, err_msg, pos))
     }
 }
 
-fn for_stmt_increment(tokens: &Vec<Token>, pos: usize) -> Option<ExprResult> {
-    match tokens[pos].token_type {
-        TokenType::RightParen => None,
-        _ => Some(expression(tokens, pos)),
-    }
+fn implicit_semicolon(tokens: &Tokens, pos: usize) -> bool {
+    tokens.options.optional_semicolons
+        && pos > 0
+        && (tokens[pos].token_type == TokenType::Eof || tokens[pos].line > tokens[pos - 1].line)
 }
 
-fn while_statement(tokens: &Vec<Token>, pos: usize) -> StmtResult {
-    match tokens[pos].token_type {
-        TokenType::LeftParen => match expression(tokens, pos + 1) {
-            Ok((condition, pos)) => match tokens[pos].token_type {
-                TokenType::RightParen => match statement(tokens, pos + 1) {
-                    Ok((body, pos)) => Ok((ast::Stmt::while_loop(condition, body), pos)),
-                    Err(err) => Err(err),
-                },
-                _ => Err(("Expect ')' after if condition.", pos)),
-            },
-            Err(err) => Err(err),
-        },
-        _ => Err(("Expect '(' after 'while'.", pos)),
+/// Parses `var [a, b] = value;` or `var {x, y} = value;`, starting at the
+/// `[` or `{`.
+fn destructuring_declaration(tokens: &Tokens, pos: usize) -> StmtResult {
+    let open = &tokens[pos];
+    let is_array = open.token_type == TokenType::LeftBracket;
+    let (close, close_msg) = if is_array {
+        (TokenType::RightBracket, "Expect ']' after names.")
+    } else {
+        (TokenType::RightBrace, "Expect '}' after names.")
+    };
+
+    let mut names: Vec<Token> = vec![];
+    let mut pos = pos + 1;
+    loop {
+        let (name, next_pos) = declared_name(tokens, pos, "Expect variable name.")?;
+        names.push(name.clone());
+        pos = next_pos;
+        if !match_type(&tokens[pos], vec![TokenType::Comma]) {
+            break;
+        }
+        pos += 1;
     }
+    let pos = expect(tokens, pos, close, close_msg)?;
+    let pos = expect(
+        tokens,
+        pos,
+        TokenType::Equal,
+        "Expect '=' after destructuring pattern.",
+    )?;
+    let (initializer, pos) = expression(tokens, pos)?;
+    let pos = end_statement(tokens, pos, "Expect ';' after variable declaration.")?;
+    Ok((ast::Stmt::destructure(open, names, initializer), pos))
 }
 
-fn print_statement(tokens: &Vec<Token>, pos: usize) -> StmtResult {
-    match expression(tokens, pos) {
-        Ok((expr, pos)) => {
-            let next_tok = &tokens[pos];
-            if match_type(next_tok, vec![TokenType::Semicolon]) {
-                let stmt = ast::Stmt::print(expr);
-                Ok((stmt, pos + 1))
-            } else {
-                Err(("Expect ';' after value.", pos))
-            }
+fn block_statement(tokens: &Tokens, mut pos: usize) -> StmtResult {
+    if tokens.options.allows_expr_blocks() {
+        // A trailing expression is allowed but its value isn't used.
+        let (mut statements, value, pos) = block_body(tokens, pos, false)?;
+        if let Some(value) = value {
+            statements.push(ast::Stmt::expr(value));
         }
-        Err(err) => Err(err),
+        return Ok((ast::Stmt::block(statements), pos));
     }
-}
 
-fn return_statement(tokens: &Vec<Token>, mut pos: usize) -> StmtResult {
-    let keyword = &tokens[pos - 1];
+    let mut statements: Vec<ast::Stmt> = vec![];
+    while !check_token(&tokens[pos], TokenType::RightBrace)
+        && tokens[pos].token_type != TokenType::Eof
+    {
+        let (stmt, next_pos) = declaration(tokens, pos)?;
+        statements.push(stmt);
+        pos = next_pos;
+    }
+    let pos = expect(
+        tokens,
+        pos,
+        TokenType::RightBrace,
+        "Expect '}' after block.",
+    )?;
+    Ok((ast::Stmt::block(statements), pos))
+}
 
+/// Parses the inside of a block under `expr-blocks`, up to and including the
+/// `}`. An expression right before the `}` needs no `;` and becomes the
+/// block's value. If the block's value is `used`, a nested `if` or block
+/// right before the `}` is parsed as an expression, so it can be the value
+/// too. Anywhere else they're statements, which `return` can leave.
+fn block_body(
+    tokens: &Tokens,
+    mut pos: usize,
+    used: bool,
+) -> Result<(Vec<ast::Stmt>, Option<ast::Expr>, usize), SyntaxError> {
+    let mut statements = vec![];
     let mut value = None;
-    if !check_token(&tokens[pos], TokenType::Semicolon) {
-        match expression(tokens, pos) {
-            Ok((expr, next_pos)) => {
-                pos = next_pos;
-                value = Some(expr);
-            }
-            Err(err) => return Err(err),
+    while !check_token(&tokens[pos], TokenType::RightBrace)
+        && tokens[pos].token_type != TokenType::Eof
+    {
+        let end = match tokens[pos].token_type {
+            TokenType::If => if_expression_end(tokens, pos),
+            TokenType::LeftBrace => closing_bracket(tokens, pos).map(|close| close + 1),
+            _ => None,
+        };
+        let is_expression = match end {
+            Some(end) => used && check_token(&tokens[end], TokenType::RightBrace),
+            None => !starts_statement(&tokens[pos]),
+        };
+        if !is_expression {
+            let (stmt, next_pos) = declaration(tokens, pos)?;
+            statements.push(stmt);
+            pos = next_pos;
+            continue;
         }
+        let (expr, next_pos) = expression(tokens, pos)?;
+        if check_token(&tokens[next_pos], TokenType::RightBrace) {
+            value = Some(expr);
+            pos = next_pos;
+            break;
+        }
+        pos = end_statement(tokens, next_pos, "Expect ';' after value.")?;
+        statements.push(ast::Stmt::expr(expr));
     }
+    let pos = expect(
+        tokens,
+        pos,
+        TokenType::RightBrace,
+        "Expect '}' after block.",
+    )?;
+    Ok((statements, value, pos))
+}
 
-    match tokens[pos].token_type {
-        TokenType::Semicolon => Ok((ast::Stmt::ret(keyword, value), pos + 1)),
-        _ => Err(("Expect ';' after return value.", pos)),
+/// Where the `if` at `pos` ends, if it has the shape of an if expression,
+/// with blocks for branches, rather than being an if statement. Only
+/// brackets are looked at, so this doesn't need to backtrack.
+fn if_expression_end(tokens: &Tokens, pos: usize) -> Option<usize> {
+    let then_branch = match closing_bracket(tokens, pos + 1) {
+        Some(close) if check_token(&tokens[close + 1], TokenType::LeftBrace) => close + 1,
+        _ => return None,
+    };
+    let after_then = closing_bracket(tokens, then_branch)? + 1;
+    if !check_token(&tokens[after_then], TokenType::Else) {
+        return Some(after_then);
+    }
+    match tokens[after_then + 1].token_type {
+        TokenType::LeftBrace => closing_bracket(tokens, after_then + 1).map(|close| close + 1),
+        TokenType::If => if_expression_end(tokens, after_then + 1),
+        _ => None,
     }
 }
 
-fn block_statement(tokens: &Vec<Token>, mut pos: usize) -> StmtResult {
-    let mut statements: Vec<ast::Stmt> = vec![];
-
-    let mut next_tok = &tokens[pos];
-    while !match_type(next_tok, vec![TokenType::RightBrace])
-        && next_tok.token_type != TokenType::Eof
-    {
-        match declaration(tokens, pos) {
-            Ok((statement, next_pos)) => {
-                statements.push(statement);
-                pos = next_pos;
+/// The position of the bracket closing the one at `pos`, if it is an opening
+/// bracket and is closed.
+fn closing_bracket(tokens: &Tokens, pos: usize) -> Option<usize> {
+    if !match_type(
+        &tokens[pos],
+        vec![
+            TokenType::LeftParen,
+            TokenType::LeftBrace,
+            TokenType::LeftBracket,
+        ],
+    ) {
+        return None;
+    }
+    let mut depth = 0;
+    for (i, token) in tokens.tokens.iter().enumerate().skip(pos) {
+        match token.token_type {
+            TokenType::LeftParen | TokenType::LeftBrace | TokenType::LeftBracket => depth += 1,
+            TokenType::RightParen | TokenType::RightBrace | TokenType::RightBracket => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
             }
-            Err(err) => return Err(err),
+            _ => (),
         }
-        next_tok = &tokens[pos];
     }
+    None
+}
 
-    // consume right brace
-    match next_tok.token_type {
-        TokenType::RightBrace => Ok((ast::Stmt::block(statements), pos + 1)),
-        _ => Err(("Expect '}' after block.", pos)),
+/// Whether a token starts a declaration or a statement other than an
+/// expression statement.
+fn starts_statement(token: &Token) -> bool {
+    matches!(
+        token.token_type,
+        TokenType::Class
+            | TokenType::Trait
+            | TokenType::Fun
+            | TokenType::Var
+            | TokenType::For
+            | TokenType::If
+            | TokenType::LeftBrace
+            | TokenType::Print
+            | TokenType::Return
+            | TokenType::While
+    )
+}
+
+fn block_expression(tokens: &Tokens, pos: usize) -> ExprResult {
+    let (statements, value, next_pos) = block_body(tokens, pos + 1, true)?;
+    Ok((ast::Expr::block(&tokens[pos], statements, value), next_pos))
+}
+
+/// `if (condition) { ... } else { ... }` as an expression. Both branches
+/// must be blocks, except that `else if` chains.
+fn if_expression(tokens: &Tokens, pos: usize) -> ExprResult {
+    let keyword = &tokens[pos];
+    let (condition, pos) = parenthesized(
+        tokens,
+        pos + 1,
+        ("Expect '(' after 'if'.", "Expect ')' after if condition."),
+        condition,
+    )?;
+    let (then_branch, pos) = if_branch(tokens, pos)?;
+    if !check_token(&tokens[pos], TokenType::Else) {
+        return Ok((
+            ast::Expr::if_else(keyword, condition, then_branch, None),
+            pos,
+        ));
     }
+    let (else_branch, pos) = if check_token(&tokens[pos + 1], TokenType::If) {
+        if_expression(tokens, pos + 1)?
+    } else {
+        if_branch(tokens, pos + 1)?
+    };
+    Ok((
+        ast::Expr::if_else(keyword, condition, then_branch, Some(else_branch)),
+        pos,
+    ))
 }
 
-fn expression_statement(tokens: &Vec<Token>, pos: usize) -> StmtResult {
-    match expression(tokens, pos) {
-        Ok((expr, pos)) => {
-            let next_tok = &tokens[pos];
-            if match_type(next_tok, vec![TokenType::Semicolon]) {
-                let stmt = ast::Stmt::expr(expr);
-                Ok((stmt, pos + 1))
-            } else {
-                Err(("Expect ';' after value.", pos))
-            }
-        }
-        Err(err) => Err(err),
+fn if_branch(tokens: &Tokens, pos: usize) -> ExprResult {
+    if !check_token(&tokens[pos], TokenType::LeftBrace) {
+        return Err(expected(
+            TokenType::LeftBrace,
+            "Expect '{' before the branch of an if expression.",
+            pos,
+        ));
     }
+    block_expression(tokens, pos)
 }
 
-fn expression(tokens: &Vec<Token>, pos: usize) -> ExprResult {
-    assignment(tokens, pos)
+fn expression_statement(tokens: &Tokens, pos: usize) -> StmtResult {
+    let (expr, pos) = expression(tokens, pos)?;
+    let pos = end_statement(tokens, pos, "Expect ';' after value.")?;
+    Ok((ast::Stmt::expr(expr), pos))
 }
 
-fn assignment(tokens: &Vec<Token>, pos: usize) -> ExprResult {
-    match or(tokens, pos) {
-        Ok((expr, pos)) => {
-            if match_type(&tokens[pos], vec![TokenType::Equal]) {
-                let value = assignment(tokens, pos + 1);
+fn expression(tokens: &Tokens, pos: usize) -> ExprResult {
+    nested(tokens, pos, assignment)
+}
 
-                match expr {
-                    ast::Expr::Variable(var_expr) => {
-                        let name = var_expr.name;
-                        match value {
-                            Ok((val_expr, pos)) => Ok((ast::Expr::assign(&name, val_expr), pos)),
-                            Err(err) => Err(err),
-                        }
-                    }
-                    _ => Err(("Invalid assignment target.", pos)),
-                }
-            } else {
-                Ok((expr, pos))
-            }
+/// The condition of an `if`, `while` or `for`, where `a = b` at the top is
+/// likely meant as `a == b`.
+fn condition(tokens: &Tokens, pos: usize) -> ExprResult {
+    let outer = tokens.condition.replace(Some(tokens.depth.get() + 1));
+    let result = expression(tokens, pos);
+    tokens.condition.set(outer);
+    result
+}
+
+fn assignment(tokens: &Tokens, pos: usize) -> ExprResult {
+    let (expr, pos) = coalesce(tokens, pos)?;
+    if !match_type(&tokens[pos], vec![TokenType::Equal]) {
+        return Ok((expr, pos));
+    }
+    let value = nested(tokens, pos + 1, assignment);
+    match expr {
+        ast::Expr::Variable(var_expr) => {
+            let (val_expr, pos) = value?;
+            Ok((ast::Expr::assign(&var_expr.name, val_expr), pos))
         }
-        Err(err) => Err(err),
+        ast::Expr::Get(get_expr) if !get_expr.safe => {
+            let (val_expr, pos) = value?;
+            Ok((
+                ast::Expr::set(*get_expr.object, &get_expr.name, val_expr),
+                pos,
+            ))
+        }
+        ast::Expr::Index(index_expr) => {
+            let (val_expr, pos) = value?;
+            Ok((
+                ast::Expr::set_index(
+                    *index_expr.object,
+                    &index_expr.bracket,
+                    *index_expr.index,
+                    val_expr,
+                ),
+                pos,
+            ))
+        }
+        // There's nothing to assign to when the object is nil.
+        ast::Expr::Get(_) => Err(error("Invalid assignment target.", pos)),
+        _ if tokens.condition.get() == Some(tokens.depth.get()) => Err(expected(
+            TokenType::EqualEqual,
+            "Invalid assignment target.",
+            pos,
+        )),
+        _ => Err(error("Invalid assignment target.", pos)),
     }
 }
 
-fn or(tokens: &Vec<Token>, pos: usize) -> ExprResult {
-    match and(tokens, pos) {
-        Ok((mut expr, mut pos)) => {
-            let mut next_tok = &tokens[pos];
-            while match_type(next_tok, vec![TokenType::Or]) {
-                let operator = &tokens[pos];
-                match and(tokens, pos + 1) {
-                    Ok((right, next_pos)) => {
-                        pos = next_pos;
-                        expr = ast::Expr::logical(expr, operator, right);
-                    }
-                    Err(err) => return Err(err),
-                }
-                next_tok = &tokens[pos];
-            }
-            Ok((expr, pos))
+/// `a ?? b` is `a` unless it's nil. It binds more loosely than `or`.
+fn coalesce(tokens: &Tokens, pos: usize) -> ExprResult {
+    if tokens.options.version < LanguageVersion::V2 {
+        let (expr, pos) = or(tokens, pos)?;
+        if check_token(&tokens[pos], TokenType::QuestionQuestion) {
+            return Err(error("'??' needs language version 2 or later.", pos));
         }
-        Err(err) => Err(err),
+        return Ok((expr, pos));
     }
+    logical_rule(tokens, pos, LogicalOp::Coalesce, or)
 }
 
-fn and(tokens: &Vec<Token>, pos: usize) -> ExprResult {
-    match equality(tokens, pos) {
-        Ok((mut expr, mut pos)) => {
-            let mut next_tok = &tokens[pos];
-            while match_type(next_tok, vec![TokenType::And]) {
-                let operator = &tokens[pos];
-                match equality(tokens, pos + 1) {
-                    Ok((right, next_pos)) => {
-                        pos = next_pos;
-                        expr = ast::Expr::logical(expr, operator, right);
-                    }
-                    Err(err) => return Err(err),
-                }
-                next_tok = &tokens[pos];
-            }
-            Ok((expr, pos))
-        }
-        Err(err) => Err(err),
+fn or(tokens: &Tokens, pos: usize) -> ExprResult {
+    logical_rule(tokens, pos, LogicalOp::Or, and)
+}
+
+fn and(tokens: &Tokens, pos: usize) -> ExprResult {
+    logical_rule(tokens, pos, LogicalOp::And, equality)
+}
+
+fn equality(tokens: &Tokens, pos: usize) -> ExprResult {
+    let (mut expr, mut pos) = comparison(tokens, pos)?;
+    let ops = [BinaryOp::NotEqual, BinaryOp::Equal, BinaryOp::Is];
+    while let Some(operator) = binary_op(&tokens[pos], &ops) {
+        let span = tokens[pos].span();
+        let (right, next_pos) = if operator == BinaryOp::Is {
+            // The right side names a class or trait, nothing more.
+            let (name, next_pos) = consume(
+                TokenType::Identifier,
+                tokens,
+                pos + 1,
+                "Expect class or trait name after 'is'.",
+            )?;
+            (ast::Expr::variable(name), next_pos)
+        } else {
+            comparison(tokens, pos + 1)?
+        };
+        expr = ast::Expr::binary_at(expr, operator, span, right);
+        pos = next_pos;
     }
+    Ok((expr, pos))
 }
 
-fn equality(tokens: &Vec<Token>, pos: usize) -> ExprResult {
-    match comparison(tokens, pos) {
-        Ok((mut expr, mut pos)) => {
-            let mut next_tok = &tokens[pos];
-            while match_type(next_tok, vec![TokenType::BangEqual, TokenType::EqualEqual]) {
-                let operator = &tokens[pos];
-                match comparison(tokens, pos + 1) {
-                    Ok((right, next_pos)) => {
-                        pos = next_pos;
-                        expr = ast::Expr::binary(expr, operator, right);
-                    }
-                    Err(err) => return Err(err),
-                }
-                next_tok = &tokens[pos];
-            }
-            Ok((expr, pos))
-        }
-        Err(err) => Err(err),
-    }
-}
-
-fn comparison(tokens: &Vec<Token>, pos: usize) -> ExprResult {
-    match addition(tokens, pos) {
-        Ok((mut expr, mut pos)) => {
-            let mut next_tok = &tokens[pos];
-            while match_type(
-                next_tok,
-                vec![
-                    TokenType::Greater,
-                    TokenType::GreaterEqual,
-                    TokenType::Less,
-                    TokenType::LessEqual,
-                ],
-            ) {
-                let operator = &tokens[pos];
-                match addition(tokens, pos + 1) {
-                    Ok((right, next_pos)) => {
-                        pos = next_pos;
-                        expr = ast::Expr::binary(expr, operator, right);
-                    }
-                    Err(err) => return Err(err),
-                }
-                next_tok = &tokens[pos];
-            }
-            Ok((expr, pos))
-        }
-        Err(err) => Err(err),
+fn comparison(tokens: &Tokens, pos: usize) -> ExprResult {
+    let ops = [
+        BinaryOp::Greater,
+        BinaryOp::GreaterEqual,
+        BinaryOp::Less,
+        BinaryOp::LessEqual,
+    ];
+    let (left, pos) = addition(tokens, pos)?;
+    let operator = match binary_op(&tokens[pos], &ops) {
+        Some(operator) => operator,
+        None => return Ok((left, pos)),
+    };
+    let span = tokens[pos].span();
+    let (right, pos) = addition(tokens, pos + 1)?;
+    // `a < b < c` would compare a boolean with c, which is never what was
+    // meant.
+    if binary_op(&tokens[pos], &ops).is_some() {
+        return Err(error(
+            "Comparisons cannot be chained; use 'and', as in 'a < b and b < c'.",
+            pos,
+        ));
     }
+    Ok((ast::Expr::binary_at(left, operator, span, right), pos))
 }
 
-fn addition(tokens: &Vec<Token>, pos: usize) -> ExprResult {
-    match multiplication(tokens, pos) {
-        Ok((mut expr, mut pos)) => {
-            let mut next_tok = &tokens[pos];
-            while match_type(next_tok, vec![TokenType::Plus, TokenType::Minus]) {
-                let operator = &tokens[pos];
-                match multiplication(tokens, pos + 1) {
-                    Ok((right, next_pos)) => {
-                        pos = next_pos;
-                        expr = ast::Expr::binary(expr, operator, right);
-                    }
-                    Err(err) => return Err(err),
-                }
-                next_tok = &tokens[pos];
-            }
-            Ok((expr, pos))
+/// The operator `token` stands for, if it's one of `ops`.
+fn binary_op(token: &Token, ops: &[BinaryOp]) -> Option<BinaryOp> {
+    BinaryOp::from_token_type(token.token_type).filter(|op| ops.contains(op))
+}
+
+fn addition(tokens: &Tokens, pos: usize) -> ExprResult {
+    binary_rule(
+        tokens,
+        pos,
+        &[BinaryOp::Add, BinaryOp::Subtract],
+        multiplication,
+    )
+}
+
+fn multiplication(tokens: &Tokens, pos: usize) -> ExprResult {
+    binary_rule(tokens, pos, &[BinaryOp::Multiply, BinaryOp::Divide], unary)
+}
+
+/// A left-associative chain of `operand`s joined by any of `ops`, as in
+/// `a + b - c`. Each precedence level of binary operators is one of these.
+fn binary_rule(tokens: &Tokens, pos: usize, ops: &[BinaryOp], operand: Rule) -> ExprResult {
+    let (mut expr, mut pos) = operand(tokens, pos)?;
+    while let Some(operator) = binary_op(&tokens[pos], ops) {
+        let span = tokens[pos].span();
+        let (right, next_pos) = operand(tokens, pos + 1)?;
+        expr = ast::Expr::binary_at(expr, operator, span, right);
+        pos = next_pos;
+    }
+    Ok((expr, pos))
+}
+
+/// Like `binary_rule`, for a logical operator.
+fn logical_rule(tokens: &Tokens, pos: usize, operator: LogicalOp, operand: Rule) -> ExprResult {
+    let (mut expr, mut pos) = operand(tokens, pos)?;
+    while LogicalOp::from_token_type(tokens[pos].token_type) == Some(operator) {
+        let span = tokens[pos].span();
+        let (right, next_pos) = operand(tokens, pos + 1)?;
+        expr = ast::Expr::logical_at(expr, operator, span, right);
+        pos = next_pos;
+    }
+    Ok((expr, pos))
+}
+
+fn unary(tokens: &Tokens, pos: usize) -> ExprResult {
+    let token = &tokens[pos];
+    match UnaryOp::from_token_type(token.token_type) {
+        Some(operator) => {
+            let (right, pos) = nested(tokens, pos + 1, unary)?;
+            Ok((ast::Expr::unary_at(operator, token.span(), right), pos))
         }
-        Err(err) => Err(err),
+        None => call(tokens, pos),
     }
 }
 
-fn multiplication(tokens: &Vec<Token>, pos: usize) -> ExprResult {
-    match unary(tokens, pos) {
-        Ok((mut expr, mut pos)) => {
-            let mut next_tok = &tokens[pos];
-            while match_type(next_tok, vec![TokenType::Star, TokenType::Slash]) {
-                let operator = &tokens[pos];
-                match unary(tokens, pos + 1) {
-                    Ok((right, next_pos)) => {
-                        pos = next_pos;
-                        expr = ast::Expr::binary(expr, operator, right);
-                    }
-                    Err(err) => return Err(err),
+fn call(tokens: &Tokens, pos: usize) -> ExprResult {
+    let (mut expr, mut pos) = primary(tokens, pos)?;
+    loop {
+        match tokens[pos].token_type {
+            TokenType::Dot => {
+                let (name, next_pos) = consume(
+                    TokenType::Identifier,
+                    tokens,
+                    pos + 1,
+                    "Expect property name after '.'.",
+                )?;
+                expr = ast::Expr::get(expr, name);
+                pos = next_pos;
+            }
+            TokenType::QuestionDot => {
+                if tokens.options.version < LanguageVersion::V2 {
+                    return Err(error("'?.' needs language version 2 or later.", pos));
                 }
-                next_tok = &tokens[pos];
+                let (name, next_pos) = consume(
+                    TokenType::Identifier,
+                    tokens,
+                    pos + 1,
+                    "Expect property name after '?.'.",
+                )?;
+                expr = ast::Expr::safe_get(expr, name);
+                pos = next_pos;
+            }
+            TokenType::LeftBracket => {
+                let bracket = &tokens[pos];
+                let (index, next_pos) = expression(tokens, pos + 1)?;
+                let next_pos = expect(
+                    tokens,
+                    next_pos,
+                    TokenType::RightBracket,
+                    "Expect ']' after index.",
+                )?;
+                expr = ast::Expr::index(expr, bracket, index);
+                pos = next_pos;
             }
-            Ok((expr, pos))
+            TokenType::LeftParen => {
+                let (args, keyword_args, next_pos) = arguments(tokens, pos + 1)?;
+                let (paren, next_pos) = consume(
+                    TokenType::RightParen,
+                    tokens,
+                    next_pos,
+                    "Expect ')' after arguments.",
+                )?;
+                expr = ast::Expr::call_with_keywords(expr, paren, args, keyword_args);
+                pos = next_pos;
+            }
+            _ => return Ok((expr, pos)),
         }
-        Err(err) => Err(err),
     }
 }
 
-fn unary(tokens: &Vec<Token>, pos: usize) -> ExprResult {
-    let next_tok = &tokens[pos];
-    if match_type(next_tok, vec![TokenType::Bang, TokenType::Minus]) {
-        let operator = &tokens[pos];
-        match unary(tokens, pos + 1) {
-            Ok((right, pos)) => Ok((ast::Expr::unary(operator, right), pos)),
-            Err(err) => Err(err),
+/// The arguments of a call, starting after the `(`: positional ones, which
+/// may be spread, and then `name: value` ones.
+fn arguments(tokens: &Tokens, mut pos: usize) -> ArgumentsResult {
+    let mut args: Vec<ast::Expr> = vec![];
+    let mut keyword_args: Vec<(Token, ast::Expr)> = vec![];
+    if check_token(&tokens[pos], TokenType::RightParen) {
+        return Ok((args, keyword_args, pos));
+    }
+    loop {
+        let max_arguments = tokens.options.limits.max_arguments;
+        if args.len() + keyword_args.len() >= max_arguments {
+            let msg = format!("Cannot have more than {} arguments.", max_arguments);
+            return Err(error(msg, pos));
         }
-    } else {
-        call(tokens, pos)
-    }
-}
-
-fn call(tokens: &Vec<Token>, pos: usize) -> ExprResult {
-    match primary(tokens, pos) {
-        Ok((mut expr, mut pos)) => {
-            loop {
-                match tokens[pos].token_type {
-                    TokenType::LeftParen => {
-                        let mut args: Vec<ast::Expr> = vec![];
-                        pos += 1;
-                        if !check_token(&tokens[pos], TokenType::RightParen) {
-                            loop {
-                                if args.len() >= 8 {
-                                    return Err(("Cannot have more than 8 arguments.", pos));
-                                }
-                                match expression(tokens, pos) {
-                                    Ok((arg, next_pos)) => {
-                                        args.push(arg);
-                                        pos = next_pos;
-                                    }
-                                    Err(err) => return Err(err),
-                                }
-
-                                match tokens[pos].token_type {
-                                    TokenType::Comma => pos += 1,
-                                    _ => break,
-                                }
-                            }
-                        }
-
-                        let paren;
-                        match tokens[pos].token_type {
-                            TokenType::RightParen => {
-                                paren = &tokens[pos];
-                                pos += 1;
-                            }
-                            _ => return Err(("Expect ')' after arguments.", pos)),
-                        }
-
-                        expr = ast::Expr::call(expr, paren, args);
-                    }
-                    _ => break,
-                }
+        let is_keyword = check_token(&tokens[pos], TokenType::Identifier)
+            && check_token(&tokens[pos + 1], TokenType::Colon);
+        if is_keyword {
+            let name = &tokens[pos];
+            if keyword_args.iter().any(|(n, _)| n.lexeme == name.lexeme) {
+                return Err(error("Duplicate keyword argument.", pos));
             }
-            Ok((expr, pos))
+            let (arg, next_pos) = expression(tokens, pos + 2)?;
+            keyword_args.push((name.clone(), arg));
+            pos = next_pos;
+        } else if !keyword_args.is_empty() {
+            return Err(error(
+                "Positional arguments must come before keyword arguments.",
+                pos,
+            ));
+        } else if check_token(&tokens[pos], TokenType::DotDotDot) {
+            let (arg, next_pos) = expression(tokens, pos + 1)?;
+            args.push(ast::Expr::spread(&tokens[pos], arg));
+            pos = next_pos;
+        } else {
+            let (arg, next_pos) = expression(tokens, pos)?;
+            args.push(arg);
+            pos = next_pos;
+        }
+        let (next_pos, more) = separator(tokens, pos, TokenType::RightParen);
+        if !more {
+            return Ok((args, keyword_args, next_pos));
         }
-        Err(err) => Err(err),
+        pos = next_pos;
     }
 }
 
-fn primary(tokens: &Vec<Token>, pos: usize) -> ExprResult {
+fn primary(tokens: &Tokens, pos: usize) -> ExprResult {
     let token = &tokens[pos];
     match token.token_type {
         TokenType::False => Ok((ast::Expr::literal(Literal::False), pos + 1)),
@@ -657,17 +1223,64 @@ fn primary(tokens: &Vec<Token>, pos: usize) -> ExprResult {
         TokenType::Nil => Ok((ast::Expr::literal(Literal::Nil), pos + 1)),
         TokenType::Number | TokenType::String => match token.literal.clone() {
             Some(literal) => Ok((ast::Expr::literal(literal), pos + 1)),
-            None => Err(("Expect literal value.", pos)),
-        },
-        TokenType::LeftParen => match expression(tokens, pos + 1) {
-            Ok((expr, pos)) => match (&tokens[pos]).token_type {
-                TokenType::RightParen => Ok((ast::Expr::grouping(expr), pos + 1)),
-                _ => Err(("Expect ')' after expression.", pos)),
-            },
-            Err(err) => Err(err),
+            None => Err(error("Expect literal value.", pos)),
         },
+        TokenType::LeftParen => {
+            let (expr, pos) = expression(tokens, pos + 1)?;
+            let pos = expect(
+                tokens,
+                pos,
+                TokenType::RightParen,
+                "Expect ')' after expression.",
+            )?;
+            Ok((ast::Expr::grouping(expr), pos))
+        }
+        TokenType::LeftBracket => array(tokens, pos),
+        TokenType::Fun => function_expression(tokens, pos),
+        TokenType::LeftBrace if tokens.options.allows_expr_blocks() => {
+            block_expression(tokens, pos)
+        }
+        TokenType::If if tokens.options.allows_expr_blocks() => if_expression(tokens, pos),
+        TokenType::This => Ok((ast::Expr::this(token), pos + 1)),
         TokenType::Identifier => Ok((ast::Expr::variable(token), pos + 1)),
-        _ => Err(("Expect expression", pos)),
+        _ => Err(error("Expect expression", pos)),
+    }
+}
+
+fn array(tokens: &Tokens, pos: usize) -> ExprResult {
+    let bracket = &tokens[pos];
+    let mut elements = vec![];
+    let mut pos = pos + 1;
+    if !check_token(&tokens[pos], TokenType::RightBracket) {
+        loop {
+            let (element, next_pos) = expression(tokens, pos)?;
+            elements.push(element);
+            let (next_pos, more) = separator(tokens, next_pos, TokenType::RightBracket);
+            pos = next_pos;
+            if !more {
+                break;
+            }
+        }
+    }
+    let pos = expect(
+        tokens,
+        pos,
+        TokenType::RightBracket,
+        "Expect ']' after array elements.",
+    )?;
+    Ok((ast::Expr::array(bracket, elements), pos))
+}
+
+/// Reads what follows an element of a list that ends with `close`: the
+/// position to go on from, and whether another element comes next. A
+/// comma before `close` is a trailing one and is skipped.
+fn separator(tokens: &Tokens, pos: usize, close: TokenType) -> (usize, bool) {
+    if !check_token(&tokens[pos], TokenType::Comma) {
+        (pos, false)
+    } else if check_token(&tokens[pos + 1], close) {
+        (pos + 1, false)
+    } else {
+        (pos + 1, true)
     }
 }
 
@@ -690,31 +1303,756 @@ fn check_token(token: &Token, tok_type: TokenType) -> bool {
     }
 }
 
+/// Suggests a quick fix for a syntax error from the token the parser expected
+/// and the token it actually found.
+fn suggest_fix(tokens: &[Token], err: &SyntaxError) -> Option<FixIt> {
+    let expected = err.expected?;
+    let expected_lexeme = expected.lexeme()?;
+    let found = &tokens[err.pos];
+
+    let confusable = matches!(
+        (expected, found.token_type),
+        (TokenType::EqualEqual, TokenType::Equal) | (TokenType::Equal, TokenType::EqualEqual)
+    );
+    if confusable {
+        return Some(FixIt::Replace {
+            line: found.line,
+            lexeme: found.lexeme.clone(),
+            text: expected_lexeme.to_string(),
+        });
+    }
+
+    if err.pos == 0 {
+        return None;
+    }
+    let previous = &tokens[err.pos - 1];
+    Some(FixIt::Insert {
+        line: previous.line,
+        after: previous.lexeme.clone(),
+        text: expected_lexeme.to_string(),
+    })
+}
+
+/// A machine-applicable suggestion attached to a `ParseError`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FixIt {
+    /// Insert `text` right after the token `after` on `line`.
+    Insert {
+        line: usize,
+        after: String,
+        text: String,
+    },
+    /// Replace the token `lexeme` on `line` with `text`.
+    Replace {
+        line: usize,
+        lexeme: String,
+        text: String,
+    },
+}
+
+impl fmt::Display for FixIt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FixIt::Insert { text, after, .. } => write!(f, "insert '{}' after '{}'", text, after),
+            FixIt::Replace { text, lexeme, .. } => {
+                write!(f, "did you mean '{}' instead of '{}'?", text, lexeme)
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ParseError {
     msg: String,
-    line: usize,
+    position: Position,
     lexeme: String,
+    fix_it: Option<Box<FixIt>>,
+    at_end: bool,
 }
 
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "[line {}] Error: {}", self.line, self.description())
+        write!(f, "[line {}] Error: {}", self.position.line, self.msg)
     }
 }
 
-impl Error for ParseError {
-    fn description(&self) -> &str {
+#[cfg(feature = "std")]
+impl Error for ParseError {}
+
+impl ParseError {
+    fn new(position: Position, lexeme: String, msg: String) -> ParseError {
+        ParseError {
+            position,
+            lexeme,
+            msg,
+            fix_it: None,
+            at_end: false,
+        }
+    }
+
+    fn with_fix_it(mut self, fix_it: Option<FixIt>) -> ParseError {
+        self.fix_it = fix_it.map(Box::new);
+        self
+    }
+
+    pub fn line(&self) -> usize {
+        self.position.line
+    }
+
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    /// Where the error is: the token it's at, when that's on one line, or
+    /// an empty span where it starts.
+    pub fn span(&self) -> Span {
+        let mut end = self.position;
+        if !self.lexeme.contains('\n') {
+            end.column += self.lexeme.chars().count();
+        }
+        Span {
+            start: self.position,
+            end,
+        }
+    }
+
+    pub fn lexeme(&self) -> &str {
+        &self.lexeme
+    }
+
+    pub fn message(&self) -> &str {
         &self.msg
     }
 
-    fn cause(&self) -> Option<&Error> {
-        None
+    /// What kind of error this is, for tools: always `syntax`.
+    pub fn code(&self) -> &'static str {
+        "syntax"
+    }
+
+    pub fn fix_it(&self) -> Option<&FixIt> {
+        self.fix_it.as_deref()
+    }
+
+    /// Whether the parser ran out of input, so that more of it might turn
+    /// this into a valid program. The REPL uses this to read another line.
+    pub fn is_at_end(&self) -> bool {
+        self.at_end
     }
 }
 
-impl ParseError {
-    fn new(line: usize, lexeme: String, msg: String) -> ParseError {
-        ParseError { line, lexeme, msg }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn first_error(source: &str) -> ParseError {
+        let mut errors = parse(source.to_string()).unwrap_err();
+        *errors.remove(0).downcast::<ParseError>().unwrap()
+    }
+
+    #[test]
+    fn fix_it_missing_semicolon() {
+        let error = first_error("var a = 1\nprint a;");
+        assert_eq!(error.line(), 2);
+        assert_eq!(error.code(), "syntax");
+        assert_eq!(
+            error.span(),
+            Span {
+                start: Position::new(2, 1),
+                end: Position::new(2, 6),
+            }
+        );
+        assert_eq!(
+            error.fix_it(),
+            Some(&FixIt::Insert {
+                line: 1,
+                after: "1".to_string(),
+                text: ";".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn fix_it_assignment_in_place_of_equality() {
+        for source in &[
+            "if (x + 1 = 2) print x;",
+            "while (1 = x) {}",
+            "for (;a.b() = c;) {}",
+        ] {
+            let error = first_error(source);
+            assert_eq!(error.message(), "Invalid assignment target.");
+            assert_eq!(
+                error.fix_it().map(|f| f.to_string()),
+                Some("did you mean '==' instead of '='?".to_string())
+            );
+        }
+
+        // Outside a condition, or nested inside one, it isn't a comparison.
+        for source in &["a + b = c;", "f() = 1;", "if (f(1 = 2)) {}"] {
+            let error = first_error(source);
+            assert_eq!(error.message(), "Invalid assignment target.");
+            assert!(error.fix_it().is_none(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn chained_comparison() {
+        let error = first_error("print 1 < x <= 3;");
+        assert_eq!(
+            error.message(),
+            "Comparisons cannot be chained; use 'and', as in 'a < b and b < c'."
+        );
+        assert_eq!(error.lexeme(), "<=");
+
+        assert!(parse("print (1 < x) == true;".to_string()).is_ok());
+        assert!(parse("print 1 < x and x < 3;".to_string()).is_ok());
+    }
+
+    #[test]
+    fn arrays() {
+        let expr = parse_expression("[1, [], a[0]][i]".to_string()).unwrap();
+        assert_eq!(expr.to_string(), "([] (array 1 (array) ([] a 0)) i)");
+
+        let program = parse("a[i][j] = 1;".to_string()).unwrap();
+        match &program[0] {
+            ast::Stmt::Expr(stmt) => {
+                assert_eq!(stmt.expression.to_string(), "(= ([] ([] a i) j) 1)")
+            }
+            stmt => panic!("Expected an expression statement, got {:?}", stmt),
+        }
+
+        let error = first_error("print [1, 2;");
+        assert_eq!(error.message(), "Expect ']' after array elements.");
+    }
+
+    #[test]
+    fn nil_safety_operators() {
+        let expr = parse_expression("a?.b.c ?? d or e".to_string()).unwrap();
+        assert_eq!(expr.to_string(), "(?? (. (?. a b) c) (or d e))");
+
+        let error = first_error("a?.b = 1;");
+        assert_eq!(error.message(), "Invalid assignment target.");
+        assert!(error.fix_it().is_none());
+        assert_eq!(
+            first_error("print a ? b;").message(),
+            "Unexpected character '?'."
+        );
+    }
+
+    #[test]
+    fn number_literals() {
+        for (source, value) in &[
+            ("1_000_000", 1_000_000.0),
+            ("1_0.2_5", 10.25),
+            ("1.5e3", 1500.0),
+            ("2E-2", 0.02),
+            ("1e+2", 100.0),
+            ("0xFF", 255.0),
+            ("0Xff_ff", 65535.0),
+            ("0o755", 493.0),
+            ("0b1010", 10.0),
+            ("0", 0.0),
+        ] {
+            match parse_expression(source.to_string()) {
+                Ok(ast::Expr::Literal(literal)) => {
+                    assert_eq!(literal.value, Literal::Number(*value), "{}", source)
+                }
+                result => panic!("{}: {:?}", source, result),
+            }
+        }
+        for (source, msg) in &[
+            ("1_", "Expect a digit after '_' in '1_'."),
+            ("1__0", "Expect a digit after '_' in '1__0'."),
+            ("1.5_e3", "Expect a digit after '_' in '1.5_e3'."),
+            ("1e", "Expect digits in the exponent of '1e'."),
+            ("1e+", "Expect digits in the exponent of '1e+'."),
+            ("2ex", "Expect digits in the exponent of '2ex'."),
+            ("0x", "Expect hexadecimal digits in '0x'."),
+            ("0xFG", "Invalid hexadecimal digit 'G' in '0xFG'."),
+            ("0o78", "Invalid octal digit '8' in '0o78'."),
+            ("0b102", "Invalid binary digit '2' in '0b102'."),
+            ("0b_1", "Expect a digit after '_' in '0b_1'."),
+            ("0b1_", "Expect a digit after '_' in '0b1_'."),
+        ] {
+            let errors = parse_expression(source.to_string()).unwrap_err();
+            assert_eq!(errors[0].message(), *msg);
+            assert_eq!(errors[0].lexeme(), *source);
+        }
+    }
+
+    #[test]
+    fn scanner_recovery() {
+        let errors = parse_program(
+            "print \u{201c}hi\u{201d} | 1;\nvar x = 1 §§ 2;".to_string(),
+            ParseOptions::default(),
+        )
+        .unwrap_err();
+        let errors: Vec<(Position, &str, &str)> = errors
+            .iter()
+            .map(|e| (e.position(), e.lexeme(), e.message()))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                (
+                    Position::new(1, 7),
+                    "\u{201c}",
+                    "Unexpected character '\u{201c}'. Strings are quoted with '\"'."
+                ),
+                (
+                    Position::new(1, 10),
+                    "\u{201d}",
+                    "Unexpected character '\u{201d}'. Strings are quoted with '\"'."
+                ),
+                (Position::new(1, 12), "|", "Unexpected character '|'."),
+                (Position::new(2, 11), "§§", "Unexpected characters '§§'."),
+            ]
+        );
+    }
+
+    #[test]
+    fn error_positions() {
+        let error = first_error("var a = 1;\n  print a +;");
+        assert_eq!(error.position(), Position::new(2, 12));
+
+        // A string that spans lines is positioned at its opening quote.
+        let error = first_error("print 1;\n  print \"a\nb\" +;");
+        assert_eq!(error.position(), Position::new(3, 5));
+        let error = first_error("print 1;\n  print \"a\nb");
+        assert_eq!(error.position(), Position::new(2, 9));
+        assert_eq!(error.message(), "Unterminated string.");
+    }
+
+    #[test]
+    fn parse_program_keeps_error_type() {
+        let program = parse_program("var class = 1;".to_string(), ParseOptions::default());
+        let errors = program.unwrap_err();
+        assert_eq!(errors[0].message(), "'class' is a reserved word.");
+        assert_eq!(errors[0].lexeme(), "class");
+    }
+
+    #[test]
+    fn reserved_words_as_names() {
+        for (source, msg) in &[
+            ("var while = 1;", "'while' is a reserved word."),
+            ("var [a, nil] = b;", "'nil' is a reserved word."),
+            ("fun this() {}", "'this' is a reserved word."),
+            ("fun f(a, or) {}", "'or' is a reserved word."),
+            ("fun f(...print) {}", "'print' is a reserved word."),
+            ("var f = fun and() {};", "'and' is a reserved word."),
+            ("class if {}", "'if' is a reserved word."),
+            ("trait with {}", "'with' is a reserved word."),
+            ("class A { return() {} }", "'return' is a reserved word."),
+            ("var 1 = 2;", "Expect variable name."),
+        ] {
+            assert_eq!(first_error(source).message(), *msg, "{}", source);
+        }
+    }
+
+    #[test]
+    fn nesting_limit() {
+        // Parsing right up to the limit needs more stack than a test thread
+        // gets by default in debug builds.
+        let parse_nested = |depth: usize| {
+            let source = format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+            std::thread::Builder::new()
+                .stack_size(16 * 1024 * 1024)
+                .spawn(move || parse_expression(source).map_err(|e| e[0].message().to_string()))
+                .unwrap()
+                .join()
+                .unwrap()
+        };
+        let max_nesting = LanguageLimits::DEFAULT.max_nesting;
+        assert!(parse_nested(max_nesting - 1).is_ok());
+        assert_eq!(
+            parse_nested(max_nesting).unwrap_err(),
+            "Cannot nest more than 200 levels deep."
+        );
+    }
+
+    #[test]
+    fn single_expression() {
+        let expr = parse_expression("1 + 2 * x".to_string()).unwrap();
+        assert!(matches!(expr, ast::Expr::Binary(_)));
+    }
+
+    #[test]
+    fn expression_rejects_trailing_tokens() {
+        let errors = parse_expression("1 + 2;".to_string()).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message(), "Expect end of expression.");
+        assert_eq!(errors[0].lexeme(), ";");
+
+        let errors = parse_expression("\"open".to_string()).unwrap_err();
+        assert_eq!(errors[0].message(), "Unterminated string.");
+    }
+
+    #[test]
+    fn print_multiple_values() {
+        let program = parse("print 1, \"a\" + \"b\", x;".to_string()).unwrap();
+        match &program[0] {
+            ast::Stmt::Print(print_stmt) => assert_eq!(print_stmt.expressions.len(), 3),
+            stmt => panic!("Expected a print statement, got {:?}", stmt),
+        }
+
+        let error = first_error("print 1,;");
+        assert_eq!(error.message(), "Expect expression");
+    }
+
+    #[test]
+    fn optional_semicolons() {
+        let options = ParseOptions {
+            optional_semicolons: true,
+            ..ParseOptions::default()
+        };
+        let program = parse_with_options(
+            "var a = 1\nprint a +\n  2\nfun f() { return\n}".to_string(),
+            options,
+        )
+        .unwrap();
+        assert_eq!(program.len(), 3);
+
+        // A line break only ends a statement, it doesn't separate two on one line.
+        assert!(parse_with_options("print 1 print 2".to_string(), options).is_err());
+        // And the default grammar still requires semicolons.
+        assert!(parse("print 1\n".to_string()).is_err());
+    }
+
+    #[test]
+    fn call_chain_across_lines() {
+        let source = "var b = builder\n  .add(1)\n  ?.add(2)\n  [0]";
+        let options = ParseOptions {
+            optional_semicolons: true,
+            ..ParseOptions::default()
+        };
+        for (source, options) in &[
+            (format!("{};", source), ParseOptions::default()),
+            (source.to_string(), options),
+        ] {
+            let program = parse_program(source.clone(), *options).unwrap();
+            assert_eq!(program.len(), 1);
+            match &program[0] {
+                ast::Stmt::Var(var) => assert_eq!(
+                    var.initializer.as_ref().unwrap().to_string(),
+                    "([] ((?. ((. builder add) 1) add) 2) 0)"
+                ),
+                stmt => panic!("expected a declaration, got {:?}", stmt),
+            }
+        }
+    }
+
+    #[test]
+    fn is_operator() {
+        let expr = parse_expression("a is B == !c is D".to_string()).unwrap();
+        assert_eq!(expr.to_string(), "(is (== (is a B) (! c)) D)");
+        assert_eq!(
+            first_error("a is 1;").message(),
+            "Expect class or trait name after 'is'."
+        );
+    }
+
+    #[test]
+    fn class_members() {
+        let program = parse("class A { var x = 1; f() {} var y; g() {} }".to_string()).unwrap();
+        match &program[0] {
+            ast::Stmt::Class(class) => {
+                assert_eq!(class.fields.len(), 2);
+                assert_eq!(class.methods.len(), 2);
+            }
+            stmt => panic!("Expected a class, got {:?}", stmt),
+        }
+
+        for (source, msg) in &[
+            (
+                "class A { fun f() {} }",
+                "Methods are declared without 'fun'.",
+            ),
+            ("class A { 1; }", "Expect method name."),
+            ("trait T { var x; }", "Expect method name."),
+            ("class A { f() {}", "Expect '}' after class body."),
+            ("trait T {", "Expect '}' after trait body."),
+        ] {
+            assert_eq!(first_error(source).message(), *msg, "{}", source);
+        }
+    }
+
+    #[test]
+    fn token_offsets() {
+        let source = "var s = \"naïve\";\nprint s;";
+        let tokens = Scanner::new(source.to_string()).scan_tokens().unwrap();
+        for token in &tokens {
+            assert_eq!(&source[token.source_range()], token.lexeme);
+        }
+        let print = &tokens[5];
+        assert_eq!((print.line, print.column), (2, 1));
+        assert_eq!(print.source_range(), 18..23);
+        assert_eq!(tokens.last().unwrap().source_range(), 26..26);
+    }
+
+    #[test]
+    fn error_at_end() {
+        assert!(first_error("builder.add(1,").is_at_end());
+        assert!(first_error("fun f() {").is_at_end());
+        assert!(first_error("print \"a").is_at_end());
+        assert!(!first_error("var 1;").is_at_end());
+    }
+
+    #[test]
+    fn expression_blocks() {
+        let options = ParseOptions {
+            expr_blocks: true,
+            ..ParseOptions::default()
+        };
+        let initializer = |source: &str| match parse_with_options(source.to_string(), options)
+            .unwrap()
+            .remove(0)
+        {
+            ast::Stmt::Var(stmt) => stmt.initializer.unwrap().to_string(),
+            stmt => panic!("Expected a var declaration, got {:?}", stmt),
+        };
+
+        assert_eq!(
+            initializer("var x = if (c) { 1 } else if (d) { 2 } else { 3 };"),
+            "(if c (block 1) (if d (block 2) (block 3)))"
+        );
+        assert_eq!(initializer("var x = if (c) { 1 };"), "(if c (block 1))");
+        assert_eq!(
+            initializer("var x = { var a = 1; a + 1 };"),
+            "(block ... (+ a 1))"
+        );
+        assert_eq!(initializer("var x = {};"), "(block)");
+        // Nested ifs and blocks can be a block's value, but if statements
+        // still parse as statements.
+        assert_eq!(
+            initializer("var x = { if (c) print 1; else print 2; { if (c) { 1 } } };"),
+            "(block ... (block (if c (block 1))))"
+        );
+
+        let errors = parse_with_options("var x = if (c) 1;".to_string(), options).unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 1] Error: Expect '{' before the branch of an if expression."
+        );
+        // Without the extension, `if` and blocks are not expressions.
+        assert!(parse("var x = if (c) { 1 };".to_string()).is_err());
+        assert!(parse("var x = {};".to_string()).is_err());
+    }
+
+    #[test]
+    fn language_versions() {
+        let at = |version| ParseOptions {
+            version,
+            ..ParseOptions::default()
+        };
+        let first_error = |source: &str, version| {
+            parse_with_options(source.to_string(), at(version)).unwrap_err()[0].to_string()
+        };
+
+        assert_eq!(
+            first_error("var f = fun () {};", LanguageVersion::V1),
+            "[line 1] Error: Function expressions need language version 2 or later."
+        );
+        assert_eq!(
+            first_error("print a?.b;", LanguageVersion::V1),
+            "[line 1] Error: '?.' needs language version 2 or later."
+        );
+        assert_eq!(
+            first_error("print a ?? b;", LanguageVersion::V1),
+            "[line 1] Error: '??' needs language version 2 or later."
+        );
+        for source in &["var f = fun () {};", "print a?.b ?? c;"] {
+            assert!(parse_with_options(source.to_string(), at(LanguageVersion::V2)).is_ok());
+        }
+        // Expression blocks are standard from version 3.
+        assert!(parse_with_options("var x = {};".to_string(), at(LanguageVersion::V2)).is_err());
+        assert!(parse_with_options("var x = {};".to_string(), at(LanguageVersion::V3)).is_ok());
+    }
+
+    #[test]
+    fn function_expressions() {
+        let expr = parse_expression("fun (a, b) { return a + b; }(1, 2)".to_string()).unwrap();
+        assert_eq!(expr.to_string(), "((fun) 1 2)");
+        let expr = parse_expression("fun fact(n) { return n; }".to_string()).unwrap();
+        assert_eq!(expr.to_string(), "(fun fact)");
+
+        // At the start of a statement `fun` is still a declaration.
+        assert_eq!(
+            first_error("fun (a) {};").message(),
+            "Expect function or method name."
+        );
+        assert_eq!(
+            first_error("var f = fun {};").message(),
+            "Expect '(' after 'fun'."
+        );
+    }
+
+    #[test]
+    fn variadics_and_spread() {
+        let program = parse("fun log(level, ...args) {}".to_string()).unwrap();
+        match &program[0] {
+            ast::Stmt::Fun(fun) => {
+                assert_eq!(fun.parameters.len(), 1);
+                assert_eq!(fun.rest.as_ref().unwrap().lexeme, "args");
+            }
+            stmt => panic!("Expected a function, got {:?}", stmt),
+        }
+
+        let expr = parse_expression("f(a, ...b, ...[c])".to_string()).unwrap();
+        assert_eq!(expr.to_string(), "(f a (... b) (... (array c)))");
+        // `...` only scans as one token when all three dots are together.
+        let expr = parse_expression("a.b".to_string()).unwrap();
+        assert_eq!(expr.to_string(), "(. a b)");
+
+        assert_eq!(
+            first_error("fun f(...a, b) {}").message(),
+            "A '...' parameter must be the last one."
+        );
+        assert_eq!(
+            first_error("fun f(...) {}").message(),
+            "Expect parameter name after '...'."
+        );
+        assert_eq!(first_error("print ...a;").message(), "Expect expression");
+    }
+
+    #[test]
+    fn configured_limits() {
+        let options = ParseOptions {
+            limits: LanguageLimits {
+                max_arguments: 2,
+                max_parameters: 2,
+                max_nesting: 3,
+                max_string_length: 4,
+            },
+            ..ParseOptions::default()
+        };
+        let first_error = |source: &str| {
+            parse_program(source.to_string(), options).unwrap_err()[0]
+                .message()
+                .to_string()
+        };
+
+        assert!(parse_with_options(
+            "f(1, 2); fun g(a, b) {} (1); \"four\";".to_string(),
+            options
+        )
+        .is_ok());
+        assert_eq!(
+            first_error("f(1, k: 2, 3);"),
+            "Cannot have more than 2 arguments."
+        );
+        assert_eq!(
+            first_error("fun g(a, b, c) {}"),
+            "Cannot have more than 2 parameters."
+        );
+        assert_eq!(
+            first_error("((1));"),
+            "Cannot nest more than 3 levels deep."
+        );
+        assert_eq!(
+            first_error("\"fives\";"),
+            "String is longer than the limit of 4 characters."
+        );
+        assert_eq!(
+            parse_program(
+                "f(1, 2, 3, 4, 5, 6, 7, 8, 9);".to_string(),
+                ParseOptions::default()
+            )
+            .unwrap_err()[0]
+                .message(),
+            "Cannot have more than 8 arguments."
+        );
+    }
+
+    #[test]
+    fn trailing_commas() {
+        let program = parse(
+            "fun g(x, y,) {}\nfun h(a, ...rest,) {}\ng(1, 2,);\nh(1, k: 2,);\nvar a = [1, 2,];"
+                .to_string(),
+        )
+        .unwrap();
+        match (&program[0], &program[1]) {
+            (ast::Stmt::Fun(g), ast::Stmt::Fun(h)) => {
+                assert_eq!(g.parameters.len(), 2);
+                assert_eq!(h.parameters.len(), 1);
+                assert!(h.rest.is_some());
+            }
+            stmts => panic!("Expected two functions, got {:?}", stmts),
+        }
+        assert_eq!(
+            parse_expression("f(1, 2,)".to_string())
+                .unwrap()
+                .to_string(),
+            "(f 1 2)"
+        );
+        assert_eq!(
+            parse_expression("[1, [2,],]".to_string())
+                .unwrap()
+                .to_string(),
+            "(array 1 (array 2))"
+        );
+
+        assert_eq!(first_error("f(,);").message(), "Expect expression");
+        assert_eq!(first_error("f(1,,);").message(), "Expect expression");
+        assert_eq!(
+            first_error("fun g(,) {}").message(),
+            "Expect parameter name."
+        );
+    }
+
+    #[test]
+    fn keyword_arguments() {
+        let expr = parse_expression("f(1, y: 2, z: a ?? b)".to_string()).unwrap();
+        assert_eq!(expr.to_string(), "(f 1 y: 2 z: (?? a b))");
+
+        assert_eq!(
+            first_error("f(x: 1, 2);").message(),
+            "Positional arguments must come before keyword arguments."
+        );
+        assert_eq!(
+            first_error("f(x: 1, x: 2);").message(),
+            "Duplicate keyword argument."
+        );
+    }
+
+    #[test]
+    fn destructuring() {
+        let program = parse_program(
+            "var [a, b] = pair; { var {x, y} = point; }".to_string(),
+            ParseOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(program.len(), 2);
+        match &program[0] {
+            ast::Stmt::Destructure(destructure) => {
+                assert!(destructure.is_array());
+                assert_eq!(destructure.names.len(), 2);
+            }
+            stmt => panic!("expected a destructuring, got {:?}", stmt),
+        }
+        match &program[1] {
+            ast::Stmt::Block(block) => match &block.statements[..] {
+                [ast::Stmt::Destructure(destructure)] => assert!(!destructure.is_array()),
+                stmts => panic!("expected a destructuring, got {:?}", stmts),
+            },
+            stmt => panic!("expected a block, got {:?}", stmt),
+        }
+
+        assert_eq!(
+            first_error("var [] = pair;").message(),
+            "Expect variable name."
+        );
+        assert_eq!(
+            first_error("var [a, b = pair;").message(),
+            "Expect ']' after names."
+        );
+        assert_eq!(
+            first_error("var {x, y};").message(),
+            "Expect '=' after destructuring pattern."
+        );
+    }
+
+    #[test]
+    fn no_fix_it_without_expected_token() {
+        let error = first_error("var 1;");
+        assert_eq!(error.fix_it(), None);
     }
 }
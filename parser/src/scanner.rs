@@ -1,116 +1,124 @@
-use ast::token::{Literal, Token, TokenType};
-use std::collections::HashMap;
-use std::error::Error;
-use std::fmt;
-use std::str;
-
-#[derive(Debug)]
-pub struct UnexpectedCharError {
-    line: usize,
-}
-
-impl fmt::Display for UnexpectedCharError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "[line {}] Error: {}", self.line, self.description())
-    }
-}
-
-impl Error for UnexpectedCharError {
-    fn description(&self) -> &str {
-        "Unexpected character."
-    }
-
-    fn cause(&self) -> Option<&Error> {
-        None
-    }
-}
-
-#[derive(Debug)]
-pub struct UnterminatedStringError {
-    line: usize,
-}
-
-impl fmt::Display for UnterminatedStringError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "[line {}] Error: {}", self.line, self.description())
-    }
-}
+use crate::ParseError;
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use ast::token::{FileId, Literal, Position, Token, TokenType};
 
-impl Error for UnterminatedStringError {
-    fn description(&self) -> &str {
-        "Unterminated string."
-    }
-
-    fn cause(&self) -> Option<&Error> {
-        None
-    }
-}
+type ScanResult = Result<(), ParseError>;
 
 pub struct Scanner {
-    source: String,
+    source: Vec<char>,
     source_len: usize,
+    // Byte offset of each character, and of the end of the source.
+    byte_offsets: Vec<usize>,
     tokens: Vec<Token>,
     start: usize,
     current: usize,
     line: usize,
-    keywords: HashMap<String, TokenType>,
+    // Index of the first character on the current line.
+    line_start: usize,
+    // Where the token being scanned starts. A string that spans lines is
+    // positioned at its opening quote.
+    start_line: usize,
+    column: usize,
+    file: FileId,
+    max_string_length: usize,
 }
 
 impl Scanner {
     pub fn new(source: String) -> Scanner {
         let tokens: Vec<Token> = vec![];
-        let source_len = source.chars().count();
-        let keywords = keywords_map();
+        let source: Vec<char> = source.chars().collect();
+        let source_len = source.len();
+        let mut byte_offsets = Vec::with_capacity(source_len + 1);
+        let mut offset = 0;
+        for c in &source {
+            byte_offsets.push(offset);
+            offset += c.len_utf8();
+        }
+        byte_offsets.push(offset);
         Scanner {
             source,
             source_len,
+            byte_offsets,
             tokens,
-            keywords,
             start: 0,
             current: 0,
             line: 1,
+            line_start: 0,
+            start_line: 1,
+            column: 1,
+            file: FileId::default(),
+            max_string_length: usize::MAX,
         }
     }
 
-    pub fn scan_tokens(mut self) -> Result<Vec<Token>, Vec<Box<Error>>> {
-        let mut errors: Vec<Box<Error>> = vec![];
+    /// Marks the tokens and errors as coming from `file`.
+    pub fn in_file(mut self, file: FileId) -> Scanner {
+        self.file = file;
+        self
+    }
+
+    /// Rejects string literals with more than `max` characters.
+    pub fn with_max_string_length(mut self, max: usize) -> Scanner {
+        self.max_string_length = max;
+        self
+    }
+
+    pub fn scan_tokens(mut self) -> Result<Vec<Token>, Vec<ParseError>> {
+        let mut errors: Vec<ParseError> = vec![];
 
         while !self.is_at_end() {
-            self.start = self.current;
-            match self.scan_token() {
-                Err(e) => errors.push(e),
-                _ => (),
+            self.start_token();
+            if let Err(e) = self.scan_token() {
+                errors.push(e)
             }
         }
 
-        self.tokens.push(Token::new(
-            TokenType::Eof,
-            String::from(""),
-            None,
-            self.line,
-        ));
-
-        if errors.len() > 0 {
+        self.start_token();
+        let eof = Token::new(TokenType::Eof, String::from(""), None, self.line);
+        let eof = self.locate(eof);
+        self.tokens.push(eof);
+
+        log::debug!(
+            "scanned {} tokens with {} errors",
+            self.tokens.len(),
+            errors.len()
+        );
+        if !errors.is_empty() {
             Err(errors)
         } else {
             Ok(self.tokens)
         }
     }
 
-    fn scan_token(&mut self) -> Result<(), Box<Error>> {
+    fn scan_token(&mut self) -> ScanResult {
         let c = self.advance();
         match c {
             ' ' | '\r' | '\t' => Ok(()),
             '\n' => {
-                self.inc_line();
+                self.inc_line(self.current);
                 Ok(())
             }
             '(' => self.add_token(TokenType::LeftParen, None),
             ')' => self.add_token(TokenType::RightParen, None),
             '{' => self.add_token(TokenType::LeftBrace, None),
             '}' => self.add_token(TokenType::RightBrace, None),
+            '[' => self.add_token(TokenType::LeftBracket, None),
+            ']' => self.add_token(TokenType::RightBracket, None),
             ',' => self.add_token(TokenType::Comma, None),
-            '.' => self.add_token(TokenType::Dot, None),
+            ':' => self.add_token(TokenType::Colon, None),
+            '.' => {
+                if self.peek() == '.' && self.peek_next() == '.' {
+                    self.current += 2;
+                    self.add_token(TokenType::DotDotDot, None)
+                } else {
+                    self.add_token(TokenType::Dot, None)
+                }
+            }
             '-' => self.add_token(TokenType::Minus, None),
             '+' => self.add_token(TokenType::Plus, None),
             ';' => self.add_token(TokenType::Semicolon, None),
@@ -131,6 +139,15 @@ impl Scanner {
                 let t_type = self.next_match_else('=', TokenType::GreaterEqual, TokenType::Greater);
                 self.add_token(t_type, None)
             }
+            '?' => {
+                if self.next_match('.') {
+                    self.add_token(TokenType::QuestionDot, None)
+                } else if self.next_match('?') {
+                    self.add_token(TokenType::QuestionQuestion, None)
+                } else {
+                    self.unexpected()
+                }
+            }
             '/' => {
                 if self.next_match('/') {
                     while self.peek() != '\n' && !self.is_at_end() {
@@ -148,73 +165,191 @@ impl Scanner {
                 } else if is_alpha(c) {
                     self.handle_identifier()
                 } else {
-                    Err(Box::new(UnexpectedCharError { line: self.line }))
+                    self.unexpected()
                 }
             }
         }
     }
 
-    fn add_token(
-        &mut self,
-        token_type: TokenType,
-        literal: Option<Literal>,
-    ) -> Result<(), Box<Error>> {
+    /// Reports the characters from the start of the token up to the next
+    /// one that could begin a token, as a single error, so scanning
+    /// carries on from a token boundary.
+    fn unexpected(&mut self) -> ScanResult {
+        while !self.is_at_end() && !is_token_start(self.peek()) {
+            self.advance();
+        }
+        let text = substr(&self.source, self.start, self.current);
+        let mut msg = if self.current - self.start == 1 {
+            format!("Unexpected character '{}'.", text)
+        } else {
+            format!("Unexpected characters '{}'.", text)
+        };
+        if text.chars().any(is_curly_quote) {
+            msg.push_str(" Strings are quoted with '\"'.");
+        }
+        Err(self.error(&msg))
+    }
+
+    fn add_token(&mut self, token_type: TokenType, literal: Option<Literal>) -> ScanResult {
         let lexeme = substr(&self.source, self.start, self.current);
-        self.tokens
-            .push(Token::new(token_type, lexeme, literal, self.line));
+        let token = Token::new(token_type, lexeme, literal, self.start_line);
+        let token = self.locate(token);
+        self.tokens.push(token);
         Ok(())
     }
 
-    fn handle_string_literal(&mut self) -> Result<(), Box<Error>> {
+    /// Gives `token` the column, offsets and file of the text just scanned.
+    fn locate(&self, token: Token) -> Token {
+        token
+            .with_column(self.column)
+            .with_offsets(
+                self.byte_offsets[self.start],
+                self.byte_offsets[self.current],
+            )
+            .with_file(self.file)
+    }
+
+    fn handle_string_literal(&mut self) -> ScanResult {
         while self.peek() != '"' && !self.is_at_end() {
             if self.peek() == '\n' {
-                self.inc_line();
+                self.inc_line(self.current + 1);
             }
             self.advance();
         }
 
         if self.is_at_end() {
-            return Err(Box::new(UnterminatedStringError { line: self.line }));
+            // More input could still close it, as in a multi-line string.
+            let mut error = self.error("Unterminated string.");
+            error.at_end = true;
+            return Err(error);
         }
 
         // closing "
         self.advance();
 
+        let length = self.current - self.start - 2;
+        if length > self.max_string_length {
+            return Err(self.error(&format!(
+                "String is longer than the limit of {} characters.",
+                self.max_string_length
+            )));
+        }
         let value = substr(&self.source, self.start + 1, self.current - 1);
 
         self.add_token(TokenType::String, Some(Literal::String(value)))
     }
 
-    fn handle_number_literal(&mut self) -> Result<(), Box<Error>> {
-        while is_digit(self.peek()) {
-            self.advance();
+    /// Scans a number such as `12`, `1_000.5`, `1.5e-3` or `0xFF`. Underscores
+    /// separate digits, and an exponent needs digits of its own.
+    fn handle_number_literal(&mut self) -> ScanResult {
+        if self.char_at(self.start) == '0' {
+            let radix = match self.peek() {
+                'x' | 'X' => Some((16, "hexadecimal")),
+                'o' | 'O' => Some((8, "octal")),
+                'b' | 'B' => Some((2, "binary")),
+                _ => None,
+            };
+            if let Some((radix, base)) = radix {
+                self.advance();
+                return self.handle_integer_literal(radix, base);
+            }
         }
 
+        self.digits()?;
+
         if self.peek() == '.' && is_digit(self.peek_next()) {
             self.advance();
+            self.digits()?;
+        }
 
-            while is_digit(self.peek()) {
+        if matches!(self.peek(), 'e' | 'E') {
+            self.advance();
+            if matches!(self.peek(), '+' | '-') {
                 self.advance();
             }
+            if !is_digit(self.peek()) {
+                return Err(self.malformed_number("Expect digits in the exponent of"));
+            }
+            self.digits()?;
         }
 
-        let val_string = substr(&self.source, self.start, self.current);
-        let val_str = &val_string[..];
-        let value = val_str.parse::<f64>().unwrap();
+        let text: String = substr(&self.source, self.start, self.current)
+            .chars()
+            .filter(|c| *c != '_')
+            .collect();
+        let value = text
+            .parse::<f64>()
+            .map_err(|_| self.malformed_number("Invalid number"))?;
+
+        self.add_token(TokenType::Number, Some(Literal::Number(value)))
+    }
+
+    /// Scans the digits of an integer such as `0xFF`, `0o755` or `0b1010`
+    /// after its prefix. Underscores separate digits here too.
+    fn handle_integer_literal(&mut self, radix: u32, base: &str) -> ScanResult {
+        let digits_start = self.current;
+        while is_alphanumeric(self.peek()) {
+            self.advance();
+        }
+        let text = substr(&self.source, self.start, self.current);
+        let digits = substr(&self.source, digits_start, self.current);
+        if digits.is_empty() {
+            return Err(self.error(&format!("Expect {} digits in '{}'.", base, text)));
+        }
+        let mut value = 0.0;
+        let mut separated = true;
+        for c in digits.chars() {
+            if c == '_' {
+                if separated {
+                    return Err(self.error(&format!("Expect a digit after '_' in '{}'.", text)));
+                }
+                separated = true;
+                continue;
+            }
+            match c.to_digit(radix) {
+                Some(digit) => value = value * radix as f64 + digit as f64,
+                None => {
+                    return Err(
+                        self.error(&format!("Invalid {} digit '{}' in '{}'.", base, c, text))
+                    )
+                }
+            }
+            separated = false;
+        }
+        if separated {
+            return Err(self.error(&format!("Expect a digit after '_' in '{}'.", text)));
+        }
 
         self.add_token(TokenType::Number, Some(Literal::Number(value)))
     }
 
-    fn handle_identifier(&mut self) -> Result<(), Box<Error>> {
+    /// Scans a run of digits, which single underscores may separate.
+    fn digits(&mut self) -> ScanResult {
+        while is_digit(self.peek()) || self.peek() == '_' {
+            if self.advance() == '_' && !is_digit(self.peek()) {
+                return Err(self.malformed_number("Expect a digit after '_' in"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Reports the number being scanned, up to the end of the word it's
+    /// in, as `msg` followed by its text.
+    fn malformed_number(&mut self, msg: &str) -> ParseError {
+        while is_alphanumeric(self.peek()) {
+            self.advance();
+        }
+        let text = substr(&self.source, self.start, self.current);
+        self.error(&format!("{} '{}'.", msg, text))
+    }
+
+    fn handle_identifier(&mut self) -> ScanResult {
         while is_alphanumeric(self.peek()) {
             self.advance();
         }
 
         let text = substr(&self.source, self.start, self.current);
-        let token_type = match self.keywords.get(&text) {
-            Some(keyword) => *keyword,
-            None => TokenType::Identifier,
-        };
+        let token_type = TokenType::keyword(&text).unwrap_or(TokenType::Identifier);
 
         let literal = match token_type {
             TokenType::True => Some(Literal::True),
@@ -226,6 +361,12 @@ impl Scanner {
         self.add_token(token_type, literal)
     }
 
+    fn error(&self, msg: &str) -> ParseError {
+        let lexeme = substr(&self.source, self.start, self.current);
+        let position = Position::new(self.start_line, self.column).in_file(self.file);
+        ParseError::new(position, lexeme, msg.to_string())
+    }
+
     fn advance(&mut self) -> char {
         self.inc_current();
         self.char_at(self.current - 1)
@@ -287,51 +428,48 @@ impl Scanner {
     }
 
     fn char_at(&self, index: usize) -> char {
-        self.source.chars().nth(index).unwrap()
+        self.source[index]
     }
 
     fn inc_current(&mut self) {
-        self.current = self.current + 1
+        self.current += 1
+    }
+
+    fn start_token(&mut self) {
+        self.start = self.current;
+        self.start_line = self.line;
+        self.column = self.current - self.line_start + 1;
     }
 
-    fn inc_line(&mut self) {
-        self.line = self.line + 1
+    fn inc_line(&mut self, line_start: usize) {
+        self.line += 1;
+        self.line_start = line_start;
     }
 }
 
-fn substr(s: &String, start: usize, end: usize) -> String {
-    s.chars().skip(start).take(end - start).collect()
+fn substr(s: &[char], start: usize, end: usize) -> String {
+    s[start..end].iter().collect()
 }
 
 fn is_digit(c: char) -> bool {
-    c >= '0' && c <= '9'
+    c.is_ascii_digit()
 }
 
 fn is_alpha(c: char) -> bool {
-    (c >= 'a' && c <= 'z') || (c >= 'A' && c <= 'Z') || c == '_'
+    c.is_ascii_lowercase() || c.is_ascii_uppercase() || c == '_'
 }
 
 fn is_alphanumeric(c: char) -> bool {
     is_alpha(c) || is_digit(c)
 }
 
-fn keywords_map() -> HashMap<String, TokenType> {
-    let mut keywords = HashMap::new();
-    keywords.insert(String::from("and"), TokenType::And);
-    keywords.insert(String::from("class"), TokenType::Class);
-    keywords.insert(String::from("else"), TokenType::Else);
-    keywords.insert(String::from("false"), TokenType::False);
-    keywords.insert(String::from("for"), TokenType::For);
-    keywords.insert(String::from("fun"), TokenType::Fun);
-    keywords.insert(String::from("if"), TokenType::If);
-    keywords.insert(String::from("nil"), TokenType::Nil);
-    keywords.insert(String::from("or"), TokenType::Or);
-    keywords.insert(String::from("print"), TokenType::Print);
-    keywords.insert(String::from("return"), TokenType::Return);
-    keywords.insert(String::from("super"), TokenType::Super);
-    keywords.insert(String::from("this"), TokenType::This);
-    keywords.insert(String::from("true"), TokenType::True);
-    keywords.insert(String::from("var"), TokenType::Var);
-    keywords.insert(String::from("while"), TokenType::While);
-    keywords
+/// Whether scanning can resume at `c`: whitespace, or a character that
+/// starts a token or comment.
+fn is_token_start(c: char) -> bool {
+    is_alphanumeric(c) || " \r\t\n(){}[],:.-+;*!=<>?/\"".contains(c)
+}
+
+/// Typographic quotes, which word processors substitute for `"` and `'`.
+fn is_curly_quote(c: char) -> bool {
+    matches!(c, '\u{201c}' | '\u{201d}' | '\u{2018}' | '\u{2019}')
 }
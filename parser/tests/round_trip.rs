@@ -0,0 +1,272 @@
+//! Formatting a parsed program and parsing the result must give back the
+//! same tree, apart from where its tokens are.
+
+use ast::{format::format_stmts, Stmt};
+use parser::{parse_program, ParseOptions};
+use proptest::{collection::vec, option, prelude::*};
+
+/// The tree's debug output with token positions and scope ids blanked out,
+/// since those are all that may change when the source is reformatted.
+fn shape(stmts: &[Stmt]) -> String {
+    let debug = format!("{:?}", stmts);
+    let mut out = String::new();
+    let mut rest = debug.as_str();
+    'outer: while let Some(c) = rest.chars().next() {
+        for prefix in &[
+            "line: ", "column: ", "start: ", "end: ", "FileId(", "ScopeId(",
+        ] {
+            if let Some(after) = rest.strip_prefix(prefix) {
+                out.push_str(prefix);
+                rest = after.trim_start_matches(|c: char| c.is_ascii_digit());
+                continue 'outer;
+            }
+        }
+        out.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    out
+}
+
+fn options(expr_blocks: bool) -> ParseOptions {
+    ParseOptions {
+        expr_blocks,
+        ..ParseOptions::default()
+    }
+}
+
+fn check_round_trip(source: &str, options: ParseOptions) -> Result<(), TestCaseError> {
+    let program = match parse_program(source.to_string(), options) {
+        Ok(program) => program,
+        Err(_) => return Err(TestCaseError::reject("not a valid program")),
+    };
+    let formatted = format_stmts(&program);
+    let reparsed = parse_program(formatted.clone(), options).map_err(|errors| {
+        TestCaseError::fail(format!(
+            "formatted source doesn't parse: {}\n{}",
+            errors[0], formatted
+        ))
+    })?;
+    prop_assert_eq!(shape(&program), shape(&reparsed), "\n{}", formatted);
+    Ok(())
+}
+
+fn name() -> impl Strategy<Value = String> {
+    prop::sample::select(vec!["a", "b", "f", "list"]).prop_map(String::from)
+}
+
+fn expr(expr_blocks: bool) -> BoxedStrategy<String> {
+    let leaf = prop_oneof![
+        (0u32..1000).prop_map(|n| n.to_string()),
+        (0u32..100, 1u32..100).prop_map(|(i, f)| format!("{}.{}", i, f)),
+        "[a-z ]{0,6}".prop_map(|s| format!("\"{}\"", s)),
+        prop::sample::select(vec!["true", "false", "nil", "this"]).prop_map(String::from),
+        name(),
+    ];
+    leaf.prop_recursive(4, 32, 3, move |inner| {
+        let binary = prop::sample::select(vec![
+            "+", "-", "*", "/", "==", "!=", "<", ">=", "and", "or", "??",
+        ]);
+        let mut cases = vec![
+            (inner.clone(), binary, inner.clone())
+                .prop_map(|(l, op, r)| format!("{} {} {}", l, op, r))
+                .boxed(),
+            (prop::sample::select(vec!["-", "!"]), inner.clone())
+                .prop_map(|(op, e)| format!("{}{}", op, e))
+                .boxed(),
+            inner.clone().prop_map(|e| format!("({})", e)).boxed(),
+            (inner.clone(), name())
+                .prop_map(|(e, n)| format!("({} = {})", n, e))
+                .boxed(),
+            (inner.clone(), name(), any::<bool>())
+                .prop_map(|(e, n, safe)| format!("{}{}{}", e, if safe { "?." } else { "." }, n))
+                .boxed(),
+            (inner.clone(), name(), inner.clone())
+                .prop_map(|(o, n, v)| format!("({}.{} = {})", o, n, v))
+                .boxed(),
+            (inner.clone(), inner.clone())
+                .prop_map(|(o, i)| format!("{}[{}]", o, i))
+                .boxed(),
+            (inner.clone(), inner.clone(), inner.clone())
+                .prop_map(|(o, i, v)| format!("({}[{}] = {})", o, i, v))
+                .boxed(),
+            vec(inner.clone(), 0..3)
+                .prop_map(|es| format!("[{}]", es.join(", ")))
+                .boxed(),
+            (
+                name(),
+                vec(inner.clone(), 0..3),
+                option::of(inner.clone()),
+                option::of(inner.clone()),
+            )
+                .prop_map(|(f, args, spread, keyword)| {
+                    let mut args = args;
+                    args.extend(spread.map(|s| format!("...{}", s)));
+                    args.extend(keyword.map(|k| format!("key: {}", k)));
+                    format!("{}({})", f, args.join(", "))
+                })
+                .boxed(),
+            inner
+                .clone()
+                .prop_map(|e| format!("fun (x, ...rest) {{ return {}; }}", e))
+                .boxed(),
+            inner.clone().prop_map(|e| format!("{} is A", e)).boxed(),
+        ];
+        if expr_blocks {
+            cases.push(
+                (inner.clone(), inner.clone())
+                    .prop_map(|(s, v)| format!("{{ print {}; {} }}", s, v))
+                    .boxed(),
+            );
+            cases.push(
+                (inner.clone(), inner.clone(), option::of(inner.clone()))
+                    .prop_map(|(c, t, e)| match e {
+                        Some(e) => format!("if ({}) {{ {} }} else {{ {} }}", c, t, e),
+                        None => format!("if ({}) {{ {} }}", c, t),
+                    })
+                    .boxed(),
+            );
+        }
+        prop::strategy::Union::new(cases)
+    })
+    .boxed()
+}
+
+/// Declarations can't be the body of an `if` or a loop on their own.
+fn as_statement(source: &str) -> String {
+    let declaration = ["var ", "fun ", "class ", "trait "]
+        .iter()
+        .any(|keyword| source.starts_with(keyword));
+    if declaration {
+        format!("{{ {} }}", source)
+    } else {
+        source.to_string()
+    }
+}
+
+fn stmt(expr_blocks: bool) -> impl Strategy<Value = String> {
+    let e = move || expr(expr_blocks);
+    let simple = prop_oneof![
+        e().prop_map(|e| format!("{};", e)),
+        vec(e(), 1..3).prop_map(|es| format!("print {};", es.join(", "))),
+        (name(), option::of(e())).prop_map(|(n, init)| match init {
+            Some(init) => format!("var {} = {};", n, init),
+            None => format!("var {};", n),
+        }),
+        (any::<bool>(), e()).prop_map(|(array, init)| if array {
+            format!("var [p, q] = {};", init)
+        } else {
+            format!("var {{p, q}} = {};", init)
+        }),
+        option::of(e()).prop_map(|value| match value {
+            Some(value) => format!("return {};", value),
+            None => "return;".to_string(),
+        }),
+    ];
+    simple.prop_recursive(3, 16, 3, move |inner| {
+        let body = inner.clone().prop_map(|s| as_statement(&s));
+        prop_oneof![
+            vec(inner.clone(), 0..3).prop_map(|s| format!("{{ {} }}", s.join(" "))),
+            (e(), body.clone(), option::of(body.clone())).prop_map(|(c, t, e)| match e {
+                Some(e) => format!("if ({}) {} else {}", c, t, e),
+                None => format!("if ({}) {}", c, t),
+            }),
+            (e(), body.clone()).prop_map(|(c, body)| format!("while ({}) {}", c, body)),
+            (any::<bool>(), e(), body).prop_map(|(init, c, body)| format!(
+                "for ({}; {}; i = i + 1) {}",
+                if init { "var i = 0" } else { "" },
+                c,
+                body
+            )),
+            vec(inner.clone(), 0..3)
+                .prop_map(|body| format!("fun g(a, ...r) {{ {} }}", body.join(" "))),
+            (e(), vec(inner.clone(), 0..3)).prop_map(|(field, body)| format!(
+                "class C with T, U {{ var x = {}; m(a) {{ {} }} }}",
+                field,
+                body.join(" ")
+            )),
+            vec(inner, 0..3).prop_map(|body| format!("trait T {{ m() {{ {} }} }}", body.join(" "))),
+        ]
+    })
+}
+
+fn program(expr_blocks: bool) -> impl Strategy<Value = String> {
+    vec(stmt(expr_blocks), 1..4).prop_map(|stmts| stmts.join("\n"))
+}
+
+proptest! {
+    #[test]
+    fn format_then_parse_gives_the_same_tree(source in program(false)) {
+        check_round_trip(&source, options(false))?;
+    }
+
+    #[test]
+    fn format_then_parse_gives_the_same_tree_with_expr_blocks(source in program(true)) {
+        check_round_trip(&source, options(true))?;
+    }
+}
+
+#[test]
+fn formatted_program() {
+    let source = "
+        class Point with Show { var z = 0; init(x, y) { this.x = x; this.y = y; } }
+        trait Show { show() { print this.x, this.y; } }
+        var [a, b] = [1, 2.5];
+        var {x, y} = Point(1, 2);
+        for (var i = 0; i < 3; i = i + 1) if (i == 1) print \"one\"; else print i;
+        fun sum(first, ...rest) { return first + (rest[0] ?? 0); }
+        print sum(...[1, 2], first: -a), x?.y is Point, fun () {};
+    ";
+    let program = parse_program(source.to_string(), ParseOptions::default()).unwrap();
+    assert_eq!(
+        format_stmts(&program),
+        "\
+class Point with Show {
+    var z = 0;
+    init(x, y) {
+        this.x = x;
+        this.y = y;
+    }
+}
+trait Show {
+    show() {
+        print this.x, this.y;
+    }
+}
+var [a, b] = [1, 2.5];
+var {x, y} = Point(1, 2);
+for (var i = 0; i < 3; i = i + 1) if (i == 1) print \"one\"; else print i;
+fun sum(first, ...rest) {
+    return first + (rest[0] ?? 0);
+}
+print sum(...[1, 2], first: -a), x?.y is Point, fun () {};
+"
+    );
+}
+
+#[test]
+fn formatted_block_expressions() {
+    let source = "var v = if (a) { print 1; 2 } else if (b) { 3 } else { {} };";
+    let program = parse_program(source.to_string(), options(true)).unwrap();
+    assert_eq!(
+        format_stmts(&program),
+        "\
+var v = if (a) {
+    print 1;
+    2
+} else if (b) {
+    3
+} else {
+    {}
+};
+"
+    );
+    check_round_trip(source, options(true)).unwrap();
+}
+
+#[test]
+fn while_loop_with_declaration_and_increment() {
+    let source = "var i = 0; while (i < 3) { var a = i; i = i + 1; }";
+    let program = parse_program(source.to_string(), options(false)).unwrap();
+    assert!(format_stmts(&program).contains("while (i < 3) {"));
+    check_round_trip(source, options(false)).unwrap();
+}
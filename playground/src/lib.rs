@@ -0,0 +1,224 @@
+//! The backend of the web playground: runs a submitted Lox program under
+//! [`InterpreterConfig::sandbox`] and reports what happened as JSON.
+//!
+//! Everything goes through the public `rlox` API, the same way any embedder
+//! would use it, so these tests double as tests of that API.
+
+#![forbid(unsafe_code)]
+
+use rlox::{
+    parse_options_for, parse_with_options, Interpreter, InterpreterConfig, OutputEvent, ParseError,
+};
+use serde_json::{json, Value};
+use std::{
+    cell::RefCell,
+    error::Error,
+    io::{self, Read},
+    rc::Rc,
+};
+
+/// Programs longer than this are rejected without being run.
+pub const MAX_SOURCE_LEN: usize = 64 * 1024;
+/// Printing past this many bytes is a runtime error.
+pub const MAX_OUTPUT_LEN: usize = 64 * 1024;
+/// Request bodies longer than this are rejected without being read in
+/// full. It leaves room for a program of `MAX_SOURCE_LEN` to be escaped.
+pub const MAX_BODY_LEN: usize = 2 * MAX_SOURCE_LEN;
+
+/// Reads a request body of at most `MAX_BODY_LEN` bytes, or gives the
+/// status code and JSON body to reject it with.
+pub fn read_body(body: impl Read) -> Result<String, (u16, Value)> {
+    let mut bytes = vec![];
+    if let Err(e) = body.take(MAX_BODY_LEN as u64 + 1).read_to_end(&mut bytes) {
+        return Err((
+            400,
+            json!({ "error": format!("Cannot read the request: {}.", e) }),
+        ));
+    }
+    if bytes.len() > MAX_BODY_LEN {
+        return Err((
+            413,
+            json!({ "error": format!("Requests are limited to {} bytes.", MAX_BODY_LEN) }),
+        ));
+    }
+    String::from_utf8(bytes)
+        .map_err(|_| (400, json!({ "error": "The request isn't valid UTF-8." })))
+}
+
+/// Handles one request, returning the status code and JSON body to send.
+pub fn handle(method: &str, url: &str, body: &str) -> (u16, Value) {
+    if url != "/run" {
+        return (404, json!({ "error": "Not found." }));
+    }
+    if method != "POST" {
+        return (405, json!({ "error": "Use POST to run a program." }));
+    }
+    let request: Value = match serde_json::from_str(body) {
+        Ok(request) => request,
+        Err(e) => return (400, json!({ "error": format!("Invalid JSON: {}.", e) })),
+    };
+    match request.get("source").and_then(Value::as_str) {
+        Some(source) if source.len() > MAX_SOURCE_LEN => (
+            413,
+            json!({ "error": format!("Programs are limited to {} bytes.", MAX_SOURCE_LEN) }),
+        ),
+        Some(source) => (200, run(source)),
+        None => (400, json!({ "error": "Expected a \"source\" string." })),
+    }
+}
+
+/// Runs `source` in a fresh sandboxed interpreter. The result has the
+/// printed `output`, the `diagnostics` (errors and warnings, in order) and
+/// whether the program ran without errors as `ok`.
+pub fn run(source: &str) -> Value {
+    let events = Rc::new(RefCell::new(Events::default()));
+    let config = InterpreterConfig::sandbox();
+    let ok = match parse_with_options(source.to_string(), parse_options_for(&config)) {
+        Ok(program) => {
+            let collected = events.clone();
+            let mut interpreter = Interpreter::with_config(config)
+                .with_output_callback(move |event| collected.borrow_mut().add(event));
+            interpreter.run(program).is_ok()
+        }
+        Err(errors) => {
+            let diagnostics = &mut events.borrow_mut().diagnostics;
+            diagnostics.extend(errors.iter().map(|e| error(e.as_ref())));
+            false
+        }
+    };
+    let events = events.borrow();
+    json!({
+        "ok": ok,
+        "output": events.output,
+        "diagnostics": events.diagnostics,
+    })
+}
+
+fn error(error: &(dyn Error + 'static)) -> Value {
+    let mut diagnostic = json!({ "severity": "error", "message": error.to_string() });
+    if let Some(fix_it) = error
+        .downcast_ref::<ParseError>()
+        .and_then(ParseError::fix_it)
+    {
+        diagnostic["help"] = json!(fix_it.to_string());
+    }
+    diagnostic
+}
+
+/// Collects what a run shows, failing prints once the output would pass
+/// `MAX_OUTPUT_LEN`.
+#[derive(Default)]
+struct Events {
+    output: String,
+    diagnostics: Vec<Value>,
+}
+
+impl Events {
+    fn add(&mut self, event: OutputEvent) -> io::Result<()> {
+        match event {
+            OutputEvent::Print(line) => {
+                if self.output.len() + line.len() + 1 > MAX_OUTPUT_LEN {
+                    return Err(io::Error::other(format!(
+                        "output is limited to {} bytes",
+                        MAX_OUTPUT_LEN
+                    )));
+                }
+                self.output.push_str(line);
+                self.output.push('\n');
+            }
+            OutputEvent::Warning(warning) => self
+                .diagnostics
+                .push(json!({ "severity": "warning", "message": warning.to_string() })),
+            OutputEvent::Error(e) => self.diagnostics.push(error(e)),
+            OutputEvent::Trace(_) => (),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_and_result() {
+        assert_eq!(
+            run("print 1 + 2;\nprint \"done\";"),
+            json!({ "ok": true, "output": "3\ndone\n", "diagnostics": [] })
+        );
+    }
+
+    #[test]
+    fn diagnostics() {
+        assert_eq!(
+            run("var a = 1;\nif (a = 2) print a;\nprint missing;"),
+            json!({
+                "ok": false,
+                "output": "2\n",
+                "diagnostics": [
+                    {
+                        "severity": "warning",
+                        "message": "[line 2] Warning: Assignment used as a condition; did you mean '=='? [assign-in-condition]",
+                    },
+                    {
+                        "severity": "error",
+                        "message": "[line 3] Error: Undefined variable 'missing'.",
+                    },
+                ],
+            })
+        );
+
+        let result = run("print (1;");
+        assert_eq!(result["ok"], json!(false));
+        assert_eq!(result["output"], json!(""));
+        assert_eq!(
+            result["diagnostics"][0]["message"],
+            json!("[line 1] Error: Expect ')' after expression.")
+        );
+    }
+
+    #[test]
+    fn sandbox_limits() {
+        let result = run("while (true) {}");
+        assert_eq!(result["ok"], json!(false));
+        assert!(result["diagnostics"][0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("Execution limit of 1000000 steps exceeded."));
+
+        let result = run("while (true) print \"spam\";");
+        assert!(result["output"].as_str().unwrap().len() <= MAX_OUTPUT_LEN);
+        assert!(result["diagnostics"][0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("Cannot print: output is limited to"));
+    }
+
+    #[test]
+    fn requests() {
+        let (status, body) = handle("POST", "/run", r#"{"source": "print 1;"}"#);
+        assert_eq!(status, 200);
+        assert_eq!(body["output"], json!("1\n"));
+
+        assert_eq!(handle("GET", "/run", "").0, 405);
+        assert_eq!(handle("POST", "/eval", "").0, 404);
+        assert_eq!(handle("POST", "/run", "print 1;").0, 400);
+        assert_eq!(handle("POST", "/run", r#"{"code": "print 1;"}"#).0, 400);
+        let source = "1;".repeat(MAX_SOURCE_LEN);
+        assert_eq!(
+            handle("POST", "/run", &json!({ "source": source }).to_string()).0,
+            413
+        );
+    }
+
+    #[test]
+    fn request_bodies() {
+        let body = r#"{"source": "print 1;"}"#;
+        assert_eq!(read_body(body.as_bytes()).unwrap(), body);
+        let body = vec![b' '; MAX_BODY_LEN];
+        assert!(read_body(&body[..]).is_ok());
+        let body = vec![b' '; MAX_BODY_LEN + 1];
+        assert_eq!(read_body(&body[..]).unwrap_err().0, 413);
+        assert_eq!(read_body(&[0xff][..]).unwrap_err().0, 400);
+    }
+}
@@ -0,0 +1,93 @@
+//! Serves `POST /run` for the web playground. Listens on the address given
+//! as the first argument, or 127.0.0.1:8080.
+
+#![forbid(unsafe_code)]
+
+use serde_json::{json, Value};
+use std::{
+    env, process,
+    sync::{
+        mpsc::{self, Receiver, TrySendError},
+        Arc, Mutex,
+    },
+    thread,
+};
+use tiny_http::{Header, Request, Response, Server};
+
+/// Lox calls recurse on the Rust stack, so give each run room for the
+/// sandbox's call depth limit.
+const STACK_SIZE: usize = 64 * 1024 * 1024;
+/// How many programs run at once. Each worker has a `STACK_SIZE` stack.
+const WORKERS: usize = 4;
+/// How many requests wait for a worker before more are turned away.
+const QUEUE_LEN: usize = 16;
+
+fn main() {
+    let address = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "127.0.0.1:8080".to_string());
+    let server = match Server::http(&address) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("Cannot listen on {}: {}", address, e);
+            process::exit(1);
+        }
+    };
+
+    let (queue, requests) = mpsc::sync_channel(QUEUE_LEN);
+    let requests = Arc::new(Mutex::new(requests));
+    for _ in 0..WORKERS {
+        let requests = requests.clone();
+        let spawned = thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(move || work(&requests));
+        if let Err(e) = spawned {
+            eprintln!("Cannot start a worker thread: {}", e);
+            process::exit(1);
+        }
+    }
+    eprintln!("Playground listening on http://{}", address);
+
+    for request in server.incoming_requests() {
+        match queue.try_send(request) {
+            Ok(()) => (),
+            Err(TrySendError::Full(request)) | Err(TrySendError::Disconnected(request)) => send(
+                request,
+                503,
+                json!({ "error": "The playground is busy. Try again shortly." }),
+            ),
+        }
+    }
+}
+
+/// Responds to queued requests, one at a time, until the queue is gone.
+fn work(requests: &Mutex<Receiver<Request>>) {
+    loop {
+        let request = match requests.lock() {
+            Ok(requests) => requests.recv(),
+            Err(_) => return,
+        };
+        match request {
+            Ok(request) => respond(request),
+            Err(_) => return,
+        }
+    }
+}
+
+fn respond(mut request: Request) {
+    let (status, json) = match playground::read_body(request.as_reader()) {
+        Ok(body) => playground::handle(request.method().as_str(), request.url(), &body),
+        Err(rejection) => rejection,
+    };
+    send(request, status, json);
+}
+
+fn send(request: Request, status: u16, json: Value) {
+    let content_type = Header::from_bytes("Content-Type", "application/json").unwrap();
+    let response = Response::from_string(json.to_string())
+        .with_status_code(status)
+        .with_header(content_type);
+    if let Err(e) = request.respond(response) {
+        eprintln!("Cannot send a response: {}", e);
+    }
+}
@@ -0,0 +1,88 @@
+//! Questions about what a source's names refer to, answered without running
+//! it. Lines and columns count from 1, as in diagnostics.
+
+use crate::{parse_file, Bindings, Diagnostic, ParseOptions};
+use ast::token::{FileId, Position, Span};
+
+/// Where the variable, function, class or trait named at `line` and
+/// `column` is declared. `None` when there's no name there, or it's a
+/// global the source doesn't declare, such as a native.
+pub fn definition_at(source: &str, line: usize, column: usize) -> Result<Option<Span>, Diagnostic> {
+    let bindings = bindings(source, FileId::default())?;
+    Ok(bindings
+        .declaration_at(Position::new(line, column))
+        .map(|declaration| declaration.span()))
+}
+
+/// Every place the name at `line` and `column` is used, in source order,
+/// with its declaration first. Empty when there's no declared name there.
+pub fn references(source: &str, line: usize, column: usize) -> Result<Vec<Span>, Diagnostic> {
+    let bindings = bindings(source, FileId::default())?;
+    let declaration = match bindings.declaration_at(Position::new(line, column)) {
+        Some(declaration) => declaration,
+        None => return Ok(vec![]),
+    };
+    let mut uses: Vec<Span> = bindings
+        .occurrences(declaration)
+        .iter()
+        .skip(1)
+        .map(|token| token.span())
+        .collect();
+    uses.sort_by_key(|span| span.start);
+    uses.insert(0, declaration.span());
+    Ok(uses)
+}
+
+/// Parses and resolves `source` for its bindings, reporting the first
+/// error if it doesn't get that far.
+pub(crate) fn bindings(source: &str, file: FileId) -> Result<Bindings, Diagnostic> {
+    let program = parse_file(source.to_string(), file, ParseOptions::default())
+        .map_err(|errors| Diagnostic::from(&errors[0]))?;
+    Bindings::resolve(&program).map_err(|errors| Diagnostic::from(&errors[0]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "fun greet(name) {
+  print \"hi \" + name;
+}
+var name = \"you\";
+greet(name);
+greet(clock());
+";
+
+    fn span(line: usize, column: usize, len: usize) -> Span {
+        Span {
+            start: Position::new(line, column),
+            end: Position::new(line, column + len),
+        }
+    }
+
+    #[test]
+    fn definitions() {
+        assert_eq!(definition_at(SOURCE, 2, 20).unwrap(), Some(span(1, 11, 4)));
+        assert_eq!(definition_at(SOURCE, 5, 9).unwrap(), Some(span(4, 5, 4)));
+        assert_eq!(definition_at(SOURCE, 6, 1).unwrap(), Some(span(1, 5, 5)));
+        assert_eq!(definition_at(SOURCE, 6, 7).unwrap(), None);
+        assert_eq!(definition_at(SOURCE, 2, 3).unwrap(), None);
+    }
+
+    #[test]
+    fn all_references() {
+        assert_eq!(
+            references(SOURCE, 1, 7).unwrap(),
+            vec![span(1, 5, 5), span(5, 1, 5), span(6, 1, 5)]
+        );
+        assert_eq!(
+            references(SOURCE, 1, 11).unwrap(),
+            vec![span(1, 11, 4), span(2, 17, 4)]
+        );
+        assert!(references(SOURCE, 6, 7).unwrap().is_empty());
+        assert_eq!(
+            references("print a +;", 1, 7).unwrap_err().to_string(),
+            "[line 1] Error: Expect expression"
+        );
+    }
+}
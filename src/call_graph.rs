@@ -0,0 +1,377 @@
+use crate::{Bindings, Diagnostic};
+use ast::{token::Position, visitor::Visitor, Expr, FunStmt, Stmt};
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+};
+
+/// Which functions refer to which, read from a program without running it.
+///
+/// The nodes are the script's top-level code, its functions, classes and
+/// traits, and their methods, named `Class.method`. A function refers to
+/// another if its code names it, whether it calls it or passes it along.
+/// Anonymous functions belong to the code they're written in. Calls through
+/// a property can only be followed when they're on `this`, to a method of
+/// the same class or trait; constructing a class refers to its `init`.
+#[derive(Debug)]
+pub struct CallGraph {
+    nodes: Vec<Node>,
+    edges: BTreeSet<(usize, usize)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Node {
+    pub name: String,
+    /// Where it's declared; the top-level code is at line 0.
+    pub position: Position,
+}
+
+/// The node for the script's top-level code.
+pub const SCRIPT: &str = "<script>";
+
+impl CallGraph {
+    pub fn new(program: &[Stmt]) -> Result<CallGraph, Diagnostic> {
+        let bindings = Bindings::resolve(program).map_err(|errors| Diagnostic::from(&errors[0]))?;
+        let mut builder = Builder {
+            bindings,
+            nodes: vec![Node {
+                name: SCRIPT.to_string(),
+                position: Position::default(),
+            }],
+            declared: HashMap::new(),
+            references: vec![],
+            edges: BTreeSet::new(),
+            current: 0,
+            methods: vec![],
+        };
+        for stmt in program {
+            builder.visit_stmt(stmt);
+        }
+
+        let declared = &builder.declared;
+        let mut edges = builder.edges;
+        edges.extend(
+            builder
+                .references
+                .iter()
+                .filter_map(|(from, declaration)| declared.get(declaration).map(|to| (*from, *to))),
+        );
+        Ok(CallGraph {
+            nodes: builder.nodes,
+            edges,
+        })
+    }
+
+    /// In the order they're declared, starting with the top-level code.
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// Each caller and what it refers to, once per pair.
+    pub fn edges(&self) -> impl Iterator<Item = (&Node, &Node)> {
+        self.edges
+            .iter()
+            .map(move |&(from, to)| (&self.nodes[from], &self.nodes[to]))
+    }
+
+    /// What the node called `name` refers to.
+    pub fn callees(&self, name: &str) -> Vec<&Node> {
+        self.edges()
+            .filter(|(from, _)| from.name == name)
+            .map(|(_, to)| to)
+            .collect()
+    }
+
+    /// The graph in Graphviz's dot language.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph calls {\n");
+        for node in &self.nodes {
+            dot.push_str(&format!("    {:?};\n", node.name));
+        }
+        for (from, to) in self.edges() {
+            dot.push_str(&format!("    {:?} -> {:?};\n", from.name, to.name));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// One line per node, naming what it refers to.
+impl fmt::Display for CallGraph {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for node in &self.nodes {
+            let callees: Vec<&str> = self
+                .callees(&node.name)
+                .iter()
+                .map(|n| n.name.as_str())
+                .collect();
+            writeln!(f, "{}: {}", node.name, callees.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+struct Builder {
+    bindings: Bindings,
+    nodes: Vec<Node>,
+    // The node each function, class and trait declaration starts.
+    declared: HashMap<Position, usize>,
+    // Which node names which declaration. Declarations can come after the
+    // code that names them, so these are matched up at the end.
+    references: Vec<(usize, Position)>,
+    // Edges known without looking anything up.
+    edges: BTreeSet<(usize, usize)>,
+    current: usize,
+    // The methods of the classes and traits being walked, innermost last.
+    methods: Vec<HashMap<String, usize>>,
+}
+
+impl Builder {
+    fn add_node(&mut self, name: String, position: Position) -> usize {
+        self.nodes.push(Node { name, position });
+        self.nodes.len() - 1
+    }
+
+    fn refer(&mut self, position: Position) {
+        if let Some(declaration) = self.bindings.declaration_at(position) {
+            self.references.push((self.current, declaration.position()));
+        }
+    }
+
+    fn in_node(&mut self, node: usize, walk: impl FnOnce(&mut Builder)) {
+        let enclosing = self.current;
+        self.current = node;
+        walk(self);
+        self.current = enclosing;
+    }
+
+    fn function(&mut self, function: &FunStmt) {
+        for stmt in &function.body {
+            self.visit_stmt(stmt);
+        }
+    }
+
+    /// Adds a node per method, named after `owner`, and walks them with
+    /// their `this` calls resolved among each other.
+    fn methods(&mut self, owner: &str, methods: &[FunStmt]) -> HashMap<String, usize> {
+        let nodes: HashMap<String, usize> = methods
+            .iter()
+            .map(|method| {
+                let name = format!("{}.{}", owner, method.name.lexeme);
+                (
+                    method.name.lexeme.clone(),
+                    self.add_node(name, method.name.position()),
+                )
+            })
+            .collect();
+        self.methods.push(nodes.clone());
+        for method in methods {
+            self.in_node(nodes[&method.name.lexeme], |b| b.function(method));
+        }
+        self.methods.pop();
+        nodes
+    }
+}
+
+impl Visitor<()> for Builder {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Block(block_stmt) => {
+                for stmt in &block_stmt.statements {
+                    self.visit_stmt(stmt);
+                }
+            }
+            Stmt::Class(class_stmt) => {
+                let name = &class_stmt.name;
+                let node = self.add_node(name.lexeme.clone(), name.position());
+                self.declared.insert(name.position(), node);
+                self.in_node(node, |b| {
+                    for t in &class_stmt.traits {
+                        b.visit_expr(t);
+                    }
+                });
+                let methods = self.methods(&name.lexeme, &class_stmt.methods);
+                self.methods.push(methods);
+                self.in_node(node, |b| {
+                    for field in &class_stmt.fields {
+                        if let Some(initializer) = &field.initializer {
+                            b.visit_expr(initializer);
+                        }
+                    }
+                });
+                let methods = self.methods.pop().unwrap_or_default();
+                if let Some(&init) = methods.get("init") {
+                    self.edges.insert((node, init));
+                }
+            }
+            Stmt::Destructure(destructure_stmt) => self.visit_expr(&destructure_stmt.initializer),
+            Stmt::Expr(expr_stmt) => self.visit_expr(&expr_stmt.expression),
+            Stmt::Fun(fun_stmt) => {
+                let name = &fun_stmt.name;
+                let node = self.add_node(name.lexeme.clone(), name.position());
+                self.declared.insert(name.position(), node);
+                self.in_node(node, |b| b.function(fun_stmt));
+            }
+            Stmt::If(if_stmt) => {
+                self.visit_expr(&if_stmt.condition);
+                self.visit_stmt(&if_stmt.then_branch);
+                if let Some(else_branch) = &if_stmt.else_branch {
+                    self.visit_stmt(else_branch);
+                }
+            }
+            Stmt::Print(print_stmt) => {
+                for expr in &print_stmt.expressions {
+                    self.visit_expr(expr);
+                }
+            }
+            Stmt::Return(return_stmt) => {
+                if let Some(value) = &return_stmt.value {
+                    self.visit_expr(value);
+                }
+            }
+            Stmt::Trait(trait_stmt) => {
+                let name = &trait_stmt.name;
+                let node = self.add_node(name.lexeme.clone(), name.position());
+                self.declared.insert(name.position(), node);
+                self.methods(&name.lexeme, &trait_stmt.methods);
+            }
+            Stmt::Var(var_stmt) => {
+                if let Some(initializer) = &var_stmt.initializer {
+                    self.visit_expr(initializer);
+                }
+            }
+            Stmt::While(while_stmt) => {
+                self.visit_expr(&while_stmt.condition);
+                self.visit_stmt(&while_stmt.body);
+            }
+            _ => (),
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Array(array_expr) => {
+                for element in &array_expr.elements {
+                    self.visit_expr(element);
+                }
+            }
+            Expr::Assign(assign_expr) => self.visit_expr(&assign_expr.value),
+            Expr::Binary(binary_expr) => {
+                self.visit_expr(&binary_expr.left);
+                self.visit_expr(&binary_expr.right);
+            }
+            Expr::Block(block_expr) => {
+                for stmt in &block_expr.statements {
+                    self.visit_stmt(stmt);
+                }
+                if let Some(value) = &block_expr.value {
+                    self.visit_expr(value);
+                }
+            }
+            Expr::Call(call_expr) => {
+                self.visit_expr(&call_expr.callee);
+                for arg in &call_expr.arguments {
+                    self.visit_expr(arg);
+                }
+                for (_, arg) in &call_expr.keyword_arguments {
+                    self.visit_expr(arg);
+                }
+            }
+            Expr::Function(fun_expr) => self.function(&fun_expr.declaration),
+            Expr::Get(get_expr) => {
+                if let Expr::This(_) = *get_expr.object {
+                    let method = self
+                        .methods
+                        .last()
+                        .and_then(|methods| methods.get(&get_expr.name.lexeme));
+                    if let Some(&method) = method {
+                        self.edges.insert((self.current, method));
+                    }
+                }
+                self.visit_expr(&get_expr.object);
+            }
+            Expr::Grouping(grouping_expr) => self.visit_expr(&grouping_expr.expression),
+            Expr::If(if_expr) => {
+                self.visit_expr(&if_expr.condition);
+                self.visit_expr(&if_expr.then_branch);
+                if let Some(else_branch) = &if_expr.else_branch {
+                    self.visit_expr(else_branch);
+                }
+            }
+            Expr::Index(index_expr) => {
+                self.visit_expr(&index_expr.object);
+                self.visit_expr(&index_expr.index);
+            }
+            Expr::Literal(_) | Expr::This(_) => (),
+            Expr::Logical(logical_expr) => {
+                self.visit_expr(&logical_expr.left);
+                self.visit_expr(&logical_expr.right);
+            }
+            Expr::Set(set_expr) => {
+                self.visit_expr(&set_expr.object);
+                self.visit_expr(&set_expr.value);
+            }
+            Expr::SetIndex(set_expr) => {
+                self.visit_expr(&set_expr.object);
+                self.visit_expr(&set_expr.index);
+                self.visit_expr(&set_expr.value);
+            }
+            Expr::Spread(spread_expr) => self.visit_expr(&spread_expr.expression),
+            Expr::Unary(unary_expr) => self.visit_expr(&unary_expr.right),
+            Expr::Variable(var_expr) => self.refer(var_expr.name.position()),
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(source: &str) -> CallGraph {
+        CallGraph::new(&crate::parse(source.to_string()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn functions_classes_and_methods() {
+        let graph = graph(
+            "fun helper() { return 1; }
+             fun unused() { return helper; }
+             class Point {
+               init(x) { this.x = x; this.check(); }
+               check() { return helper(); }
+               norm() { fun helper() {} return helper(); }
+             }
+             fun main() { var each = fun (p) { print Point(p); }; each(2); }
+             main();",
+        );
+        assert_eq!(
+            graph.to_string(),
+            "<script>: main
+helper: \nunused: helper
+Point: Point.init
+Point.init: Point.check
+Point.check: helper
+Point.norm: helper
+helper: \nmain: Point
+"
+        );
+        assert_eq!(graph.callees("main")[0].position.line, 3);
+    }
+
+    #[test]
+    fn dot() {
+        let graph = graph("fun f() { g(); }\nfun g() {}\nf();");
+        assert_eq!(
+            graph.to_dot(),
+            "digraph calls {
+    \"<script>\";
+    \"f\";
+    \"g\";
+    \"<script>\" -> \"f\";
+    \"f\" -> \"g\";
+}
+"
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use crate::{ParseError, ResolverError, RuntimeError};
+use ast::token::{Position, Span};
+use std::{error::Error, fmt};
+
+/// Why a tooling request on a source failed: an error in the source itself,
+/// or a reason the request can't be carried out there.
+///
+/// It's also the one shape every error takes for tools that would rather
+/// not depend on each error type: see `from_error`. Its accessors, like the
+/// errors' own `line`, `code`, `message` and `span`, stay as they are when
+/// the errors' insides change.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    span: Span,
+    code: &'static str,
+    message: String,
+}
+
+impl Diagnostic {
+    /// A reason a request can't be carried out at `position`, with the code
+    /// `request`.
+    pub fn new(position: Position, message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            span: Span {
+                start: position,
+                end: position,
+            },
+            code: "request",
+            message: message.into(),
+        }
+    }
+
+    /// The diagnostic for a parse, resolver or runtime error, or a
+    /// diagnostic, as `run` and the parse functions return them boxed.
+    pub fn from_error(error: &(dyn Error + 'static)) -> Option<Diagnostic> {
+        if let Some(error) = error.downcast_ref::<ParseError>() {
+            Some(Diagnostic::from(error))
+        } else if let Some(error) = error.downcast_ref::<ResolverError>() {
+            Some(Diagnostic::from(error))
+        } else if let Some(error) = error.downcast_ref::<RuntimeError>() {
+            Some(Diagnostic::from(error))
+        } else {
+            error.downcast_ref::<Diagnostic>().cloned()
+        }
+    }
+
+    pub fn line(&self) -> usize {
+        self.span.start.line
+    }
+
+    pub fn position(&self) -> Position {
+        self.span.start
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// What kind of error this is: `syntax`, `resolve`, `runtime`,
+    /// `interrupted`, `internal` or `request`.
+    pub fn code(&self) -> &'static str {
+        self.code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[line {}] Error: {}", self.line(), self.message)
+    }
+}
+
+impl Error for Diagnostic {}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(error: &ParseError) -> Diagnostic {
+        Diagnostic {
+            span: error.span(),
+            code: error.code(),
+            message: error.message().to_string(),
+        }
+    }
+}
+
+impl From<&ResolverError> for Diagnostic {
+    fn from(error: &ResolverError) -> Diagnostic {
+        Diagnostic {
+            span: error.span(),
+            code: error.code(),
+            message: error.message().to_string(),
+        }
+    }
+}
+
+impl From<&RuntimeError> for Diagnostic {
+    fn from(error: &RuntimeError) -> Diagnostic {
+        Diagnostic {
+            span: error.span(),
+            code: error.code(),
+            message: error.message().to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, Interpreter};
+
+    fn diagnostics(source: &str) -> Vec<Diagnostic> {
+        let errors = match parse(source.to_string()) {
+            Ok(program) => Interpreter::new().run(program).unwrap_err(),
+            Err(errors) => errors,
+        };
+        errors
+            .iter()
+            .map(|error| Diagnostic::from_error(error.as_ref()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn from_errors() {
+        let syntax = &diagnostics("print 1")[0];
+        assert_eq!(
+            (syntax.line(), syntax.code(), syntax.message()),
+            (1, "syntax", "Expect ';' after value.")
+        );
+        assert_eq!(syntax.span().start, Position::new(1, 8));
+
+        let resolve = &diagnostics("{ var a; var a; }")[0];
+        assert_eq!(
+            (resolve.line(), resolve.code(), resolve.message()),
+            (
+                1,
+                "resolve",
+                "Variable with this name already declared in this scope."
+            )
+        );
+
+        let runtime = &diagnostics("\nprint -nil;")[0];
+        assert_eq!(
+            (runtime.line(), runtime.code(), runtime.message()),
+            (2, "runtime", "Operand must be a number.")
+        );
+        assert_eq!(runtime.span().start, runtime.span().end);
+
+        let request = Diagnostic::new(Position::new(3, 4), "No variable here.");
+        assert_eq!(Diagnostic::from_error(&request), Some(request.clone()));
+        assert_eq!(request.code(), "request");
+    }
+}
@@ -0,0 +1,501 @@
+//! Random Lox programs, for fuzzing the interpreter and for running the same
+//! program through other Lox implementations to compare what they print.
+
+use ast::{
+    token::{Literal, Token, TokenType},
+    BinaryOp, Expr, FunStmt, LogicalOp, Stmt, UnaryOp,
+};
+
+/// A random program built from the AST, the same for the same `seed`.
+/// `depth` bounds how deeply statements and expressions nest.
+///
+/// The programs stick to the language of the book, without arrays, traits
+/// or the other extensions, so jlox and clox run them too. They parse,
+/// resolve, finish and don't raise runtime errors. They only print whole
+/// numbers, strings and booleans, which every implementation shows the same
+/// way: loops count to at most 3, a function can only call the ones
+/// declared before it, and assignments only add to a variable, so numbers
+/// stay small.
+pub fn generate(seed: u64, depth: usize) -> Vec<Stmt> {
+    let mut generator = Generator {
+        rng: Rng(seed),
+        scopes: vec![vec![]],
+        next_name: 0,
+        in_method: false,
+    };
+    let count = 3 + generator.rng.below(2 * depth + 1);
+    (0..count).map(|_| generator.stmt(depth, true)).collect()
+}
+
+/// SplitMix64, which is plenty for picking among a few choices.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn one_in(&mut self, n: usize) -> bool {
+        self.below(n) == 0
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> Option<&'a T> {
+        if items.is_empty() {
+            None
+        } else {
+            Some(&items[self.below(items.len())])
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Type {
+    Number,
+    String,
+    Bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Kind {
+    /// A variable or parameter. Loop counters can't be assigned to.
+    Value {
+        ty: Type,
+        assignable: bool,
+    },
+    /// A function taking this many numbers and returning a number.
+    Function(usize),
+    /// A class whose instances have a number `value`, set by `init`, and a
+    /// `plus(n)` method returning a number.
+    Class,
+    Instance,
+}
+
+#[derive(Clone, Debug)]
+struct Name {
+    name: String,
+    kind: Kind,
+}
+
+struct Generator {
+    rng: Rng,
+    // What's declared in each enclosing scope, innermost last.
+    scopes: Vec<Vec<Name>>,
+    next_name: usize,
+    // Whether `this.value` can be used.
+    in_method: bool,
+}
+
+impl Generator {
+    fn fresh(&mut self, prefix: &str) -> Token {
+        self.next_name += 1;
+        token(
+            TokenType::Identifier,
+            &format!("{}{}", prefix, self.next_name),
+        )
+    }
+
+    fn declare(&mut self, name: &Token, kind: Kind) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push(Name {
+                name: name.lexeme.clone(),
+                kind,
+            });
+        }
+    }
+
+    fn visible(&self, wanted: impl Fn(&Kind) -> bool) -> Vec<Name> {
+        self.scopes
+            .iter()
+            .flatten()
+            .filter(|name| wanted(&name.kind))
+            .cloned()
+            .collect()
+    }
+
+    fn scoped<T>(&mut self, names: Vec<Name>, walk: impl FnOnce(&mut Generator) -> T) -> T {
+        self.scopes.push(names);
+        let result = walk(self);
+        self.scopes.pop();
+        result
+    }
+
+    fn any_type(&mut self) -> Type {
+        [Type::Number, Type::String, Type::Bool][self.rng.below(3)]
+    }
+
+    fn stmts(&mut self, depth: usize) -> Vec<Stmt> {
+        let count = 1 + self.rng.below(3);
+        (0..count).map(|_| self.stmt(depth, false)).collect()
+    }
+
+    /// Classes are only declared at the top level, so they can't capture
+    /// anything.
+    fn stmt(&mut self, depth: usize, top_level: bool) -> Stmt {
+        let choices = match depth {
+            0 => 3,
+            _ if top_level => 8,
+            _ => 7,
+        };
+        match self.rng.below(choices) {
+            0 => self.var(depth),
+            1 => {
+                let ty = self.any_type();
+                Stmt::print(
+                    &token(TokenType::Print, "print"),
+                    vec![self.expr(ty, depth)],
+                )
+            }
+            2 => self.assignment(depth),
+            3 => {
+                let condition = self.expr(Type::Bool, depth);
+                let then_branch = self.block(depth - 1);
+                if self.rng.one_in(2) {
+                    let else_branch = self.block(depth - 1);
+                    Stmt::if_then_else(condition, then_branch, else_branch)
+                } else {
+                    Stmt::if_then(condition, then_branch)
+                }
+            }
+            4 => self.counted_loop(depth),
+            5 => self.block(depth - 1),
+            6 => self.function(depth),
+            _ => self.class(depth),
+        }
+    }
+
+    fn block(&mut self, depth: usize) -> Stmt {
+        let stmts = self.scoped(vec![], |g| g.stmts(depth));
+        Stmt::block(stmts)
+    }
+
+    fn var(&mut self, depth: usize) -> Stmt {
+        let classes = self.visible(|kind| *kind == Kind::Class);
+        if !classes.is_empty() && self.rng.one_in(3) {
+            let class = token(
+                TokenType::Identifier,
+                &classes[self.rng.below(classes.len())].name,
+            );
+            let value = self.expr(Type::Number, depth);
+            let name = self.fresh("o");
+            self.declare(&name, Kind::Instance);
+            return Stmt::var_init(&name, call(variable(&class), vec![value]));
+        }
+        let ty = self.any_type();
+        let value = self.expr(ty, depth);
+        let name = self.fresh("v");
+        self.declare(
+            &name,
+            Kind::Value {
+                ty,
+                assignable: true,
+            },
+        );
+        Stmt::var_init(&name, value)
+    }
+
+    /// Adds to a variable or an instance's `value`, or sets it to a literal,
+    /// so values grow no faster than the loops around them run.
+    fn assignment(&mut self, depth: usize) -> Stmt {
+        let targets = self.visible(|kind| match kind {
+            Kind::Value { assignable, .. } => *assignable,
+            Kind::Instance => true,
+            _ => false,
+        });
+        let target = match self.rng.pick(&targets) {
+            Some(target) => target.clone(),
+            None => {
+                return Stmt::print(
+                    &token(TokenType::Print, "print"),
+                    vec![self.expr(Type::String, depth)],
+                )
+            }
+        };
+        let name = token(TokenType::Identifier, &target.name);
+        let ty = match target.kind {
+            Kind::Value { ty, .. } => ty,
+            _ => Type::Number,
+        };
+        let current = match target.kind {
+            Kind::Instance => get(variable(&name), "value"),
+            _ => variable(&name),
+        };
+        let value = match ty {
+            Type::Bool => self.leaf(Type::Bool),
+            _ if self.rng.one_in(3) => self.literal(ty),
+            _ => {
+                let literal = self.literal(ty);
+                binary(current, BinaryOp::Add, literal)
+            }
+        };
+        match target.kind {
+            Kind::Instance => Stmt::expr(Expr::set(
+                variable(&name),
+                &token(TokenType::Identifier, "value"),
+                value,
+            )),
+            _ => Stmt::expr(Expr::assign(&name, value)),
+        }
+    }
+
+    /// `for (var i = 0; i < n; i = i + 1) body`, as the parser desugars it.
+    fn counted_loop(&mut self, depth: usize) -> Stmt {
+        let counter = self.fresh("i");
+        let times = number(1 + self.rng.below(3));
+        let counter_name = Name {
+            name: counter.lexeme.clone(),
+            kind: Kind::Value {
+                ty: Type::Number,
+                assignable: false,
+            },
+        };
+        let mut body = self.scoped(vec![counter_name], |g| g.stmts(depth - 1));
+        body.push(Stmt::expr(Expr::assign(
+            &counter,
+            binary(variable(&counter), BinaryOp::Add, number(1)),
+        )));
+        Stmt::block(vec![
+            Stmt::var_init(&counter, number(0)),
+            Stmt::while_loop(
+                binary(variable(&counter), BinaryOp::Less, times),
+                Stmt::block(body),
+            ),
+        ])
+    }
+
+    /// A function of up to two numbers that returns a number. It's declared
+    /// after its body is made, so it can't call itself.
+    fn function(&mut self, depth: usize) -> Stmt {
+        let name = self.fresh("f");
+        let params: Vec<Token> = (0..self.rng.below(3)).map(|_| self.fresh("a")).collect();
+        let body = self.function_body(&params, depth);
+        self.declare(&name, Kind::Function(params.len()));
+        Stmt::function(&name, params, None, body)
+    }
+
+    fn function_body(&mut self, params: &[Token], depth: usize) -> Vec<Stmt> {
+        let params = params
+            .iter()
+            .map(|param| Name {
+                name: param.lexeme.clone(),
+                kind: Kind::Value {
+                    ty: Type::Number,
+                    assignable: true,
+                },
+            })
+            .collect();
+        self.scoped(params, |g| {
+            let mut body = g.stmts(depth - 1);
+            let value = g.expr(Type::Number, depth - 1);
+            body.push(Stmt::ret(&token(TokenType::Return, "return"), Some(value)));
+            body
+        })
+    }
+
+    fn class(&mut self, depth: usize) -> Stmt {
+        let name = self.fresh("C");
+        let value = token(TokenType::Identifier, "value");
+        let this = Expr::this(&token(TokenType::This, "this"));
+
+        let init_param = self.fresh("a");
+        let init = FunStmt {
+            name: token(TokenType::Identifier, "init"),
+            parameters: vec![init_param.clone()],
+            rest: None,
+            body: vec![Stmt::expr(Expr::set(this, &value, variable(&init_param)))],
+        };
+        let plus_params = vec![self.fresh("a")];
+        self.in_method = true;
+        let plus_body = self.function_body(&plus_params, depth);
+        self.in_method = false;
+        let plus = FunStmt {
+            name: token(TokenType::Identifier, "plus"),
+            parameters: plus_params,
+            rest: None,
+            body: plus_body,
+        };
+
+        self.declare(&name, Kind::Class);
+        Stmt::class(&name, vec![init, plus])
+    }
+
+    fn expr(&mut self, ty: Type, depth: usize) -> Expr {
+        if depth == 0 || self.rng.one_in(3) {
+            return self.leaf(ty);
+        }
+        let depth = depth - 1;
+        match ty {
+            Type::Number => match self.rng.below(5) {
+                0 => unary(UnaryOp::Negate, self.expr(ty, depth)),
+                1 => self.call(depth),
+                2 => Expr::grouping(self.expr(ty, depth)),
+                _ => {
+                    let operator = [BinaryOp::Add, BinaryOp::Subtract][self.rng.below(2)];
+                    binary(self.expr(ty, depth), operator, self.expr(ty, depth))
+                }
+            },
+            Type::String => binary(self.expr(ty, depth), BinaryOp::Add, self.expr(ty, depth)),
+            Type::Bool => match self.rng.below(4) {
+                0 => unary(UnaryOp::Not, self.expr(ty, depth)),
+                1 => {
+                    let operator = [
+                        BinaryOp::Less,
+                        BinaryOp::LessEqual,
+                        BinaryOp::Greater,
+                        BinaryOp::GreaterEqual,
+                    ][self.rng.below(4)];
+                    binary(
+                        self.expr(Type::Number, depth),
+                        operator,
+                        self.expr(Type::Number, depth),
+                    )
+                }
+                2 => {
+                    let compared = self.any_type();
+                    let operator = [BinaryOp::Equal, BinaryOp::NotEqual][self.rng.below(2)];
+                    binary(
+                        self.expr(compared, depth),
+                        operator,
+                        self.expr(compared, depth),
+                    )
+                }
+                _ => {
+                    let operator = [LogicalOp::And, LogicalOp::Or][self.rng.below(2)];
+                    Expr::logical(
+                        operand(self.expr(ty, depth)),
+                        operator,
+                        operand(self.expr(ty, depth)),
+                    )
+                }
+            },
+        }
+    }
+
+    /// A call to a function, a class's `plus` method or, failing those, a
+    /// plain number.
+    fn call(&mut self, depth: usize) -> Expr {
+        let callees = self.visible(|kind| matches!(kind, Kind::Function(_) | Kind::Instance));
+        let callee = match self.rng.pick(&callees) {
+            Some(callee) => callee.clone(),
+            None => return self.leaf(Type::Number),
+        };
+        let name = token(TokenType::Identifier, &callee.name);
+        match callee.kind {
+            Kind::Function(arity) => {
+                let args = (0..arity).map(|_| self.expr(Type::Number, depth)).collect();
+                call(variable(&name), args)
+            }
+            _ => {
+                let arg = self.expr(Type::Number, depth);
+                call(get(variable(&name), "plus"), vec![arg])
+            }
+        }
+    }
+
+    fn leaf(&mut self, ty: Type) -> Expr {
+        let mut variables: Vec<Expr> = self
+            .visible(|kind| matches!(kind, Kind::Value { ty: t, .. } if *t == ty))
+            .iter()
+            .map(|name| variable(&token(TokenType::Identifier, &name.name)))
+            .collect();
+        if ty == Type::Number {
+            variables.extend(
+                self.visible(|kind| *kind == Kind::Instance)
+                    .iter()
+                    .map(|name| get(variable(&token(TokenType::Identifier, &name.name)), "value")),
+            );
+            if self.in_method {
+                variables.push(get(Expr::this(&token(TokenType::This, "this")), "value"));
+            }
+        }
+        match self.rng.pick(&variables) {
+            Some(variable) if !self.rng.one_in(3) => variable.clone(),
+            _ => self.literal(ty),
+        }
+    }
+
+    fn literal(&mut self, ty: Type) -> Expr {
+        match ty {
+            Type::Number => number(self.rng.below(10)),
+            Type::String => {
+                let word = ["a", "b", "lox", "hi", ""][self.rng.below(5)];
+                Expr::literal(Literal::String(word.to_string()))
+            }
+            Type::Bool if self.rng.one_in(2) => Expr::literal(Literal::True),
+            Type::Bool => Expr::literal(Literal::False),
+        }
+    }
+}
+
+fn token(token_type: TokenType, lexeme: &str) -> Token {
+    Token::new(token_type, lexeme.to_string(), None, 1)
+}
+
+/// Parenthesizes an operand that would otherwise bind differently, since
+/// the formatter only prints the groupings in the tree.
+fn operand(expr: Expr) -> Expr {
+    match expr {
+        Expr::Assign(_) | Expr::Binary(_) | Expr::Logical(_) | Expr::Unary(_) => {
+            Expr::grouping(expr)
+        }
+        expr => expr,
+    }
+}
+
+fn binary(left: Expr, operator: BinaryOp, right: Expr) -> Expr {
+    Expr::binary(operand(left), operator, operand(right))
+}
+
+fn unary(operator: UnaryOp, right: Expr) -> Expr {
+    Expr::unary(operator, operand(right))
+}
+
+fn number(n: usize) -> Expr {
+    Expr::literal(Literal::Number(n as f64))
+}
+
+fn variable(name: &Token) -> Expr {
+    Expr::variable(name)
+}
+
+fn get(object: Expr, name: &str) -> Expr {
+    Expr::get(object, &token(TokenType::Identifier, name))
+}
+
+fn call(callee: Expr, args: Vec<Expr>) -> Expr {
+    Expr::call(callee, &token(TokenType::RightParen, ")"), args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{format_stmts, parse, Interpreter, InterpreterConfig};
+    use std::io;
+
+    #[test]
+    fn programs_run() {
+        for seed in 0..200 {
+            let source = format_stmts(&generate(seed, 4));
+            let program = parse(source.clone())
+                .unwrap_or_else(|e| panic!("seed {}: {}\n{}", seed, e[0], source));
+            let mut interpreter =
+                Interpreter::with_config(InterpreterConfig::sandbox()).with_output(io::sink());
+            if let Err(errors) = interpreter.run(program) {
+                panic!("seed {}: {}\n{}", seed, errors[0], source);
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed_same_program() {
+        assert_eq!(format_stmts(&generate(7, 3)), format_stmts(&generate(7, 3)));
+        assert_ne!(format_stmts(&generate(7, 3)), format_stmts(&generate(8, 3)));
+    }
+}
@@ -0,0 +1,73 @@
+use ast::token::TokenClass;
+use parser::scan;
+
+/// `source` with terminal colors: keywords magenta, strings green, numbers
+/// yellow and comments grey. Source that doesn't scan, such as a string
+/// left open, comes back as it is.
+pub fn highlight(source: &str) -> String {
+    let tokens = match scan(source.to_string()) {
+        Ok(tokens) => tokens,
+        Err(_) => return source.to_string(),
+    };
+    let mut out = String::new();
+    let mut end = 0;
+    for token in &tokens {
+        highlight_gap(&source[end..token.start], &mut out);
+        let text = &source[token.start..token.end];
+        match color(token.token_type.class()) {
+            Some(color) => paint(text, color, &mut out),
+            None => out.push_str(text),
+        }
+        end = token.end;
+    }
+    highlight_gap(&source[end..], &mut out);
+    out
+}
+
+fn color(class: TokenClass) -> Option<&'static str> {
+    match class {
+        TokenClass::Keyword => Some("35"),
+        TokenClass::String => Some("32"),
+        TokenClass::Number => Some("33"),
+        TokenClass::Identifier
+        | TokenClass::Operator
+        | TokenClass::Punctuation
+        | TokenClass::Eof => None,
+    }
+}
+
+fn paint(text: &str, color: &str, out: &mut String) {
+    out.push_str(&format!("\x1b[{}m{}\x1b[0m", color, text));
+}
+
+/// What's between two tokens is whitespace and comments, which run from
+/// `//` to the end of the line.
+fn highlight_gap(gap: &str, out: &mut String) {
+    for (i, line) in gap.split('\n').enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        match line.find("//") {
+            Some(comment) => {
+                out.push_str(&line[..comment]);
+                paint(&line[comment..], "90", out);
+            }
+            None => out.push_str(line),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors() {
+        assert_eq!(
+            highlight("var x = \"a\"; // one\nprint x + 1.5;\n"),
+            "\x1b[35mvar\x1b[0m x = \x1b[32m\"a\"\x1b[0m; \x1b[90m// one\x1b[0m\n\
+             \x1b[35mprint\x1b[0m x + \x1b[33m1.5\x1b[0m;\n"
+        );
+        assert_eq!(highlight("print \"open"), "print \"open");
+    }
+}
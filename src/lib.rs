@@ -1,56 +1,284 @@
-use interpreter::Interpreter;
-use parser::parse;
-use std::{error::Error, fmt};
+//! A tree-walking interpreter for Lox.
+//!
+//! This crate is the public face of the workspace: it re-exports what
+//! embedders need from the `ast`, `parser` and `interpreter` crates so they
+//! can depend on `rlox` alone.
+//!
+//! ```
+//! let program = rlox::parse("1 + 2;".to_string()).unwrap();
+//! let value = rlox::Interpreter::new().run(program).unwrap();
+//! assert_eq!(value, Some(rlox::Value::Number(3.0)));
+//! ```
 
-#[derive(Debug)]
-pub struct LoxError;
+#![forbid(unsafe_code)]
+
+pub mod analysis;
+mod call_graph;
+mod diagnostic;
+pub mod grammar;
+mod highlight;
+mod metrics;
+pub mod mutate;
+mod pure;
+pub mod refactor;
+mod repl;
+mod source_map;
+pub mod testing;
+
+pub use crate::{
+    call_graph::{CallGraph, Node},
+    diagnostic::Diagnostic,
+    highlight::highlight,
+    metrics::{metrics, FunctionMetrics},
+    pure::evaluate_pure,
+    repl::ReplConfig,
+    source_map::SourceMap,
+};
+pub use ast;
+pub use ast::format::{format_expr, format_stmt, format_stmts};
+pub use ast::{
+    limits::LanguageLimits,
+    token::{Position, Span},
+    version::LanguageVersion,
+};
+pub use interpreter::{
+    evaluate_expr, native_args, portable_path, Bindings, Capabilities, ConfigError, Environment,
+    ExecutionLimits, FileLoader, Input, Interpreter, InterpreterConfig, LintConfig, LoxString,
+    MemoryLoader, ModuleLoader, NativeArg, OutputEvent, Recording, ResolverError, RunReport,
+    RuntimeError, Value, Warning, CONFIG_FILE_NAME,
+};
+pub use parser::{
+    parse, parse_expression, parse_file, parse_with_options, scan, FixIt, ParseError, ParseOptions,
+};
+
+use crate::repl::ReplOutput;
+use std::{
+    error::Error,
+    fmt,
+    fs::File,
+    io::Write,
+    sync::{atomic::AtomicBool, Arc},
+};
+
+/// Why a run failed. The errors themselves have already been reported,
+/// except by `evaluate_pure`.
+#[derive(Debug, PartialEq)]
+pub enum LoxError {
+    /// The source didn't parse or resolve, so none of it ran.
+    Compile,
+    Runtime,
+    /// The host cancelled the run. See `Interpreter::cancellation`.
+    Interrupted,
+    /// `evaluate_pure` couldn't parse, allow or evaluate its expression,
+    /// for the reason given.
+    Pure(String),
+}
 
 impl fmt::Display for LoxError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.description())
+        match self {
+            LoxError::Compile => write!(f, "Compile error."),
+            LoxError::Runtime => write!(f, "Runtime error."),
+            LoxError::Interrupted => write!(f, "Interrupted."),
+            LoxError::Pure(msg) => write!(f, "{}", msg),
+        }
     }
 }
 
-impl Error for LoxError {
-    fn description(&self) -> &str {
-        "Error."
-    }
+impl Error for LoxError {}
 
-    fn cause(&self) -> Option<&Error> {
-        None
+/// The options to parse programs with before running them under `config`:
+/// its language version and limits, and the standard grammar otherwise.
+pub fn parse_options_for(config: &InterpreterConfig) -> ParseOptions {
+    ParseOptions {
+        version: config.version,
+        limits: config.language_limits,
+        ..ParseOptions::default()
     }
 }
 
 pub struct Repl {
     interpreter: Interpreter,
+    parse_options: ParseOptions,
+    sources: SourceMap,
+    color: bool,
+    output: ReplOutput,
+    // The file `:transcript` is writing the session to.
+    transcript: Option<File>,
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Repl {
     pub fn new() -> Repl {
+        Repl::with_config(InterpreterConfig::default())
+    }
+
+    pub fn with_config(config: InterpreterConfig) -> Repl {
+        let output = ReplOutput::default();
         Repl {
-            interpreter: Interpreter::new(),
+            parse_options: parse_options_for(&config),
+            interpreter: Interpreter::with_config(config).with_output(output.clone()),
+            sources: SourceMap::new(),
+            color: false,
+            output,
+            transcript: None,
         }
     }
 
+    /// Parses inputs with `parse_options` instead of `parse_options_for`
+    /// the config.
+    pub fn with_parse_options(mut self, parse_options: ParseOptions) -> Repl {
+        self.parse_options = parse_options;
+        self
+    }
+
+    /// Colors what's printed by type and, in `interact`, shows each input
+    /// again highlighted once it's entered, for a terminal.
+    pub fn with_color(mut self) -> Repl {
+        self.interpreter = self.interpreter.with_color();
+        self.color = true;
+        self
+    }
+
+    /// Records what natives read from outside, for replaying the session.
+    pub fn with_recording(mut self) -> Repl {
+        self.interpreter = self.interpreter.with_recording();
+        self
+    }
+
+    /// Gives natives the inputs of a recorded session instead.
+    pub fn with_replay(mut self, recording: Recording) -> Repl {
+        self.interpreter = self.interpreter.with_replay(recording);
+        self
+    }
+
+    /// What's been recorded so far, when recording.
+    pub fn recording(&self) -> Option<Recording> {
+        self.interpreter.recording()
+    }
+
+    /// Stops the running program when set. See `Interpreter::cancellation`.
+    pub fn cancellation(&self) -> Arc<AtomicBool> {
+        self.interpreter.cancellation()
+    }
+
+    /// How many times each kind of statement and expression has run this
+    /// session, when the config's `count_nodes` is set.
+    pub fn node_counts(&self) -> Option<Vec<(&'static str, u64)>> {
+        self.interpreter.node_counts()
+    }
+
+    /// What this session's runs did, when the config's `report` is set.
+    pub fn report(&self) -> Option<RunReport> {
+        self.interpreter.report()
+    }
+
+    /// The environments where the last runtime error was raised, when the
+    /// config's `dump_env_on_error` is set. See
+    /// `Interpreter::error_environments`.
+    pub fn error_environments(&self) -> Option<&[Vec<(String, String)>]> {
+        self.interpreter.error_environments()
+    }
+
+    /// Everything this session has run, for rendering diagnostics.
+    pub fn sources(&self) -> &SourceMap {
+        &self.sources
+    }
+
+    /// Runs one more input, named `<repl:N>` in diagnostics.
     pub fn run(&mut self, source: String) -> Result<(), LoxError> {
-        match parse(source) {
-            Ok(program) => match self.interpreter.run(program) {
-                Ok(_) => Ok(()),
-                Err(errors) => {
-                    report_errors(errors);
-                    Err(LoxError)
-                }
-            },
+        let name = format!("<repl:{}>", self.sources.len() + 1);
+        self.run_named(&name, source)
+    }
+
+    /// Runs a source that diagnostics should call `name`, such as the path
+    /// of the script it was read from.
+    pub fn run_named(&mut self, name: &str, source: String) -> Result<(), LoxError> {
+        let file = self.sources.add(name, &source);
+        match parse_file(source, file, self.parse_options) {
+            Ok(program) => {
+                // Resolver warnings come before anything the program prints.
+                let resolved = self.interpreter.resolve(&program);
+                self.report_warnings();
+                let result = resolved.and_then(|()| self.interpreter.run_resolved(program));
+                self.report_warnings();
+                result.map(|_| ()).map_err(|errors| {
+                    let runtime = errors.iter().find_map(|e| e.downcast_ref::<RuntimeError>());
+                    let error = match runtime {
+                        Some(e) if e.is_interrupted() => LoxError::Interrupted,
+                        Some(_) => LoxError::Runtime,
+                        None => LoxError::Compile,
+                    };
+                    self.report_errors(errors);
+                    self.report_environments();
+                    error
+                })
+            }
             Err(errors) => {
-                report_errors(errors);
-                Err(LoxError)
+                self.report_errors(errors.into_iter().map(|e| Box::new(e) as Box<dyn Error>));
+                Err(LoxError::Compile)
+            }
+        }
+    }
+
+    fn report_warnings(&mut self) {
+        for warning in self.interpreter.take_warnings() {
+            eprintln!("{}", warning);
+            if let Some(snippet) = self.sources.snippet(warning.position()) {
+                eprintln!("{}", snippet);
+            }
+        }
+    }
+
+    // Errors go where `print` output goes, so a transcript has them too.
+    fn report_errors(&self, errors: impl IntoIterator<Item = Box<dyn Error>>) {
+        let mut out = self.output.clone();
+        for error in errors {
+            let _ = writeln!(out, "{}", error);
+            if let Some(snippet) =
+                error_position(error.as_ref()).and_then(|position| self.sources.snippet(position))
+            {
+                let _ = writeln!(out, "{}", snippet);
+            }
+            if let Some(fix_it) = error
+                .downcast_ref::<ParseError>()
+                .and_then(ParseError::fix_it)
+            {
+                let _ = writeln!(out, "  help: {}", fix_it);
+            }
+        }
+    }
+
+    /// Prints the environments kept from a runtime error to stderr, one
+    /// line per scope, innermost first.
+    fn report_environments(&self) {
+        if let Some(environments) = self.error_environments() {
+            eprintln!("Environments at the error, innermost first:");
+            for (depth, bindings) in environments.iter().enumerate() {
+                let bindings: Vec<String> = bindings
+                    .iter()
+                    .map(|(name, value)| format!("{} = {}", name, value))
+                    .collect();
+                eprintln!("  [{}] {}", depth, bindings.join(", "));
             }
         }
     }
 }
 
-fn report_errors(errors: Vec<Box<Error>>) {
-    for error in errors {
-        println!("{}", error);
+/// Where a parse, resolver or runtime error points, including which file.
+pub fn error_position(error: &(dyn Error + 'static)) -> Option<Position> {
+    if let Some(error) = error.downcast_ref::<ParseError>() {
+        Some(error.position())
+    } else if let Some(error) = error.downcast_ref::<ResolverError>() {
+        Some(error.position())
+    } else {
+        error
+            .downcast_ref::<RuntimeError>()
+            .map(RuntimeError::position)
     }
 }
@@ -1,36 +1,342 @@
+#![forbid(unsafe_code)]
+
 extern crate rlox;
 
-use rlox::Repl;
+use rlox::{
+    ast::Stmt,
+    format_stmts,
+    grammar::generate,
+    metrics,
+    mutate::{mutants, Mutant},
+    parse, portable_path,
+    testing::TestScript,
+    CallGraph, ConfigError, InterpreterConfig, LanguageVersion, LoxError, ParseOptions, Recording,
+    Repl, ReplConfig, RunReport,
+};
+use signal_hook::consts::SIGINT;
 use std::env;
-use std::fs::File;
-use std::io::{self, Read, Write};
+use std::fs::{self, File};
+use std::io::{self, IsTerminal, Read};
+use std::path::{Path, PathBuf};
 use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const USAGE: &str = "Usage: rlox [options] [script]
+       rlox graph [--dot] <script>
+       rlox metrics [--json] <script>
+       rlox mutate <script> --tests <dir>
+       rlox test [--update-snapshots] <path>
+       rlox gen [--seed <n>] [--depth <n>]
+
+Commands:
+  graph                 Print which functions refer to which, as Graphviz with --dot
+  metrics               Print each function's size, nesting and cyclomatic complexity
+  mutate                Report changes to the script that the .lox tests in <dir> don't catch
+  test                  Run a .lox test, or every one in a directory, checking each
+                        '// expect: <output>' comment and expect_snapshot(name, value) call;
+                        --update-snapshots records the values instead
+  gen                   Print a random program that runs without errors, for differential
+                        testing against other Lox implementations (default depth 3)
+
+Options:
+  --config <file>       Use the given config file instead of searching for rlox.toml
+  --no-config           Do not load any rlox.toml
+  --strict              Enable strict mode
+  --debug-resolver      Print variable resolution and scope events to stderr
+  --trace-exprs         Print every evaluated expression and its value to stderr
+  --checked-arithmetic  Warn when arithmetic gives a number past 2^53, where integers
+                        stop being exact, or infinity (an error with --strict)
+  --dump-env-on-error   When a runtime error stops the script, print the variables in
+                        the innermost scopes (3, or dump_env_on_error in the config)
+                        to stderr
+  --stats               After the script runs, print how many times each kind of
+                        statement and expression ran to stderr
+  --report              After the script runs, print how long it took, how many calls
+                        it made, how deep its scopes nested and what it allocated
+                        to stderr
+  -I, --module-path <dir>
+                        Add a module search path (searched before config paths)
+  --lang-ext <name>     Enable a language extension (expr-blocks)
+  --lang-version <n>    Accept language version <n> (1 to 3, default 2); syntax added
+                        later is an error, and what it deprecates gets a warning
+  --enable <capability> Enable a native capability (clock, env, fs, net)
+  --disable <capability>
+                        Disable a native capability
+  --record <file>       Save what natives read from outside, such as the time, to <file>
+  --replay <file>       Give natives the inputs saved by --record instead
+  --history <file>      Add each input the REPL runs to <file>
+  --no-color            Don't highlight the REPL's input or color what it prints
+
+In the REPL, ':transcript <file>' writes each input from then on, with what it
+printed, to <file> as Markdown.
+Set RLOX_PATH to directories separated like PATH to search them for modules after
+the -I paths and before the config's.
+Set NO_COLOR to anything to turn colors off, as --no-color does.
+Set RUST_LOG=debug or RUST_LOG=trace to log the interpreter's internals to stderr.";
+
+// How many scopes --dump-env-on-error shows when the config doesn't say.
+const DUMPED_ENVIRONMENTS: usize = 3;
+
+#[derive(Default)]
+struct Options {
+    script: Option<String>,
+    config_file: Option<PathBuf>,
+    no_config: bool,
+    strict: bool,
+    debug_resolver: bool,
+    trace_exprs: bool,
+    checked_arithmetic: bool,
+    dump_env_on_error: bool,
+    stats: bool,
+    report: bool,
+    module_paths: Vec<PathBuf>,
+    capabilities: Vec<(String, bool)>,
+    parse_options: ParseOptions,
+    version: Option<LanguageVersion>,
+    record: Option<PathBuf>,
+    history: Option<PathBuf>,
+    no_color: bool,
+    replay: Option<PathBuf>,
+}
 
 fn main() {
-    // run_file("fib.lox");
-    let args: Vec<String> = env::args().collect();
-
-    if args.len() > 2 {
-        println!("Usage: rlox [script]");
-    } else if args.len() == 2 {
-        let filename = &args[1];
-        run_file(filename);
+    env_logger::init();
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("graph") => return run_graph(&args[1..]),
+        Some("metrics") => return run_metrics(&args[1..]),
+        Some("mutate") => return run_mutate(&args[1..]),
+        Some("test") => return run_test(&args[1..]),
+        Some("gen") => return run_gen(&args[1..]),
+        _ => (),
+    }
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(msg) => {
+            eprintln!("{}\n\n{}", msg, USAGE);
+            process::exit(64);
+        }
+    };
+
+    let config = match load_config(&options) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(78);
+        }
+    };
+
+    match options.script {
+        Some(ref filename) => run_file(filename, config, &options),
+        None => run_prompt(config, &options),
+    }
+}
+
+fn parse_args(args: Vec<String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => options.config_file = Some(cli_path(&flag_value(&arg, &mut args)?)),
+            "--no-config" => options.no_config = true,
+            "--strict" => options.strict = true,
+            "--debug-resolver" => options.debug_resolver = true,
+            "--trace-exprs" => options.trace_exprs = true,
+            "--checked-arithmetic" => options.checked_arithmetic = true,
+            "--dump-env-on-error" => options.dump_env_on_error = true,
+            "--stats" => options.stats = true,
+            "--report" => options.report = true,
+            "-I" | "--module-path" => options
+                .module_paths
+                .push(cli_path(&flag_value(&arg, &mut args)?)),
+            "--lang-ext" => match flag_value(&arg, &mut args)?.as_str() {
+                "expr-blocks" => options.parse_options.expr_blocks = true,
+                name => return Err(format!("Unknown language extension '{}'.", name)),
+            },
+            "--lang-version" => {
+                let number = flag_value(&arg, &mut args)?;
+                let version = number
+                    .parse()
+                    .ok()
+                    .and_then(LanguageVersion::from_number)
+                    .ok_or_else(|| {
+                        format!(
+                            "Unknown language version '{}', expected 1 to {}.",
+                            number,
+                            LanguageVersion::LATEST
+                        )
+                    })?;
+                options.version = Some(version);
+            }
+            "--record" => options.record = Some(PathBuf::from(flag_value(&arg, &mut args)?)),
+            "--replay" => options.replay = Some(PathBuf::from(flag_value(&arg, &mut args)?)),
+            "--history" => options.history = Some(PathBuf::from(flag_value(&arg, &mut args)?)),
+            "--no-color" => options.no_color = true,
+            "--enable" => options
+                .capabilities
+                .push((flag_value(&arg, &mut args)?, true)),
+            "--disable" => options
+                .capabilities
+                .push((flag_value(&arg, &mut args)?, false)),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ if arg.starts_with('-') => return Err(format!("Unknown option '{}'.", arg)),
+            _ => {
+                if options.script.is_some() {
+                    return Err("Expected at most one script.".to_string());
+                }
+                options.script = Some(cli_path(&arg).display().to_string());
+            }
+        }
+    }
+    Ok(options)
+}
+
+/// A path from the command line, where `\` separates directories as on
+/// Windows unless a file is named with it.
+fn cli_path(arg: &str) -> PathBuf {
+    let path = PathBuf::from(arg);
+    if path.exists() {
+        path
     } else {
-        run_prompt()
+        portable_path(arg)
     }
 }
 
-fn run_file(filename: &str) {
-    // let mut interpreter = Interpreter::new();
-    let mut interpreter = Repl::new();
+fn flag_value(flag: &str, args: &mut impl Iterator<Item = String>) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("Expected a value after '{}'.", flag))
+}
+
+/// Builds the interpreter config from the discovered (or given) rlox.toml,
+/// with command line flags taking precedence over the file.
+fn load_config(options: &Options) -> Result<InterpreterConfig, ConfigError> {
+    let config_file = if options.no_config {
+        None
+    } else if let Some(ref path) = options.config_file {
+        Some(path.clone())
+    } else {
+        let start = match options.script {
+            Some(ref script) => Path::new(script)
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default(),
+            None => PathBuf::from("."),
+        };
+        let start = start.canonicalize().unwrap_or(start);
+        InterpreterConfig::discover(&start)
+    };
+
+    let mut config = match config_file {
+        Some(path) => InterpreterConfig::from_file(&path)?,
+        None => InterpreterConfig::default(),
+    };
+
+    if options.strict {
+        config.strict = true;
+    }
+    if options.debug_resolver {
+        config.debug_resolver = true;
+    }
+    if options.trace_exprs {
+        config.trace_exprs = true;
+    }
+    if options.checked_arithmetic {
+        config.checked_arithmetic = true;
+    }
+    if options.dump_env_on_error && config.dump_env_on_error.is_none() {
+        config.dump_env_on_error = Some(DUMPED_ENVIRONMENTS);
+    }
+    if options.stats {
+        config.count_nodes = true;
+    }
+    if options.report {
+        config.report = true;
+    }
+    if let Some(version) = options.version {
+        config.version = version;
+    }
+
+    let mut module_paths = options.module_paths.clone();
+    if let Some(paths) = env::var_os("RLOX_PATH") {
+        module_paths.extend(env::split_paths(&paths).filter(|p| !p.as_os_str().is_empty()));
+    }
+    module_paths.append(&mut config.module_paths);
+    config.module_paths = module_paths;
+
+    for (name, enabled) in &options.capabilities {
+        config.capabilities.set(name, *enabled)?;
+    }
+
+    Ok(config)
+}
+
+/// Sets up recording or replay, as the options ask.
+fn start_session(repl: Repl, options: &Options) -> Repl {
+    let repl = match options.replay {
+        Some(ref path) => {
+            let recording = fs::read_to_string(path)
+                .map_err(|e| format!("{}: {}", path.display(), e))
+                .and_then(|source| Recording::from_toml(&source));
+            match recording {
+                Ok(recording) => repl.with_replay(recording),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(65);
+                }
+            }
+        }
+        None => repl,
+    };
+    if options.record.is_some() {
+        repl.with_recording()
+    } else {
+        repl
+    }
+}
+
+/// Writes out what's been recorded so far, if recording. It's written
+/// after every run, so a session that fails or is interrupted still has
+/// its inputs saved.
+fn save_recording(repl: &Repl, options: &Options) {
+    if let (Some(path), Some(recording)) = (&options.record, repl.recording()) {
+        if let Err(e) = fs::write(path, recording.to_toml()) {
+            eprintln!("{}: {}", path.display(), e);
+        }
+    }
+}
+
+fn run_file(filename: &str, config: InterpreterConfig, options: &Options) {
+    let parse_options = ParseOptions {
+        version: config.version,
+        limits: config.language_limits,
+        ..options.parse_options
+    };
+    let repl = Repl::with_config(config).with_parse_options(parse_options);
+    let mut interpreter = start_session(repl, options);
+    interrupt_on_ctrl_c(&interpreter);
     let mut source = String::new();
 
     match File::open(filename) {
         Ok(mut f) => match f.read_to_string(&mut source) {
-            Ok(_) => match interpreter.run(source) {
-                Ok(_) => (),
-                Err(_) => process::exit(70),
-            },
+            Ok(_) => {
+                let result = interpreter.run_named(filename, source.replace("\r\n", "\n"));
+                save_recording(&interpreter, options);
+                if let Some(counts) = interpreter.node_counts() {
+                    print_node_counts(&counts);
+                }
+                if let Some(report) = interpreter.report() {
+                    print_report(&report);
+                }
+                match result {
+                    Ok(()) => (),
+                    Err(LoxError::Compile) | Err(LoxError::Pure(_)) => process::exit(65),
+                    Err(LoxError::Runtime) => process::exit(70),
+                    Err(LoxError::Interrupted) => process::exit(130),
+                }
+            }
             Err(e) => {
                 eprintln!("{}", e);
                 process::exit(65);
@@ -43,19 +349,323 @@ fn run_file(filename: &str) {
     }
 }
 
-fn run_prompt() {
-    // let mut interpreter = Interpreter::new();
-    let mut interpreter = Repl::new();
-    loop {
-        let mut line = String::new();
-        print!(">");
-        io::stdout().flush().unwrap();
-        match io::stdin().read_line(&mut line) {
-            Ok(_) => match interpreter.run(line) {
-                Ok(_) => (),
-                Err(_) => (),
+/// Makes Ctrl-C stop the running program instead of the process, so the
+/// REPL can go back to its prompt. A second Ctrl-C before the program
+/// notices the first still exits.
+fn interrupt_on_ctrl_c(interpreter: &Repl) {
+    let cancellation = interpreter.cancellation();
+    let registered =
+        signal_hook::flag::register_conditional_shutdown(SIGINT, 130, cancellation.clone())
+            .and_then(|_| signal_hook::flag::register(SIGINT, cancellation));
+    if let Err(e) = registered {
+        eprintln!("Cannot handle Ctrl-C: {}", e);
+    }
+}
+
+fn print_report(report: &RunReport) {
+    let allocations: Vec<String> = report
+        .allocations
+        .iter()
+        .map(|(kind, count)| format!("{} {}", count, kind))
+        .collect();
+    eprintln!(
+        "time         {:.3} ms",
+        report.elapsed.as_secs_f64() * 1000.0
+    );
+    eprintln!("calls        {}", report.calls);
+    eprintln!("scope depth  {}", report.peak_environment_depth);
+    eprintln!("allocated    {}", allocations.join(", "));
+}
+
+fn print_node_counts(counts: &[(&str, u64)]) {
+    let width = counts.iter().map(|(kind, _)| kind.len()).max().unwrap_or(0);
+    eprintln!("{:<width$}  {:>10}", "node", "count", width = width);
+    for (kind, count) in counts {
+        eprintln!("{:<width$}  {:>10}", kind, count, width = width);
+    }
+}
+
+/// The script a command's arguments name, and whether `flag` was given.
+fn command_args(args: &[String], flag: &str) -> (String, bool) {
+    let mut flagged = false;
+    let mut script = None;
+    for arg in args {
+        match arg.as_str() {
+            _ if arg == flag => flagged = true,
+            _ if arg.starts_with('-') || script.is_some() => {
+                eprintln!("Unexpected argument '{}'.\n\n{}", arg, USAGE);
+                process::exit(64);
+            }
+            _ => script = Some(arg.clone()),
+        }
+    }
+    match script {
+        Some(script) => (script, flagged),
+        None => {
+            eprintln!("Expected a script.\n\n{}", USAGE);
+            process::exit(64);
+        }
+    }
+}
+
+/// Reads and parses a script for a command that looks at it without
+/// running it, exiting if that fails.
+fn parse_script(script: &str) -> Vec<Stmt> {
+    let source = match fs::read_to_string(script) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(65);
+        }
+    };
+    match parse(source) {
+        Ok(program) => program,
+        Err(errors) => {
+            for error in errors {
+                eprintln!("{}", error);
+            }
+            process::exit(65);
+        }
+    }
+}
+
+fn run_graph(args: &[String]) {
+    let (script, dot) = command_args(args, "--dot");
+    match CallGraph::new(&parse_script(&script)) {
+        Ok(graph) if dot => print!("{}", graph.to_dot()),
+        Ok(graph) => print!("{}", graph),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(65);
+        }
+    }
+}
+
+fn run_metrics(args: &[String]) {
+    let (script, json) = command_args(args, "--json");
+    let metrics = metrics(&parse_script(&script));
+    if json {
+        let functions: Vec<_> = metrics
+            .iter()
+            .map(|m| {
+                serde_json::json!({
+                    "name": m.name,
+                    "line": m.line,
+                    "statements": m.statements,
+                    "max_nesting": m.max_nesting,
+                    "complexity": m.complexity,
+                })
+            })
+            .collect();
+        println!("{}", serde_json::Value::Array(functions));
+        return;
+    }
+    let width = metrics.iter().map(|m| m.name.len()).max().unwrap_or(0);
+    println!(
+        "{:<width$}  {:>5}  {:>10}  {:>7}  {:>10}",
+        "function",
+        "line",
+        "statements",
+        "nesting",
+        "complexity",
+        width = width
+    );
+    for m in &metrics {
+        println!(
+            "{:<width$}  {:>5}  {:>10}  {:>7}  {:>10}",
+            m.name,
+            m.line,
+            m.statements,
+            m.max_nesting,
+            m.complexity,
+            width = width
+        );
+    }
+}
+
+fn run_mutate(args: &[String]) {
+    let mut script = None;
+    let mut tests_dir = None;
+    let mut args = args.iter().cloned();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--tests" => match flag_value(&arg, &mut args) {
+                Ok(dir) => tests_dir = Some(PathBuf::from(dir)),
+                Err(msg) => {
+                    eprintln!("{}\n\n{}", msg, USAGE);
+                    process::exit(64);
+                }
             },
-            Err(e) => panic!(e),
+            _ if arg.starts_with('-') || script.is_some() => {
+                eprintln!("Unexpected argument '{}'.\n\n{}", arg, USAGE);
+                process::exit(64);
+            }
+            _ => script = Some(arg),
+        }
+    }
+    let (script, tests_dir) = match (script, tests_dir) {
+        (Some(script), Some(tests_dir)) => (script, tests_dir),
+        _ => {
+            eprintln!("Expected a script and '--tests <dir>'.\n\n{}", USAGE);
+            process::exit(64);
         }
+    };
+    let program = parse_script(&script);
+    let tests = load_tests(&tests_dir);
+
+    let failing: Vec<&str> = tests
+        .iter()
+        .filter(|test| !test.passes(&program))
+        .map(|test| test.name.as_str())
+        .collect();
+    if !failing.is_empty() {
+        eprintln!("These tests fail before mutating: {}", failing.join(", "));
+        process::exit(65);
+    }
+
+    let mutants = mutants(&program);
+    let survivors: Vec<&Mutant> = mutants
+        .iter()
+        .filter(|mutant| tests.iter().all(|test| test.passes(&mutant.program)))
+        .collect();
+    println!(
+        "{} mutants against {} tests: {} killed, {} survived.",
+        mutants.len(),
+        tests.len(),
+        mutants.len() - survivors.len(),
+        survivors.len()
+    );
+    for mutant in survivors {
+        println!("survived: line {}: {}", mutant.line, mutant.description);
+    }
+}
+
+/// Reads the test at `path`, or each `.lox` test in the directory at
+/// `path`, exiting if one can't be read or parsed. A test's snapshots are
+/// kept in `snapshots/<test name>/` beside it.
+fn load_tests(path: &Path) -> Vec<TestScript> {
+    let mut paths: Vec<PathBuf> = if path.is_dir() {
+        match fs::read_dir(path) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "lox"))
+                .collect(),
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                process::exit(65);
+            }
+        }
+    } else {
+        vec![path.to_path_buf()]
+    };
+    paths.sort();
+    let mut tests = vec![];
+    for path in paths {
+        let name = path.display().to_string();
+        let source = match fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("{}: {}", name, e);
+                process::exit(65);
+            }
+        };
+        let snapshots = path
+            .with_file_name("snapshots")
+            .join(path.file_stem().unwrap_or_default());
+        match TestScript::new(name.clone(), &source) {
+            Ok(test) => tests.push(test.with_snapshots(snapshots)),
+            Err(errors) => {
+                for error in errors {
+                    eprintln!("{}: {}", name, error);
+                }
+                process::exit(65);
+            }
+        }
+    }
+    tests
+}
+
+fn run_test(args: &[String]) {
+    let (path, update_snapshots) = command_args(args, "--update-snapshots");
+    let tests = load_tests(Path::new(&path));
+    let mut failed = 0;
+    for test in &tests {
+        match test.run(&[], update_snapshots) {
+            Ok(()) => println!("ok   {}", test.name),
+            Err(e) => {
+                failed += 1;
+                println!("FAIL {}", test.name);
+                for line in e.lines() {
+                    println!("     {}", line);
+                }
+            }
+        }
+    }
+    println!("{} passed, {} failed.", tests.len() - failed, failed);
+    if failed > 0 {
+        process::exit(1);
+    }
+}
+
+fn run_gen(args: &[String]) {
+    let mut seed = None;
+    let mut depth = 3;
+    let mut args = args.iter().cloned();
+    while let Some(arg) = args.next() {
+        let number = match arg.as_str() {
+            "--seed" | "--depth" => flag_value(&arg, &mut args).and_then(|value| {
+                value
+                    .parse::<u64>()
+                    .map_err(|_| format!("Expected a number after '{}'.", arg))
+            }),
+            _ => Err(format!("Unexpected argument '{}'.", arg)),
+        };
+        match number {
+            Ok(n) if arg == "--seed" => seed = Some(n),
+            Ok(n) => depth = n as usize,
+            Err(msg) => {
+                eprintln!("{}\n\n{}", msg, USAGE);
+                process::exit(64);
+            }
+        }
+    }
+    let seed = seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64)
+    });
+    println!("// rlox gen --seed {} --depth {}", seed, depth);
+    print!("{}", format_stmts(&generate(seed, depth)));
+}
+
+fn run_prompt(config: InterpreterConfig, options: &Options) {
+    // Each line is a complete input, so let it end without a semicolon.
+    let parse_options = ParseOptions {
+        optional_semicolons: true,
+        version: config.version,
+        limits: config.language_limits,
+        ..options.parse_options
+    };
+    let mut repl = Repl::with_config(config).with_parse_options(parse_options);
+    // Colors only make sense when someone is typing at a terminal.
+    if !options.no_color
+        && env::var_os("NO_COLOR").is_none()
+        && io::stdin().is_terminal()
+        && io::stdout().is_terminal()
+    {
+        repl = repl.with_color();
+    }
+    let mut interpreter = start_session(repl, options);
+    interrupt_on_ctrl_c(&interpreter);
+    let repl_config = ReplConfig {
+        history_file: options.history.clone(),
+        ..ReplConfig::default()
+    };
+    let result = interpreter.interact(&repl_config, io::stdin().lock(), |repl| {
+        save_recording(repl, options)
+    });
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(74);
     }
 }
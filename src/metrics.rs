@@ -0,0 +1,267 @@
+use crate::call_graph::SCRIPT;
+use ast::{visitor::Visitor, Expr, FunStmt, Stmt};
+
+/// Size and shape figures for one function, method or the top-level code.
+///
+/// They're taken from the parsed program, so a `for` loop counts the
+/// statements it's desugared into, its initializer and increment included.
+/// Anonymous functions count towards the code they're written in; named
+/// functions, methods, classes and traits inside a function count as one
+/// statement of it and are measured on their own.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FunctionMetrics {
+    /// Named like the nodes of a `CallGraph`: `<script>`, `name` or
+    /// `Class.method`.
+    pub name: String,
+    /// Where it's declared; the top-level code is at line 0.
+    pub line: usize,
+    /// Statements, not counting the blocks that group them.
+    pub statements: usize,
+    /// How deep `if`s and loops go inside one another.
+    pub max_nesting: usize,
+    /// One more than the number of places the code can branch: `if`s,
+    /// loops, `and`, `or`, `??` and `?.`.
+    pub complexity: usize,
+}
+
+/// Measures the top-level code and each function, method and class in
+/// `program`, in the order they're declared.
+pub fn metrics(program: &[Stmt]) -> Vec<FunctionMetrics> {
+    let mut measure = Measure {
+        done: vec![],
+        current: FunctionMetrics::new(SCRIPT.to_string(), 0),
+        nesting: 0,
+    };
+    for stmt in program {
+        measure.visit_stmt(stmt);
+    }
+    let script = measure.current;
+    let mut all = vec![script];
+    all.append(&mut measure.done);
+    all
+}
+
+impl FunctionMetrics {
+    fn new(name: String, line: usize) -> FunctionMetrics {
+        FunctionMetrics {
+            name,
+            line,
+            statements: 0,
+            max_nesting: 0,
+            complexity: 1,
+        }
+    }
+}
+
+struct Measure {
+    // Finished functions, in the order they started.
+    done: Vec<FunctionMetrics>,
+    current: FunctionMetrics,
+    nesting: usize,
+}
+
+impl Measure {
+    fn function(&mut self, name: String, function: &FunStmt) {
+        let enclosing = std::mem::replace(
+            &mut self.current,
+            FunctionMetrics::new(name, function.name.line),
+        );
+        let enclosing_nesting = std::mem::replace(&mut self.nesting, 0);
+        // Reserve its place so nested functions come after it.
+        let index = self.done.len();
+        self.done.push(FunctionMetrics::new(String::new(), 0));
+        for stmt in &function.body {
+            self.visit_stmt(stmt);
+        }
+        self.done[index] = std::mem::replace(&mut self.current, enclosing);
+        self.nesting = enclosing_nesting;
+    }
+
+    fn methods(&mut self, owner: &str, methods: &[FunStmt]) {
+        for method in methods {
+            self.function(format!("{}.{}", owner, method.name.lexeme), method);
+        }
+    }
+
+    fn branch(&mut self) {
+        self.current.complexity += 1;
+    }
+
+    fn nested(&mut self, walk: impl FnOnce(&mut Measure)) {
+        self.branch();
+        self.nesting += 1;
+        self.current.max_nesting = self.current.max_nesting.max(self.nesting);
+        walk(self);
+        self.nesting -= 1;
+    }
+}
+
+impl Visitor<()> for Measure {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        if !matches!(stmt, Stmt::Block(_)) {
+            self.current.statements += 1;
+        }
+        match stmt {
+            Stmt::Block(block_stmt) => {
+                for stmt in &block_stmt.statements {
+                    self.visit_stmt(stmt);
+                }
+            }
+            Stmt::Class(class_stmt) => {
+                for t in &class_stmt.traits {
+                    self.visit_expr(t);
+                }
+                for field in &class_stmt.fields {
+                    if let Some(initializer) = &field.initializer {
+                        self.visit_expr(initializer);
+                    }
+                }
+                self.methods(&class_stmt.name.lexeme, &class_stmt.methods);
+            }
+            Stmt::Destructure(destructure_stmt) => self.visit_expr(&destructure_stmt.initializer),
+            Stmt::Expr(expr_stmt) => self.visit_expr(&expr_stmt.expression),
+            Stmt::Fun(fun_stmt) => self.function(fun_stmt.name.lexeme.clone(), fun_stmt),
+            Stmt::If(if_stmt) => {
+                self.visit_expr(&if_stmt.condition);
+                self.nested(|m| {
+                    m.visit_stmt(&if_stmt.then_branch);
+                    if let Some(else_branch) = &if_stmt.else_branch {
+                        m.visit_stmt(else_branch);
+                    }
+                });
+            }
+            Stmt::Print(print_stmt) => {
+                for expr in &print_stmt.expressions {
+                    self.visit_expr(expr);
+                }
+            }
+            Stmt::Return(return_stmt) => {
+                if let Some(value) = &return_stmt.value {
+                    self.visit_expr(value);
+                }
+            }
+            Stmt::Trait(trait_stmt) => self.methods(&trait_stmt.name.lexeme, &trait_stmt.methods),
+            Stmt::Var(var_stmt) => {
+                if let Some(initializer) = &var_stmt.initializer {
+                    self.visit_expr(initializer);
+                }
+            }
+            Stmt::While(while_stmt) => {
+                self.visit_expr(&while_stmt.condition);
+                self.nested(|m| m.visit_stmt(&while_stmt.body));
+            }
+            _ => (),
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Array(array_expr) => {
+                for element in &array_expr.elements {
+                    self.visit_expr(element);
+                }
+            }
+            Expr::Assign(assign_expr) => self.visit_expr(&assign_expr.value),
+            Expr::Binary(binary_expr) => {
+                self.visit_expr(&binary_expr.left);
+                self.visit_expr(&binary_expr.right);
+            }
+            Expr::Block(block_expr) => {
+                for stmt in &block_expr.statements {
+                    self.visit_stmt(stmt);
+                }
+                if let Some(value) = &block_expr.value {
+                    self.visit_expr(value);
+                }
+            }
+            Expr::Call(call_expr) => {
+                self.visit_expr(&call_expr.callee);
+                for arg in &call_expr.arguments {
+                    self.visit_expr(arg);
+                }
+                for (_, arg) in &call_expr.keyword_arguments {
+                    self.visit_expr(arg);
+                }
+            }
+            Expr::Function(fun_expr) => {
+                for stmt in &fun_expr.declaration.body {
+                    self.visit_stmt(stmt);
+                }
+            }
+            Expr::Get(get_expr) => {
+                if get_expr.safe {
+                    self.branch();
+                }
+                self.visit_expr(&get_expr.object);
+            }
+            Expr::Grouping(grouping_expr) => self.visit_expr(&grouping_expr.expression),
+            Expr::If(if_expr) => {
+                self.visit_expr(&if_expr.condition);
+                self.nested(|m| {
+                    m.visit_expr(&if_expr.then_branch);
+                    if let Some(else_branch) = &if_expr.else_branch {
+                        m.visit_expr(else_branch);
+                    }
+                });
+            }
+            Expr::Index(index_expr) => {
+                self.visit_expr(&index_expr.object);
+                self.visit_expr(&index_expr.index);
+            }
+            Expr::Literal(_) | Expr::This(_) | Expr::Variable(_) => (),
+            Expr::Logical(logical_expr) => {
+                self.branch();
+                self.visit_expr(&logical_expr.left);
+                self.visit_expr(&logical_expr.right);
+            }
+            Expr::Set(set_expr) => {
+                self.visit_expr(&set_expr.object);
+                self.visit_expr(&set_expr.value);
+            }
+            Expr::SetIndex(set_expr) => {
+                self.visit_expr(&set_expr.object);
+                self.visit_expr(&set_expr.index);
+                self.visit_expr(&set_expr.value);
+            }
+            Expr::Spread(spread_expr) => self.visit_expr(&spread_expr.expression),
+            Expr::Unary(unary_expr) => self.visit_expr(&unary_expr.right),
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn measure(source: &str) -> Vec<(String, usize, usize, usize, usize)> {
+        metrics(&crate::parse(source.to_string()).unwrap())
+            .into_iter()
+            .map(|m| (m.name, m.line, m.statements, m.max_nesting, m.complexity))
+            .collect()
+    }
+
+    #[test]
+    fn functions_and_methods() {
+        let source = "fun grade(score) {
+  if (score >= 90) return \"A\";
+  while (score > 0 and score < 50) {
+    if (score ?? 0) score = score - 1;
+  }
+  fun inner() { return 1; }
+  return \"B\";
+}
+class Box { get() { return this?.value; } }
+print grade(95);
+";
+        assert_eq!(
+            measure(source),
+            vec![
+                ("<script>".to_string(), 0, 3, 0, 1),
+                ("grade".to_string(), 1, 7, 2, 6),
+                ("inner".to_string(), 6, 1, 0, 1),
+                ("Box.get".to_string(), 9, 1, 0, 2),
+            ]
+        );
+    }
+}
@@ -0,0 +1,331 @@
+//! Mutation testing: small deliberate bugs are put into a program one at a
+//! time, and a test suite that still passes with one in place has a gap.
+
+use crate::{format_expr, format_stmt};
+use ast::{
+    token::{Position, Span},
+    BinaryOp, Expr, LogicalOp, Stmt, UnaryOp,
+};
+
+/// A copy of a program with one change made to it.
+#[derive(Clone, Debug)]
+pub struct Mutant {
+    pub line: usize,
+    /// What was changed, as source: "`a < b` -> `a <= b`".
+    pub description: String,
+    pub program: Vec<Stmt>,
+}
+
+/// Every mutant of `program`, in source order. Each one swaps an
+/// arithmetic, comparison or logical operator for a related one, negates
+/// the condition of an `if` or loop, or removes an expression or `print`
+/// statement.
+pub fn mutants(program: &[Stmt]) -> Vec<Mutant> {
+    let mut mutants = vec![];
+    for target in 0.. {
+        let mut mutated = program.to_vec();
+        let mut mutator = Mutator {
+            target,
+            seen: 0,
+            line: 0,
+            done: None,
+        };
+        mutator.stmts(&mut mutated);
+        match mutator.done {
+            Some((line, description)) => mutants.push(Mutant {
+                line,
+                description,
+                program: mutated,
+            }),
+            None => break,
+        }
+    }
+    mutants
+}
+
+/// Walks a program counting the places it could change, and makes the
+/// change at the `target`th one.
+struct Mutator {
+    target: usize,
+    seen: usize,
+    // The line of the last token seen, for nodes without one.
+    line: usize,
+    done: Option<(usize, String)>,
+}
+
+impl Mutator {
+    fn site(&mut self) -> bool {
+        let hit = self.done.is_none() && self.seen == self.target;
+        self.seen += 1;
+        hit
+    }
+
+    fn stmts(&mut self, stmts: &mut Vec<Stmt>) {
+        let mut i = 0;
+        while i < stmts.len() && self.done.is_none() {
+            if matches!(stmts[i], Stmt::Expr(_) | Stmt::Print(_)) && self.site() {
+                let removed = stmts.remove(i);
+                let line = first_line(&removed).unwrap_or(self.line);
+                let source = format_stmt(&removed);
+                self.done = Some((line, format!("removed `{}`", source.trim())));
+                return;
+            }
+            self.stmt(&mut stmts[i]);
+            i += 1;
+        }
+    }
+
+    fn stmt(&mut self, stmt: &mut Stmt) {
+        if let Some(position) = stmt.position() {
+            self.line = position.line;
+        }
+        match stmt {
+            Stmt::Block(block_stmt) => self.stmts(&mut block_stmt.statements),
+            Stmt::Class(class_stmt) => {
+                for field in &mut class_stmt.fields {
+                    if let Some(initializer) = &mut field.initializer {
+                        self.expr(initializer);
+                    }
+                }
+                for method in &mut class_stmt.methods {
+                    self.stmts(&mut method.body);
+                }
+            }
+            Stmt::Destructure(destructure_stmt) => self.expr(&mut destructure_stmt.initializer),
+            Stmt::Expr(expr_stmt) => self.expr(&mut expr_stmt.expression),
+            Stmt::Fun(fun_stmt) => self.stmts(&mut fun_stmt.body),
+            Stmt::If(if_stmt) => {
+                self.condition(&mut if_stmt.condition);
+                self.stmt(&mut if_stmt.then_branch);
+                if let Some(else_branch) = &mut if_stmt.else_branch {
+                    self.stmt(else_branch);
+                }
+            }
+            Stmt::Print(print_stmt) => {
+                for expr in &mut print_stmt.expressions {
+                    self.expr(expr);
+                }
+            }
+            Stmt::Return(return_stmt) => {
+                if let Some(value) = &mut return_stmt.value {
+                    self.expr(value);
+                }
+            }
+            Stmt::Trait(trait_stmt) => {
+                for method in &mut trait_stmt.methods {
+                    self.stmts(&mut method.body);
+                }
+            }
+            Stmt::Var(var_stmt) => {
+                if let Some(initializer) = &mut var_stmt.initializer {
+                    self.expr(initializer);
+                }
+            }
+            Stmt::While(while_stmt) => {
+                self.condition(&mut while_stmt.condition);
+                self.stmt(&mut while_stmt.body);
+            }
+            _ => (),
+        }
+    }
+
+    /// Offers negating a condition before looking inside it.
+    fn condition(&mut self, condition: &mut Expr) {
+        if self.site() {
+            let position = condition
+                .position()
+                .unwrap_or_else(|| Position::new(self.line, 0));
+            let line = position.line;
+            let span = Span {
+                start: position,
+                end: position,
+            };
+            let original = condition.clone();
+            *condition = Expr::unary_at(UnaryOp::Not, span, Expr::grouping(original.clone()));
+            self.done = Some((
+                line,
+                format!(
+                    "`{}` -> `{}`",
+                    format_expr(&original),
+                    format_expr(condition)
+                ),
+            ));
+            return;
+        }
+        self.expr(condition);
+    }
+
+    fn expr(&mut self, expr: &mut Expr) {
+        if self.done.is_some() {
+            return;
+        }
+        if let Some(position) = expr.position() {
+            self.line = position.line;
+        }
+        let swappable = match expr {
+            Expr::Binary(binary_expr) => swap_binary(binary_expr.operator).is_some(),
+            Expr::Logical(logical_expr) => swap_logical(logical_expr.operator).is_some(),
+            _ => false,
+        };
+        if swappable && self.site() {
+            let original = format_expr(expr);
+            match expr {
+                Expr::Binary(binary_expr) => {
+                    if let Some(operator) = swap_binary(binary_expr.operator) {
+                        binary_expr.operator = operator;
+                    }
+                }
+                Expr::Logical(logical_expr) => {
+                    if let Some(operator) = swap_logical(logical_expr.operator) {
+                        logical_expr.operator = operator;
+                    }
+                }
+                _ => (),
+            }
+            self.done = Some((
+                self.line,
+                format!("`{}` -> `{}`", original, format_expr(expr)),
+            ));
+            return;
+        }
+
+        match expr {
+            Expr::Array(array_expr) => {
+                for element in &mut array_expr.elements {
+                    self.expr(element);
+                }
+            }
+            Expr::Assign(assign_expr) => self.expr(&mut assign_expr.value),
+            Expr::Binary(binary_expr) => {
+                self.expr(&mut binary_expr.left);
+                self.expr(&mut binary_expr.right);
+            }
+            Expr::Block(block_expr) => {
+                self.stmts(&mut block_expr.statements);
+                if let Some(value) = &mut block_expr.value {
+                    self.expr(value);
+                }
+            }
+            Expr::Call(call_expr) => {
+                self.expr(&mut call_expr.callee);
+                for arg in &mut call_expr.arguments {
+                    self.expr(arg);
+                }
+                for (_, arg) in &mut call_expr.keyword_arguments {
+                    self.expr(arg);
+                }
+            }
+            Expr::Function(fun_expr) => self.stmts(&mut fun_expr.declaration.body),
+            Expr::Get(get_expr) => self.expr(&mut get_expr.object),
+            Expr::Grouping(grouping_expr) => self.expr(&mut grouping_expr.expression),
+            Expr::If(if_expr) => {
+                self.condition(&mut if_expr.condition);
+                self.expr(&mut if_expr.then_branch);
+                if let Some(else_branch) = &mut if_expr.else_branch {
+                    self.expr(else_branch);
+                }
+            }
+            Expr::Index(index_expr) => {
+                self.expr(&mut index_expr.object);
+                self.expr(&mut index_expr.index);
+            }
+            Expr::Literal(_) | Expr::This(_) | Expr::Variable(_) => (),
+            Expr::Logical(logical_expr) => {
+                self.expr(&mut logical_expr.left);
+                self.expr(&mut logical_expr.right);
+            }
+            Expr::Set(set_expr) => {
+                self.expr(&mut set_expr.object);
+                self.expr(&mut set_expr.value);
+            }
+            Expr::SetIndex(set_expr) => {
+                self.expr(&mut set_expr.object);
+                self.expr(&mut set_expr.index);
+                self.expr(&mut set_expr.value);
+            }
+            Expr::Spread(spread_expr) => self.expr(&mut spread_expr.expression),
+            Expr::Unary(unary_expr) => self.expr(&mut unary_expr.right),
+            _ => (),
+        }
+    }
+}
+
+/// The operator a mutant uses in place of `token_type`: the opposite
+/// arithmetic or logical operator, or the comparison off by one.
+fn swap_binary(operator: BinaryOp) -> Option<BinaryOp> {
+    Some(match operator {
+        BinaryOp::Add => BinaryOp::Subtract,
+        BinaryOp::Subtract => BinaryOp::Add,
+        BinaryOp::Multiply => BinaryOp::Divide,
+        BinaryOp::Divide => BinaryOp::Multiply,
+        BinaryOp::Less => BinaryOp::LessEqual,
+        BinaryOp::LessEqual => BinaryOp::Less,
+        BinaryOp::Greater => BinaryOp::GreaterEqual,
+        BinaryOp::GreaterEqual => BinaryOp::Greater,
+        BinaryOp::Equal => BinaryOp::NotEqual,
+        BinaryOp::NotEqual => BinaryOp::Equal,
+        BinaryOp::Is => return None,
+    })
+}
+
+fn swap_logical(operator: LogicalOp) -> Option<LogicalOp> {
+    match operator {
+        LogicalOp::And => Some(LogicalOp::Or),
+        LogicalOp::Or => Some(LogicalOp::And),
+        LogicalOp::Coalesce => None,
+    }
+}
+
+fn first_line(stmt: &Stmt) -> Option<usize> {
+    let expr = match stmt {
+        Stmt::Expr(expr_stmt) => &expr_stmt.expression,
+        Stmt::Print(print_stmt) => print_stmt.expressions.first()?,
+        _ => return stmt.position().map(|p| p.line),
+    };
+    expr.position().map(|p| p.line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse, testing::TestScript};
+
+    const PROGRAM: &str = "fun max(a, b) {
+  if (a > b) return a;
+  return b;
+}
+";
+
+    #[test]
+    fn mutants_in_order() {
+        let program = parse(PROGRAM.to_string()).unwrap();
+        let descriptions: Vec<(usize, String)> = mutants(&program)
+            .into_iter()
+            .map(|m| (m.line, m.description))
+            .collect();
+        assert_eq!(
+            descriptions,
+            vec![
+                (2, "`a > b` -> `!(a > b)`".to_string()),
+                (2, "`a > b` -> `a >= b`".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn surviving_mutants() {
+        let program = parse(PROGRAM.to_string()).unwrap();
+        let weak = TestScript::new("weak", "print max(3, 1); // expect: 3").unwrap();
+        let strong = TestScript::new(
+            "strong",
+            "print max(3, 1); // expect: 3\nprint max(1, 3); // expect: 3",
+        )
+        .unwrap();
+        assert!(weak.passes(&program) && strong.passes(&program));
+
+        let mutants = mutants(&program);
+        assert!(!strong.passes(&mutants[0].program));
+        assert!(weak.passes(&mutants[1].program));
+        assert!(strong.passes(&mutants[1].program));
+    }
+}
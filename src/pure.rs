@@ -0,0 +1,139 @@
+use crate::{parse_expression, Interpreter, LoxError, Value};
+use ast::{Expr, Stmt};
+
+/// Evaluates `source` as a single expression that can't have side effects,
+/// so a host can use Lox for config values written by someone it doesn't
+/// trust.
+///
+/// The expression may use literals, arrays, operators, indexing, spreads
+/// and calls to the natives that read nothing from outside and change
+/// nothing, such as `len` and `union`. Assignments, functions and `this`
+/// are rejected before anything runs, and the sandbox limits still apply
+/// while it runs. Errors aren't reported
+/// anywhere; the `LoxError::Pure` says what went wrong.
+///
+/// ```
+/// use rlox::{evaluate_pure, Value};
+///
+/// assert_eq!(evaluate_pure("len([1, 2]) * 10"), Ok(Value::Number(20.0)));
+/// assert!(evaluate_pure("x = 1").is_err());
+/// ```
+pub fn evaluate_pure(source: &str) -> Result<Value, LoxError> {
+    let expr = parse_expression(source.to_string()).map_err(|errors| {
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        LoxError::Pure(messages.join("\n"))
+    })?;
+    if let Some(msg) = impurity(&expr) {
+        return Err(LoxError::Pure(msg));
+    }
+    match Interpreter::pure().run(vec![Stmt::expr(expr)]) {
+        Ok(value) => Ok(value.unwrap_or(Value::Nil)),
+        Err(errors) => {
+            let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            Err(LoxError::Pure(messages.join("\n")))
+        }
+    }
+}
+
+/// Why `expr` might not be pure: the first part of it that could change
+/// something, or run statements.
+fn impurity(expr: &Expr) -> Option<String> {
+    let rejected = |what: &str| {
+        let line = expr.position().map_or(1, |position| position.line);
+        Some(format!(
+            "[line {}] Error: {} can't be used in a pure expression.",
+            line, what
+        ))
+    };
+    match expr {
+        Expr::Array(array_expr) => array_expr.elements.iter().find_map(impurity),
+        Expr::Assign(_) | Expr::Set(_) | Expr::SetIndex(_) => rejected("Assignment"),
+        Expr::Binary(binary_expr) => {
+            impurity(&binary_expr.left).or_else(|| impurity(&binary_expr.right))
+        }
+        Expr::Block(_) => rejected("A block"),
+        Expr::Call(call_expr) => impurity(&call_expr.callee)
+            .or_else(|| call_expr.arguments.iter().find_map(impurity))
+            .or_else(|| {
+                call_expr
+                    .keyword_arguments
+                    .iter()
+                    .find_map(|(_, arg)| impurity(arg))
+            }),
+        Expr::Function(_) => rejected("A function"),
+        Expr::Get(get_expr) => impurity(&get_expr.object),
+        Expr::Grouping(grouping_expr) => impurity(&grouping_expr.expression),
+        Expr::If(if_expr) => impurity(&if_expr.condition)
+            .or_else(|| impurity(&if_expr.then_branch))
+            .or_else(|| if_expr.else_branch.as_deref().and_then(impurity)),
+        Expr::Index(index_expr) => {
+            impurity(&index_expr.object).or_else(|| impurity(&index_expr.index))
+        }
+        Expr::Literal(_) | Expr::Variable(_) => None,
+        Expr::Logical(logical_expr) => {
+            impurity(&logical_expr.left).or_else(|| impurity(&logical_expr.right))
+        }
+        Expr::Spread(spread_expr) => impurity(&spread_expr.expression),
+        Expr::This(_) => rejected("'this'"),
+        Expr::Unary(unary_expr) => impurity(&unary_expr.right),
+        _ => rejected(expr.kind()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values() {
+        assert_eq!(
+            evaluate_pure("len(\"ab\") > 1 and \"long\" or \"short\"").map(|v| v.print()),
+            Ok("long".to_string())
+        );
+        assert_eq!(
+            evaluate_pure("[1, [2, 3][1], ord(...[\"a\"])]").map(|v| v.print()),
+            Ok("[1, 3, 97]".to_string())
+        );
+        assert_eq!(evaluate_pure("nil ?? 4"), Ok(Value::Number(4.0)));
+    }
+
+    #[test]
+    fn side_effects_are_rejected() {
+        for (source, msg) in &[
+            (
+                "x = 1",
+                "[line 1] Error: Assignment can't be used in a pure expression.",
+            ),
+            (
+                "[1][0] = 2",
+                "[line 1] Error: Assignment can't be used in a pure expression.",
+            ),
+            (
+                "len([fun () {}])",
+                "[line 1] Error: A function can't be used in a pure expression.",
+            ),
+            (
+                "this.x",
+                "[line 1] Error: 'this' can't be used in a pure expression.",
+            ),
+        ] {
+            assert_eq!(evaluate_pure(source), Err(LoxError::Pure(msg.to_string())));
+        }
+    }
+
+    #[test]
+    fn only_pure_natives() {
+        for native in &["clock()", "add(set(), 1)", "freeze([1])", "memory_stats()"] {
+            assert!(matches!(
+                evaluate_pure(native),
+                Err(LoxError::Pure(msg)) if msg.contains("Undefined variable")
+            ));
+        }
+        assert_eq!(
+            evaluate_pure("1 +"),
+            Err(LoxError::Pure(
+                "[line 1] Error: Expect expression".to_string()
+            ))
+        );
+    }
+}
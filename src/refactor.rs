@@ -0,0 +1,232 @@
+//! Rewrites of Lox source that keep what it means.
+
+use crate::{analysis, parse_expression, Diagnostic};
+use ast::{token::Position, Expr};
+
+/// Renames the variable, function, class or trait whose name is at
+/// `position`, at its declaration and every use, and returns the rewritten
+/// source. Other names spelled the same, such as a shadowing local, are
+/// left alone. Properties aren't bindings, so a method or field of the same
+/// name isn't renamed either. Renaming a parameter renames the `name:`
+/// labels of the calls that pass it by name.
+///
+/// It fails if the source doesn't parse and resolve, if there's no name at
+/// `position`, if the new name would change what a name refers to, by
+/// shadowing another declaration or being shadowed by one, or if a label
+/// spelled like a parameter is passed to a callee only known when the
+/// program runs, such as a method.
+pub fn rename(source: &str, position: Position, new_name: &str) -> Result<String, Diagnostic> {
+    if !is_identifier(new_name) {
+        return Err(Diagnostic::new(
+            position,
+            format!("'{}' is not a valid name.", new_name),
+        ));
+    }
+    let bindings = analysis::bindings(source, position.file)?;
+    let declaration = bindings
+        .declaration_at(position)
+        .ok_or_else(|| Diagnostic::new(position, "No variable, function or class here."))?;
+    let old_name = &declaration.lexeme;
+    if let Some(label) = bindings.unbound_label(declaration) {
+        return Err(Diagnostic::new(
+            label.position(),
+            format!(
+                "Renaming '{}' to '{}' might miss this label, since what's called is only known when the program runs.",
+                old_name, new_name
+            ),
+        ));
+    }
+    let mut occurrences: Vec<Position> = bindings
+        .occurrences(declaration)
+        .iter()
+        .map(|token| token.position())
+        .collect();
+    occurrences.sort_by_key(|p| (p.line, p.column));
+
+    let renamed = replace(source, &occurrences, old_name, new_name);
+    let moved = shift(&occurrences, old_name, new_name);
+
+    // The renamed source has to bind exactly the renamed names together,
+    // or some use now refers to something else.
+    let changes_meaning = || {
+        Diagnostic::new(
+            declaration.position(),
+            format!(
+                "Renaming '{}' to '{}' would change what a name refers to.",
+                old_name, new_name
+            ),
+        )
+    };
+    let check = analysis::bindings(&renamed, position.file).map_err(|e| {
+        Diagnostic::new(
+            declaration.position(),
+            format!(
+                "Renaming '{}' to '{}' would break the program: {}",
+                old_name,
+                new_name,
+                e.message()
+            ),
+        )
+    })?;
+    let new_declaration = occurrences
+        .iter()
+        .zip(&moved)
+        .find(|(old, _)| **old == declaration.position())
+        .and_then(|(_, new)| check.declaration_at(*new))
+        .ok_or_else(changes_meaning)?;
+    let mut bound: Vec<Position> = check
+        .occurrences(new_declaration)
+        .iter()
+        .map(|token| token.position())
+        .collect();
+    bound.sort_by_key(|p| (p.line, p.column));
+    if bound != moved {
+        return Err(changes_meaning());
+    }
+    Ok(renamed)
+}
+
+fn is_identifier(name: &str) -> bool {
+    match parse_expression(name.to_string()) {
+        Ok(Expr::Variable(var_expr)) => var_expr.name.lexeme == name,
+        _ => false,
+    }
+}
+
+/// `source` with `old_name` at each of `positions`, in order, replaced.
+fn replace(source: &str, positions: &[Position], old_name: &str, new_name: &str) -> String {
+    let old_len = old_name.chars().count();
+    let mut out = String::with_capacity(source.len());
+    let mut targets = positions.iter().peekable();
+    for (i, line) in source.split('\n').enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        let mut chars = line.chars().enumerate().peekable();
+        while let Some((column, c)) = chars.next() {
+            match targets.peek() {
+                Some(target) if target.line == i + 1 && target.column == column + 1 => {
+                    targets.next();
+                    out.push_str(new_name);
+                    for _ in 1..old_len {
+                        chars.next();
+                    }
+                }
+                _ => out.push(c),
+            }
+        }
+    }
+    out
+}
+
+/// Where each of `positions` is once the names before it on its line are
+/// renamed.
+fn shift(positions: &[Position], old_name: &str, new_name: &str) -> Vec<Position> {
+    let delta = new_name.chars().count() as isize - old_name.chars().count() as isize;
+    let mut moved = Vec::with_capacity(positions.len());
+    let mut before = 0;
+    for (i, position) in positions.iter().enumerate() {
+        if i > 0 && positions[i - 1].line != position.line {
+            before = 0;
+        }
+        let column = position.column as isize + delta * before;
+        moved.push(Position::new(position.line, column as usize).in_file(position.file));
+        before += 1;
+    }
+    moved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "var count = 0;
+var step = 1;
+fun bump(count) {
+  return count + step;
+}
+class Counter {
+  count() { return count; }
+}
+print bump(count), count;
+";
+
+    #[test]
+    fn renames_a_binding_and_its_uses() {
+        assert_eq!(
+            rename(SOURCE, Position::new(9, 12), "total").unwrap(),
+            "var total = 0;
+var step = 1;
+fun bump(count) {
+  return count + step;
+}
+class Counter {
+  count() { return total; }
+}
+print bump(total), total;
+"
+        );
+        assert_eq!(
+            rename(SOURCE, Position::new(4, 10), "n").unwrap(),
+            "var count = 0;
+var step = 1;
+fun bump(n) {
+  return n + step;
+}
+class Counter {
+  count() { return count; }
+}
+print bump(count), count;
+"
+        );
+    }
+
+    #[test]
+    fn renames_keyword_argument_labels() {
+        let source = "fun f(a, b) {}\nf(b: 1, a: 5);\nf(1, 2);\n";
+        assert_eq!(
+            rename(source, Position::new(1, 7), "x").unwrap(),
+            "fun f(x, b) {}\nf(b: 1, x: 5);\nf(1, 2);\n"
+        );
+        assert_eq!(
+            rename(source, Position::new(2, 9), "x").unwrap(),
+            "fun f(x, b) {}\nf(b: 1, x: 5);\nf(1, 2);\n"
+        );
+
+        let source = "fun f(a) {}\nvar g = f;\ng(a: 1);\n";
+        let error = rename(source, Position::new(1, 7), "x").unwrap_err();
+        assert_eq!(error.line(), 3);
+        assert_eq!(
+            error.message(),
+            "Renaming 'a' to 'x' might miss this label, since what's called is only known when the program runs."
+        );
+    }
+
+    #[test]
+    fn refuses_to_change_meaning() {
+        let error = rename(SOURCE, Position::new(1, 5), "step").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "[line 1] Error: Renaming 'count' to 'step' would break the program: \
+             Variable with this name already declared in this scope."
+        );
+        assert_eq!(
+            rename(SOURCE, Position::new(3, 10), "step")
+                .unwrap_err()
+                .message(),
+            "Renaming 'count' to 'step' would change what a name refers to."
+        );
+        assert_eq!(
+            rename(SOURCE, Position::new(1, 5), "fun")
+                .unwrap_err()
+                .message(),
+            "'fun' is not a valid name."
+        );
+        assert_eq!(
+            rename(SOURCE, Position::new(1, 1), "x")
+                .unwrap_err()
+                .message(),
+            "No variable, function or class here."
+        );
+    }
+}
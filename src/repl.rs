@@ -0,0 +1,387 @@
+use crate::{highlight, parse_with_options, ParseError, ParseOptions, Repl, Value};
+use ast::token::KEYWORDS;
+use std::{
+    cell::RefCell,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, Write},
+    mem,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+/// How an interactive session looks: what it prompts with and says first,
+/// and where it keeps the inputs it's run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReplConfig {
+    /// Shown when the session is waiting for a new input.
+    pub prompt: String,
+    /// Shown when the lines so far aren't a complete input yet, as after
+    /// `fun f() {`.
+    pub continuation_prompt: String,
+    /// Printed once when the session starts.
+    pub banner: Option<String>,
+    /// A file each input is added to once it's run, so a session can be
+    /// looked back over or replayed as a script.
+    pub history_file: Option<PathBuf>,
+}
+
+impl Default for ReplConfig {
+    fn default() -> ReplConfig {
+        ReplConfig {
+            prompt: ">".to_string(),
+            continuation_prompt: "...".to_string(),
+            banner: None,
+            history_file: None,
+        }
+    }
+}
+
+impl Repl {
+    /// Reads lines from `input` until it ends, running each input as soon
+    /// as it's complete and prompting on stdout as `config` says.
+    /// `after_run` is called after each input has run, with its result
+    /// already reported.
+    ///
+    /// An input spans lines until it parses or fails for a reason more
+    /// lines can't fix. A blank line runs what's there even if it's
+    /// unfinished, so a stray `(` can't keep the prompt waiting.
+    pub fn interact(
+        &mut self,
+        config: &ReplConfig,
+        mut input: impl BufRead,
+        mut after_run: impl FnMut(&Repl),
+    ) -> io::Result<()> {
+        if let Some(banner) = &config.banner {
+            println!("{}", banner);
+        }
+        let mut source = String::new();
+        loop {
+            let mut line = String::new();
+            let shown = if source.is_empty() {
+                &config.prompt
+            } else {
+                &config.continuation_prompt
+            };
+            if prompt(shown).is_err() {
+                return Ok(());
+            }
+            // End of input, as after Ctrl-D.
+            if input.read_line(&mut line)? == 0 {
+                println!();
+                return Ok(());
+            }
+            if source.is_empty() && line.trim_start().starts_with(':') {
+                self.command(line.trim());
+                continue;
+            }
+            let blank = line.trim().is_empty();
+            // Windows consoles end lines with "\r\n", which would end up
+            // inside multi-line strings.
+            source.push_str(&line.replace("\r\n", "\n"));
+            if !blank && is_incomplete(&source, self.parse_options) {
+                continue;
+            }
+            if self.color {
+                print!("{}", highlighted_echo(&source, config));
+            }
+            if let Some(path) = &config.history_file {
+                if let Err(e) = add_to_history(path, &source) {
+                    eprintln!("{}: {}", path.display(), e);
+                }
+            }
+            let input = mem::take(&mut source);
+            self.output.start_capture();
+            let _ = self.run(input.clone());
+            self.transcribe(&input);
+            after_run(self);
+        }
+    }
+
+    /// Runs a REPL command, a line starting with `:`.
+    ///
+    /// - `:transcript <file>` writes each input from then on, and what it
+    ///   printed, to `<file>` as Markdown code blocks.
+    fn command(&mut self, line: &str) {
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next(), words.next()) {
+            (Some(":transcript"), Some(path), None) => match File::create(path) {
+                Ok(file) => self.transcript = Some(file),
+                Err(e) => eprintln!("{}: {}", path, e),
+            },
+            (Some(":transcript"), _, _) => eprintln!("Usage: :transcript <file>"),
+            (Some(command), _, _) => eprintln!("Unknown command '{}'.", command),
+            (None, _, _) => (),
+        }
+    }
+
+    /// Adds `input` and what it printed to the transcript, if there is one.
+    fn transcribe(&mut self, input: &str) {
+        let printed = self.output.take_capture();
+        let file = match &mut self.transcript {
+            Some(file) => file,
+            None => return,
+        };
+        if input.trim().is_empty() {
+            return;
+        }
+        let mut entry = format!("```lox\n{}\n```\n\n", input.trim_end());
+        if !printed.is_empty() {
+            let printed = strip_colors(&String::from_utf8_lossy(&printed));
+            entry.push_str(&format!("```\n{}\n```\n\n", printed.trim_end()));
+        }
+        if let Err(e) = file.write_all(entry.as_bytes()) {
+            eprintln!("Cannot write the transcript: {}", e);
+            self.transcript = None;
+        }
+    }
+}
+
+/// Where a REPL's output goes: stdout and, between `start_capture` and
+/// `take_capture`, a buffer for the transcript.
+#[derive(Clone, Default)]
+pub(crate) struct ReplOutput(Rc<RefCell<Option<Vec<u8>>>>);
+
+impl ReplOutput {
+    fn start_capture(&self) {
+        *self.0.borrow_mut() = Some(vec![]);
+    }
+
+    fn take_capture(&self) -> Vec<u8> {
+        self.0.borrow_mut().take().unwrap_or_default()
+    }
+}
+
+impl Write for ReplOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        io::stdout().write_all(buf)?;
+        if let Some(captured) = self.0.borrow_mut().as_mut() {
+            captured.extend_from_slice(buf);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()
+    }
+}
+
+/// `text` without the terminal color codes `highlight` and colored values
+/// add.
+fn strip_colors(text: &str) -> String {
+    let mut plain = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("\x1b[") {
+        plain.push_str(&rest[..start]);
+        rest = &rest[start..];
+        rest = match rest.find('m') {
+            Some(end) => &rest[end + 1..],
+            None => "",
+        };
+    }
+    plain.push_str(rest);
+    plain
+}
+
+impl Repl {
+    /// The words the one being typed at the end of `line` could become,
+    /// for tab completion: keywords and names the session has defined or,
+    /// after `name.`, the fields and methods of what `name` holds, sorted.
+    pub fn completions(&self, line: &str) -> Vec<String> {
+        let (before, partial) = line.split_at(word_start(line));
+        let mut candidates: Vec<String> = match before.strip_suffix('.') {
+            Some(receiver) => {
+                let receiver = &receiver[word_start(receiver)..];
+                match self.interpreter.environment().get(&receiver.to_string()) {
+                    Some(value) => members(&value),
+                    None => vec![],
+                }
+            }
+            None => KEYWORDS
+                .iter()
+                .map(|(keyword, _)| keyword.to_string())
+                .chain(self.interpreter.environment().names())
+                .collect(),
+        };
+        candidates.retain(|candidate| candidate.starts_with(partial));
+        candidates.sort();
+        candidates.dedup();
+        candidates
+    }
+}
+
+/// Where the identifier that `text` ends with starts.
+fn word_start(text: &str) -> usize {
+    text.char_indices()
+        .rev()
+        .take_while(|(_, c)| c.is_ascii_alphanumeric() || *c == '_')
+        .last()
+        .map_or(text.len(), |(i, _)| i)
+}
+
+/// The names that can follow `value.`: an instance's fields and its
+/// class's methods, or a class's methods.
+fn members(value: &Value) -> Vec<String> {
+    match value {
+        Value::Instance(instance) => instance
+            .fields()
+            .into_iter()
+            .map(|(name, _)| name)
+            .chain(instance.class.method_names().into_iter().map(String::from))
+            .collect(),
+        Value::Class(class) => class.method_names().into_iter().map(String::from).collect(),
+        _ => vec![],
+    }
+}
+
+/// Redraws the lines of `source`, which the terminal has just echoed as
+/// they were typed, highlighted and with their prompts.
+fn highlighted_echo(source: &str, config: &ReplConfig) -> String {
+    let lines: Vec<&str> = source.trim_end_matches('\n').split('\n').collect();
+    // Up to the start of the input's first line, then each line cleared and
+    // written again.
+    let mut echo = format!("\x1b[{}F", lines.len());
+    for (i, line) in highlight(&lines.join("\n")).split('\n').enumerate() {
+        let prompt = if i == 0 {
+            &config.prompt
+        } else {
+            &config.continuation_prompt
+        };
+        echo.push_str(&format!("\x1b[2K{}{}\n", prompt, line));
+    }
+    echo
+}
+
+/// Shows a prompt. Fails once nothing is reading the output, such as a
+/// closed pipe, so the REPL stops instead of panicking.
+fn prompt(prompt: &str) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    write!(stdout, "{}", prompt)?;
+    stdout.flush()
+}
+
+fn add_to_history(path: &Path, source: &str) -> io::Result<()> {
+    if source.trim().is_empty() {
+        return Ok(());
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(source.as_bytes())
+}
+
+/// Whether `source` only fails to parse because it stops early, as in
+/// `fun f() {`, `list.add(` or an unterminated string, so another line
+/// could complete it.
+fn is_incomplete(source: &str, parse_options: ParseOptions) -> bool {
+    match parse_with_options(source.to_string(), parse_options) {
+        Ok(_) => false,
+        Err(errors) => errors.iter().all(|error| {
+            error
+                .downcast_ref::<ParseError>()
+                .is_some_and(ParseError::is_at_end)
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{InterpreterConfig, LanguageLimits, LoxError};
+    use std::fs;
+
+    #[test]
+    fn history_file() {
+        let path = std::env::temp_dir().join(format!("rlox-history-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let config = ReplConfig {
+            history_file: Some(path.clone()),
+            ..ReplConfig::default()
+        };
+        let mut runs = 0;
+        Repl::new()
+            .interact(
+                &config,
+                "var x = 1;\r\nfun f() {\n  return x;\n}\n\nprint (\n\n".as_bytes(),
+                |_| runs += 1,
+            )
+            .unwrap();
+
+        assert_eq!(runs, 4);
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "var x = 1;\nfun f() {\n  return x;\n}\nprint (\n\n"
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn completions() {
+        let mut repl = Repl::new();
+        repl.run(
+            "class Point { init(x) { this.x = x; } norm() {} }\nvar point = Point(1);\nvar printed;"
+                .to_string(),
+        )
+        .unwrap();
+        assert_eq!(repl.completions("pri"), vec!["print", "printed"]);
+        assert_eq!(repl.completions("var a = po"), vec!["point"]);
+        assert_eq!(repl.completions("point."), vec!["init", "norm", "x"]);
+        assert_eq!(repl.completions("print point.n"), vec!["norm"]);
+        assert_eq!(repl.completions("str.o"), vec!["ord"]);
+        assert_eq!(repl.completions("1."), Vec::<String>::new());
+        assert_eq!(repl.completions("missing.x"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn transcript() {
+        let path = std::env::temp_dir().join(format!("rlox-transcript-{}", std::process::id()));
+        let input = format!(
+            "var x = 1;\n:transcript {}\nprint x;\n\nfun f() {{\n}}\nprint -\"a\";\n:nope\n",
+            path.display()
+        );
+        Repl::new()
+            .with_color()
+            .interact(&ReplConfig::default(), input.as_bytes(), |_| ())
+            .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "```lox\nprint x;\n```\n\n```\n1\n```\n\n\
+             ```lox\nfun f() {\n}\n```\n\n\
+             ```lox\nprint -\"a\";\n```\n\n```\n[line 1] Error: Operand must be a number.\n\
+             \x20 --> <repl:5>:1:7\n   | print -\"a\";\n   |       ^\n```\n\n"
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn echo() {
+        let config = ReplConfig::default();
+        assert_eq!(
+            highlighted_echo("fun f() {\n}\n", &config),
+            "\x1b[2F\x1b[2K>\x1b[35mfun\x1b[0m f() {\n\x1b[2K...}\n"
+        );
+    }
+
+    #[test]
+    fn parses_with_config_limits() {
+        let config = InterpreterConfig {
+            language_limits: LanguageLimits {
+                max_arguments: 1,
+                ..LanguageLimits::default()
+            },
+            ..InterpreterConfig::default()
+        };
+        let mut repl = Repl::with_config(config);
+        assert_eq!(repl.run("fun f(a) {} f(1);".to_string()), Ok(()));
+        assert_eq!(repl.run("f(1, 2);".to_string()), Err(LoxError::Compile));
+    }
+
+    #[test]
+    fn incomplete_inputs() {
+        let options = ParseOptions::default();
+        assert!(is_incomplete("fun f() {", options));
+        assert!(is_incomplete("print [1,", options));
+        assert!(is_incomplete("print \"a", options));
+        assert!(!is_incomplete("print \"a\nb\";", options));
+        assert!(!is_incomplete("print 1;", options));
+        assert!(!is_incomplete("print );", options));
+    }
+}
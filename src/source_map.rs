@@ -0,0 +1,105 @@
+use ast::token::{FileId, Position};
+
+/// The sources a session has loaded, by file id, so a diagnostic can name
+/// the file it's in and show the line it points at. That works for errors
+/// in functions loaded earlier too, since their tokens keep their file.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+#[derive(Debug)]
+struct SourceFile {
+    name: String,
+    source: String,
+}
+
+impl SourceMap {
+    pub fn new() -> SourceMap {
+        SourceMap::default()
+    }
+
+    /// Registers a source under `name` and returns the id to parse it with.
+    pub fn add(&mut self, name: impl Into<String>, source: &str) -> FileId {
+        self.files.push(SourceFile {
+            name: name.into(),
+            source: source.to_string(),
+        });
+        FileId(self.files.len() - 1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn name(&self, file: FileId) -> Option<&str> {
+        self.files.get(file.0).map(|f| f.name.as_str())
+    }
+
+    pub fn source(&self, file: FileId) -> Option<&str> {
+        self.files.get(file.0).map(|f| f.source.as_str())
+    }
+
+    /// The text of a line, counting from 1.
+    pub fn line(&self, file: FileId, line: usize) -> Option<&str> {
+        self.source(file)?.lines().nth(line.checked_sub(1)?)
+    }
+
+    /// Where `position` is and the line it's on, with a caret under the
+    /// column when it's known:
+    ///
+    /// ```text
+    ///   --> script.lox:3:7
+    ///    | print x;
+    ///    |       ^
+    /// ```
+    pub fn snippet(&self, position: Position) -> Option<String> {
+        let name = self.name(position.file)?;
+        let text = self.line(position.file, position.line)?;
+        if position.column == 0 {
+            return Some(format!("  --> {}:{}\n   | {}", name, position.line, text));
+        }
+        // Keep tabs so the caret lines up however they're displayed.
+        let indent: String = text
+            .chars()
+            .take(position.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        Some(format!(
+            "  --> {}:{}:{}\n   | {}\n   | {}^",
+            name, position.line, position.column, text, indent
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snippets() {
+        let mut sources = SourceMap::new();
+        let main = sources.add("main.lox", "var a = 1;\n\tprint b;\n");
+        let repl = sources.add("<repl:1>", "a +;");
+
+        assert_eq!(sources.name(repl), Some("<repl:1>"));
+        assert_eq!(sources.line(main, 2), Some("\tprint b;"));
+        assert_eq!(sources.line(main, 3), None);
+        assert_eq!(
+            sources.snippet(Position::new(2, 8).in_file(main)).unwrap(),
+            "  --> main.lox:2:8\n   | \tprint b;\n   | \t      ^"
+        );
+        assert_eq!(
+            sources.snippet(Position::new(1, 0).in_file(repl)).unwrap(),
+            "  --> <repl:1>:1\n   | a +;"
+        );
+        assert_eq!(
+            sources.snippet(Position::new(1, 1).in_file(FileId(2))),
+            None
+        );
+    }
+}
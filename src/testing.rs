@@ -0,0 +1,147 @@
+//! Lox test scripts, as run by `rlox test` and `rlox mutate`.
+
+use crate::{parse, Interpreter, InterpreterConfig, OutputEvent};
+use ast::Stmt;
+use std::{cell::RefCell, error::Error, path::PathBuf, rc::Rc};
+
+/// A Lox script that checks the program under test, in the format of the
+/// conformance suite: each `// expect: <output>` comment is a line it
+/// should print.
+#[derive(Clone, Debug)]
+pub struct TestScript {
+    pub name: String,
+    program: Vec<Stmt>,
+    expected: Vec<String>,
+    snapshots: Option<PathBuf>,
+}
+
+impl TestScript {
+    pub fn new(name: impl Into<String>, source: &str) -> Result<TestScript, Vec<Box<dyn Error>>> {
+        let expected = source
+            .lines()
+            .filter_map(|line| line.split("// expect: ").nth(1))
+            .map(String::from)
+            .collect();
+        Ok(TestScript {
+            name: name.into(),
+            program: parse(source.to_string())?,
+            expected,
+            snapshots: None,
+        })
+    }
+
+    /// Lets the test call `expect_snapshot(name, value)`, with its
+    /// snapshots kept in `dir`.
+    pub fn with_snapshots(mut self, dir: impl Into<PathBuf>) -> TestScript {
+        self.snapshots = Some(dir.into());
+        self
+    }
+
+    /// Runs `program` and then the test in the same sandboxed interpreter,
+    /// so the test can use what the program defines. It passes if both run
+    /// and the test prints what it expects; otherwise the error says why
+    /// not. With `update_snapshots`, snapshots are written instead of
+    /// checked.
+    pub fn run(&self, program: &[Stmt], update_snapshots: bool) -> Result<(), String> {
+        let output = Rc::new(RefCell::new(String::new()));
+        let printed = output.clone();
+        let mut interpreter = Interpreter::with_config(InterpreterConfig::sandbox())
+            .with_output_callback(move |event| {
+                if let OutputEvent::Print(line) = event {
+                    let mut printed = printed.borrow_mut();
+                    printed.push_str(line);
+                    printed.push('\n');
+                }
+                Ok(())
+            });
+        if let Some(dir) = &self.snapshots {
+            interpreter = interpreter.with_snapshots(dir, update_snapshots);
+        }
+        interpreter.run(program.to_vec()).map_err(describe)?;
+        output.borrow_mut().clear();
+        interpreter.run(self.program.clone()).map_err(describe)?;
+
+        let printed = output.borrow();
+        let printed: Vec<&str> = printed.lines().collect();
+        for (i, expected) in self.expected.iter().enumerate() {
+            match printed.get(i) {
+                Some(line) if line == expected => (),
+                Some(line) => {
+                    return Err(format!(
+                        "Expected '{}' on line {} of the output, got '{}'.",
+                        expected,
+                        i + 1,
+                        line
+                    ))
+                }
+                None => {
+                    return Err(format!(
+                        "Expected '{}' on line {} of the output, got nothing.",
+                        expected,
+                        i + 1
+                    ))
+                }
+            }
+        }
+        match printed.get(self.expected.len()) {
+            Some(line) => Err(format!("Expected no more output, got '{}'.", line)),
+            None => Ok(()),
+        }
+    }
+
+    pub fn passes(&self, program: &[Stmt]) -> bool {
+        self.run(program, false).is_ok()
+    }
+}
+
+fn describe(errors: Vec<Box<dyn Error>>) -> String {
+    let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+    messages.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs};
+
+    #[test]
+    fn failures() {
+        let test = TestScript::new("t", "print 1; // expect: 1\nprint 2; // expect: 3").unwrap();
+        assert_eq!(
+            test.run(&[], false),
+            Err("Expected '3' on line 2 of the output, got '2'.".to_string())
+        );
+        let test = TestScript::new("t", "print 1; print 2; // expect: 1").unwrap();
+        assert_eq!(
+            test.run(&[], false),
+            Err("Expected no more output, got '2'.".to_string())
+        );
+        let test = TestScript::new("t", "print nope;").unwrap();
+        assert_eq!(
+            test.run(&[], false),
+            Err("[line 1] Error: Undefined variable 'nope'.".to_string())
+        );
+    }
+
+    #[test]
+    fn snapshots() {
+        let dir = env::temp_dir().join(format!("rlox-test-snapshots-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let test = TestScript::new("t", "expect_snapshot(\"pair\", [1, \"a\"]);")
+            .unwrap()
+            .with_snapshots(&dir);
+
+        assert!(test
+            .run(&[], false)
+            .unwrap_err()
+            .contains("no snapshot 'pair'"));
+        assert_eq!(test.run(&[], true), Ok(()));
+        assert_eq!(test.run(&[], false), Ok(()));
+        assert_eq!(
+            fs::read_to_string(dir.join("pair.snap")).unwrap(),
+            "[1, \"a\"]\n"
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
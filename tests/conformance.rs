@@ -0,0 +1,268 @@
+//! Runs the Crafting Interpreters test corpus vendored in `tests/lox` against
+//! the `rlox` binary.
+//!
+//! Each script states what it expects in comments, in the reference suite's
+//! format:
+//!
+//! - `// expect: <output>` for a line printed by the script,
+//! - `// expect runtime error: <message>` for a runtime error on that line,
+//! - `// Error at '<lexeme>': <message>` or `// [line N] Error...` for a
+//!   compile error.
+//!
+//! jlox names the offending token in compile errors and rlox doesn't, so the
+//! location is dropped before comparing. Runtime errors match on a prefix
+//! since rlox appends hints after the message.
+//!
+//! Scripts for features rlox doesn't have yet are listed in `SKIPPED`. Run
+//! with `--nocapture` to see the compatibility score.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Scripts (or whole directories) under `tests/lox` that are known not to
+/// pass, and why. A skipped script that starts passing fails the run so
+/// this list can't go stale.
+const SKIPPED: &[(&str, &str)] = &[
+    ("inheritance/", "inheritance is not implemented"),
+    ("super/", "inheritance is not implemented"),
+    (
+        "function/body_must_be_block.lox",
+        "message says 'function or method body'",
+    ),
+    (
+        "operator/add_bool_nil.lox",
+        "operand errors name the operand side",
+    ),
+    (
+        "operator/less_nonnum_num.lox",
+        "operand errors name the operand side",
+    ),
+    (
+        "print/missing_argument.lox",
+        "message has no trailing period",
+    ),
+    (
+        "return/at_top_level.lox",
+        "a top-level return ends the script",
+    ),
+    (
+        "unexpected_character.lox",
+        "scan errors stop before parsing",
+    ),
+    (
+        "variable/redeclare_global.lox",
+        "globals are resolved as a scope, so redeclaring one is an error",
+    ),
+    (
+        "variable/redefine_global.lox",
+        "globals are resolved as a scope, so redeclaring one is an error",
+    ),
+    (
+        "variable/use_false_as_var.lox",
+        "a reserved word as a name gets its own message",
+    ),
+    (
+        "variable/use_local_in_initializer.lox",
+        "message says 'Cannot' and misspells 'initializer'",
+    ),
+    (
+        "number/nan_equality.lox",
+        "like jlox, NaN is equal to itself (the reference runner skips this for jlox too)",
+    ),
+];
+
+#[derive(Debug, Default, PartialEq)]
+struct Expectations {
+    output: Vec<String>,
+    compile_errors: Vec<String>,
+    runtime_error: Option<(usize, String)>,
+}
+
+fn parse_expectations(source: &str) -> Expectations {
+    let mut expected = Expectations::default();
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let comment = match line.find("// ") {
+            Some(start) => &line[start + 3..],
+            None => continue,
+        };
+
+        if let Some(output) = comment.strip_prefix("expect: ") {
+            expected.output.push(output.to_string());
+        } else if let Some(msg) = comment.strip_prefix("expect runtime error: ") {
+            expected.runtime_error = Some((line_number, msg.to_string()));
+        } else if comment.starts_with("Error") {
+            expected
+                .compile_errors
+                .push(compile_error(line_number, comment));
+        } else if let Some(rest) = comment
+            .strip_prefix("[line ")
+            .or_else(|| comment.strip_prefix("[java line "))
+        {
+            if let Some(end) = rest.find("] ") {
+                if let Ok(line_number) = rest[..end].parse() {
+                    expected
+                        .compile_errors
+                        .push(compile_error(line_number, &rest[end + 2..]));
+                }
+            }
+        }
+    }
+    expected
+}
+
+/// Maps a jlox compile error ("Error at 'x': msg", "Error at end: msg" or
+/// "Error: msg") to the way rlox reports it.
+fn compile_error(line: usize, error: &str) -> String {
+    let msg = if let Some(rest) = error.strip_prefix("Error at '") {
+        rest.find("': ").map_or(rest, |i| &rest[i + 3..])
+    } else if let Some(rest) = error.strip_prefix("Error at end: ") {
+        rest
+    } else {
+        error.trim_start_matches("Error: ")
+    };
+    format!("[line {}] Error: {}", line, msg)
+}
+
+fn check(path: &Path) -> Result<(), String> {
+    let source = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let expected = parse_expectations(&source);
+
+    let result = Command::new(env!("CARGO_BIN_EXE_rlox"))
+        .arg("--no-config")
+        .arg(path)
+        .output()
+        .map_err(|e| e.to_string())?;
+    let stdout = String::from_utf8_lossy(&result.stdout);
+
+    let mut output = vec![];
+    let mut errors = vec![];
+    for line in stdout.lines() {
+        if line.starts_with("[line ") && line.contains("] Error") {
+            errors.push(line.to_string());
+        } else if !is_error_detail(line) {
+            output.push(line.to_string());
+        }
+    }
+
+    if output != expected.output {
+        return Err(format!(
+            "expected output {:?}, got {:?}",
+            expected.output, output
+        ));
+    }
+
+    if let Some((line, ref msg)) = expected.runtime_error {
+        let prefix = format!("[line {}] Error: {}", line, msg);
+        if errors.len() != 1 || !errors[0].starts_with(&prefix) {
+            return Err(format!(
+                "expected runtime error {:?}, got {:?}",
+                prefix, errors
+            ));
+        }
+    } else if errors != expected.compile_errors {
+        return Err(format!(
+            "expected errors {:?}, got {:?}",
+            expected.compile_errors, errors
+        ));
+    }
+
+    let should_fail = expected.runtime_error.is_some() || !expected.compile_errors.is_empty();
+    if result.status.success() == should_fail {
+        return Err(format!("unexpected exit status {}", result.status));
+    }
+    // jlox has no warnings, so rlox's are allowed on top of what it expects.
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    if stderr
+        .lines()
+        .any(|line| !line.contains("] Warning: ") && !is_error_detail(line))
+    {
+        return Err(format!("unexpected stderr {:?}", stderr));
+    }
+    Ok(())
+}
+
+/// The lines rlox prints under a diagnostic: where it is, the source line
+/// and a suggested fix.
+fn is_error_detail(line: &str) -> bool {
+    line.starts_with("  --> ") || line.starts_with("   | ") || line.starts_with("  help: ")
+}
+
+fn collect_scripts(dir: &Path, scripts: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            collect_scripts(&path, scripts);
+        } else if path.extension().is_some_and(|ext| ext == "lox") {
+            scripts.push(path);
+        }
+    }
+}
+
+fn skip_reason(name: &str) -> Option<&'static str> {
+    SKIPPED
+        .iter()
+        .find(|(prefix, _)| name.starts_with(prefix))
+        .map(|(_, reason)| *reason)
+}
+
+#[test]
+fn conformance() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/lox");
+    let mut scripts = vec![];
+    collect_scripts(&root, &mut scripts);
+    scripts.sort();
+
+    let mut passed = 0;
+    let mut skipped = 0;
+    let mut failures = vec![];
+    for script in &scripts {
+        let name = script
+            .strip_prefix(&root)
+            .unwrap()
+            .to_string_lossy()
+            .replace('\\', "/");
+        match (check(script), skip_reason(&name)) {
+            (Ok(()), None) => passed += 1,
+            (Err(_), Some(_)) => skipped += 1,
+            (Ok(()), Some(reason)) => failures.push(format!(
+                "{}: passes but is skipped ({}); remove it from SKIPPED",
+                name, reason
+            )),
+            (Err(msg), None) => failures.push(format!("{}: {}", name, msg)),
+        }
+    }
+
+    println!(
+        "conformance: {}/{} scripts pass ({} skipped)",
+        passed,
+        scripts.len(),
+        skipped
+    );
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn expectation_comments() {
+    let expected = parse_expectations(
+        "print 1; // expect: 1\n\
+         (a) = 2; // Error at '=': Invalid assignment target.\n\
+         // [java line 3] Error at end: Expect ';' after value.\n\
+         // [c line 3] Error at end: Expect ';' after value.\n\
+         x; // expect runtime error: Undefined variable 'x'.\n",
+    );
+    assert_eq!(
+        expected,
+        Expectations {
+            output: vec!["1".to_string()],
+            compile_errors: vec![
+                "[line 2] Error: Invalid assignment target.".to_string(),
+                "[line 3] Error: Expect ';' after value.".to_string(),
+            ],
+            runtime_error: Some((5, "Undefined variable 'x'.".to_string())),
+        }
+    );
+}
@@ -0,0 +1,172 @@
+//! Runs the same programs through the `rlox` binary and a reference Lox
+//! implementation and reports where they differ, to catch semantic drift as
+//! features land.
+//!
+//! The reference is any binary that takes a script path, prints the
+//! script's output to stdout and exits like jlox and clox: 65 for a compile
+//! error and 70 for a runtime error. Point `RLOX_REFERENCE` at one to run
+//! the comparison, e.g. `RLOX_REFERENCE=path/to/jlox cargo test --test
+//! differential -- --nocapture`; without it the comparison is skipped.
+//!
+//! The programs come from `rlox::grammar::generate`, so a divergence can be
+//! reproduced with `rlox gen --seed <seed> --depth <depth>`.
+//! `RLOX_DIFFERENTIAL_SEEDS` sets how many are run (default 200).
+
+use rlox::{format_stmts, grammar::generate};
+use std::{
+    env, fs,
+    path::Path,
+    process::{Command, Output},
+};
+
+const DEPTH: usize = 3;
+
+#[derive(Debug, PartialEq)]
+enum ErrorClass {
+    None,
+    Compile,
+    Runtime,
+    Other(Option<i32>),
+}
+
+#[derive(Debug, PartialEq)]
+struct Outcome {
+    output: Vec<String>,
+    error: ErrorClass,
+}
+
+impl Outcome {
+    /// `rlox` prints its errors to stdout, so those lines and the detail
+    /// under them are left out of the output.
+    fn new(result: &Output) -> Outcome {
+        let error = match result.status.code() {
+            Some(0) => ErrorClass::None,
+            Some(65) => ErrorClass::Compile,
+            Some(70) => ErrorClass::Runtime,
+            code => ErrorClass::Other(code),
+        };
+        let output = String::from_utf8_lossy(&result.stdout)
+            .lines()
+            .filter(|line| !(line.starts_with("[line ") && line.contains("] Error")))
+            .filter(|line| !is_error_detail(line))
+            .map(String::from)
+            .collect();
+        Outcome { output, error }
+    }
+}
+
+/// The lines rlox prints under a diagnostic: where it is, the source line
+/// and a suggested fix.
+fn is_error_detail(line: &str) -> bool {
+    line.starts_with("  --> ") || line.starts_with("   | ") || line.starts_with("  help: ")
+}
+
+/// How `rlox` and the reference differ, if they do.
+fn compare(rlox: &Outcome, reference: &Outcome) -> Option<String> {
+    if rlox.error != reference.error {
+        return Some(format!(
+            "rlox ended with {:?}, the reference with {:?}",
+            rlox.error, reference.error
+        ));
+    }
+    let lines = rlox.output.len().max(reference.output.len());
+    (0..lines).find_map(|i| {
+        let ours = rlox.output.get(i);
+        let theirs = reference.output.get(i);
+        if ours == theirs {
+            None
+        } else {
+            Some(format!(
+                "line {} of the output: rlox printed {:?}, the reference {:?}",
+                i + 1,
+                ours,
+                theirs
+            ))
+        }
+    })
+}
+
+fn run(binary: &str, args: &[&str], script: &Path) -> Outcome {
+    let result = Command::new(binary)
+        .args(args)
+        .arg(script)
+        .output()
+        .unwrap_or_else(|e| panic!("cannot run {}: {}", binary, e));
+    Outcome::new(&result)
+}
+
+#[test]
+fn generated_programs() {
+    let reference = match env::var("RLOX_REFERENCE") {
+        Ok(reference) => reference,
+        Err(_) => {
+            println!("differential: skipped, set RLOX_REFERENCE to a jlox or clox binary");
+            return;
+        }
+    };
+    let seeds: u64 = env::var("RLOX_DIFFERENTIAL_SEEDS")
+        .ok()
+        .and_then(|seeds| seeds.parse().ok())
+        .unwrap_or(200);
+
+    let script = env::temp_dir().join(format!("rlox-differential-{}.lox", std::process::id()));
+    let mut divergences = vec![];
+    for seed in 0..seeds {
+        fs::write(&script, format_stmts(&generate(seed, DEPTH))).unwrap();
+        let ours = run(env!("CARGO_BIN_EXE_rlox"), &["--no-config"], &script);
+        let theirs = run(&reference, &[], &script);
+        if let Some(divergence) = compare(&ours, &theirs) {
+            divergences.push(format!(
+                "rlox gen --seed {} --depth {}: {}",
+                seed, DEPTH, divergence
+            ));
+        }
+    }
+    let _ = fs::remove_file(&script);
+
+    println!(
+        "differential: {}/{} programs agree",
+        seeds as usize - divergences.len(),
+        seeds
+    );
+    assert!(divergences.is_empty(), "\n{}", divergences.join("\n"));
+}
+
+#[test]
+fn divergences() {
+    let outcome = |output: &[&str], error| Outcome {
+        output: output.iter().map(|line| line.to_string()).collect(),
+        error,
+    };
+    let ours = outcome(&["1", "2"], ErrorClass::None);
+    assert_eq!(
+        compare(&ours, &outcome(&["1", "2"], ErrorClass::None)),
+        None
+    );
+    assert_eq!(
+        compare(&ours, &outcome(&["1"], ErrorClass::None)).unwrap(),
+        "line 2 of the output: rlox printed Some(\"2\"), the reference None"
+    );
+    assert_eq!(
+        compare(&ours, &outcome(&["1", "2"], ErrorClass::Runtime)).unwrap(),
+        "rlox ended with None, the reference with Runtime"
+    );
+}
+
+#[test]
+fn outcome_of_a_failed_run() {
+    let script = env::temp_dir().join(format!(
+        "rlox-differential-error-{}.lox",
+        std::process::id()
+    ));
+    fs::write(&script, "print 1;\nprint -\"a\";\n").unwrap();
+    let outcome = run(env!("CARGO_BIN_EXE_rlox"), &["--no-config"], &script);
+    fs::write(&script, "print 1;\nprint (;\n").unwrap();
+    let compile = run(env!("CARGO_BIN_EXE_rlox"), &["--no-config"], &script);
+    let _ = fs::remove_file(&script);
+
+    assert_eq!(outcome.output, vec!["1".to_string()]);
+    assert_eq!(outcome.error, ErrorClass::Runtime);
+    assert_eq!(compile.output, Vec::<String>::new());
+    assert_eq!(compile.error, ErrorClass::Compile);
+}
@@ -0,0 +1,157 @@
+//! Feeds the scanner, parser, resolver and interpreter random input and
+//! checks that none of them panics: bad input is an error, and anything
+//! that can't happen is an internal error diagnostic rather than a crash.
+//!
+//! Inputs are random runs of Lox fragments and random edits of the scripts
+//! in `tests/lox`, the same for the same seed. `RLOX_NO_PANIC_CASES` sets
+//! how many of each are tried (default 500). A failure names the seed and
+//! the input.
+
+use rlox::{
+    parse, parse_expression, scan, Capabilities, ExecutionLimits, Interpreter, InterpreterConfig,
+};
+use std::{
+    env, fs, io,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    thread,
+};
+
+// Lox calls and nested expressions recurse on the Rust stack.
+const STACK_SIZE: usize = 64 * 1024 * 1024;
+
+const FRAGMENTS: &[&str] = &[
+    "var", "fun", "class", "trait", "with", "is", "if", "else", "while", "for", "return", "print",
+    "this", "super", "and", "or", "nil", "true", "false", "x", "f", "A", "init", "len", "capture",
+    "(", ")", "{", "}", "[", "]", ",", ".", "?.", "??", "...", ":", ";", "+", "-", "*", "/", "!",
+    "=", "==", "!=", "<", "<=", ">", ">=", "0", "1.5", "1e3", "1e", "1_", "0x1F", "0b2", "0o",
+    "\"s\"", "\"", "// c\n", "\n", " ", "é", "\t", "@", "#", "\0",
+];
+
+/// SplitMix64, as in `rlox::grammar`.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+fn cases() -> u64 {
+    env::var("RLOX_NO_PANIC_CASES")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(500)
+}
+
+fn fragments(seed: u64) -> String {
+    let mut rng = Rng(seed);
+    (0..1 + rng.below(40))
+        .map(|_| FRAGMENTS[rng.below(FRAGMENTS.len())])
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// `script` with a few fragments inserted and a few characters deleted.
+fn edit(seed: u64, script: &str) -> String {
+    let mut rng = Rng(seed);
+    let mut chars: Vec<char> = script.chars().collect();
+    for _ in 0..1 + rng.below(4) {
+        let at = rng.below(chars.len() + 1);
+        if rng.below(2) == 0 && at < chars.len() {
+            chars.remove(at);
+        } else {
+            let fragment = FRAGMENTS[rng.below(FRAGMENTS.len())];
+            chars.splice(at..at, fragment.chars());
+        }
+    }
+    chars.into_iter().collect()
+}
+
+fn scripts(dir: &Path, found: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            scripts(&path, found);
+        } else if path.extension().is_some_and(|ext| ext == "lox") {
+            found.push(path);
+        }
+    }
+}
+
+/// Runs `source` as far as it gets, ignoring the errors along the way.
+fn run(source: &str) {
+    let _ = scan(source.to_string());
+    let _ = parse_expression(source.to_string());
+    if let Ok(program) = parse(source.to_string()) {
+        let config = InterpreterConfig {
+            capabilities: Capabilities {
+                clock: false,
+                env: false,
+                fs: false,
+                net: false,
+            },
+            limits: ExecutionLimits {
+                max_steps: Some(10_000),
+                max_call_depth: Some(200),
+                timeout_ms: Some(200),
+            },
+            ..InterpreterConfig::sandbox()
+        };
+        let _ = Interpreter::with_config(config)
+            .with_output(io::sink())
+            .run(program);
+    }
+}
+
+fn check(seed: u64, source: &str) {
+    if panic::catch_unwind(AssertUnwindSafe(|| run(source))).is_err() {
+        panic!("seed {} panicked on:\n{}", seed, source);
+    }
+}
+
+fn on_big_stack(f: impl FnOnce() + Send + 'static) {
+    thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(f)
+        .unwrap()
+        .join()
+        .unwrap();
+}
+
+#[test]
+fn random_fragments() {
+    on_big_stack(|| {
+        for seed in 0..cases() {
+            check(seed, &fragments(seed));
+        }
+    });
+}
+
+#[test]
+fn edited_scripts() {
+    on_big_stack(|| {
+        let mut found = vec![];
+        scripts(
+            &Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/lox"),
+            &mut found,
+        );
+        found.sort();
+        let sources: Vec<String> = found
+            .iter()
+            .map(|path| fs::read_to_string(path).unwrap())
+            .collect();
+        for seed in 0..cases() {
+            let script = &sources[seed as usize % sources.len()];
+            check(seed, &edit(seed, script));
+        }
+    });
+}